@@ -2,6 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 /// Request body for ingestion endpoint
 #[derive(Debug, Deserialize, ToSchema)]
@@ -9,6 +10,27 @@ pub struct IngestRequest {
     /// Raw binary data to ingest (array of bytes representing "Hello")
     #[schema(example = json!([72, 101, 108, 108, 111]))]
     pub data: Vec<u8>,
+    /// Optional client-chosen segment ID: retrying with the same ID returns
+    /// the existing segment instead of creating a duplicate
+    #[serde(default)]
+    #[schema(example = "0192a1b2-7c3d-7e4f-8a9b-0c1d2e3f4a5b")]
+    pub segment_id: Option<Uuid>,
+    /// Optional record key: the segment becomes the latest value of this key,
+    /// and empty `data` deletes the key (tombstone)
+    #[serde(default)]
+    #[schema(example = "user-42")]
+    pub key: Option<String>,
+    /// Optional partition key: segments with the same partition key go to
+    /// the same partition, processed in order by a single receiver
+    #[serde(default)]
+    #[schema(example = "customer-42")]
+    pub partition_key: Option<String>,
+
+    /// Optional topic: segments of a topic are stored with the S3 options
+    /// of that topic (`ZUKLINK_S3_TOPIC_*`)
+    #[serde(default)]
+    #[schema(example = "billing")]
+    pub topic: Option<String>,
 }
 
 /// Response body for successful ingestion
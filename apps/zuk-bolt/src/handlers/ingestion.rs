@@ -1,23 +1,51 @@
 //! Ingestion handler
 
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use tracing::{error, info};
-use zuklink_domain::ingestion::error::IngestionError;
+use zuklink_domain::ingestion::{
+    checksum::Checksum, error::IngestionError, ids::SegmentId, service::IngestOptions,
+};
 
 use crate::{
     dto::ingestion::{ErrorResponse, IngestRequest, IngestResponse},
     AppState,
 };
 
+/// Header carrying the client idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header carrying the hex SHA-256 checksum of the data, as computed by the client
+pub const CHECKSUM_HEADER: &str = "X-Checksum-SHA256";
+
 /// Handle ingestion requests
+///
+/// With an `Idempotency-Key` header or a `segment_id` in the body, a retried
+/// request returns the segment stored by the first attempt with `200 OK`
+/// instead of creating a duplicate. With an `X-Checksum-SHA256` header, the
+/// data received is verified against the client's checksum before storage.
+/// With a `key` in the body, the segment is the latest value of that record
+/// key, and empty data is a tombstone deleting the key. With a
+/// `partition_key`, the segment is routed to the partition of that key.
+/// With a `topic`, the segment is stored with the S3 options of that topic.
+/// With `BOLT_NOTIFY=true`, a new segment is also pushed to its receiver.
 #[utoipa::path(
     post,
     path = "/ingest",
     request_body = IngestRequest,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Client key making retries of this request idempotent"),
+        ("X-Checksum-SHA256" = Option<String>, Header, description = "Hex SHA-256 checksum of the data, verified before storage")
+    ),
     responses(
         (status = 201, description = "Segment ingested successfully", body = IngestResponse),
-        (status = 400, description = "Bad request - empty or invalid data", body = ErrorResponse),
-        (status = 409, description = "Conflict - segment already exists", body = ErrorResponse),
+        (status = 200, description = "Segment already ingested by an earlier request with the same key", body = IngestResponse),
+        (status = 400, description = "Bad request - empty unkeyed data, invalid data, record or partition key or topic, or checksum mismatch", body = ErrorResponse),
+        (status = 409, description = "Conflict - segment already exists, or a retry carries other data than the original request", body = ErrorResponse),
         (status = 413, description = "Payload too large", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
@@ -25,43 +53,209 @@ use crate::{
 )]
 pub async fn ingest_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<IngestRequest>,
-) -> impl IntoResponse {
-    info!(data_size = payload.data.len(), "Received ingest request");
+) -> Response {
+    let data_size = payload.data.len();
+    info!(data_size, "Received ingest request");
+
+    let options = match ingest_options(
+        &headers,
+        payload.segment_id,
+        payload.key,
+        payload.partition_key,
+        payload.topic,
+    ) {
+        Ok(options) => options,
+        Err(err) => return error_response(err),
+    };
+
+    let partition_key = options.partition_key.clone();
+    let outcome = state.ingestion_service.ingest(payload.data, options).await;
+
+    match outcome {
+        Ok(outcome) => {
+            let segment_id = outcome.segment_id();
+            let (status, message) = if outcome.is_created() {
+                info!(segment_id = %segment_id, "Successfully ingested segment");
+                if let Some(notifier) = &state.notifier {
+                    notifier.notify(segment_id, partition_key.as_deref(), data_size);
+                }
+                (StatusCode::CREATED, "Segment ingested successfully")
+            } else {
+                info!(segment_id = %segment_id, "Segment already ingested, returning original");
+                (StatusCode::OK, "Segment already ingested")
+            };
 
-    match state.ingestion_service.ingest_data(payload.data).await {
-        Ok(segment_id) => {
-            info!(segment_id = %segment_id, "Successfully ingested segment");
             (
-                StatusCode::CREATED,
+                status,
                 Json(IngestResponse {
                     segment_id: segment_id.to_string(),
-                    message: "Segment ingested successfully".to_string(),
+                    message: message.to_string(),
                 }),
             )
                 .into_response()
         }
-        Err(err) => {
-            error!(error = ?err, "Failed to ingest segment");
-            let (status, message) = match err {
-                IngestionError::EmptySegment => {
-                    (StatusCode::BAD_REQUEST, "Data cannot be empty".to_string())
-                }
-                IngestionError::SegmentTooLarge { size, max } => (
-                    StatusCode::PAYLOAD_TOO_LARGE,
-                    format!(
-                        "Segment size ({} bytes) exceeds maximum ({} bytes)",
-                        size, max
-                    ),
-                ),
-                IngestionError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
-                IngestionError::StorageFailure(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-                IngestionError::SegmentAlreadyExists(msg) => (StatusCode::CONFLICT, msg),
-                IngestionError::ConfigError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-                IngestionError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            };
+        Err(err) => error_response(err),
+    }
+}
+
+/// Build the ingestion options from the request headers and body
+fn ingest_options(
+    headers: &HeaderMap,
+    segment_id: Option<uuid::Uuid>,
+    record_key: Option<String>,
+    partition_key: Option<String>,
+    topic: Option<String>,
+) -> Result<IngestOptions, IngestionError> {
+    let mut options = IngestOptions {
+        segment_id: segment_id.map(SegmentId::from_uuid),
+        record_key,
+        partition_key,
+        topic,
+        ..IngestOptions::default()
+    };
 
-            (status, Json(ErrorResponse { error: message })).into_response()
+    if let Some(key) = header_str(headers, IDEMPOTENCY_KEY_HEADER)? {
+        options = options.with_idempotency_key(key);
+    }
+    if let Some(checksum) = header_str(headers, CHECKSUM_HEADER)? {
+        options = options.with_expected_checksum(checksum.parse::<Checksum>()?);
+    }
+
+    Ok(options)
+}
+
+/// Read an optional header as a string
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, IngestionError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value.to_str().map_err(|_| {
+                IngestionError::invalid_data(format!("{} must be visible ASCII", name))
+            })
+        })
+        .transpose()
+}
+
+/// Map an ingestion error to its HTTP response
+fn error_response(err: IngestionError) -> Response {
+    error!(error = ?err, "Failed to ingest segment");
+    let (status, message) = match err {
+        IngestionError::EmptySegment => {
+            (StatusCode::BAD_REQUEST, "Data cannot be empty".to_string())
         }
+        IngestionError::SegmentTooLarge { size, max } => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Segment size ({} bytes) exceeds maximum ({} bytes)",
+                size, max
+            ),
+        ),
+        IngestionError::InvalidData(msg) => (StatusCode::BAD_REQUEST, msg),
+        IngestionError::StorageFailure(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+        IngestionError::SegmentAlreadyExists(msg) => (StatusCode::CONFLICT, msg),
+        err @ IngestionError::IdempotencyConflict(_) => (StatusCode::CONFLICT, err.to_string()),
+        err @ IngestionError::ChecksumMismatch { .. } => (StatusCode::BAD_REQUEST, err.to_string()),
+        IngestionError::ConfigError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+        IngestionError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+    };
+
+    (status, Json(ErrorResponse { error: message })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_options_from_headers() {
+        let checksum = Checksum::sha256(b"Hello");
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static("order-42"));
+        headers.insert(
+            CHECKSUM_HEADER,
+            HeaderValue::from_str(&checksum.to_string()).unwrap(),
+        );
+
+        let options =
+            ingest_options(&headers, None, Some("user-42".to_string()), None, None).unwrap();
+
+        assert_eq!(options.idempotency_key.as_deref(), Some("order-42"));
+        assert_eq!(options.expected_checksum, Some(checksum));
+        assert_eq!(options.record_key.as_deref(), Some("user-42"));
+        assert_eq!(options.segment_id, None);
+    }
+
+    #[test]
+    fn test_options_from_body() {
+        let segment_id = SegmentId::new();
+        let options = ingest_options(
+            &HeaderMap::new(),
+            Some(*segment_id.as_uuid()),
+            None,
+            Some("customer-42".to_string()),
+            Some("billing".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(options.segment_id, Some(segment_id));
+        assert_eq!(options.partition_key.as_deref(), Some("customer-42"));
+        assert_eq!(options.topic.as_deref(), Some("billing"));
+        assert_eq!(options.idempotency_key, None);
+    }
+
+    #[test]
+    fn test_invalid_headers_rejected() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CHECKSUM_HEADER, HeaderValue::from_static("not-a-checksum"));
+        assert!(matches!(
+            ingest_options(&headers, None, None, None, None),
+            Err(IngestionError::InvalidData(_))
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_bytes("clé".as_bytes()).unwrap(),
+        );
+        assert!(matches!(
+            ingest_options(&headers, None, None, None, None),
+            Err(IngestionError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_error_statuses() {
+        let status = |err| error_response(err).status();
+
+        assert_eq!(
+            status(IngestionError::EmptySegment),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(IngestionError::SegmentTooLarge { size: 2, max: 1 }),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            status(IngestionError::SegmentAlreadyExists("id".to_string())),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(IngestionError::idempotency_conflict("id")),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status(IngestionError::checksum_mismatch(
+                Checksum::sha256(b"a"),
+                Checksum::sha256(b"b")
+            )),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(IngestionError::storage_failure("S3 down")),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
     }
 }
@@ -0,0 +1,74 @@
+//! Metrics handler
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::fmt::Write;
+use zuklink_domain::ingestion::metrics::IngestionMetricsSnapshot;
+
+use crate::AppState;
+
+/// Expose the ingestion metrics in the Prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Ingestion metrics in the Prometheus text format", body = String)
+    ),
+    tag = "metrics"
+)]
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_metrics(&state.ingestion_service.metrics()),
+    )
+}
+
+/// Render the metrics in the Prometheus text format
+fn render_metrics(metrics: &IngestionMetricsSnapshot) -> String {
+    let gauges = [(
+        "zuk_bolt_compression_ratio",
+        "Bytes received per byte stored",
+        metrics.compression_ratio(),
+    )];
+    let counters = [
+        (
+            "zuk_bolt_segments_ingested_total",
+            "Segments stored",
+            metrics.segments_ingested as f64,
+        ),
+        (
+            "zuk_bolt_received_bytes_total",
+            "Bytes received from producers, before compression",
+            metrics.bytes_received as f64,
+        ),
+        (
+            "zuk_bolt_stored_bytes_total",
+            "Bytes written to storage, after compression",
+            metrics.bytes_stored as f64,
+        ),
+        (
+            "zuk_bolt_compression_seconds_total",
+            "Time spent compressing segments",
+            metrics.compression_time.as_secs_f64(),
+        ),
+        (
+            "zuk_bolt_segments_read_total",
+            "Segments read back through the service",
+            metrics.segments_read as f64,
+        ),
+        (
+            "zuk_bolt_decompression_seconds_total",
+            "Time spent decompressing and verifying segments read back",
+            metrics.decompression_time.as_secs_f64(),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (kind, family) in [("gauge", &gauges[..]), ("counter", &counters[..])] {
+        for (name, help, value) in family {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+    }
+    out
+}
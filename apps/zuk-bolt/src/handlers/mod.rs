@@ -1,3 +1,4 @@
 //! Request handlers
 
 pub mod ingestion;
+pub mod metrics;
@@ -2,21 +2,62 @@
 //!
 //! HTTP service for ingesting data into ZukLink distributed streaming platform.
 //! Follows the "Flat Storage" pattern: writes to S3 without coordination.
+//!
+//! Segments are compressed with the codec set in `BOLT_COMPRESSION` (none,
+//! gzip, zstd or lz4); compression ratio and time are exposed on `/metrics`.
+//!
+//! With `ZUKLINK_ENCRYPTION_KEYFILE` set, segments are encrypted client-side
+//! (AES-256-GCM envelope encryption) with the master keys of that keyfile.
+//! Server-side encryption, storage class and tags of the objects are set with
+//! the `ZUKLINK_S3_*` variables.
+//!
+//! Records sent with a partition key are routed to one of `ZUKLINK_PARTITIONS`
+//! partitions (default 64) by hashing the key.
+//!
+//! With `BOLT_MANIFESTS=true`, every stored segment is also recorded in the
+//! manifest of its window (`ZUKLINK_MANIFEST_WINDOW`, minute or hour), for
+//! receivers discovering segments from manifests.
+//!
+//! With `BOLT_NOTIFY=true`, the sender joins the Yellowpage cluster as an
+//! observer and pushes every new segment to the receiver owning it, so
+//! receivers only scan the bucket to reconcile.
+//!
+//! On SIGTERM the server stops accepting connections and waits for in-flight
+//! requests to complete, so no acknowledged segment is lost during rollouts.
 
 mod dto;
 mod handlers;
+mod notifier;
 mod routes;
+mod storage;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
-use zuklink_domain::ingestion::service::IngestionService;
-use zuklink_s3::infrastructure::S3StorageRepository;
+use zuklink_crypto::{EncryptedStorageRepository, LocalKeyProvider};
+use zuklink_domain::{
+    ingestion::{
+        compression::Compression,
+        partition::DEFAULT_PARTITION_COUNT,
+        service::{IngestionConfig, IngestionService},
+    },
+    manifest::window::WindowSize,
+};
+use zuklink_s3::infrastructure::{S3ManifestStore, S3StorageRepository, S3WriteOptions};
+use zuklink_yellowpage::{Yellowpage, YellowpageConfig};
+
+use crate::{
+    notifier::Notifier,
+    storage::{Backend, Storage},
+};
 
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub ingestion_service: Arc<IngestionService<S3StorageRepository>>,
+    pub ingestion_service: Arc<IngestionService<Storage>>,
+    /// Pushes new segments to their receiver, with `BOLT_NOTIFY=true`
+    pub notifier: Option<Arc<Notifier>>,
 }
 
 #[tokio::main]
@@ -49,15 +90,95 @@ async fn main() -> Result<()> {
 
     info!(bucket = %bucket, "Initializing S3 storage repository");
 
-    // Create S3 repository
-    let repository = S3StorageRepository::new(s3_client, bucket);
+    // Create S3 repository, with the object options of ZUKLINK_S3_* variables
+    let repository = S3StorageRepository::new(s3_client, bucket.clone())
+        .with_write_options(S3WriteOptions::from_env()?);
+
+    // Record segments in manifests if enabled
+    let manifests = if std::env::var("BOLT_MANIFESTS").is_ok_and(|value| value == "true") {
+        let window = match std::env::var("ZUKLINK_MANIFEST_WINDOW") {
+            Ok(name) => name.parse::<WindowSize>()?,
+            Err(_) => WindowSize::default(),
+        };
+        info!(window = %window, "Manifest recording enabled");
+        Some(S3ManifestStore::new(repository.clone(), window))
+    } else {
+        None
+    };
+
+    // Encrypt segments if a keyfile is configured
+    let backend = match std::env::var("ZUKLINK_ENCRYPTION_KEYFILE") {
+        Ok(path) => {
+            let keys = LocalKeyProvider::from_file(&path)?;
+            info!(keyfile = %path, primary_key = keys.primary().id(), "Segment encryption enabled");
+            Backend::Encrypted(EncryptedStorageRepository::new(repository, keys))
+        }
+        Err(_) => Backend::Plain(repository),
+    };
+    let repository = Storage::new(backend, manifests);
+
+    // Get compression codec from environment
+    let compression = match std::env::var("BOLT_COMPRESSION") {
+        Ok(name) => name.parse::<Compression>()?,
+        Err(_) => Compression::None,
+    };
+    if !compression.is_available() {
+        anyhow::bail!(
+            "BOLT_COMPRESSION={} is not enabled in this build",
+            compression
+        );
+    }
+
+    info!(compression = %compression, "Segment compression configured");
+
+    // Get the number of partitions from environment
+    let partitions = match std::env::var("ZUKLINK_PARTITIONS") {
+        Ok(count) => count.parse::<u32>()?,
+        Err(_) => DEFAULT_PARTITION_COUNT,
+    };
+    if partitions == 0 {
+        anyhow::bail!("ZUKLINK_PARTITIONS must be positive");
+    }
+
+    info!(partitions, "Partitioning configured");
 
     // Create ingestion service
-    let service = IngestionService::with_repository(repository);
+    let config = IngestionConfig {
+        compression,
+        partitions,
+        ..IngestionConfig::default()
+    };
+    let service = IngestionService::new(repository, config);
+
+    // Join the cluster as an observer to push new segments to their receiver
+    let (yellowpage, gossip_encrypted) =
+        if std::env::var("BOLT_NOTIFY").is_ok_and(|value| value == "true") {
+            let (yellowpage, gossip_encrypted) = join_as_observer().await?;
+            (Some(Arc::new(yellowpage)), gossip_encrypted)
+        } else {
+            (None, false)
+        };
+    let notifier = yellowpage
+        .as_ref()
+        .map(|yellowpage| {
+            let token = std::env::var("BOLT_NOTIFICATION_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty());
+            Notifier::new(
+                yellowpage.clone(),
+                bucket,
+                partitions,
+                token,
+                gossip_encrypted,
+            )
+            .map(Arc::new)
+        })
+        .transpose()?;
 
     // Create shared application state
     let state = AppState {
         ingestion_service: Arc::new(service),
+        notifier,
     };
 
     // Build HTTP router
@@ -72,7 +193,83 @@ async fn main() -> Result<()> {
 
     // Start server
     let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // Announce the departure, even while pushes still hold the cluster handle
+    if let Some(yellowpage) = yellowpage {
+        yellowpage.leave().await;
+    }
 
+    info!("Shutdown complete");
     Ok(())
 }
+
+/// Join the Yellowpage cluster of the receivers as an observer
+///
+/// Uses its own `BOLT_*` gossip address, so it can share the `.env` of a
+/// receiver; gossip timings are tuned
+/// through `YELLOWPAGE_*` variables. `BOLT_SEEDS` replaces the Yellowpage
+/// seeds only when it lists some. Also returns whether gossip is encrypted,
+/// which the notifier requires before it sends a token.
+async fn join_as_observer() -> Result<(Yellowpage, bool)> {
+    let node_id =
+        std::env::var("BOLT_NODE_ID").context("BOLT_NODE_ID must be set with BOLT_NOTIFY")?;
+    let host = std::env::var("BOLT_GOSSIP_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+    let port = std::env::var("BOLT_GOSSIP_PORT").unwrap_or_else(|_| "7100".to_string());
+    let gossip_addr: SocketAddr = format!("{}:{}", host, port)
+        .parse()
+        .context("Invalid BOLT_GOSSIP_HOST/BOLT_GOSSIP_PORT")?;
+    let seeds: Vec<String> = std::env::var("BOLT_SEEDS")
+        .map(|seeds| {
+            seeds
+                .split(',')
+                .map(str::trim)
+                .filter(|seed| !seed.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut config = YellowpageConfig::load()?
+        .with_node_id(node_id)
+        .with_listen_addr(gossip_addr)
+        .with_observer(true);
+    if !seeds.is_empty() {
+        config = config.with_seeds(seeds);
+    }
+    let gossip_encrypted = !config.gossip_keys.is_empty();
+    let yellowpage = Yellowpage::with_config(config).await?;
+    yellowpage.set_metadata("role", "sender").await?;
+
+    info!(node_id = %yellowpage.node_id(), addr = %gossip_addr, "Joined the cluster as an observer");
+    Ok((yellowpage, gossip_encrypted))
+}
+
+/// Wait for Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining in-flight requests");
+}
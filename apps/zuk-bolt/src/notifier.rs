@@ -0,0 +1,166 @@
+//! Push notifications of new segments
+//!
+//! With `BOLT_NOTIFY=true`, the sender joins the Yellowpage cluster as an
+//! observer: it follows the receivers and their metadata, but owns no shards
+//! and never counts for quorum. After every segment it stores, it finds the
+//! receiver owning the segment, with the assignment receivers use, and posts
+//! an S3 event message to the endpoint that receiver advertises.
+//!
+//! Pushes are best effort and never delay the ingest response. A segment
+//! whose push is lost (no owner, no endpoint, receiver down) is found by the
+//! next reconciling scan of its receiver.
+//!
+//! Endpoints are read from gossip: in plaintext, anyone able to send gossip
+//! packets could advertise its own URL and collect the bearer token. A token
+//! is therefore only accepted with encrypted gossip.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+use zuklink_domain::ingestion::{
+    ids::SegmentId,
+    partition::{self, PartitionId},
+};
+use zuklink_s3::infrastructure::{
+    assignment_key, created_event_message, segment_key, CreatedObject, EVENTS_URL_METADATA_KEY,
+};
+use zuklink_yellowpage::Yellowpage;
+
+/// Time given to a receiver to accept a notification
+const PUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pushes new segments to the receiver owning them
+pub struct Notifier {
+    yellowpage: Arc<Yellowpage>,
+    client: reqwest::Client,
+    bucket: String,
+    partitions: u32,
+    token: Option<String>,
+}
+
+impl Notifier {
+    /// Create a notifier for the segments of `bucket`
+    ///
+    /// `partitions` must match the ingestion configuration, so partitioned
+    /// segments are pushed to the owner of their partition.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a `token` is set but `gossip_encrypted` is not.
+    pub fn new(
+        yellowpage: Arc<Yellowpage>,
+        bucket: String,
+        partitions: u32,
+        token: Option<String>,
+        gossip_encrypted: bool,
+    ) -> Result<Self> {
+        check_token(token.as_deref(), gossip_encrypted)?;
+
+        let client = reqwest::Client::builder()
+            .timeout(PUSH_TIMEOUT)
+            .build()
+            .context("Failed to build the notification client")?;
+
+        Ok(Self {
+            yellowpage,
+            client,
+            bucket,
+            partitions,
+            token,
+        })
+    }
+
+    /// Push a segment to its owner in the background
+    ///
+    /// `size` is the size of the data received; the stored object may be
+    /// smaller once compressed.
+    pub fn notify(
+        self: &Arc<Self>,
+        segment_id: SegmentId,
+        partition_key: Option<&str>,
+        size: usize,
+    ) {
+        let partition = partition_key.map(|key| partition::partition_of(key, self.partitions));
+        let notifier = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = notifier.push(segment_id, partition, size).await {
+                warn!(segment_id = %segment_id, error = %err, "Failed to notify receiver, left to its next scan");
+            }
+        });
+    }
+
+    /// Post the event of a segment to the endpoint of its owner
+    async fn push(
+        &self,
+        segment_id: SegmentId,
+        partition: Option<PartitionId>,
+        size: usize,
+    ) -> Result<()> {
+        let routing_key = assignment_key(&segment_id, partition);
+        let view = self.yellowpage.cluster_view().await;
+        let Some(owner) = view.owner(&routing_key) else {
+            debug!(segment_id = %segment_id, "No receiver owns the segment, skipping notification");
+            return Ok(());
+        };
+        let Some(url) = self
+            .yellowpage
+            .get_metadata(owner, EVENTS_URL_METADATA_KEY)
+            .await
+        else {
+            debug!(segment_id = %segment_id, owner = %owner, "Owner advertises no event endpoint, skipping notification");
+            return Ok(());
+        };
+
+        let body = created_event_message(&[CreatedObject {
+            bucket: self.bucket.clone(),
+            key: segment_key(&segment_id, partition),
+            size: i64::try_from(size).unwrap_or(i64::MAX),
+            event_time: Some(Utc::now()),
+        }])?;
+
+        let mut request = self
+            .client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("POST {} failed", url))?;
+
+        debug!(segment_id = %segment_id, owner = %owner, "Notified receiver");
+        Ok(())
+    }
+}
+
+/// Check that the token is only sent to endpoints advertised by trusted nodes
+fn check_token(token: Option<&str>, gossip_encrypted: bool) -> Result<()> {
+    if token.is_some() && !gossip_encrypted {
+        anyhow::bail!(
+            "BOLT_NOTIFICATION_TOKEN requires encrypted gossip (YELLOWPAGE_GOSSIP_KEYS): \
+             endpoints advertised in plaintext could collect the token"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_requires_encrypted_gossip() {
+        assert!(check_token(Some("secret"), false).is_err());
+
+        assert!(check_token(Some("secret"), true).is_ok());
+        assert!(check_token(None, false).is_ok());
+    }
+}
@@ -16,6 +16,7 @@ use crate::{
 #[openapi(
     paths(
         handlers::ingestion::ingest_handler,
+        handlers::metrics::metrics_handler,
         health_handler
     ),
     components(
@@ -23,7 +24,8 @@ use crate::{
     ),
     tags(
         (name = "ingestion", description = "Data ingestion endpoints"),
-        (name = "health", description = "Health check endpoints")
+        (name = "health", description = "Health check endpoints"),
+        (name = "metrics", description = "Prometheus metrics")
     ),
     info(
         title = "ZukBolt API",
@@ -42,6 +44,10 @@ pub fn create_router(state: AppState) -> Router {
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .merge(ingestion::routes())
         .route("/health", axum::routing::get(health_handler))
+        .route(
+            "/metrics",
+            axum::routing::get(handlers::metrics::metrics_handler),
+        )
         .with_state(state)
 }
 
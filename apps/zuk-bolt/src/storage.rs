@@ -0,0 +1,150 @@
+//! Storage backend selected at startup
+//!
+//! Segments go to S3 as is, or through the envelope encryption layer when
+//! `ZUKLINK_ENCRYPTION_KEYFILE` is set. With `BOLT_MANIFESTS=true`, every
+//! stored segment is also recorded in the manifest of its window, as
+//! written: sealed size and key id included.
+
+use std::borrow::Cow;
+
+use zuklink_crypto::{EncryptedStorageRepository, LocalKeyProvider};
+use zuklink_domain::{
+    ingestion::{
+        entity::{Segment, StoredSegment},
+        error::IngestionError,
+        idempotency::IdempotencyClaim,
+        ids::SegmentId,
+    },
+    manifest::entry::ManifestEntry,
+    ports::StorageRepository,
+};
+use zuklink_s3::infrastructure::{S3ManifestStore, S3StorageRepository};
+
+/// S3 storage, optionally encrypted client-side
+pub enum Backend {
+    Plain(S3StorageRepository),
+    Encrypted(EncryptedStorageRepository<S3StorageRepository, LocalKeyProvider>),
+}
+
+/// Storage of segments, recording them in manifests if enabled
+pub struct Storage {
+    backend: Backend,
+    manifests: Option<S3ManifestStore>,
+}
+
+impl Storage {
+    /// Store segments in `backend`, recording them in `manifests` if set
+    pub fn new(backend: Backend, manifests: Option<S3ManifestStore>) -> Self {
+        Self { backend, manifests }
+    }
+
+    /// Segment and bytes as written to S3, sealed if encryption is enabled
+    async fn prepare<'a>(
+        &self,
+        segment: &'a Segment,
+        data: &'a [u8],
+    ) -> Result<(Cow<'a, Segment>, Cow<'a, [u8]>), IngestionError> {
+        match &self.backend {
+            Backend::Plain(_) => Ok((Cow::Borrowed(segment), Cow::Borrowed(data))),
+            Backend::Encrypted(repository) => {
+                let (segment, sealed) = repository.encrypt(segment, data).await?;
+                Ok((Cow::Owned(segment), Cow::Owned(sealed)))
+            }
+        }
+    }
+
+    /// Repository writing to S3, below the encryption layer if any
+    fn s3(&self) -> &S3StorageRepository {
+        match &self.backend {
+            Backend::Plain(repository) => repository,
+            Backend::Encrypted(repository) => repository.inner(),
+        }
+    }
+
+    /// Record a stored segment in its manifest, if manifests are enabled
+    ///
+    /// A segment is only acknowledged once recorded: receivers reading
+    /// manifests would never see it otherwise. The retry of a request that
+    /// failed here finds the segment stored and records it again.
+    async fn record(&self, segment: &Segment, data: &[u8]) -> Result<(), IngestionError> {
+        match &self.manifests {
+            Some(manifests) => {
+                manifests
+                    .record(&ManifestEntry::of(segment, data.len()))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl StorageRepository for Storage {
+    async fn save(&self, segment: &Segment, data: &[u8]) -> Result<String, IngestionError> {
+        let (segment, data) = self.prepare(segment, data).await?;
+        let key = self.s3().save(&segment, &data).await?;
+        self.record(&segment, &data).await?;
+        Ok(key)
+    }
+
+    async fn save_if_absent(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> Result<String, IngestionError> {
+        let (segment, data) = self.prepare(segment, data).await?;
+        let result = self.s3().save_if_absent(&segment, &data).await;
+        match result {
+            // Stored by an earlier attempt, which may have failed to record it
+            Ok(_) | Err(IngestionError::SegmentAlreadyExists(_)) => {
+                self.record(&segment, &data).await?
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> Result<Option<IdempotencyClaim>, IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.claim_idempotency_key(claim).await,
+            Backend::Encrypted(repository) => repository.claim_idempotency_key(claim).await,
+        }
+    }
+
+    async fn commit_idempotency_key(&self, claim: &IdempotencyClaim) -> Result<(), IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.commit_idempotency_key(claim).await,
+            Backend::Encrypted(repository) => repository.commit_idempotency_key(claim).await,
+        }
+    }
+
+    async fn get(&self, segment_id: &SegmentId) -> Result<Vec<u8>, IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.get(segment_id).await,
+            Backend::Encrypted(repository) => repository.get(segment_id).await,
+        }
+    }
+
+    async fn load(&self, segment_id: &SegmentId) -> Result<StoredSegment, IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.load(segment_id).await,
+            Backend::Encrypted(repository) => repository.load(segment_id).await,
+        }
+    }
+
+    async fn exists(&self, segment_id: &SegmentId) -> Result<bool, IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.exists(segment_id).await,
+            Backend::Encrypted(repository) => repository.exists(segment_id).await,
+        }
+    }
+
+    async fn delete(&self, segment_id: &SegmentId) -> Result<(), IngestionError> {
+        match &self.backend {
+            Backend::Plain(repository) => repository.delete(segment_id).await,
+            Backend::Encrypted(repository) => repository.delete(segment_id).await,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Compaction of small segments and keyed segments
+//!
+//! Like garbage collection, compaction is a cluster-wide duty run by the
+//! Yellowpage leader only, and skipped while the leader lacks quorum. Each
+//! run first removes the older versions of keyed segments, then merges the
+//! small segments left, so both never work on the same segments at once.
+//!
+//! The same compaction can be run once, without joining the cluster, with
+//! `zuk-sink compact [--keys]`.
+
+use anyhow::Result;
+use aws_sdk_s3::Client;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use zuklink_s3::infrastructure::{
+    CompactionReport, KeyCompactionReport, S3Compactor, S3KeyCompactor, S3StorageRepository,
+};
+use zuklink_yellowpage::Yellowpage;
+
+use crate::config::CompactionConfig;
+
+/// Compactors described by the configuration
+pub struct Compactors {
+    /// Merges small segments into containers
+    pub segments: S3Compactor,
+    /// Removes older versions of keyed segments
+    pub keys: S3KeyCompactor,
+}
+
+/// Build the compactors described by the configuration
+pub fn compactors(client: Client, config: &CompactionConfig) -> Compactors {
+    let repository = S3StorageRepository::new(client, config.bucket.clone())
+        .with_write_options(config.s3_options.clone());
+
+    Compactors {
+        segments: S3Compactor::new(repository.clone(), config.policy.clone()),
+        keys: S3KeyCompactor::new(repository, config.key_policy.clone()),
+    }
+}
+
+/// Compact periodically while this node leads, until `shutdown` flips
+pub async fn run_leader_duty(
+    compactors: Compactors,
+    yellowpage: Arc<Yellowpage>,
+    config: CompactionConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    if !config.enabled && !config.keys_enabled {
+        info!("Compaction disabled");
+        return;
+    }
+
+    info!(
+        interval_secs = config.interval.as_secs(),
+        segments = config.enabled,
+        keys = config.keys_enabled,
+        "Compaction enabled on the leader"
+    );
+    let mut interval = tokio::time::interval(config.interval);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        if !yellowpage.is_leader() {
+            debug!("Not the leader, skipping compaction");
+            continue;
+        }
+        if !yellowpage.has_quorum().await {
+            warn!("Leader without quorum, skipping compaction");
+            continue;
+        }
+
+        if config.keys_enabled {
+            if let Err(err) = compactors.keys.run_once().await {
+                warn!(error = %err, "Key compaction failed");
+            }
+        }
+        if config.enabled {
+            if let Err(err) = compactors.segments.run_once().await {
+                warn!(error = %err, "Compaction failed");
+            }
+        }
+    }
+}
+
+/// Merge small segments once, for the `compact` command
+pub async fn run_once(client: Client, config: &CompactionConfig) -> Result<CompactionReport> {
+    Ok(compactors(client, config).segments.run_once().await?)
+}
+
+/// Compact keyed segments once, for the `compact --keys` command
+pub async fn run_keys_once(
+    client: Client,
+    config: &CompactionConfig,
+) -> Result<KeyCompactionReport> {
+    Ok(compactors(client, config).keys.run_once().await?)
+}
@@ -0,0 +1,746 @@
+//! Receiver configuration
+//!
+//! All settings are read from environment variables (see `.env.example`).
+//!
+//! The `ZUK_*` cluster settings are shorthands for their `YELLOWPAGE_*`
+//! equivalents: each one overrides the Yellowpage configuration only when set.
+
+use anyhow::{Context, Result};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::info;
+use zuklink_domain::{
+    compaction::{keyed::KeyCompactionPolicy, policy::CompactionPolicy},
+    delivery::output::validate_group,
+    ingestion::topic,
+    ordering::policy::OrderingPolicy,
+    retention::policy::RetentionPolicy,
+};
+use zuklink_s3::infrastructure::S3WriteOptions;
+use zuklink_yellowpage::YellowpageConfig;
+
+/// Configuration of a zuk-sink instance
+#[derive(Debug, Clone)]
+pub struct SinkConfig {
+    /// Cluster settings overriding the Yellowpage configuration (`ZUK_*`)
+    pub cluster: ClusterOverrides,
+    /// Bucket holding the segments (`ZUKLINK_BUCKET`)
+    pub bucket: String,
+    /// Master keys of encrypted segments (`ZUKLINK_ENCRYPTION_KEYFILE`)
+    pub encryption_keyfile: Option<PathBuf>,
+    /// Process unencrypted segments despite the keyfile
+    /// (`ZUKLINK_ENCRYPTION_ALLOW_PLAINTEXT`)
+    pub allow_plaintext: bool,
+    /// Object options of the bucket, for SSE-C keys (`ZUKLINK_S3_*`)
+    pub s3_options: S3WriteOptions,
+    /// Interval between two bucket scans (`SINK_POLL_INTERVAL_MS`)
+    pub poll_interval: Duration,
+    /// Maximum number of segments processed concurrently (`SINK_MAX_IN_FLIGHT`)
+    pub max_in_flight: usize,
+    /// Whether the segments of a shard are processed in order (`SINK_ORDERED`)
+    pub ordered: bool,
+    /// Lateness window of ordered shards (`SINK_LATENESS_MS`)
+    pub ordering: OrderingPolicy,
+    /// Consumer group output batches are tagged with (`SINK_GROUP`)
+    pub group: String,
+    /// Where processed segments are written (`SINK_OUTPUT`), only logged if unset
+    pub output: Option<OutputTarget>,
+    /// Whether output batches are committed exactly once (`SINK_EXACTLY_ONCE`)
+    pub exactly_once: bool,
+    /// Object-created events, replacing most bucket scans (`SINK_NOTIFICATIONS`)
+    pub notifications: Option<NotificationConfig>,
+    /// Manifests read instead of listing the bucket (`SINK_MANIFESTS`)
+    pub manifests: Option<ManifestConfig>,
+    /// Address of the health and metrics endpoints (`SINK_HOST`, `SINK_PORT`)
+    pub http_addr: SocketAddr,
+    /// Garbage collection, run by the cluster leader
+    pub gc: GcConfig,
+    /// Compaction of small segments and keyed segments, run by the cluster leader
+    pub compaction: CompactionConfig,
+}
+
+impl SinkConfig {
+    /// Load the configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        let cluster = ClusterOverrides::from_lookup(|key| std::env::var(key).ok())?;
+
+        let bucket = env_or("ZUKLINK_BUCKET", "zuklink");
+
+        let encryption_keyfile = std::env::var("ZUKLINK_ENCRYPTION_KEYFILE")
+            .ok()
+            .map(PathBuf::from);
+
+        let s3_options = S3WriteOptions::from_env()?;
+
+        let poll_interval = Duration::from_millis(
+            env_or("SINK_POLL_INTERVAL_MS", "1000")
+                .parse()
+                .context("Invalid SINK_POLL_INTERVAL_MS")?,
+        );
+
+        let max_in_flight = env_or("SINK_MAX_IN_FLIGHT", "16")
+            .parse()
+            .context("Invalid SINK_MAX_IN_FLIGHT")?;
+
+        let ordered = env_parse("SINK_ORDERED")?.unwrap_or(false);
+        let ordering = OrderingPolicy {
+            lateness: env_parse("SINK_LATENESS_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(OrderingPolicy::default().lateness),
+        };
+
+        let group = env_or("SINK_GROUP", "zuk-sink");
+        validate_group(&group).context("Invalid SINK_GROUP")?;
+
+        let output = std::env::var("SINK_OUTPUT")
+            .ok()
+            .map(|value| OutputTarget::parse(&value))
+            .transpose()?;
+        let exactly_once = env_parse("SINK_EXACTLY_ONCE")?.unwrap_or(false);
+        if exactly_once && output.is_none() {
+            anyhow::bail!("SINK_EXACTLY_ONCE requires SINK_OUTPUT");
+        }
+
+        let http_host = env_or("SINK_HOST", "0.0.0.0");
+        let http_port = env_or("SINK_PORT", "3001");
+        let http_addr = format!("{}:{}", http_host, http_port)
+            .parse()
+            .context("Invalid SINK_HOST/SINK_PORT")?;
+
+        let notifications = if env_parse("SINK_NOTIFICATIONS")?.unwrap_or(false) {
+            Some(NotificationConfig::from_env()?)
+        } else {
+            None
+        };
+
+        let manifests = if env_parse("SINK_MANIFESTS")?.unwrap_or(false) {
+            Some(ManifestConfig::from_env()?)
+        } else {
+            None
+        };
+
+        let gc = GcConfig::from_env()?;
+        let compaction = CompactionConfig::from_env()?;
+
+        Ok(Self {
+            cluster,
+            bucket,
+            encryption_keyfile,
+            allow_plaintext: env_parse("ZUKLINK_ENCRYPTION_ALLOW_PLAINTEXT")?.unwrap_or(false),
+            s3_options,
+            poll_interval,
+            max_in_flight,
+            ordered,
+            ordering,
+            group,
+            output,
+            exactly_once,
+            notifications,
+            manifests,
+            http_addr,
+            gc,
+            compaction,
+        })
+    }
+}
+
+/// Cluster settings of the `ZUK_*` variables
+///
+/// Unset variables leave the Yellowpage configuration (`YELLOWPAGE_*` and
+/// `YELLOWPAGE_CONFIG_FILE`) untouched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClusterOverrides {
+    /// Unique node identifier (`ZUK_NODE_ID`)
+    pub node_id: Option<String>,
+    /// Address used for gossip, also advertised to peers (`ZUK_GOSSIP_HOST`,
+    /// `ZUK_GOSSIP_PORT`, defaulting to `127.0.0.1` and `7000` when only one is set)
+    pub gossip_addr: Option<SocketAddr>,
+    /// Seed nodes to join the cluster (`ZUK_SEEDS`, comma-separated), ignored if empty
+    pub seeds: Option<Vec<String>>,
+}
+
+impl ClusterOverrides {
+    /// Read the settings from the variables returned by `lookup`
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let node_id = lookup("ZUK_NODE_ID").filter(|node_id| !node_id.is_empty());
+
+        let host = lookup("ZUK_GOSSIP_HOST");
+        let port = lookup("ZUK_GOSSIP_PORT");
+        let gossip_addr = if host.is_some() || port.is_some() {
+            let host = host.as_deref().unwrap_or("127.0.0.1");
+            let port = port.as_deref().unwrap_or("7000");
+            let addr = format!("{}:{}", host, port)
+                .parse()
+                .context("Invalid ZUK_GOSSIP_HOST/ZUK_GOSSIP_PORT")?;
+            Some(addr)
+        } else {
+            None
+        };
+
+        let seeds = lookup("ZUK_SEEDS")
+            .map(|seeds| {
+                seeds
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|seed| !seed.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|seeds| !seeds.is_empty());
+
+        Ok(Self {
+            node_id,
+            gossip_addr,
+            seeds,
+        })
+    }
+
+    /// Apply the settings that are set to a Yellowpage configuration
+    pub fn apply(&self, mut config: YellowpageConfig) -> YellowpageConfig {
+        if let Some(node_id) = &self.node_id {
+            config = config.with_node_id(node_id.clone());
+        }
+        if let Some(gossip_addr) = self.gossip_addr {
+            config = config.with_listen_addr(gossip_addr);
+        }
+        if let Some(seeds) = &self.seeds {
+            config = config.with_seeds(seeds.clone());
+        }
+        config
+    }
+}
+
+/// Configuration of the event notification endpoint
+#[derive(Debug, Clone)]
+pub struct NotificationConfig {
+    /// Token expected as `Authorization: Bearer <token>` (`SINK_NOTIFICATION_TOKEN`)
+    pub token: Option<String>,
+    /// Interval between two reconciling bucket scans (`SINK_RECONCILE_INTERVAL_MS`)
+    pub reconcile_interval: Duration,
+    /// URL of the endpoint advertised to senders (`SINK_EVENTS_URL`)
+    pub events_url: Option<String>,
+}
+
+impl NotificationConfig {
+    /// Load the configuration from environment variables
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            token: std::env::var("SINK_NOTIFICATION_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            reconcile_interval: Duration::from_millis(
+                env_or("SINK_RECONCILE_INTERVAL_MS", "60000")
+                    .parse()
+                    .context("Invalid SINK_RECONCILE_INTERVAL_MS")?,
+            ),
+            events_url: std::env::var("SINK_EVENTS_URL").ok(),
+        })
+    }
+
+    /// URL of the endpoint advertised to senders
+    ///
+    /// Unless `SINK_EVENTS_URL` is set, senders reach the endpoint on
+    /// `http_port` at the address advertised for gossip.
+    pub fn events_url(&self, gossip_addr: SocketAddr, http_port: u16) -> String {
+        self.events_url.clone().unwrap_or_else(|| {
+            format!(
+                "http://{}/events",
+                SocketAddr::new(gossip_addr.ip(), http_port)
+            )
+        })
+    }
+}
+
+/// Configuration of the discovery of segments from manifests
+#[derive(Debug, Clone)]
+pub struct ManifestConfig {
+    /// Time a window stays open past its end, for late entries
+    /// (`ZUKLINK_MANIFEST_GRACE_SECS`)
+    pub grace: Duration,
+    /// Interval between two sealing passes on the leader
+    /// (`SINK_MANIFEST_SEAL_INTERVAL_SECS`)
+    pub seal_interval: Duration,
+}
+
+impl ManifestConfig {
+    /// Load the configuration from environment variables
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            grace: Duration::from_secs(
+                env_or("ZUKLINK_MANIFEST_GRACE_SECS", "60")
+                    .parse()
+                    .context("Invalid ZUKLINK_MANIFEST_GRACE_SECS")?,
+            ),
+            seal_interval: Duration::from_secs(
+                env_or("SINK_MANIFEST_SEAL_INTERVAL_SECS", "60")
+                    .parse()
+                    .context("Invalid SINK_MANIFEST_SEAL_INTERVAL_SECS")?,
+            ),
+        })
+    }
+}
+
+/// Where processed segments are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Directory on a local or mounted filesystem (`file:<dir>`)
+    File(PathBuf),
+    /// Prefix in an S3 bucket (`s3://<bucket>/<prefix>`)
+    S3 { bucket: String, prefix: String },
+}
+
+impl OutputTarget {
+    /// Parse `file:<dir>` or `s3://<bucket>[/<prefix>]`
+    fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        if let Some(location) = value.strip_prefix("s3://") {
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            if bucket.is_empty() {
+                anyhow::bail!("Invalid SINK_OUTPUT '{}': missing bucket", value);
+            }
+            let prefix = match prefix.trim_end_matches('/') {
+                "" => String::new(),
+                prefix => format!("{}/", prefix),
+            };
+            return Ok(Self::S3 {
+                bucket: bucket.to_string(),
+                prefix,
+            });
+        }
+        match value.strip_prefix("file:") {
+            Some(dir) if !dir.is_empty() => Ok(Self::File(PathBuf::from(dir))),
+            _ => anyhow::bail!(
+                "Invalid SINK_OUTPUT '{}', expected file:<dir> or s3://<bucket>/<prefix>",
+                value
+            ),
+        }
+    }
+}
+
+/// Configuration of the segment garbage collection
+///
+/// Also used by the standalone `zuk-sink gc` command, which does not join
+/// the cluster.
+#[derive(Debug, Clone)]
+pub struct GcConfig {
+    /// Bucket holding the segments (`ZUKLINK_BUCKET`)
+    pub bucket: String,
+    /// Options the progress of consumer groups is read with (`ZUKLINK_S3_*`)
+    pub s3_options: S3WriteOptions,
+    /// Retention rules (`ZUKLINK_RETENTION_*`), nothing is deleted if none is set
+    ///
+    /// Topics listed in `ZUKLINK_RETENTION_TOPICS` have their own rules
+    /// (`ZUKLINK_RETENTION_TOPIC_<TOPIC>_*`).
+    pub policy: RetentionPolicy,
+    /// Interval between two runs on the leader (`SINK_GC_INTERVAL_SECS`)
+    pub interval: Duration,
+    /// Only log what would be deleted (`ZUKLINK_GC_DRY_RUN`)
+    pub dry_run: bool,
+    /// File the audit entries are appended to (`ZUKLINK_GC_AUDIT_LOG`)
+    pub audit_log: Option<PathBuf>,
+}
+
+impl GcConfig {
+    /// Load the configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        let policy = retention_policy(|key| std::env::var(key).ok())?;
+
+        let interval = Duration::from_secs(
+            env_or("SINK_GC_INTERVAL_SECS", "3600")
+                .parse()
+                .context("Invalid SINK_GC_INTERVAL_SECS")?,
+        );
+
+        Ok(Self {
+            bucket: env_or("ZUKLINK_BUCKET", "zuklink"),
+            s3_options: S3WriteOptions::from_env()?,
+            policy,
+            interval,
+            dry_run: env_parse("ZUKLINK_GC_DRY_RUN")?.unwrap_or(false),
+            audit_log: std::env::var("ZUKLINK_GC_AUDIT_LOG")
+                .ok()
+                .map(PathBuf::from),
+        })
+    }
+}
+
+/// Read the retention policy from the variables returned by `lookup`
+///
+/// The rules of a topic listed in `ZUKLINK_RETENTION_TOPICS` are set with the
+/// same variables, prefixed with `ZUKLINK_RETENTION_TOPIC_<TOPIC>_` instead of
+/// `ZUKLINK_RETENTION_`, where `<TOPIC>` is the topic name in upper case with
+/// `-` and `.` replaced by `_`. Rules a topic does not set are the global
+/// ones, and `none` lifts a global limit.
+fn retention_policy(lookup: impl Fn(&str) -> Option<String>) -> Result<RetentionPolicy> {
+    let global = overridden_policy(&RetentionPolicy::default(), "ZUKLINK_RETENTION", &lookup)?;
+
+    let mut policy = global.clone();
+    let topics = lookup("ZUKLINK_RETENTION_TOPICS").unwrap_or_default();
+    for name in topics
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        topic::validate(name).context("Invalid ZUKLINK_RETENTION_TOPICS")?;
+
+        let prefix = format!("ZUKLINK_RETENTION_TOPIC_{}", variable_name(name));
+        let overrides = overridden_policy(&global, &prefix, &lookup)?;
+        policy.topics.insert(name.to_string(), overrides);
+    }
+
+    Ok(policy)
+}
+
+/// Copy of a policy, with the rules of the variables set under `prefix`
+fn overridden_policy(
+    policy: &RetentionPolicy,
+    prefix: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<RetentionPolicy> {
+    let name = |setting: &str| format!("{}_{}", prefix, setting);
+
+    Ok(RetentionPolicy {
+        max_age: lookup_limit::<u64>(lookup, &name("MAX_AGE_SECS"))?
+            .map_or(policy.max_age, |max_age| max_age.map(Duration::from_secs)),
+        max_bytes: lookup_limit(lookup, &name("MAX_BYTES"))?.unwrap_or(policy.max_bytes),
+        delete_checkpointed: lookup_parse(lookup, &name("CHECKPOINTS"))?
+            .unwrap_or(policy.delete_checkpointed),
+        topics: Default::default(),
+    })
+}
+
+/// Name of a topic in variable names
+fn variable_name(topic: &str) -> String {
+    topic
+        .chars()
+        .map(|c| match c {
+            '-' | '.' => '_',
+            c => c.to_ascii_uppercase(),
+        })
+        .collect()
+}
+
+/// Configuration of the compaction of small segments and keyed segments
+///
+/// Also used by the standalone `zuk-sink compact` command, which does not
+/// join the cluster.
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+    /// Bucket holding the segments (`ZUKLINK_BUCKET`)
+    pub bucket: String,
+    /// Options containers are written with (`ZUKLINK_S3_*`)
+    pub s3_options: S3WriteOptions,
+    /// Whether the leader compacts (`ZUKLINK_COMPACTION_ENABLED`)
+    pub enabled: bool,
+    /// Compaction rules (`ZUKLINK_COMPACTION_*`)
+    pub policy: CompactionPolicy,
+    /// Whether the leader removes older versions of keyed segments
+    /// (`ZUKLINK_KEY_COMPACTION_ENABLED`)
+    pub keys_enabled: bool,
+    /// Key compaction rules (`ZUKLINK_TOMBSTONE_GRACE_SECS`)
+    pub key_policy: KeyCompactionPolicy,
+    /// Interval between two runs on the leader (`SINK_COMPACTION_INTERVAL_SECS`)
+    pub interval: Duration,
+}
+
+impl CompactionConfig {
+    /// Load the configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        let defaults = CompactionPolicy::default();
+        let policy = CompactionPolicy {
+            small_segment_bytes: env_parse("ZUKLINK_COMPACTION_SMALL_SEGMENT_BYTES")?
+                .unwrap_or(defaults.small_segment_bytes),
+            target_bytes: env_parse("ZUKLINK_COMPACTION_TARGET_BYTES")?
+                .unwrap_or(defaults.target_bytes),
+            window: env_parse("ZUKLINK_COMPACTION_WINDOW_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.window),
+            min_age: env_parse("ZUKLINK_COMPACTION_MIN_AGE_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.min_age),
+            min_segments: env_parse("ZUKLINK_COMPACTION_MIN_SEGMENTS")?
+                .unwrap_or(defaults.min_segments),
+        };
+
+        let key_policy = KeyCompactionPolicy {
+            tombstone_grace: env_parse("ZUKLINK_TOMBSTONE_GRACE_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(KeyCompactionPolicy::default().tombstone_grace),
+        };
+
+        let interval = Duration::from_secs(
+            env_or("SINK_COMPACTION_INTERVAL_SECS", "600")
+                .parse()
+                .context("Invalid SINK_COMPACTION_INTERVAL_SECS")?,
+        );
+
+        Ok(Self {
+            bucket: env_or("ZUKLINK_BUCKET", "zuklink"),
+            s3_options: S3WriteOptions::from_env()?,
+            enabled: env_parse("ZUKLINK_COMPACTION_ENABLED")?.unwrap_or(false),
+            policy,
+            keys_enabled: env_parse("ZUKLINK_KEY_COMPACTION_ENABLED")?.unwrap_or(false),
+            key_policy,
+            interval,
+        })
+    }
+}
+
+/// Configuration of the standalone `zuk-sink reset` command
+#[derive(Debug, Clone)]
+pub struct ResetConfig {
+    /// Bucket holding the segments and the progress (`ZUKLINK_BUCKET`)
+    pub bucket: String,
+    /// Options the reset is written with (`ZUKLINK_S3_*`)
+    pub s3_options: S3WriteOptions,
+    /// Consumer group to reset (`SINK_GROUP`)
+    pub group: String,
+}
+
+impl ResetConfig {
+    /// Load the configuration from environment variables
+    pub fn from_env() -> Result<Self> {
+        let group = env_or("SINK_GROUP", "zuk-sink");
+        validate_group(&group).context("Invalid SINK_GROUP")?;
+
+        Ok(Self {
+            bucket: env_or("ZUKLINK_BUCKET", "zuklink"),
+            s3_options: S3WriteOptions::from_env()?,
+            group,
+        })
+    }
+}
+
+/// Parse an optional environment variable
+fn env_parse<T>(key: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    lookup_parse(&|key| std::env::var(key).ok(), key)
+}
+
+/// Parse an optional variable returned by `lookup`
+fn lookup_parse<T>(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    lookup(key)
+        .map(|value| value.trim().parse())
+        .transpose()
+        .with_context(|| format!("Invalid {}", key))
+}
+
+/// Parse an optional limit returned by `lookup`, `Some(None)` for `none`
+fn lookup_limit<T>(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Result<Option<Option<T>>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match lookup(key).as_deref().map(str::trim) {
+        Some("none") => Ok(Some(None)),
+        _ => Ok(lookup_parse(lookup, key)?.map(Some)),
+    }
+}
+
+/// Read an environment variable, falling back to a default value
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key).unwrap_or_else(|_| {
+        info!("{} not set, using default: {}", key, default);
+        default.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn overrides(vars: &[(&str, &str)]) -> Result<ClusterOverrides> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        ClusterOverrides::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    fn yellowpage() -> YellowpageConfig {
+        YellowpageConfig::new("from-yellowpage", "10.0.0.1:7100".parse().unwrap())
+            .with_seeds(vec!["10.0.0.2:7100".to_string()])
+    }
+
+    #[test]
+    fn test_unset_cluster_variables_keep_yellowpage_config() {
+        let cluster = overrides(&[("ZUK_SEEDS", " , "), ("ZUK_NODE_ID", "")]).unwrap();
+
+        assert_eq!(cluster, ClusterOverrides::default());
+        assert_eq!(cluster.apply(yellowpage()), yellowpage());
+    }
+
+    #[test]
+    fn test_cluster_variables_override_yellowpage_config() {
+        let cluster = overrides(&[
+            ("ZUK_NODE_ID", "receiver-1"),
+            ("ZUK_GOSSIP_PORT", "7001"),
+            ("ZUK_SEEDS", "10.0.0.3:7000, 10.0.0.4:7000,"),
+        ])
+        .unwrap();
+
+        let config = cluster.apply(yellowpage());
+        assert_eq!(config.node_id, "receiver-1");
+        assert_eq!(config.listen_addr, "127.0.0.1:7001".parse().unwrap());
+        assert_eq!(config.seeds, vec!["10.0.0.3:7000", "10.0.0.4:7000"]);
+    }
+
+    #[test]
+    fn test_invalid_gossip_address() {
+        assert!(overrides(&[("ZUK_GOSSIP_PORT", "gossip")]).is_err());
+        assert!(overrides(&[("ZUK_GOSSIP_HOST", "not an ip")]).is_err());
+    }
+
+    #[test]
+    fn test_events_url() {
+        let mut notifications = NotificationConfig {
+            token: None,
+            reconcile_interval: Duration::from_secs(60),
+            events_url: None,
+        };
+        let gossip_addr = "10.0.0.1:7000".parse().unwrap();
+
+        assert_eq!(
+            notifications.events_url(gossip_addr, 3001),
+            "http://10.0.0.1:3001/events"
+        );
+
+        notifications.events_url = Some("https://sink-1.internal/events".to_string());
+        assert_eq!(
+            notifications.events_url(gossip_addr, 3001),
+            "https://sink-1.internal/events"
+        );
+    }
+
+    #[test]
+    fn test_parse_file_output() {
+        assert_eq!(
+            OutputTarget::parse(" file:/var/zuk/out ").unwrap(),
+            OutputTarget::File(PathBuf::from("/var/zuk/out"))
+        );
+        assert!(OutputTarget::parse("file:").is_err());
+    }
+
+    #[test]
+    fn test_parse_s3_output() {
+        assert_eq!(
+            OutputTarget::parse("s3://exports/billing/daily/").unwrap(),
+            OutputTarget::S3 {
+                bucket: "exports".to_string(),
+                prefix: "billing/daily/".to_string(),
+            }
+        );
+        assert_eq!(
+            OutputTarget::parse("s3://exports").unwrap(),
+            OutputTarget::S3 {
+                bucket: "exports".to_string(),
+                prefix: String::new(),
+            }
+        );
+        assert!(OutputTarget::parse("s3:///billing").is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_output() {
+        assert!(OutputTarget::parse("/var/zuk/out").is_err());
+        assert!(OutputTarget::parse("http://exports").is_err());
+    }
+
+    fn policy(vars: &[(&str, &str)]) -> Result<RetentionPolicy> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        retention_policy(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_retention_policy() {
+        let policy = policy(&[
+            ("ZUKLINK_RETENTION_MAX_AGE_SECS", "3600"),
+            ("ZUKLINK_RETENTION_CHECKPOINTS", "true"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            policy,
+            RetentionPolicy {
+                max_age: Some(Duration::from_secs(3600)),
+                delete_checkpointed: true,
+                ..RetentionPolicy::default()
+            }
+        );
+        assert!(!self::policy(&[]).unwrap().is_enabled());
+    }
+
+    #[test]
+    fn test_topic_retention_policies() {
+        let policy = policy(&[
+            ("ZUKLINK_RETENTION_MAX_AGE_SECS", "3600"),
+            ("ZUKLINK_RETENTION_MAX_BYTES", "1000"),
+            ("ZUKLINK_RETENTION_TOPICS", "billing, audit.events,"),
+            ("ZUKLINK_RETENTION_TOPIC_BILLING_MAX_BYTES", "50"),
+            ("ZUKLINK_RETENTION_TOPIC_AUDIT_EVENTS_MAX_AGE_SECS", "none"),
+            ("ZUKLINK_RETENTION_TOPIC_AUDIT_EVENTS_CHECKPOINTS", "true"),
+        ])
+        .unwrap();
+
+        let billing = policy.for_topic(Some("billing"));
+        assert_eq!(billing.max_age, Some(Duration::from_secs(3600)));
+        assert_eq!(billing.max_bytes, Some(50));
+        assert!(!billing.delete_checkpointed);
+
+        let audit = policy.for_topic(Some("audit.events"));
+        assert_eq!(audit.max_age, None);
+        assert_eq!(audit.max_bytes, Some(1000));
+        assert!(audit.delete_checkpointed);
+
+        assert_eq!(policy.for_topic(Some("metrics")).max_bytes, Some(1000));
+    }
+
+    #[test]
+    fn test_invalid_retention_policy() {
+        assert!(policy(&[("ZUKLINK_RETENTION_MAX_AGE_SECS", "week")]).is_err());
+        assert!(policy(&[("ZUKLINK_RETENTION_TOPICS", "billing/eu")]).is_err());
+        assert!(policy(&[
+            ("ZUKLINK_RETENTION_TOPICS", "billing"),
+            ("ZUKLINK_RETENTION_TOPIC_BILLING_CHECKPOINTS", "yes"),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_env_parse() {
+        // Variables are process-wide: every test uses its own
+        std::env::set_var("ZUK_SINK_TEST_ENV_PARSE", " 42 ");
+        assert_eq!(
+            env_parse::<u64>("ZUK_SINK_TEST_ENV_PARSE").unwrap(),
+            Some(42)
+        );
+
+        std::env::set_var("ZUK_SINK_TEST_ENV_PARSE_BOOL", "yes");
+        let err = env_parse::<bool>("ZUK_SINK_TEST_ENV_PARSE_BOOL").unwrap_err();
+        assert!(err.to_string().contains("ZUK_SINK_TEST_ENV_PARSE_BOOL"));
+
+        assert_eq!(
+            env_parse::<u64>("ZUK_SINK_TEST_ENV_PARSE_UNSET").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_env_or() {
+        std::env::set_var("ZUK_SINK_TEST_ENV_OR", "billing");
+        assert_eq!(env_or("ZUK_SINK_TEST_ENV_OR", "zuk-sink"), "billing");
+        assert_eq!(env_or("ZUK_SINK_TEST_ENV_OR_UNSET", "zuk-sink"), "zuk-sink");
+    }
+}
@@ -0,0 +1,85 @@
+//! Segment garbage collection
+//!
+//! Retention is a cluster-wide duty: every receiver runs this loop, but only
+//! the Yellowpage leader collects, so segments are deleted by a single node.
+//! A leader that lost quorum skips its runs, since the other side of a
+//! partition may have elected its own leader.
+//!
+//! The same collection can be run once, without joining the cluster, with
+//! `zuk-sink gc [--dry-run]`.
+
+use anyhow::Result;
+use aws_sdk_s3::Client;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use zuklink_s3::infrastructure::{GcReport, S3GarbageCollector};
+use zuklink_yellowpage::Yellowpage;
+
+use crate::config::GcConfig;
+
+/// Build the collector described by the configuration
+pub fn collector(client: Client, config: &GcConfig) -> S3GarbageCollector {
+    let collector = S3GarbageCollector::new(client, config.bucket.clone(), config.policy.clone())
+        .with_write_options(config.s3_options.clone())
+        .with_dry_run(config.dry_run);
+
+    match &config.audit_log {
+        Some(path) => collector.with_audit_log(path),
+        None => collector,
+    }
+}
+
+/// Collect periodically while this node leads, until `shutdown` flips
+pub async fn run_leader_duty(
+    collector: S3GarbageCollector,
+    yellowpage: Arc<Yellowpage>,
+    config: GcConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    if !collector.policy().is_enabled() {
+        info!("No retention rule set, garbage collection disabled");
+        return;
+    }
+
+    info!(
+        interval_secs = config.interval.as_secs(),
+        dry_run = config.dry_run,
+        "Garbage collection enabled on the leader"
+    );
+    let mut interval = tokio::time::interval(config.interval);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        if !yellowpage.is_leader() {
+            debug!("Not the leader, skipping garbage collection");
+            continue;
+        }
+        if !yellowpage.has_quorum().await {
+            warn!("Leader without quorum, skipping garbage collection");
+            continue;
+        }
+
+        if let Err(err) = collector.run_once().await {
+            warn!(error = %err, "Garbage collection failed");
+        }
+    }
+}
+
+/// Run the collection once, for the `gc` command
+pub async fn run_once(client: Client, config: &GcConfig) -> Result<GcReport> {
+    let collector = collector(client, config);
+    if !collector.policy().is_enabled() {
+        anyhow::bail!(
+            "No retention rule set: configure ZUKLINK_RETENTION_MAX_AGE_SECS, \
+             ZUKLINK_RETENTION_MAX_BYTES, ZUKLINK_RETENTION_CHECKPOINTS or the rules \
+             of ZUKLINK_RETENTION_TOPICS"
+        );
+    }
+
+    Ok(collector.run_once().await?)
+}
@@ -0,0 +1,175 @@
+//! Health and metrics endpoints
+//!
+//! - `GET /health` answers `200` while the node can claim segments, and `503`
+//!   when it lost its quorum (minority side of a partition) or is leaving, so
+//!   orchestrators and load balancers see the split.
+//! - `GET /metrics` exposes the cluster membership counters in the Prometheus
+//!   text format.
+//! - `POST /events`, with `SINK_NOTIFICATIONS=true`, receives the S3/MinIO
+//!   object-created event notifications and hands them to the receiver.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use zuklink_s3::infrastructure::{parse_created_objects, CreatedObject};
+use zuklink_yellowpage::{MetricsSnapshot, NodeStatus, Yellowpage};
+
+/// Receiving side of the event notifications
+#[derive(Clone)]
+pub struct EventEndpoint {
+    /// Hands the created objects to the receiver
+    pub sender: mpsc::Sender<Vec<CreatedObject>>,
+    /// Token expected as `Authorization: Bearer <token>`
+    pub token: Option<String>,
+}
+
+/// Health report returned by `GET /health`
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    node_id: String,
+    live_nodes: usize,
+    quorum: Option<usize>,
+    has_quorum: bool,
+}
+
+/// Create the router of the health and metrics endpoints, and of the event
+/// notifications when enabled
+pub fn create_router(yellowpage: Arc<Yellowpage>, events: Option<EventEndpoint>) -> Router {
+    let router = Router::new()
+        .route("/health", get(health_handler))
+        .route("/metrics", get(metrics_handler))
+        .with_state(yellowpage);
+
+    match events {
+        Some(events) => router.merge(
+            Router::new()
+                .route("/events", post(events_handler))
+                .with_state(events),
+        ),
+        None => router,
+    }
+}
+
+async fn health_handler(State(yellowpage): State<Arc<Yellowpage>>) -> impl IntoResponse {
+    let view = yellowpage.cluster_view().await;
+    let leaving = yellowpage.node_status(yellowpage.node_id()).await != Some(NodeStatus::Ready);
+
+    let (status_code, status) = if leaving {
+        (StatusCode::SERVICE_UNAVAILABLE, "leaving")
+    } else if !view.has_quorum() {
+        (StatusCode::SERVICE_UNAVAILABLE, "no_quorum")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+
+    let body = HealthResponse {
+        status,
+        node_id: yellowpage.node_id().to_string(),
+        live_nodes: view.len(),
+        quorum: view.quorum(),
+        has_quorum: view.has_quorum(),
+    };
+
+    (status_code, Json(body))
+}
+
+async fn events_handler(
+    State(events): State<EventEndpoint>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    if let Some(token) = &events.token {
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // In constant time, so that response times do not reveal the token
+        if !bool::from(presented.as_bytes().ct_eq(token.as_bytes())) {
+            return StatusCode::UNAUTHORIZED;
+        }
+    }
+
+    let objects = match parse_created_objects(&body) {
+        Ok(objects) => objects,
+        Err(err) => {
+            warn!(error = %err, "Rejected event notification");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    debug!(objects = objects.len(), "Received event notification");
+
+    if objects.is_empty() {
+        return StatusCode::OK;
+    }
+    // Waits while the receiver is busy, slowing the sender down
+    match events.sender.send(objects).await {
+        Ok(()) => StatusCode::OK,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+async fn metrics_handler(State(yellowpage): State<Arc<Yellowpage>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_metrics(&yellowpage.metrics(), yellowpage.is_leader()),
+    )
+}
+
+/// Render the metrics in the Prometheus text format
+fn render_metrics(metrics: &MetricsSnapshot, is_leader: bool) -> String {
+    let gauges = [
+        (
+            "zuk_cluster_live_nodes",
+            "Active nodes in this node's cluster view",
+            metrics.live_nodes,
+        ),
+        (
+            "zuk_cluster_has_quorum",
+            "Whether this node's view holds a majority of the expected cluster",
+            u64::from(metrics.has_quorum),
+        ),
+        (
+            "zuk_cluster_is_leader",
+            "Whether this node is the cluster leader",
+            u64::from(is_leader),
+        ),
+    ];
+    let counters = [
+        (
+            "zuk_cluster_membership_changes_total",
+            "Changes of the set of active nodes",
+            metrics.membership_changes_total,
+        ),
+        (
+            "zuk_cluster_quorum_lost_total",
+            "Quorum losses, a sign of network partitions",
+            metrics.quorum_lost_total,
+        ),
+        (
+            "zuk_cluster_leader_changes_total",
+            "Cluster leadership changes",
+            metrics.leader_changes_total,
+        ),
+    ];
+
+    let mut out = String::new();
+    for (kind, family) in [("gauge", &gauges), ("counter", &counters)] {
+        for (name, help, value) in family {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+    }
+    out
+}
@@ -0,0 +1,395 @@
+//! ZukSink - Smart Receiver Service
+//!
+//! Joins the Yellowpage cluster, polls S3 and processes the segments assigned
+//! to this node by consistent hashing.
+//!
+//! On SIGTERM the node leaves gracefully: it marks itself as `leaving`, stops
+//! claiming segments, finishes in-flight ones, flushes the processor and then
+//! announces its departure so peers rebalance immediately.
+//!
+//! Health and cluster metrics are served over HTTP (`/health`, `/metrics`).
+//!
+//! The cluster leader also applies the retention policy (`ZUKLINK_RETENTION_*`).
+//! `zuk-sink gc [--dry-run]` runs a single collection instead, without joining
+//! the cluster.
+//!
+//! With `ZUKLINK_COMPACTION_ENABLED=true`, the leader also merges small
+//! segments into containers, and with `ZUKLINK_KEY_COMPACTION_ENABLED=true`
+//! removes the older versions of keyed segments. `zuk-sink compact [--keys]`
+//! runs a single compaction.
+//!
+//! With `SINK_ORDERED=true`, the segments of a shard are processed in
+//! creation order, with per-shard progress saved in the bucket.
+//!
+//! With `SINK_OUTPUT`, processed segments are written to a directory or an S3
+//! prefix, and with `SINK_EXACTLY_ONCE=true` every output batch is committed
+//! exactly once.
+//!
+//! With `SINK_NOTIFICATIONS=true`, new segments are learned from the S3/MinIO
+//! event notifications posted to `POST /events`, and bucket scans only
+//! reconcile every `SINK_RECONCILE_INTERVAL_MS`. The endpoint is advertised
+//! in the gossip metadata, so senders joined as observers push new segments
+//! straight to their owner.
+//!
+//! With `SINK_MANIFESTS=true`, segments are discovered from the manifests
+//! recorded by senders with `BOLT_MANIFESTS=true` instead of listing the
+//! bucket, and the leader seals the manifests of closed windows.
+//!
+//! Every consumer group (`SINK_GROUP`) saves the progress of its shards.
+//! `zuk-sink reset <position>` moves the group to the earliest segment, the
+//! latest one, a time or a segment, and running receivers follow it.
+
+mod compaction;
+mod config;
+mod gc;
+mod http;
+mod manifests;
+mod ordering;
+mod output;
+mod positions;
+mod processor;
+mod receiver;
+mod replay;
+
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+use zuklink_crypto::LocalKeyProvider;
+use zuklink_domain::{manifest::window::WindowSize, replay::reset::StartFrom};
+use zuklink_s3::infrastructure::{
+    CreatedObject, S3ManifestStore, S3OutputCommitter, S3StorageRepository, EVENTS_URL_METADATA_KEY,
+};
+use zuklink_yellowpage::{Yellowpage, YellowpageConfig};
+
+use crate::{
+    config::{CompactionConfig, GcConfig, OutputTarget, ResetConfig, SinkConfig},
+    http::EventEndpoint,
+    output::{FileCommitter, Output, OutputProcessor},
+    processor::{LogProcessor, SegmentProcessor},
+    receiver::Receiver,
+};
+
+/// Event notifications waiting for the receiver before the endpoint blocks
+const EVENT_QUEUE_SIZE: usize = 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    info!("Starting ZukSink receiver service");
+
+    // Load environment variables
+    dotenvy::dotenv().ok();
+
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        None => {}
+        Some("gc") => return run_gc(args.any(|arg| arg == "--dry-run")).await,
+        Some("compact") => return run_compaction(args.any(|arg| arg == "--keys")).await,
+        Some("reset") => return run_reset(args.next()).await,
+        Some(other) => anyhow::bail!(
+            "Unknown command '{}', expected: gc [--dry-run], compact [--keys] or reset <position>",
+            other
+        ),
+    }
+
+    let config = SinkConfig::from_env()?;
+    let s3_client = s3_client().await;
+
+    // Join the cluster, ZUK_* variables override YELLOWPAGE_* ones when set
+    let yellowpage_config = config.cluster.apply(YellowpageConfig::load()?);
+    if yellowpage_config.node_id.is_empty() {
+        anyhow::bail!("ZUK_NODE_ID or YELLOWPAGE_NODE_ID must be set");
+    }
+    let gossip_addr = yellowpage_config.listen_addr;
+    let yellowpage = Arc::new(Yellowpage::with_config(yellowpage_config).await?);
+    yellowpage.set_metadata("role", "receiver").await?;
+
+    // Event notifications, posted to the HTTP server and consumed by the receiver
+    let (events, event_rx) = match &config.notifications {
+        Some(notifications) => {
+            // Senders joined as observers push new segments to the advertised endpoint
+            let events_url = notifications.events_url(gossip_addr, config.http_addr.port());
+            yellowpage
+                .set_metadata(EVENTS_URL_METADATA_KEY, &events_url)
+                .await?;
+            info!(events_url = %events_url, "Event endpoint advertised");
+
+            let (sender, receiver) = mpsc::channel(EVENT_QUEUE_SIZE);
+            let endpoint = EventEndpoint {
+                sender,
+                token: notifications.token.clone(),
+            };
+            (Some(endpoint), Some(receiver))
+        }
+        None => (None, None),
+    };
+
+    // Start the receiver, writing processed segments to the configured output
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut receiver_task = match &config.output {
+        None => start_receiver(
+            LogProcessor,
+            &s3_client,
+            &yellowpage,
+            &config,
+            event_rx,
+            shutdown_rx.clone(),
+        )?,
+        Some(target) => {
+            let output = match target {
+                OutputTarget::File(dir) => {
+                    Output::File(FileCommitter::new(dir, config.exactly_once))
+                }
+                OutputTarget::S3 { bucket, prefix } => {
+                    let repository = S3StorageRepository::new(s3_client.clone(), bucket.clone())
+                        .with_write_options(config.s3_options.clone());
+                    Output::S3(S3OutputCommitter::new(
+                        repository,
+                        prefix.clone(),
+                        config.exactly_once,
+                    ))
+                }
+            };
+            info!(
+                target = ?target,
+                exactly_once = config.exactly_once,
+                group = %config.group,
+                "Writing processed segments to output"
+            );
+            start_receiver(
+                OutputProcessor::new(output, config.group.clone()),
+                &s3_client,
+                &yellowpage,
+                &config,
+                event_rx,
+                shutdown_rx.clone(),
+            )?
+        }
+    };
+
+    // Apply the retention policy while this node leads the cluster
+    let gc_task = tokio::spawn(gc::run_leader_duty(
+        gc::collector(s3_client.clone(), &config.gc),
+        yellowpage.clone(),
+        config.gc.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    // Seal the manifests of closed windows while this node leads the cluster
+    let manifest_store = S3ManifestStore::new(
+        S3StorageRepository::new(s3_client.clone(), config.bucket.clone())
+            .with_write_options(config.s3_options.clone()),
+        WindowSize::default(),
+    );
+    let manifest_task = tokio::spawn(manifests::run_leader_duty(
+        manifest_store,
+        yellowpage.clone(),
+        config.manifests.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    // Merge small segments while this node leads the cluster
+    let compaction_task = tokio::spawn(compaction::run_leader_duty(
+        compaction::compactors(s3_client, &config.compaction),
+        yellowpage.clone(),
+        config.compaction.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    // Serve health and metrics until the receiver is drained
+    let listener = tokio::net::TcpListener::bind(config.http_addr).await?;
+    info!(addr = %config.http_addr, "Starting HTTP server");
+    let mut http_shutdown = shutdown_rx;
+    let router = http::create_router(yellowpage.clone(), events);
+    let http_task = tokio::spawn(async move {
+        axum::serve(listener, router)
+            .with_graceful_shutdown(async move {
+                let _ = http_shutdown.wait_for(|stop| *stop).await;
+            })
+            .await
+    });
+
+    let stopped = tokio::select! {
+        _ = shutdown_signal() => {
+            info!("Shutdown signal received, leaving the cluster");
+            None
+        }
+        result = &mut receiver_task => {
+            warn!("Receiver stopped unexpectedly, leaving the cluster");
+            Some(result)
+        }
+    };
+    let unexpected = stopped.is_some();
+
+    // Graceful leave: stop claiming, drain, then announce departure
+    yellowpage.begin_leave().await;
+    let _ = shutdown_tx.send(true);
+    let received = match stopped {
+        Some(result) => result,
+        None => receiver_task.await,
+    };
+    http_task.await??;
+    gc_task.await?;
+    compaction_task.await?;
+    manifest_task.await?;
+
+    yellowpage.leave().await;
+
+    // Report why the receiver stopped once peers know this node is gone
+    received??;
+    if unexpected {
+        anyhow::bail!("Receiver stopped unexpectedly");
+    }
+
+    info!("Shutdown complete");
+    Ok(())
+}
+
+/// Spawn the receiver with a processor
+fn start_receiver<P>(
+    processor: P,
+    s3_client: &aws_sdk_s3::Client,
+    yellowpage: &Arc<Yellowpage>,
+    config: &SinkConfig,
+    events: Option<mpsc::Receiver<Vec<CreatedObject>>>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<JoinHandle<Result<()>>>
+where
+    P: SegmentProcessor + 'static,
+{
+    let mut receiver = Receiver::new(
+        s3_client.clone(),
+        yellowpage.clone(),
+        Arc::new(processor),
+        config,
+    );
+    if let Some(path) = &config.encryption_keyfile {
+        receiver =
+            receiver.with_key_provider(LocalKeyProvider::from_file(path)?, config.allow_plaintext);
+    }
+    if let Some(events) = events {
+        receiver = receiver.with_notifications(events);
+    }
+    Ok(tokio::spawn(receiver.run(shutdown)))
+}
+
+/// Create the S3 client
+async fn s3_client() -> aws_sdk_s3::Client {
+    // Initialize AWS S3 client with MinIO-compatible configuration
+    let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+
+    // Configure S3 client with path-style addressing for MinIO compatibility
+    let s3_config = aws_sdk_s3::config::Builder::from(&aws_config)
+        .force_path_style(true) // Required for MinIO
+        .build();
+
+    aws_sdk_s3::Client::from_conf(s3_config)
+}
+
+/// Run one garbage collection and exit
+async fn run_gc(dry_run: bool) -> Result<()> {
+    let mut config = GcConfig::from_env()?;
+    config.dry_run |= dry_run;
+
+    let report = gc::run_once(s3_client().await, &config).await?;
+
+    info!(
+        dry_run = config.dry_run,
+        scanned = report.scanned,
+        expired = report.expired,
+        deleted = report.deleted,
+        failed = report.failed,
+        freed_bytes = report.freed_bytes,
+        "Garbage collection complete"
+    );
+    if report.failed > 0 {
+        anyhow::bail!("{} segments could not be deleted", report.failed);
+    }
+    Ok(())
+}
+
+/// Run one compaction and exit
+async fn run_compaction(keys: bool) -> Result<()> {
+    let config = CompactionConfig::from_env()?;
+
+    if keys {
+        let report = compaction::run_keys_once(s3_client().await, &config).await?;
+
+        info!(
+            scanned = report.scanned,
+            keyed = report.keyed,
+            superseded = report.superseded,
+            tombstones_expired = report.tombstones_expired,
+            failed = report.failed,
+            "Key compaction complete"
+        );
+        if report.failed > 0 {
+            anyhow::bail!("{} keyed segments could not be removed", report.failed);
+        }
+        return Ok(());
+    }
+
+    let report = compaction::run_once(s3_client().await, &config).await?;
+
+    info!(
+        scanned = report.scanned,
+        containers = report.containers,
+        compacted = report.compacted,
+        bytes = report.bytes,
+        "Compaction complete"
+    );
+    Ok(())
+}
+
+/// Reset the positions of the consumer group and exit
+async fn run_reset(position: Option<String>) -> Result<()> {
+    let Some(position) = position else {
+        anyhow::bail!("Usage: zuk-sink reset earliest|latest|<RFC 3339 time>|<segment-id>");
+    };
+    let start: StartFrom = position.parse()?;
+    let config = ResetConfig::from_env()?;
+
+    let reset = replay::reset(s3_client().await, &config, start).await?;
+
+    info!(
+        group = %reset.group,
+        generation = reset.generation,
+        start = %reset.start,
+        progress = ?reset.progress,
+        "Consumer group reset, receivers resume from it at their next poll"
+    );
+    Ok(())
+}
+
+/// Wait for Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,171 @@
+//! Discovery of segments from manifests
+//!
+//! With `SINK_MANIFESTS=true`, receivers find segments in the manifests
+//! recorded by `zuk-bolt` (`BOLT_MANIFESTS=true`) instead of listing the whole
+//! bucket: only `_manifests/` is listed, and a sealed manifest is only read
+//! again when its ETag changes.
+//!
+//! Sealing is a cluster-wide duty run by the Yellowpage leader, like garbage
+//! collection and compaction: every `SINK_MANIFEST_SEAL_INTERVAL_SECS`, the
+//! windows closed for more than `ZUKLINK_MANIFEST_GRACE_SECS` are merged into
+//! one manifest each.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use zuklink_domain::{
+    ingestion::{ids::SegmentId, partition::PartitionId},
+    manifest::window::Window,
+};
+use zuklink_s3::infrastructure::{ListedManifest, S3ManifestStore};
+use zuklink_yellowpage::Yellowpage;
+
+use crate::config::ManifestConfig;
+
+/// A segment found by a scan of the bucket or of the manifests
+#[derive(Debug, Clone)]
+pub struct Scanned {
+    pub segment_id: SegmentId,
+    pub partition: Option<PartitionId>,
+    /// Size of the segment in bytes, 0 if unknown
+    pub size: i64,
+    /// Time the segment was stored, if known
+    pub uploaded_at: Option<DateTime<Utc>>,
+}
+
+/// Sealed manifest read by a receiver
+struct Cached {
+    etag: Option<String>,
+    segments: Vec<Scanned>,
+}
+
+/// Reads the segments of the manifests, caching the sealed ones
+pub struct ManifestReader {
+    store: S3ManifestStore,
+    sealed: BTreeMap<Window, Cached>,
+}
+
+impl ManifestReader {
+    /// Create a reader of the manifests of a store
+    pub fn new(store: S3ManifestStore) -> Self {
+        Self {
+            store,
+            sealed: BTreeMap::new(),
+        }
+    }
+
+    /// List the segments of all manifests, sealed or not
+    ///
+    /// Segments are returned once each: those of sealed windows first, in
+    /// window order, then those of open windows.
+    pub async fn scan(&mut self) -> Result<Vec<Scanned>> {
+        let objects = self.store.list().await?;
+
+        let mut listed = HashMap::new();
+        let mut open = Vec::new();
+        for object in objects {
+            match object.listed {
+                ListedManifest::Sealed(window) => {
+                    listed.insert(window, object.etag);
+                }
+                ListedManifest::Entry {
+                    segment_id,
+                    partition,
+                    ..
+                } => open.push(Scanned {
+                    segment_id,
+                    partition,
+                    size: 0,
+                    uploaded_at: Some(object.last_modified),
+                }),
+            }
+        }
+
+        // Windows gone from the bucket are forgotten
+        self.sealed.retain(|window, _| listed.contains_key(window));
+        for (window, etag) in listed {
+            if self
+                .sealed
+                .get(&window)
+                .is_some_and(|cached| cached.etag.is_some() && cached.etag == etag)
+            {
+                continue;
+            }
+            let Some(manifest) = self.store.read(&window).await? else {
+                continue;
+            };
+            debug!(window = %window, entries = manifest.entries.len(), "Read sealed manifest");
+
+            let segments = manifest
+                .entries
+                .into_iter()
+                .map(|entry| Scanned {
+                    segment_id: entry.segment_id,
+                    partition: entry.partition,
+                    size: i64::try_from(entry.size).unwrap_or(i64::MAX),
+                    uploaded_at: Some(entry.created_at),
+                })
+                .collect();
+            self.sealed.insert(window, Cached { etag, segments });
+        }
+
+        // Entries recorded again, or late in a sealed window, are kept once
+        let mut seen = HashSet::new();
+        Ok(self
+            .sealed
+            .values()
+            .flat_map(|cached| cached.segments.iter().cloned())
+            .chain(open)
+            .filter(|segment| seen.insert((segment.segment_id, segment.partition)))
+            .collect())
+    }
+}
+
+/// Seal closed windows periodically while this node leads, until `shutdown` flips
+pub async fn run_leader_duty(
+    store: S3ManifestStore,
+    yellowpage: Arc<Yellowpage>,
+    config: Option<ManifestConfig>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let Some(config) = config else {
+        info!("Manifest sealing disabled");
+        return;
+    };
+
+    info!(
+        interval_secs = config.seal_interval.as_secs(),
+        grace_secs = config.grace.as_secs(),
+        "Manifest sealing enabled on the leader"
+    );
+    let mut interval = tokio::time::interval(config.seal_interval);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+
+        if !yellowpage.is_leader() {
+            debug!("Not the leader, skipping manifest sealing");
+            continue;
+        }
+        if !yellowpage.has_quorum().await {
+            warn!("Leader without quorum, skipping manifest sealing");
+            continue;
+        }
+
+        match store.seal(Utc::now(), config.grace).await {
+            Ok(report) if report.windows > 0 => info!(
+                windows = report.windows,
+                entries = report.entries,
+                "Sealed manifests"
+            ),
+            Ok(_) => {}
+            Err(err) => warn!(error = %err, "Manifest sealing failed"),
+        }
+    }
+}
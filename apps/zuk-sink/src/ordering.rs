@@ -0,0 +1,247 @@
+//! Ordered processing of shards
+//!
+//! With `SINK_ORDERED=true`, the segments of a shard are processed one at a
+//! time, in the order of their UUID v7 IDs. A segment waits for the lateness
+//! window (`SINK_LATENESS_MS`) past its creation, so that segments created
+//! earlier but uploaded later still take their place.
+//!
+//! The last segment processed in every shard is saved under
+//! `_progress/<group>/` at the start of every poll and when draining. A node
+//! taking over a shard resumes from there, or from the last reset of the
+//! group if it is more recent: segments behind the progress are skipped.
+//! Segments processed after the last save may be processed again after a
+//! crash.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tracing::{debug, warn};
+use zuklink_domain::{
+    ordering::policy::{Candidate, OrderingPolicy, Position, ShardProgress},
+    replay::reset::{self, GroupReset},
+};
+use zuklink_s3::infrastructure::S3ProgressStore;
+use zuklink_yellowpage::ShardId;
+
+/// An owned segment of a shard, not claimed yet
+#[derive(Debug, Clone)]
+pub struct Pending {
+    /// Segment key
+    pub key: String,
+    /// Position of the segment in its shard
+    pub position: Position,
+    /// Time the segment was uploaded
+    pub uploaded_at: DateTime<Utc>,
+    /// Size of the segment in bytes
+    pub size: i64,
+}
+
+/// Segments to process, skip and keep waiting after a dispatch
+#[derive(Debug, Default)]
+pub struct Dispatch {
+    /// Segments to process now, one per shard
+    pub ready: Vec<String>,
+    /// Segments behind the progress of their shard, never to be processed
+    pub skipped: Vec<String>,
+    /// Shard and size of the segments waiting for their turn
+    pub waiting: Vec<(ShardId, i64)>,
+}
+
+/// Ordering state of a shard owned by this node
+#[derive(Debug, Default)]
+struct ShardState {
+    /// Last segment processed
+    progress: Option<Position>,
+    /// Whether the progress changed since it was saved
+    dirty: bool,
+    /// Segment in flight
+    busy: Option<(String, Position)>,
+    /// Segments listed by the last poll, not claimed yet
+    pending: Vec<Pending>,
+}
+
+/// Orders the processing of the shards owned by this node
+pub struct OrderedShards {
+    store: S3ProgressStore,
+    policy: OrderingPolicy,
+    /// Last reset of the consumer group, if any
+    reset: Option<GroupReset>,
+    shards: HashMap<ShardId, ShardState>,
+}
+
+impl OrderedShards {
+    /// Create the ordering state, loading progress on demand
+    pub fn new(store: S3ProgressStore, policy: OrderingPolicy) -> Self {
+        Self {
+            store,
+            policy,
+            reset: None,
+            shards: HashMap::new(),
+        }
+    }
+
+    /// Forget every shard and resume them from a reset of the group
+    ///
+    /// Call once no segment is in flight: progress not saved yet is dropped.
+    pub fn reload(&mut self, reset: Option<GroupReset>) {
+        self.reset = reset;
+        self.shards.clear();
+    }
+
+    /// Replace the pending segments with the ones of the latest listing
+    ///
+    /// Saves changed progress first. Idle shards without pending segments are
+    /// forgotten once saved, their progress is read again if they come back: another
+    /// node may have owned them in the meantime.
+    pub async fn refresh(&mut self, listed: HashMap<ShardId, Vec<Pending>>) {
+        self.persist().await;
+
+        self.shards.retain(|shard, state| {
+            state.busy.is_some() || state.dirty || listed.contains_key(shard)
+        });
+
+        for (shard, pending) in listed {
+            if let Some(state) = self.state(shard).await {
+                state.pending = pending;
+            }
+        }
+    }
+
+    /// Add segments reported created since the last listing
+    pub async fn add(&mut self, reported: HashMap<ShardId, Vec<Pending>>) {
+        for (shard, reported) in reported {
+            let Some(state) = self.state(shard).await else {
+                continue;
+            };
+            for pending in reported {
+                let known = state.pending.iter().any(|known| known.key == pending.key)
+                    || state
+                        .busy
+                        .as_ref()
+                        .is_some_and(|(key, _)| *key == pending.key);
+                if !known {
+                    state.pending.push(pending);
+                }
+            }
+        }
+    }
+
+    /// State of a shard, reading its progress if it is new
+    async fn state(&mut self, shard: ShardId) -> Option<&mut ShardState> {
+        if !self.shards.contains_key(&shard) {
+            let progress = match self.store.load(shard).await {
+                Ok(progress) => reset::resume_from(self.reset.as_ref(), progress),
+                Err(err) => {
+                    // Without its progress, the shard could go backwards
+                    warn!(shard, error = ?err, "Failed to read shard progress, skipping shard");
+                    return None;
+                }
+            };
+            self.shards.insert(
+                shard,
+                ShardState {
+                    progress,
+                    ..ShardState::default()
+                },
+            );
+        }
+        self.shards.get_mut(&shard)
+    }
+
+    /// Pick the next segment of every idle shard, up to `capacity`
+    pub fn dispatch(&mut self, mut capacity: usize, now: DateTime<Utc>) -> Dispatch {
+        let mut dispatch = Dispatch::default();
+
+        for (shard, state) in &mut self.shards {
+            if state.busy.is_some() {
+                dispatch
+                    .waiting
+                    .extend(state.pending.iter().map(|pending| (*shard, pending.size)));
+                continue;
+            }
+
+            let candidates: Vec<Candidate> = state
+                .pending
+                .iter()
+                .map(|pending| Candidate {
+                    position: pending.position,
+                    uploaded_at: pending.uploaded_at,
+                })
+                .collect();
+            let mut plan = self.policy.plan(&candidates, state.progress.as_ref(), now);
+
+            if capacity == 0 {
+                plan.next = None;
+            }
+
+            let mut remaining = Vec::with_capacity(state.pending.len());
+            for pending in state.pending.drain(..) {
+                if plan.late.contains(&pending.position) {
+                    warn!(
+                        shard,
+                        key = %pending.key,
+                        "Segment uploaded after its shard progressed past it, skipping"
+                    );
+                    dispatch.skipped.push(pending.key);
+                } else if plan.done.contains(&pending.position) {
+                    debug!(shard, key = %pending.key, "Segment processed already, skipping");
+                    dispatch.skipped.push(pending.key);
+                } else if plan.next == Some(pending.position) {
+                    state.busy = Some((pending.key.clone(), pending.position));
+                    dispatch.ready.push(pending.key);
+                    capacity -= 1;
+                } else {
+                    dispatch.waiting.push((*shard, pending.size));
+                    remaining.push(pending);
+                }
+            }
+            state.pending = remaining;
+        }
+
+        dispatch
+    }
+
+    /// Record the outcome of a dispatched segment
+    ///
+    /// A failed segment blocks its shard until the next poll lists it again.
+    pub fn finished(&mut self, key: &str, ok: bool) {
+        for state in self.shards.values_mut() {
+            let Some((busy, position)) = &state.busy else {
+                continue;
+            };
+            if busy != key {
+                continue;
+            }
+
+            if ok {
+                state.progress = Some(*position);
+                state.dirty = true;
+            } else {
+                state.pending.clear();
+            }
+            state.busy = None;
+            return;
+        }
+    }
+
+    /// Save the progress of the shards that moved since the last save
+    pub async fn persist(&mut self) {
+        let now = Utc::now();
+
+        for (shard, state) in &mut self.shards {
+            let Some(position) = state.progress.filter(|_| state.dirty) else {
+                continue;
+            };
+            let progress = ShardProgress {
+                shard: *shard,
+                position,
+                generation: reset::generation(self.reset.as_ref()),
+                updated_at: now,
+            };
+
+            match self.store.save(&progress).await {
+                Ok(()) => state.dirty = false,
+                Err(err) => warn!(shard, error = ?err, "Failed to save shard progress"),
+            }
+        }
+    }
+}
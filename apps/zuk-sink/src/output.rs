@@ -0,0 +1,249 @@
+//! Output of processed segments
+//!
+//! With `SINK_OUTPUT` set, the payload of every processed segment is written
+//! to a filesystem directory or an S3 prefix, as one batch tagged with its
+//! origin (group, partition, segment ID, record offset) and named after it.
+//!
+//! With `SINK_EXACTLY_ONCE=true`, batches are committed atomically and never
+//! replaced: a batch is written to `.tmp/` then hard linked into place on a
+//! filesystem, which fails if the batch exists (create-exclusive), and written
+//! conditionally on S3. Of concurrent commits of a batch, only one succeeds.
+//! The committed batch is the record of its delivery, so a segment processed
+//! again after a rebalance or a retry finds its batch committed and is
+//! dropped: duplicates are invisible downstream.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info};
+use zuklink_domain::{
+    delivery::output::{CommitOutcome, OutputCommitter, OutputTag},
+    ingestion::{error::IngestionError, ids::SegmentId},
+};
+use zuklink_s3::infrastructure::{listed_segment, S3OutputCommitter};
+
+use crate::processor::SegmentProcessor;
+
+/// Directory of the batches being written, under the output root
+const TMP_DIR: &str = ".tmp";
+
+/// Commits output batches to a local or mounted directory
+#[derive(Debug, Clone)]
+pub struct FileCommitter {
+    root: PathBuf,
+    exactly_once: bool,
+}
+
+impl FileCommitter {
+    /// Create a committer writing under `root`
+    ///
+    /// Without `exactly_once`, batches are written in place and a duplicate
+    /// commit replaces the batch.
+    pub fn new(root: impl Into<PathBuf>, exactly_once: bool) -> Self {
+        Self {
+            root: root.into(),
+            exactly_once,
+        }
+    }
+
+    /// Write a batch to a temporary file, then hard link it into place
+    ///
+    /// The temporary file is on the same filesystem as the batch, so the link
+    /// is atomic, and it fails with `AlreadyExists` if the batch exists:
+    /// returns whether this call committed the batch.
+    async fn write_exclusive(&self, path: &Path, data: &[u8]) -> std::io::Result<bool> {
+        let tmp_dir = self.root.join(TMP_DIR);
+        tokio::fs::create_dir_all(&tmp_dir).await?;
+        let tmp = tmp_dir.join(format!("{}.tmp", SegmentId::new()));
+
+        let published = async {
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            file.write_all(data).await?;
+            file.sync_all().await?;
+            drop(file);
+
+            match tokio::fs::hard_link(&tmp, path).await {
+                Ok(()) => Ok(true),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+                Err(err) => Err(err),
+            }
+        }
+        .await;
+
+        let _ = tokio::fs::remove_file(&tmp).await;
+        published
+    }
+}
+
+impl OutputCommitter for FileCommitter {
+    async fn commit(&self, tag: &OutputTag, data: &[u8]) -> Result<CommitOutcome, IngestionError> {
+        let path = self.root.join(tag.name());
+        let failure = |err: std::io::Error| {
+            IngestionError::storage_failure(format!(
+                "Failed to write output batch '{}': {}",
+                path.display(),
+                err
+            ))
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(failure)?;
+        }
+        if !self.exactly_once {
+            tokio::fs::write(&path, data).await.map_err(failure)?;
+        } else if !self.write_exclusive(&path, data).await.map_err(failure)? {
+            debug!(path = %path.display(), "Output batch committed already, dropping duplicate");
+            return Ok(CommitOutcome::Duplicate);
+        }
+
+        debug!(path = %path.display(), "Committed output batch");
+        Ok(CommitOutcome::Committed)
+    }
+}
+
+/// Target of the output batches
+#[derive(Clone)]
+pub enum Output {
+    /// Directory on a local or mounted filesystem
+    File(FileCommitter),
+    /// Prefix in an S3 bucket
+    S3(S3OutputCommitter),
+}
+
+impl OutputCommitter for Output {
+    async fn commit(&self, tag: &OutputTag, data: &[u8]) -> Result<CommitOutcome, IngestionError> {
+        match self {
+            Self::File(committer) => committer.commit(tag, data).await,
+            Self::S3(committer) => committer.commit(tag, data).await,
+        }
+    }
+}
+
+/// Processor writing the payload of every segment to an output
+pub struct OutputProcessor<C> {
+    committer: C,
+    group: String,
+}
+
+impl<C> OutputProcessor<C>
+where
+    C: OutputCommitter,
+{
+    /// Create a processor committing the batches of `group`
+    pub fn new(committer: C, group: impl Into<String>) -> Self {
+        Self {
+            committer,
+            group: group.into(),
+        }
+    }
+}
+
+impl<C> SegmentProcessor for OutputProcessor<C>
+where
+    C: OutputCommitter,
+{
+    async fn process(&self, key: &str, record_key: Option<&str>, data: Bytes) -> Result<()> {
+        let (segment_id, partition) =
+            listed_segment(key).with_context(|| format!("Invalid segment key '{}'", key))?;
+
+        // A segment is written as a single batch, starting at its first record
+        let tag = OutputTag::new(self.group.clone(), partition, segment_id, 0);
+        let outcome = self
+            .committer
+            .commit(&tag, &data)
+            .await
+            .with_context(|| format!("Failed to commit the output of segment '{}'", key))?;
+
+        match outcome {
+            CommitOutcome::Committed => {
+                info!(key = %key, record_key, size = data.len(), output = %tag.name(), "Processed segment")
+            }
+            CommitOutcome::Duplicate => {
+                info!(key = %key, output = %tag.name(), "Segment output committed already, skipping")
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        // Batches are committed as they are processed
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty output directory, unique to the test
+    fn root() -> PathBuf {
+        std::env::temp_dir().join(format!("zuk-sink-output-{}", SegmentId::new()))
+    }
+
+    fn tag() -> OutputTag {
+        OutputTag::new("billing", None, SegmentId::new(), 0)
+    }
+
+    #[tokio::test]
+    async fn test_exactly_once_drops_duplicates() {
+        let root = root();
+        let committer = FileCommitter::new(&root, true);
+        let tag = tag();
+
+        let first = committer.commit(&tag, b"first").await.unwrap();
+        let second = committer.commit(&tag, b"second").await.unwrap();
+
+        assert_eq!(first, CommitOutcome::Committed);
+        assert_eq!(second, CommitOutcome::Duplicate);
+        assert_eq!(std::fs::read(root.join(tag.name())).unwrap(), b"first");
+
+        // Nothing left behind in the temporary directory
+        let leftovers = std::fs::read_dir(root.join(TMP_DIR)).unwrap().count();
+        assert_eq!(leftovers, 0);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_commits_commit_once() {
+        let root = root();
+        let committer = FileCommitter::new(&root, true);
+        let tag = tag();
+
+        let commits: Vec<_> = (0..8u8)
+            .map(|attempt| {
+                let (committer, tag) = (committer.clone(), tag.clone());
+                tokio::spawn(async move { committer.commit(&tag, &[attempt]).await.unwrap() })
+            })
+            .collect();
+        let mut committed = 0;
+        for commit in commits {
+            if commit.await.unwrap() == CommitOutcome::Committed {
+                committed += 1;
+            }
+        }
+
+        assert_eq!(committed, 1);
+        assert_eq!(std::fs::read(root.join(tag.name())).unwrap().len(), 1);
+        let leftovers = std::fs::read_dir(root.join(TMP_DIR)).unwrap().count();
+        assert_eq!(leftovers, 0);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_at_least_once_replaces_batches() {
+        let root = root();
+        let committer = FileCommitter::new(&root, false);
+        let tag = tag();
+
+        committer.commit(&tag, b"first").await.unwrap();
+        let outcome = committer.commit(&tag, b"second").await.unwrap();
+
+        assert_eq!(outcome, CommitOutcome::Committed);
+        assert_eq!(std::fs::read(root.join(tag.name())).unwrap(), b"second");
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
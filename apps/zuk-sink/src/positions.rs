@@ -0,0 +1,255 @@
+//! Progress of shards processed in any order
+//!
+//! Without `SINK_ORDERED`, the segments of a shard are processed concurrently
+//! and finish in any order. The progress of a shard is the last segment up
+//! to which every owned segment is processed, in creation order. It only
+//! moves past segments older than the lateness window (`SINK_LATENESS_MS`),
+//! so segments uploaded slightly late are not left behind it.
+//!
+//! Progress is saved under `_progress/<group>/` after every poll and when
+//! draining, like the progress of ordered shards. A node taking over a shard
+//! skips the segments behind it, instead of processing the whole shard again.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tracing::warn;
+use zuklink_domain::{
+    ordering::policy::{OrderingPolicy, Position, ShardProgress},
+    replay::reset::{self, GroupReset},
+};
+use zuklink_s3::infrastructure::S3ProgressStore;
+use zuklink_yellowpage::ShardId;
+
+/// Progress of a shard owned by this node
+#[derive(Debug, Default)]
+struct PositionState {
+    /// Last segment up to which every segment is processed
+    progress: Option<Position>,
+    /// Whether the progress changed since it was saved
+    dirty: bool,
+}
+
+/// Tracks the progress of the shards owned by this node
+pub struct ShardPositions {
+    store: S3ProgressStore,
+    policy: OrderingPolicy,
+    /// Last reset of the consumer group, if any
+    reset: Option<GroupReset>,
+    shards: HashMap<ShardId, PositionState>,
+}
+
+impl ShardPositions {
+    /// Create the position tracking, loading progress on demand
+    pub fn new(store: S3ProgressStore, policy: OrderingPolicy) -> Self {
+        Self {
+            store,
+            policy,
+            reset: None,
+            shards: HashMap::new(),
+        }
+    }
+
+    /// Forget every shard and resume them from a reset of the group
+    ///
+    /// Call once no segment is in flight: progress not saved yet is dropped.
+    pub fn reload(&mut self, reset: Option<GroupReset>) {
+        self.reset = reset;
+        self.shards.clear();
+    }
+
+    /// Whether a segment is behind the progress of its shard
+    ///
+    /// Returns `None` if the progress of the shard cannot be read: without
+    /// it, the segment may or may not be processed already.
+    pub async fn is_processed(&mut self, shard: ShardId, position: &Position) -> Option<bool> {
+        if !self.shards.contains_key(&shard) {
+            let progress = match self.store.load(shard).await {
+                Ok(progress) => reset::resume_from(self.reset.as_ref(), progress),
+                Err(err) => {
+                    warn!(shard, error = ?err, "Failed to read shard progress, skipping shard");
+                    return None;
+                }
+            };
+            self.shards.insert(
+                shard,
+                PositionState {
+                    progress,
+                    dirty: false,
+                },
+            );
+        }
+
+        let progress = self.shards.get(&shard)?.progress;
+        Some(progress.is_some_and(|progress| *position <= progress))
+    }
+
+    /// Whether a segment is behind the known progress of its shard
+    ///
+    /// Unlike [`Self::is_processed`], never reads the progress: segments of a
+    /// shard not loaded yet are not behind.
+    pub fn is_behind(&self, shard: ShardId, position: &Position) -> bool {
+        self.shards
+            .get(&shard)
+            .and_then(|state| state.progress)
+            .is_some_and(|progress| *position <= progress)
+    }
+
+    /// Move the progress of shards past their processed segments
+    ///
+    /// `listed` holds, per shard, every owned segment of the latest scan and
+    /// whether it is processed. Shards left out are no longer owned, and are
+    /// forgotten once saved.
+    pub fn advance(&mut self, listed: HashMap<ShardId, Vec<(Position, bool)>>, now: DateTime<Utc>) {
+        self.shards
+            .retain(|shard, state| state.dirty || listed.contains_key(shard));
+
+        for (shard, mut segments) in listed {
+            let Some(state) = self.shards.get_mut(&shard) else {
+                continue;
+            };
+            segments.sort();
+
+            for (position, processed) in segments {
+                if state.progress.is_some_and(|progress| position <= progress) {
+                    continue;
+                }
+                if !processed || !self.policy.is_ready(&position, now) {
+                    break;
+                }
+                state.progress = Some(position);
+                state.dirty = true;
+            }
+        }
+    }
+
+    /// Save the progress of the shards that moved since the last save
+    pub async fn persist(&mut self) {
+        let now = Utc::now();
+
+        for (shard, state) in &mut self.shards {
+            let Some(position) = state.progress.filter(|_| state.dirty) else {
+                continue;
+            };
+            let progress = ShardProgress {
+                shard: *shard,
+                position,
+                generation: reset::generation(self.reset.as_ref()),
+                updated_at: now,
+            };
+
+            match self.store.save(&progress).await {
+                Ok(()) => state.dirty = false,
+                Err(err) => warn!(shard, error = ?err, "Failed to save shard progress"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Region};
+    use std::time::Duration;
+    use zuklink_domain::ingestion::ids::SegmentId;
+    use zuklink_s3::infrastructure::S3StorageRepository;
+
+    /// Positions of a bucket never reached: progress is set by the tests
+    fn positions() -> ShardPositions {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build();
+        let repository =
+            S3StorageRepository::new(aws_sdk_s3::Client::from_conf(config), "zuklink".to_string());
+
+        ShardPositions::new(
+            S3ProgressStore::new(repository, "zuk-sink"),
+            OrderingPolicy {
+                lateness: Duration::from_secs(5),
+            },
+        )
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn segment(secs: i64) -> Position {
+        Position {
+            time: at(secs),
+            segment_id: SegmentId::new(),
+        }
+    }
+
+    fn load(positions: &mut ShardPositions, shard: ShardId, progress: Option<Position>) {
+        positions.shards.insert(
+            shard,
+            PositionState {
+                progress,
+                dirty: false,
+            },
+        );
+    }
+
+    #[test]
+    fn test_advance_stops_at_first_unprocessed_segment() {
+        let mut positions = positions();
+        load(&mut positions, 1, None);
+        let (first, second, third) = (segment(0), segment(1), segment(2));
+
+        // Listed out of order, processed except the second
+        let listed = HashMap::from([(1, vec![(third, true), (first, true), (second, false)])]);
+        positions.advance(listed, at(60));
+
+        assert!(positions.is_behind(1, &first));
+        assert!(!positions.is_behind(1, &second));
+        assert!(!positions.is_behind(1, &third));
+        assert!(positions.shards[&1].dirty);
+    }
+
+    #[test]
+    fn test_advance_waits_for_lateness_window() {
+        let mut positions = positions();
+        load(&mut positions, 1, None);
+        let (first, second) = (segment(0), segment(10));
+
+        let listed = HashMap::from([(1, vec![(first, true), (second, true)])]);
+        positions.advance(listed, at(12));
+
+        // The second segment may still be preceded by a late upload
+        assert_eq!(positions.shards[&1].progress, Some(first));
+    }
+
+    #[test]
+    fn test_advance_skips_segments_behind_progress() {
+        let mut positions = positions();
+        let (old, next) = (segment(0), segment(1));
+        load(&mut positions, 1, Some(old));
+
+        // Behind the progress, a segment no longer claimed is not in the way
+        let listed = HashMap::from([(1, vec![(old, false), (next, true)])]);
+        positions.advance(listed, at(60));
+
+        assert_eq!(positions.shards[&1].progress, Some(next));
+    }
+
+    #[test]
+    fn test_advance_forgets_shards_no_longer_owned() {
+        let mut positions = positions();
+        load(&mut positions, 1, Some(segment(0)));
+        load(&mut positions, 2, None);
+        positions.shards.get_mut(&2).unwrap().dirty = true;
+
+        positions.advance(HashMap::new(), at(60));
+
+        // Unsaved progress is kept until persisted
+        assert!(!positions.shards.contains_key(&1));
+        assert!(positions.shards.contains_key(&2));
+    }
+
+    #[test]
+    fn test_unknown_shards_are_not_behind() {
+        let positions = positions();
+        assert!(!positions.is_behind(7, &segment(0)));
+    }
+}
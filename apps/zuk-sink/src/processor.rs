@@ -0,0 +1,58 @@
+//! Segment processors
+//!
+//! A processor receives the payload of every segment assigned to this node.
+//! It is the extension point where business logic plugs into the receiver.
+
+use anyhow::Result;
+use bytes::Bytes;
+use std::future::Future;
+use tracing::info;
+
+/// Port for segment processing
+///
+/// Implementations must be idempotent: during topology changes a segment may
+/// be delivered twice (at-least-once semantics). Processors writing through
+/// an `OutputCommitter` in exactly-once mode get that for free (see
+/// [`crate::output`]).
+pub trait SegmentProcessor: Send + Sync {
+    /// Process the payload of one segment
+    ///
+    /// `record_key` is set for keyed segments, which hold the latest value of
+    /// that key. An empty payload with a record key is a tombstone: the key
+    /// was deleted.
+    ///
+    /// Returning an error leaves the segment unclaimed so it is retried on
+    /// the next poll.
+    fn process(
+        &self,
+        key: &str,
+        record_key: Option<&str>,
+        data: Bytes,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Flush any buffered output
+    ///
+    /// Called once all in-flight segments are done, before the node leaves
+    /// the cluster.
+    fn flush(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Processor that only logs the segments it receives
+#[derive(Debug, Default)]
+pub struct LogProcessor;
+
+impl SegmentProcessor for LogProcessor {
+    fn process(
+        &self,
+        key: &str,
+        record_key: Option<&str>,
+        data: Bytes,
+    ) -> impl Future<Output = Result<()>> + Send {
+        info!(key = %key, record_key, size = data.len(), "Processed segment");
+        async { Ok(()) }
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
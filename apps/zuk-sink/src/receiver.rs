@@ -0,0 +1,1042 @@
+//! Segment receiver
+//!
+//! Polls the bucket, keeps only the segments assigned to this node by the
+//! capacity-weighted sharding of the cluster view and hands them to the
+//! processor. Larger nodes (higher `YELLOWPAGE_CAPACITY`, by default their CPU
+//! count) receive a proportionally larger share.
+//!
+//! ## Partitions
+//!
+//! Segments ingested with a partition key are stored as
+//! `<id>.p<partition>.zuk`. Whole partitions are assigned to receivers, by
+//! hashing their routing key (`partition-<n>`) instead of the segment key:
+//! all the segments of a partition key are processed by the same node.
+//! Unpartitioned segments are still assigned one by one.
+//!
+//! ## Ordering
+//!
+//! With `SINK_ORDERED=true`, the segments of a shard are processed one at a
+//! time in UUID v7 order, each waiting out the lateness window
+//! (`SINK_LATENESS_MS`) past its creation. Progress is saved per shard under
+//! `_progress/`, so the order survives restarts and rebalances (see
+//! [`crate::ordering`]).
+//!
+//! ## Positions
+//!
+//! Without `SINK_ORDERED`, the progress of every owned shard is saved too
+//! (see [`crate::positions`]), so a restart or a rebalance skips what the
+//! consumer group (`SINK_GROUP`) processed already.
+//!
+//! `zuk-sink reset <position>` moves the whole group to the earliest segment,
+//! the latest one, a time or a segment (see [`crate::replay`]). Every poll
+//! checks for a new reset: the receiver then pauses, finishes its in-flight
+//! segments and flushes the processor, reloads the progress of its shards
+//! from the reset and resumes.
+//!
+//! ## Event Notifications
+//!
+//! With `SINK_NOTIFICATIONS=true`, the receiver also consumes the
+//! object-created events posted by S3 or MinIO to `POST /events`: an owned
+//! segment is claimed as soon as it is reported, without waiting for a scan.
+//! Senders joined as observers post the same events for the segments they
+//! write, to the receiver owning them by [`assignment_key`].
+//! Bucket scans still run every `SINK_RECONCILE_INTERVAL_MS`, catching the
+//! segments whose event was lost, and right away when events arrive while
+//! in-flight is full.
+//!
+//! ## Manifests
+//!
+//! With `SINK_MANIFESTS=true`, scans read the manifests recorded by
+//! `zuk-bolt` instead of listing the whole bucket (see [`crate::manifests`]).
+//! Segments gone since they were recorded, e.g. deleted by the retention
+//! policy, are skipped.
+//!
+//! ## Quorum
+//!
+//! When an expected cluster size is configured, a node whose view lost the
+//! majority stops claiming segments: it is likely on the minority side of a
+//! partition, where the hashing rule would hand out segments also claimed on
+//! the other side. In-flight segments are still finished.
+//!
+//! ## Load Reports
+//!
+//! Every poll ends with a load report gossiped through Yellowpage: segments
+//! owned but not processed yet (per shard) and the processing rate. With
+//! `YELLOWPAGE_REBALANCE=true`, the leader uses these reports to move hot
+//! shards from overloaded receivers to idle ones.
+//!
+//! ## Compaction
+//!
+//! Segments compacted into a container are listed through their index entries
+//! and read from the container. Ownership and claims use the segment key
+//! (`<id>.zuk` or `<id>.p<partition>.zuk`) either way, so compacting a segment
+//! neither moves it to another node nor makes it processed twice by the same
+//! one.
+//!
+//! ## Integrity
+//!
+//! Segments carry the SHA-256 checksum computed by `zuk-bolt` on ingest.
+//! Compressed segments are decompressed first, then the data is verified
+//! against the checksum: a corrupted segment fails like any other processing
+//! error.
+//!
+//! ## Encryption
+//!
+//! Segments encrypted by `zuk-bolt` are decrypted (before decompression) with
+//! the master keys of `ZUKLINK_ENCRYPTION_KEYFILE`. Without a keyfile, an
+//! encrypted segment fails processing. With one, an unencrypted segment fails
+//! too, unless `ZUKLINK_ENCRYPTION_ALLOW_PLAINTEXT` is set. Segments written
+//! with SSE-C are read with the customer key of `ZUKLINK_S3_SSE_C_KEY`.
+//!
+//! ## Claims
+//!
+//! Claimed segments are remembered so that every scan does not process them
+//! again. The claims are pruned after every scan: segments no longer listed
+//! or no longer owned are forgotten, and without `SINK_ORDERED` so are the
+//! segments behind the progress of their shard, which records them already.
+//! Claims made since the previous scan are kept one more scan, for segments
+//! reported by an event before they are listed.
+//!
+//! ## Draining
+//!
+//! Segments are processed in background tasks so that a shutdown request
+//! never interrupts them: once stopped, the receiver claims nothing new,
+//! waits for in-flight segments and flushes the processor.
+
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::{self, JoinError, JoinSet};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+use zuklink_crypto::{envelope, EncryptedStorageRepository, LocalKeyProvider};
+use zuklink_domain::{
+    ingestion::{ids::SegmentId, partition::PartitionId},
+    manifest::window::WindowSize,
+    ordering::policy::Position,
+    replay::reset::{self, GroupReset},
+};
+use zuklink_s3::infrastructure::{
+    assignment_key, listed_segment, segment_key, CreatedObject, S3ManifestStore, S3ProgressStore,
+    S3StorageRepository,
+};
+use zuklink_yellowpage::{shard_of, ClusterView, LoadReport, ShardId, Yellowpage};
+
+use crate::{
+    config::SinkConfig,
+    manifests::{ManifestReader, Scanned},
+    ordering::{OrderedShards, Pending},
+    positions::ShardPositions,
+    processor::SegmentProcessor,
+};
+
+/// Number of busiest shards included in load reports
+const REPORTED_SHARDS: usize = 32;
+
+/// Weight of the latest measure in the processing rate average
+const RATE_SMOOTHING: f64 = 0.2;
+
+/// Polling receiver for the segments assigned to this node
+pub struct Receiver<P> {
+    client: Client,
+    bucket: String,
+    yellowpage: Arc<Yellowpage>,
+    processor: Arc<P>,
+    /// Decrypts the segments read by `repository`, when a keyfile is set
+    encryption: Option<Arc<EncryptedStorageRepository<S3StorageRepository, LocalKeyProvider>>>,
+    /// Reads segments, standalone or compacted, with the SSE-C key of the bucket
+    repository: S3StorageRepository,
+    poll_interval: Duration,
+    max_in_flight: usize,
+    /// Segments currently being processed
+    in_flight: JoinSet<Result<()>>,
+    /// Keys already claimed by this node (in flight or done)
+    claimed: Claims,
+    /// Keys currently being processed, by task
+    ///
+    /// A task that panicked only returns its id: the key is found here.
+    running: HashMap<task::Id, String>,
+    /// Progress of the consumer group, and its resets
+    progress: S3ProgressStore,
+    /// Last reset of the consumer group applied, if any
+    reset: Option<GroupReset>,
+    /// Per-shard ordering, when segments are processed in order
+    ordered: Option<OrderedShards>,
+    /// Per-shard progress, when segments are processed in any order
+    positions: Option<ShardPositions>,
+    /// Reads the manifests scanned instead of the bucket, when enabled
+    manifests: Option<ManifestReader>,
+    /// Object-created events, when notifications are enabled
+    events: Option<mpsc::Receiver<Vec<CreatedObject>>>,
+    /// Whether reported segments were left over for lack of in-flight slots
+    rescan: bool,
+    /// Whether claiming is paused for lack of quorum
+    paused: bool,
+    /// Segments processed since the last load report
+    processed: u64,
+    /// Smoothed processing rate, in segments per second
+    rate: f64,
+    /// Time of the last load report
+    last_report: Instant,
+}
+
+impl<P> Receiver<P>
+where
+    P: SegmentProcessor + 'static,
+{
+    /// Create a new receiver
+    pub fn new(
+        client: Client,
+        yellowpage: Arc<Yellowpage>,
+        processor: Arc<P>,
+        config: &SinkConfig,
+    ) -> Self {
+        let repository = S3StorageRepository::new(client.clone(), config.bucket.clone())
+            .with_write_options(config.s3_options.clone());
+
+        let progress = S3ProgressStore::new(repository.clone(), config.group.clone());
+        let (ordered, positions) = if config.ordered {
+            (
+                Some(OrderedShards::new(progress.clone(), config.ordering)),
+                None,
+            )
+        } else {
+            (
+                None,
+                Some(ShardPositions::new(progress.clone(), config.ordering)),
+            )
+        };
+        let manifests = config.manifests.as_ref().map(|_| {
+            ManifestReader::new(S3ManifestStore::new(
+                repository.clone(),
+                WindowSize::default(),
+            ))
+        });
+
+        Self {
+            client,
+            bucket: config.bucket.clone(),
+            yellowpage,
+            processor,
+            encryption: None,
+            repository,
+            poll_interval: config
+                .notifications
+                .as_ref()
+                .map_or(config.poll_interval, |notifications| {
+                    notifications.reconcile_interval
+                }),
+            max_in_flight: config.max_in_flight,
+            in_flight: JoinSet::new(),
+            claimed: Claims::default(),
+            running: HashMap::new(),
+            progress,
+            reset: None,
+            ordered,
+            positions,
+            manifests,
+            events: None,
+            rescan: false,
+            paused: false,
+            processed: 0,
+            rate: 0.0,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Decrypt encrypted segments with these master keys
+    ///
+    /// Unencrypted segments are then rejected, unless `allow_plaintext` is set.
+    pub fn with_key_provider(mut self, keys: LocalKeyProvider, allow_plaintext: bool) -> Self {
+        let encryption = EncryptedStorageRepository::new(self.repository.clone(), keys);
+        self.encryption = Some(Arc::new(if allow_plaintext {
+            encryption
+        } else {
+            encryption.reject_plaintext()
+        }));
+        self
+    }
+
+    /// Claim the segments reported by these object-created events
+    ///
+    /// Bucket scans then only reconcile, at the configured interval.
+    pub fn with_notifications(mut self, events: mpsc::Receiver<Vec<CreatedObject>>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Run the polling loop until `shutdown` flips, then drain
+    pub async fn run(mut self, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        let mut events = self.events.take();
+
+        // Resume from the last reset of the group, not as a new one
+        match self.progress.load_reset().await {
+            Ok(reset) => self.reload(reset),
+            Err(err) => warn!(error = ?err, "Failed to read the last reset of the group"),
+        }
+
+        info!(
+            bucket = %self.bucket,
+            group = %self.progress.group(),
+            generation = reset::generation(self.reset.as_ref()),
+            notifications = events.is_some(),
+            manifests = self.manifests.is_some(),
+            "Receiver started"
+        );
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(err) = self.poll_once().await {
+                        warn!(error = ?err, "Failed to poll bucket");
+                    }
+                }
+                Some(objects) = next_event(&mut events) => self.on_created(objects).await,
+                _ = shutdown.changed() => break,
+            }
+
+            self.reap_finished();
+            self.dispatch_ordered();
+
+            // Reported segments left over once in-flight was full
+            if self.rescan && self.in_flight.len() < self.max_in_flight {
+                if let Err(err) = self.poll_once().await {
+                    warn!(error = ?err, "Failed to poll bucket");
+                }
+            }
+        }
+
+        self.drain().await
+    }
+
+    /// Claim and spawn the segments assigned to this node
+    async fn poll_once(&mut self) -> Result<()> {
+        self.check_reset().await?;
+
+        let view = self.yellowpage.cluster_view().await;
+
+        if !view.has_quorum() {
+            if !self.paused {
+                warn!(
+                    live_nodes = view.len(),
+                    quorum = ?view.quorum(),
+                    "No quorum, pausing segment claims"
+                );
+                self.paused = true;
+            }
+            return Ok(());
+        }
+        if self.paused {
+            info!(
+                live_nodes = view.len(),
+                "Quorum regained, resuming segment claims"
+            );
+            self.paused = false;
+        }
+
+        if view.my_index().is_none() {
+            debug!("Node not in cluster view, skipping poll");
+            return Ok(());
+        }
+
+        self.rescan = false;
+
+        // Owned segments not claimed yet, left over once in-flight is full
+        let mut backlog = Backlog::default();
+        // Owned segments not claimed yet per shard, when processed in order
+        let mut listed: HashMap<ShardId, Vec<Pending>> = HashMap::new();
+        // Owned segments per shard, when processed in any order
+        let mut owned: HashMap<ShardId, Vec<(Position, String)>> = HashMap::new();
+        // Owned segments whose claim is still needed
+        let mut keep: HashSet<String> = self.running.values().cloned().collect();
+
+        if let Some(manifests) = &mut self.manifests {
+            for segment in manifests.scan().await? {
+                self.consider(
+                    &view,
+                    segment,
+                    &mut backlog,
+                    &mut listed,
+                    &mut owned,
+                    &mut keep,
+                )
+                .await;
+            }
+        } else {
+            let mut pages = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .into_paginator()
+                .send();
+
+            while let Some(page) = pages.next().await {
+                let page = page.context("S3 list_objects_v2 failed")?;
+
+                for object in page.contents() {
+                    let Some((segment_id, partition)) = object.key().and_then(listed_segment)
+                    else {
+                        continue;
+                    };
+                    let segment = Scanned {
+                        segment_id,
+                        partition,
+                        size: object.size().unwrap_or(0),
+                        uploaded_at: object.last_modified().and_then(|time| {
+                            DateTime::from_timestamp(time.secs(), time.subsec_nanos())
+                        }),
+                    };
+                    self.consider(
+                        &view,
+                        segment,
+                        &mut backlog,
+                        &mut listed,
+                        &mut owned,
+                        &mut keep,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        if self.positions.is_some() {
+            let processed = owned
+                .iter()
+                .map(|(shard, segments)| {
+                    let segments = segments
+                        .iter()
+                        .map(|(position, key)| (*position, self.is_done(key)))
+                        .collect();
+                    (*shard, segments)
+                })
+                .collect();
+            if let Some(positions) = &mut self.positions {
+                positions.advance(processed, Utc::now());
+                positions.persist().await;
+
+                // Segments behind the progress are recorded by it
+                for (shard, segments) in owned {
+                    keep.extend(
+                        segments
+                            .into_iter()
+                            .filter(|(position, _)| !positions.is_behind(shard, position))
+                            .map(|(_, key)| key),
+                    );
+                }
+            }
+        }
+        self.claimed.prune(&keep);
+        debug!(claims = self.claimed.len(), "Pruned segment claims");
+
+        if let Some(ordered) = &mut self.ordered {
+            ordered.refresh(listed).await;
+            for (shard, size) in self.dispatch_ordered() {
+                backlog.add(shard, size);
+            }
+        }
+
+        self.report_load(backlog).await;
+
+        Ok(())
+    }
+
+    /// Claim a scanned segment if this node owns it
+    ///
+    /// Segments processed in order are added to `listed` instead, and those
+    /// that find no free in-flight slot to `backlog`. Segments processed in
+    /// any order are all added to `owned`, claimed or not, to track the
+    /// progress of their shard. Segments processed in order are added to
+    /// `keep`, their claim is needed as long as they are listed.
+    async fn consider(
+        &mut self,
+        view: &ClusterView,
+        segment: Scanned,
+        backlog: &mut Backlog,
+        listed: &mut HashMap<ShardId, Vec<Pending>>,
+        owned: &mut HashMap<ShardId, Vec<(Position, String)>>,
+        keep: &mut HashSet<String>,
+    ) {
+        let Some((key, routing_key)) = self.owned(view, &segment.segment_id, segment.partition)
+        else {
+            return;
+        };
+        let shard = shard_of(&routing_key);
+        let uploaded_at = segment.uploaded_at.unwrap_or_else(Utc::now);
+        let position = Position::of(segment.segment_id, uploaded_at);
+
+        if self.ordered.is_some() {
+            keep.insert(key.clone());
+            if !self.claimed.contains(&key) {
+                listed.entry(shard).or_default().push(Pending {
+                    key,
+                    position,
+                    uploaded_at,
+                    size: segment.size,
+                });
+            }
+            return;
+        }
+
+        owned
+            .entry(shard)
+            .or_default()
+            .push((position, key.clone()));
+        if self.claimed.contains(&key) || !self.is_due(shard, &position).await {
+            return;
+        }
+
+        if self.in_flight.len() >= self.max_in_flight {
+            backlog.add(shard, segment.size);
+            return;
+        }
+
+        self.spawn(key);
+    }
+
+    /// Claim the owned segments among objects reported created
+    ///
+    /// Segments that find no free in-flight slot are picked up by a scan
+    /// once one frees up.
+    async fn on_created(&mut self, objects: Vec<CreatedObject>) {
+        let view = self.yellowpage.cluster_view().await;
+        if self.paused || !view.has_quorum() || view.my_index().is_none() {
+            // Left to the scans, which also track the quorum
+            return;
+        }
+
+        let mut reported: HashMap<ShardId, Vec<Pending>> = HashMap::new();
+
+        for object in objects {
+            if object.bucket != self.bucket {
+                continue;
+            }
+            let Some((segment_id, partition)) = listed_segment(&object.key) else {
+                continue;
+            };
+            let Some((key, routing_key)) = self.owned(&view, &segment_id, partition) else {
+                continue;
+            };
+            if self.claimed.contains(&key) {
+                continue;
+            }
+            debug!(key = %key, "Segment reported created");
+            let shard = shard_of(&routing_key);
+            let uploaded_at = object.event_time.unwrap_or_else(Utc::now);
+            let position = Position::of(segment_id, uploaded_at);
+
+            if self.ordered.is_some() {
+                reported.entry(shard).or_default().push(Pending {
+                    key,
+                    position,
+                    uploaded_at,
+                    size: object.size,
+                });
+                continue;
+            }
+
+            if !self.is_due(shard, &position).await {
+                continue;
+            }
+
+            if self.in_flight.len() >= self.max_in_flight {
+                self.rescan = true;
+                continue;
+            }
+
+            self.spawn(key);
+        }
+
+        if let Some(ordered) = &mut self.ordered {
+            ordered.add(reported).await;
+        }
+    }
+
+    /// Key and routing key of a segment, if this node owns it
+    fn owned(
+        &self,
+        view: &ClusterView,
+        segment_id: &SegmentId,
+        partition: Option<PartitionId>,
+    ) -> Option<(String, String)> {
+        let key = segment_key(segment_id, partition);
+        let routing_key = assignment_key(segment_id, partition);
+
+        view.owns(&routing_key).then_some((key, routing_key))
+    }
+
+    /// Whether an unclaimed segment processed in any order is still to process
+    ///
+    /// Segments behind the progress of their shard are processed already.
+    /// Segments of a shard whose progress cannot be read wait for the next poll.
+    async fn is_due(&mut self, shard: ShardId, position: &Position) -> bool {
+        let Some(positions) = &mut self.positions else {
+            return true;
+        };
+        positions.is_processed(shard, position).await == Some(false)
+    }
+
+    /// Whether a claimed segment is processed
+    fn is_done(&self, key: &str) -> bool {
+        self.claimed.contains(key) && !self.running.values().any(|running| running == key)
+    }
+
+    /// Pause, reload and resume if the group was reset since the last poll
+    ///
+    /// In-flight segments are finished and the processor flushed first: the
+    /// segments of the new position start from a clean state.
+    async fn check_reset(&mut self) -> Result<()> {
+        let reset = self
+            .progress
+            .load_reset()
+            .await
+            .context("Failed to read the last reset of the group")?;
+        let generation = reset::generation(reset.as_ref());
+        if generation <= reset::generation(self.reset.as_ref()) {
+            return Ok(());
+        }
+
+        if let Some(reset) = &reset {
+            info!(
+                group = %reset.group,
+                generation,
+                start = %reset.start,
+                in_flight = self.in_flight.len(),
+                "Consumer group reset, pausing"
+            );
+        }
+        while let Some(joined) = self.in_flight.join_next_with_id().await {
+            self.handle_finished(joined);
+        }
+        self.processor.flush().await?;
+
+        self.reload(reset);
+        info!(generation, "Positions reloaded, resuming");
+        Ok(())
+    }
+
+    /// Resume every shard from a reset of the group, forgetting claims
+    fn reload(&mut self, reset: Option<GroupReset>) {
+        self.claimed.clear();
+        if let Some(ordered) = &mut self.ordered {
+            ordered.reload(reset.clone());
+        }
+        if let Some(positions) = &mut self.positions {
+            positions.reload(reset.clone());
+        }
+        self.reset = reset;
+    }
+
+    /// Spawn the next ready segment of every idle shard, when processed in order
+    ///
+    /// Returns the shard and size of the segments still waiting.
+    fn dispatch_ordered(&mut self) -> Vec<(ShardId, i64)> {
+        if self.paused {
+            return Vec::new();
+        }
+        let capacity = self.max_in_flight.saturating_sub(self.in_flight.len());
+        let Some(ordered) = &mut self.ordered else {
+            return Vec::new();
+        };
+
+        let dispatch = ordered.dispatch(capacity, Utc::now());
+        self.claimed.extend(dispatch.skipped);
+        for key in dispatch.ready {
+            self.spawn(key);
+        }
+        dispatch.waiting
+    }
+
+    /// Gossip the backlog and processing rate of this node
+    async fn report_load(&mut self, backlog: Backlog) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_report).as_secs_f64();
+        if elapsed > 0.0 {
+            let measured = self.processed as f64 / elapsed;
+            self.rate = RATE_SMOOTHING * measured + (1.0 - RATE_SMOOTHING) * self.rate;
+        }
+        self.processed = 0;
+        self.last_report = now;
+
+        let report = backlog.into_report(self.in_flight.len() as u64, self.rate);
+        if let Err(err) = self.yellowpage.report_load(&report).await {
+            warn!(error = ?err, "Failed to report load");
+        }
+    }
+
+    /// Process one segment in the background
+    fn spawn(&mut self, key: String) {
+        self.claimed.insert(key.clone());
+
+        let repository = self.repository.clone();
+        let processor = self.processor.clone();
+        let encryption = self.encryption.clone();
+
+        let running = key.clone();
+        let task = self.in_flight.spawn(async move {
+            let (segment_id, partition) =
+                listed_segment(&key).with_context(|| format!("Invalid segment key '{}'", key))?;
+            let stored = match repository.load_partitioned(&segment_id, partition).await {
+                Ok(stored) => stored,
+                // Deleted since it was found, e.g. by the retention policy
+                Err(_)
+                    if !repository
+                        .exists_partitioned(&segment_id, partition)
+                        .await? =>
+                {
+                    warn!(key = %key, "Segment no longer exists, skipping");
+                    return Ok(());
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Failed to load segment '{}'", key))
+                }
+            };
+            let stored = match &encryption {
+                Some(encryption) => encryption
+                    .open(&segment_id, stored)
+                    .await
+                    .with_context(|| format!("Failed to decrypt segment '{}'", key))?,
+                None if envelope::is_envelope(&stored.data) => anyhow::bail!(
+                    "Segment '{}' is encrypted but ZUKLINK_ENCRYPTION_KEYFILE is not set",
+                    key
+                ),
+                None => stored,
+            };
+
+            let record_key = stored.record.as_ref().map(|record| record.key.clone());
+            let data = stored
+                .into_verified_data()
+                .with_context(|| format!("Corrupted segment '{}'", key))?;
+            processor
+                .process(&key, record_key.as_deref(), data.into())
+                .await
+        });
+        self.running.insert(task.id(), running);
+    }
+
+    /// Collect finished tasks without waiting
+    fn reap_finished(&mut self) {
+        while let Some(joined) = self.in_flight.try_join_next_with_id() {
+            self.handle_finished(joined);
+        }
+    }
+
+    /// Release the claim of failed segments so they are retried
+    ///
+    /// A segment whose task panicked is failed too, so that its shard does
+    /// not stay busy.
+    fn handle_finished(&mut self, joined: Result<(task::Id, Result<()>), JoinError>) {
+        let (id, result) = match joined {
+            Ok((id, result)) => (id, result),
+            Err(err) => (
+                err.id(),
+                Err(anyhow::anyhow!("Segment processing task panicked: {}", err)),
+            ),
+        };
+        let Some(key) = self.running.remove(&id) else {
+            return;
+        };
+
+        match result {
+            Ok(()) => {
+                self.processed += 1;
+                if let Some(ordered) = &mut self.ordered {
+                    ordered.finished(&key, true);
+                }
+            }
+            Err(err) => {
+                warn!(key = %key, error = ?err, "Failed to process segment, will retry");
+                self.claimed.remove(&key);
+                if let Some(ordered) = &mut self.ordered {
+                    ordered.finished(&key, false);
+                }
+            }
+        }
+    }
+
+    /// Wait for in-flight segments, then flush the processor
+    async fn drain(mut self) -> Result<()> {
+        info!(in_flight = self.in_flight.len(), "Draining receiver");
+
+        while let Some(joined) = self.in_flight.join_next_with_id().await {
+            self.handle_finished(joined);
+        }
+
+        self.processor.flush().await?;
+
+        if let Some(ordered) = &mut self.ordered {
+            ordered.persist().await;
+        }
+        if let Some(positions) = &mut self.positions {
+            positions.persist().await;
+        }
+
+        info!("Receiver drained");
+        Ok(())
+    }
+}
+
+/// Keys claimed by this node, in flight or done
+#[derive(Debug, Default)]
+struct Claims {
+    keys: HashSet<String>,
+    /// Keys claimed since the last prune
+    recent: HashSet<String>,
+}
+
+impl Claims {
+    fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    fn insert(&mut self, key: String) {
+        self.recent.insert(key.clone());
+        self.keys.insert(key);
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.keys.remove(key);
+        self.recent.remove(key);
+    }
+
+    fn extend(&mut self, keys: impl IntoIterator<Item = String>) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.recent.clear();
+    }
+
+    /// Forget the keys not in `keep`, except those claimed since the last prune
+    ///
+    /// A key claimed from an event may be missing from the scan that follows,
+    /// e.g. when its manifest entry is not written yet.
+    fn prune(&mut self, keep: &HashSet<String>) {
+        let recent = std::mem::take(&mut self.recent);
+        self.keys
+            .retain(|key| keep.contains(key) || recent.contains(key));
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// Owned segments waiting for a processing slot
+#[derive(Debug, Default)]
+struct Backlog {
+    segments: u64,
+    bytes: u64,
+    shards: BTreeMap<ShardId, u64>,
+}
+
+impl Backlog {
+    fn add(&mut self, shard: ShardId, size: i64) {
+        self.segments += 1;
+        self.bytes += u64::try_from(size).unwrap_or(0);
+        *self.shards.entry(shard).or_default() += 1;
+    }
+
+    /// Build the load report, keeping only the busiest shards
+    fn into_report(self, in_flight: u64, processing_rate: f64) -> LoadReport {
+        let mut shards: Vec<(ShardId, u64)> = self.shards.into_iter().collect();
+        shards.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        shards.truncate(REPORTED_SHARDS);
+
+        LoadReport {
+            pending_segments: self.segments + in_flight,
+            pending_bytes: self.bytes,
+            processing_rate,
+            shards: shards.into_iter().collect(),
+        }
+    }
+}
+
+/// Next batch of object-created events, never resolving without notifications
+async fn next_event(
+    events: &mut Option<mpsc::Receiver<Vec<CreatedObject>>>,
+) -> Option<Vec<CreatedObject>> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+    use bytes::Bytes;
+    use zuklink_yellowpage::{ChannelTransport, YellowpageConfig};
+
+    /// Processor panicking on every segment
+    struct PanickingProcessor;
+
+    impl SegmentProcessor for PanickingProcessor {
+        async fn process(&self, key: &str, _record_key: Option<&str>, _data: Bytes) -> Result<()> {
+            panic!("processor bug on {}", key);
+        }
+
+        async fn flush(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Ordered receiver of a mocked bucket where every segment holds `payload`
+    async fn ordered_receiver<P: SegmentProcessor + 'static>(processor: P) -> Receiver<P> {
+        let http_client = infallible_client_fn(|request| {
+            let response = http::Response::builder();
+            if request.uri().path().contains("/_progress/") {
+                return response
+                    .status(404)
+                    .body(SdkBody::from(
+                        "<Error><Code>NoSuchKey</Code><Message>Not found</Message></Error>",
+                    ))
+                    .unwrap();
+            }
+            response.status(200).body(SdkBody::from("payload")).unwrap()
+        });
+        let s3_config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .force_path_style(true)
+            .http_client(http_client)
+            .build();
+
+        let yellowpage = Yellowpage::with_transport(
+            YellowpageConfig::local("sink-1", "10.0.0.1:7000".parse().unwrap()),
+            &ChannelTransport::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut config = SinkConfig::from_env().unwrap();
+        config.ordered = true;
+        config.ordering.lateness = Duration::ZERO;
+
+        Receiver::new(
+            Client::from_conf(s3_config),
+            Arc::new(yellowpage),
+            Arc::new(processor),
+            &config,
+        )
+    }
+
+    fn keys(keys: &[&str]) -> HashSet<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prune_forgets_keys_no_longer_owned() {
+        let mut claims = Claims::default();
+        claims.extend(["a.zuk", "b.zuk", "c.zuk"].map(String::from));
+
+        // Claims made before the scan survive it once
+        claims.prune(&keys(&[]));
+        assert_eq!(claims.len(), 3);
+
+        claims.prune(&keys(&["a.zuk", "c.zuk"]));
+        assert!(claims.contains("a.zuk"));
+        assert!(!claims.contains("b.zuk"));
+        assert!(claims.contains("c.zuk"));
+    }
+
+    #[test]
+    fn test_prune_keeps_recent_claims_one_scan() {
+        let mut claims = Claims::default();
+        claims.insert("listed.zuk".to_string());
+        claims.prune(&keys(&["listed.zuk"]));
+
+        // Reported by an event, not listed by the next scan yet
+        claims.insert("reported.zuk".to_string());
+        claims.prune(&keys(&["listed.zuk"]));
+        assert!(claims.contains("reported.zuk"));
+
+        claims.prune(&keys(&["listed.zuk"]));
+        assert!(!claims.contains("reported.zuk"));
+        assert_eq!(claims.len(), 1);
+    }
+
+    #[test]
+    fn test_released_claims_are_retried() {
+        let mut claims = Claims::default();
+        claims.insert("failed.zuk".to_string());
+        claims.remove("failed.zuk");
+
+        assert!(!claims.contains("failed.zuk"));
+        claims.prune(&keys(&[]));
+        assert_eq!(claims.len(), 0);
+    }
+
+    #[test]
+    fn test_reload_forgets_every_claim() {
+        let mut claims = Claims::default();
+        claims.extend(["a.zuk", "b.zuk"].map(String::from));
+        claims.clear();
+
+        assert_eq!(claims.len(), 0);
+        claims.prune(&keys(&["a.zuk"]));
+        assert!(!claims.contains("a.zuk"));
+    }
+
+    #[tokio::test]
+    async fn test_panicked_segment_is_released() {
+        let mut receiver = ordered_receiver(PanickingProcessor).await;
+        let segment_id = SegmentId::new();
+        let key = segment_key(&segment_id, None);
+        let uploaded_at = Utc::now() - chrono::Duration::seconds(1);
+        let listed = || {
+            HashMap::from([(
+                7,
+                vec![Pending {
+                    key: key.clone(),
+                    position: Position::of(segment_id, uploaded_at),
+                    uploaded_at,
+                    size: 7,
+                }],
+            )])
+        };
+
+        receiver.ordered.as_mut().unwrap().refresh(listed()).await;
+        receiver.dispatch_ordered();
+        assert_eq!(receiver.running.len(), 1);
+
+        let joined = receiver.in_flight.join_next_with_id().await.unwrap();
+        assert!(joined.as_ref().is_err_and(|err| err.is_panic()));
+        receiver.handle_finished(joined);
+
+        // The claim is released and the shard is free to retry the segment
+        assert!(receiver.running.is_empty());
+        assert!(!receiver.claimed.contains(&key));
+        let ordered = receiver.ordered.as_mut().unwrap();
+        ordered.refresh(listed()).await;
+        assert_eq!(ordered.dispatch(1, Utc::now()).ready, vec![key]);
+    }
+
+    #[test]
+    fn test_backlog_reports_busiest_shards() {
+        let mut backlog = Backlog::default();
+        for shard in 0..(REPORTED_SHARDS as ShardId + 8) {
+            backlog.add(shard, 10);
+        }
+        backlog.add(3, 10);
+        backlog.add(3, -1);
+
+        let report = backlog.into_report(2, 1.5);
+
+        assert_eq!(report.pending_segments, REPORTED_SHARDS as u64 + 8 + 2 + 2);
+        assert_eq!(report.pending_bytes, (REPORTED_SHARDS as u64 + 9) * 10);
+        assert_eq!(report.shards.len(), REPORTED_SHARDS);
+        assert_eq!(report.shards[&3], 3);
+    }
+}
@@ -0,0 +1,36 @@
+//! Resets of the consumer group positions
+//!
+//! `zuk-sink reset <position>` records a reset of the group (`SINK_GROUP`)
+//! next to its progress, without joining the cluster. Running receivers pick
+//! it up at their next poll: they finish their in-flight segments, flush,
+//! then resume every shard they own from the reset position.
+//!
+//! The position is `earliest`, `latest`, an RFC 3339 time or a segment ID.
+//! Segments deleted by the retention policy cannot be processed again.
+
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use chrono::Utc;
+use zuklink_domain::replay::reset::{GroupReset, StartFrom};
+use zuklink_s3::infrastructure::{S3ProgressStore, S3StorageRepository};
+
+use crate::config::ResetConfig;
+
+/// Reset the positions of the group to `start`
+pub async fn reset(client: Client, config: &ResetConfig, start: StartFrom) -> Result<GroupReset> {
+    let repository = S3StorageRepository::new(client, config.bucket.clone())
+        .with_write_options(config.s3_options.clone());
+    let store = S3ProgressStore::new(repository, config.group.clone());
+
+    let previous = store
+        .load_reset()
+        .await
+        .context("Failed to read the previous reset")?;
+    let reset = GroupReset::after(previous.as_ref(), config.group.clone(), start, Utc::now())?;
+    store
+        .save_reset(&reset)
+        .await
+        .context("Failed to save the reset")?;
+
+    Ok(reset)
+}
@@ -0,0 +1,121 @@
+//! AES-256-GCM primitives
+//!
+//! Sealed values are laid out as `nonce (12) | ciphertext | tag (16)`. Nonces
+//! are random: every key seals a single value (data keys are per segment) or a
+//! small number of data keys (master keys), far below the GCM random nonce
+//! limit.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::{CryptoError, Result};
+
+/// Size of an AES-256 key
+pub const KEY_LEN: usize = 32;
+
+/// Size of the authentication tag appended to the ciphertext
+const TAG_LEN: usize = 16;
+
+/// Bytes added to every sealed value (nonce and tag)
+pub(crate) const OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// Fill a buffer with secure random bytes
+pub(crate) fn random<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| CryptoError::key_provider_failure("system random generator failed"))?;
+    Ok(bytes)
+}
+
+/// Encrypt and authenticate `plaintext`, binding it to `aad`
+pub(crate) fn seal(key: &[u8; KEY_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = random::<NONCE_LEN>()?;
+
+    let mut sealed = Vec::with_capacity(plaintext.len() + OVERHEAD);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(plaintext);
+
+    let mut in_out = sealed.split_off(NONCE_LEN);
+    cipher(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut in_out,
+        )
+        .map_err(|_| CryptoError::key_provider_failure("AES-256-GCM encryption failed"))?;
+
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+/// Verify and decrypt a value sealed with `seal`
+///
+/// # Errors
+///
+/// Returns `CryptoError::DecryptionFailed` if the value is truncated, or was
+/// sealed with another key or associated data.
+pub(crate) fn open(key: &[u8; KEY_LEN], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < OVERHEAD {
+        return Err(CryptoError::decryption_failed(format!(
+            "sealed value too short ({} bytes)",
+            sealed.len()
+        )));
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce is NONCE_LEN bytes");
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext_len = cipher(key)
+        .open_in_place(nonce, Aad::from(aad), &mut in_out)
+        .map_err(|_| CryptoError::decryption_failed("authentication failed"))?
+        .len();
+
+    in_out.truncate(plaintext_len);
+    Ok(in_out)
+}
+
+fn cipher(key: &[u8; KEY_LEN]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).expect("key is KEY_LEN bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let key = random::<KEY_LEN>().unwrap();
+
+        let sealed = seal(&key, b"aad", b"payload").unwrap();
+
+        assert_eq!(sealed.len(), b"payload".len() + OVERHEAD);
+        assert_eq!(open(&key, b"aad", &sealed).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_nonces_are_random() {
+        let key = random::<KEY_LEN>().unwrap();
+
+        assert_ne!(
+            seal(&key, b"", b"payload").unwrap(),
+            seal(&key, b"", b"payload").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wrong_key_aad_or_tampering_rejected() {
+        let key = random::<KEY_LEN>().unwrap();
+        let sealed = seal(&key, b"aad", b"payload").unwrap();
+
+        assert!(open(&random::<KEY_LEN>().unwrap(), b"aad", &sealed).is_err());
+        assert!(open(&key, b"other", &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[NONCE_LEN] ^= 0x01;
+        assert!(open(&key, b"aad", &tampered).is_err());
+
+        assert!(open(&key, b"aad", &sealed[..OVERHEAD - 1]).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Envelope format of encrypted segments
+//!
+//! ```text
+//! ┌───────┬────────────┬────────┬────────────────┬─────────────┬───────────────────────────┐
+//! │ ZKE1  │ id len (1) │ key id │ wrapped len (2)│ wrapped key │ nonce │ ciphertext │ tag  │
+//! └───────┴────────────┴────────┴────────────────┴─────────────┴───────────────────────────┘
+//! ```
+//!
+//! Lengths are big-endian. The wrapped key is the data key sealed by the
+//! master key `key id`. The header (everything before the nonce) and the
+//! segment id are authenticated as associated data, so an envelope cannot be
+//! moved to another segment or have its key id swapped.
+
+use zuklink_domain::ingestion::ids::SegmentId;
+
+use crate::cipher;
+use crate::error::{CryptoError, Result};
+use crate::key::{validate_key_id, DataKey};
+use crate::provider::{KeyProvider, WrappedKey};
+
+/// Leading bytes identifying an encrypted segment
+pub const MAGIC: &[u8; 4] = b"ZKE1";
+
+/// Check whether stored data is an encrypted envelope
+///
+/// Data without the magic prefix is a segment stored before encryption was
+/// enabled.
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt a segment payload into an envelope
+///
+/// # Errors
+///
+/// Returns `CryptoError::InvalidKey` if the key id or wrapped key do not fit
+/// in the header
+pub fn seal(
+    segment_id: &SegmentId,
+    data_key: &DataKey,
+    wrapped_key: &WrappedKey,
+    plaintext: &[u8],
+) -> Result<Vec<u8>> {
+    validate_key_id(&wrapped_key.key_id)?;
+    let wrapped_len = u16::try_from(wrapped_key.wrapped.len()).map_err(|_| {
+        CryptoError::invalid_key(format!(
+            "wrapped data key too large ({} bytes)",
+            wrapped_key.wrapped.len()
+        ))
+    })?;
+
+    let mut header =
+        Vec::with_capacity(MAGIC.len() + 3 + wrapped_key.key_id.len() + wrapped_key.wrapped.len());
+    header.extend_from_slice(MAGIC);
+    header.push(wrapped_key.key_id.len() as u8);
+    header.extend_from_slice(wrapped_key.key_id.as_bytes());
+    header.extend_from_slice(&wrapped_len.to_be_bytes());
+    header.extend_from_slice(&wrapped_key.wrapped);
+
+    let sealed = cipher::seal(data_key.as_bytes(), &aad(&header, segment_id), plaintext)?;
+
+    let mut envelope = header;
+    envelope.extend_from_slice(&sealed);
+    Ok(envelope)
+}
+
+/// Decrypt an envelope, unwrapping its data key with `keys`
+///
+/// For readers of the raw objects, like the receivers.
+///
+/// # Errors
+///
+/// - `CryptoError::InvalidEnvelope` if the data is not a valid envelope
+/// - `CryptoError::KeyNotFound` if the master key is unknown to `keys`
+/// - `CryptoError::DecryptionFailed` if the data does not verify
+pub async fn decrypt<K: KeyProvider>(
+    keys: &K,
+    segment_id: &SegmentId,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let envelope = Envelope::parse(data)?;
+    let wrapped_key = envelope.wrapped_key();
+    let data_key = keys
+        .unwrap_data_key(&wrapped_key.key_id, &wrapped_key.wrapped)
+        .await?;
+
+    envelope.open(segment_id, &data_key)
+}
+
+/// A parsed envelope, borrowing the stored data
+#[derive(Debug)]
+pub struct Envelope<'a> {
+    header: &'a [u8],
+    wrapped_key: WrappedKey,
+    sealed: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Parse an envelope
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidEnvelope` if the data does not start with
+    /// the magic prefix or is truncated
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(CryptoError::invalid_envelope("missing magic prefix"));
+        }
+
+        let id_len = reader.take(1)?[0] as usize;
+        let key_id = std::str::from_utf8(reader.take(id_len)?)
+            .map_err(|_| CryptoError::invalid_envelope("key id is not UTF-8"))?
+            .to_string();
+
+        let wrapped_len = u16::from_be_bytes(reader.take(2)?.try_into().expect("2 bytes")) as usize;
+        let wrapped = reader.take(wrapped_len)?.to_vec();
+
+        let (header, sealed) = data.split_at(reader.pos);
+
+        Ok(Self {
+            header,
+            wrapped_key: WrappedKey { key_id, wrapped },
+            sealed,
+        })
+    }
+
+    /// The wrapped data key and the id of the master key that wrapped it
+    pub fn wrapped_key(&self) -> &WrappedKey {
+        &self.wrapped_key
+    }
+
+    /// Decrypt the payload with the unwrapped data key
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::DecryptionFailed` if the payload does not verify
+    /// (wrong key, other segment, or tampered data)
+    pub fn open(&self, segment_id: &SegmentId, data_key: &DataKey) -> Result<Vec<u8>> {
+        cipher::open(
+            data_key.as_bytes(),
+            &aad(self.header, segment_id),
+            self.sealed,
+        )
+    }
+}
+
+/// Associated data: the envelope header followed by the segment id
+fn aad(header: &[u8], segment_id: &SegmentId) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + 16);
+    aad.extend_from_slice(header);
+    aad.extend_from_slice(segment_id.as_uuid().as_bytes());
+    aad
+}
+
+/// Cursor over the envelope header
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| CryptoError::invalid_envelope("truncated header"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::MasterKey;
+    use crate::provider::LocalKeyProvider;
+
+    fn sealed(segment_id: &SegmentId, plaintext: &[u8]) -> (DataKey, Vec<u8>) {
+        let master = MasterKey::generate("2024-06").unwrap();
+        let data_key = DataKey::generate().unwrap();
+        let wrapped = WrappedKey {
+            key_id: master.id().to_string(),
+            wrapped: master.wrap(&data_key).unwrap(),
+        };
+
+        let envelope = seal(segment_id, &data_key, &wrapped, plaintext).unwrap();
+        (data_key, envelope)
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let segment_id = SegmentId::new();
+        let (data_key, data) = sealed(&segment_id, b"payload");
+
+        assert!(is_envelope(&data));
+
+        let envelope = Envelope::parse(&data).unwrap();
+        assert_eq!(envelope.wrapped_key().key_id, "2024-06");
+        assert_eq!(envelope.open(&segment_id, &data_key).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_envelope_bound_to_segment() {
+        let segment_id = SegmentId::new();
+        let (data_key, data) = sealed(&segment_id, b"payload");
+
+        let envelope = Envelope::parse(&data).unwrap();
+        assert!(envelope.open(&SegmentId::new(), &data_key).is_err());
+    }
+
+    #[test]
+    fn test_tampered_header_rejected() {
+        let segment_id = SegmentId::new();
+        let (data_key, mut data) = sealed(&segment_id, b"payload");
+
+        // Change the key id from "2024-06" to "2024-07"
+        data[MAGIC.len() + 7] = b'7';
+
+        let envelope = Envelope::parse(&data).unwrap();
+        assert_eq!(envelope.wrapped_key().key_id, "2024-07");
+        assert!(envelope.open(&segment_id, &data_key).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_with_provider() {
+        let master = MasterKey::generate("2024-06").unwrap();
+        let keys = LocalKeyProvider::new(vec![master]).unwrap();
+        let (data_key, wrapped) = keys.generate_data_key().await.unwrap();
+
+        let segment_id = SegmentId::new();
+        let data = seal(&segment_id, &data_key, &wrapped, b"payload").unwrap();
+
+        assert_eq!(
+            decrypt(&keys, &segment_id, &data).await.unwrap(),
+            b"payload"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_data() {
+        assert!(!is_envelope(b"plain segment"));
+        assert!(Envelope::parse(b"plain segment").is_err());
+        assert!(Envelope::parse(b"ZKE1").is_err());
+        assert!(Envelope::parse(b"ZKE1\x07short").is_err());
+    }
+}
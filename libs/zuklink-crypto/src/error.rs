@@ -0,0 +1,110 @@
+//! Error types for segment encryption
+
+use thiserror::Error;
+use zuklink_domain::ingestion::error::IngestionError;
+
+/// Result type alias for encryption operations
+pub type Result<T> = std::result::Result<T, CryptoError>;
+
+/// Errors that can occur while encrypting or decrypting segments
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    /// No master key with this id is available
+    #[error("Master key '{0}' not found")]
+    KeyNotFound(String),
+
+    /// A master key or keyfile is malformed
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    /// The key provider (keyfile, KMS) could not be reached or failed
+    #[error("Key provider failure: {0}")]
+    KeyProviderFailure(String),
+
+    /// The data or wrapped key failed authentication
+    #[error("Decryption failed: {0}")]
+    DecryptionFailed(String),
+
+    /// The stored data is not a valid envelope
+    #[error("Invalid envelope: {0}")]
+    InvalidEnvelope(String),
+}
+
+impl CryptoError {
+    /// Create a key not found error
+    pub fn key_not_found(key_id: impl Into<String>) -> Self {
+        Self::KeyNotFound(key_id.into())
+    }
+
+    /// Create an invalid key error
+    pub fn invalid_key(msg: impl Into<String>) -> Self {
+        Self::InvalidKey(msg.into())
+    }
+
+    /// Create a key provider failure
+    pub fn key_provider_failure(msg: impl ToString) -> Self {
+        Self::KeyProviderFailure(msg.to_string())
+    }
+
+    /// Create a decryption failure
+    pub fn decryption_failed(msg: impl Into<String>) -> Self {
+        Self::DecryptionFailed(msg.into())
+    }
+
+    /// Create an invalid envelope error
+    pub fn invalid_envelope(msg: impl Into<String>) -> Self {
+        Self::InvalidEnvelope(msg.into())
+    }
+}
+
+/// Surface encryption failures as domain errors
+///
+/// Missing or malformed keys are configuration problems, while data that does
+/// not decrypt is reported as invalid (corrupted or tampered with).
+impl From<CryptoError> for IngestionError {
+    fn from(err: CryptoError) -> Self {
+        match err {
+            CryptoError::KeyNotFound(_) | CryptoError::InvalidKey(_) => {
+                IngestionError::config_error(err.to_string())
+            }
+            CryptoError::KeyProviderFailure(_) => IngestionError::internal_error(err.to_string()),
+            CryptoError::DecryptionFailed(_) | CryptoError::InvalidEnvelope(_) => {
+                IngestionError::invalid_data(err.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_not_found_error() {
+        let err = CryptoError::key_not_found("2024-01");
+        assert_eq!(err.to_string(), "Master key '2024-01' not found");
+        assert!(matches!(
+            IngestionError::from(err),
+            IngestionError::ConfigError(_)
+        ));
+    }
+
+    #[test]
+    fn test_decryption_failure_is_invalid_data() {
+        let err = CryptoError::decryption_failed("authentication failed");
+        assert_eq!(err.to_string(), "Decryption failed: authentication failed");
+        assert!(matches!(
+            IngestionError::from(err),
+            IngestionError::InvalidData(_)
+        ));
+    }
+
+    #[test]
+    fn test_key_provider_failure_is_internal() {
+        let err = CryptoError::key_provider_failure("KMS unreachable");
+        assert!(matches!(
+            IngestionError::from(err),
+            IngestionError::InternalError(_)
+        ));
+    }
+}
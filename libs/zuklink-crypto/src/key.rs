@@ -0,0 +1,236 @@
+//! Master and data keys
+//!
+//! Key material is never printed: `Debug` output is redacted.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cipher::{self, KEY_LEN};
+use crate::error::{CryptoError, Result};
+
+/// Maximum length of a key id, stored with every segment
+pub const MAX_KEY_ID_LEN: usize = 255;
+
+/// Key wrapping the per-segment data keys
+///
+/// Written as `<id>:<base64 key>`, e.g. `2024-06:q2mN...=`, where the id names
+/// the key during rotation and is recorded with every segment it protects.
+/// Generate the key material with `openssl rand -base64 32`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MasterKey {
+    id: String,
+    key: [u8; KEY_LEN],
+}
+
+impl MasterKey {
+    /// Create a master key from raw bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidKey` if the id is invalid
+    pub fn new(id: impl Into<String>, key: [u8; KEY_LEN]) -> Result<Self> {
+        let id = id.into();
+        validate_key_id(&id)?;
+        Ok(Self { id, key })
+    }
+
+    /// Generate a random master key
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidKey` if the id is invalid
+    pub fn generate(id: impl Into<String>) -> Result<Self> {
+        Self::new(id, cipher::random()?)
+    }
+
+    /// Identifier of the key, recorded with every segment it protects
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Wrap a data key, binding it to this key's id
+    pub(crate) fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>> {
+        cipher::seal(&self.key, self.id.as_bytes(), &data_key.0)
+    }
+
+    /// Unwrap a data key wrapped by this key
+    pub(crate) fn unwrap(&self, wrapped: &[u8]) -> Result<DataKey> {
+        let key = cipher::open(&self.key, self.id.as_bytes(), wrapped)?;
+        DataKey::from_slice(&key)
+    }
+}
+
+/// Never print key material
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterKey")
+            .field("id", &self.id)
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+impl FromStr for MasterKey {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (id, key) = s
+            .split_once(':')
+            .ok_or_else(|| CryptoError::invalid_key("expected '<id>:<base64 key>'"))?;
+
+        let id = id.trim();
+        let key = BASE64.decode(key.trim()).map_err(|e| {
+            CryptoError::invalid_key(format!("key '{}' is not valid base64: {}", id, e))
+        })?;
+        let key = key.try_into().map_err(|key: Vec<u8>| {
+            CryptoError::invalid_key(format!(
+                "key '{}' must be {} bytes, got {}",
+                id,
+                KEY_LEN,
+                key.len()
+            ))
+        })?;
+
+        Self::new(id, key)
+    }
+}
+
+/// Check that a key id can be stored in an envelope and in S3 metadata
+///
+/// Ids are 1 to 255 printable ASCII characters, without whitespace or `:`.
+pub(crate) fn validate_key_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_KEY_ID_LEN {
+        return Err(CryptoError::invalid_key(format!(
+            "key id must be 1 to {} characters, got {}",
+            MAX_KEY_ID_LEN,
+            id.len()
+        )));
+    }
+    if !id.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+        return Err(CryptoError::invalid_key(format!(
+            "key id '{}' must be printable ASCII without ':'",
+            id.escape_default()
+        )));
+    }
+    Ok(())
+}
+
+/// Per-segment AES-256-GCM key
+///
+/// Generated for each segment and stored only in wrapped form.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DataKey([u8; KEY_LEN]);
+
+impl DataKey {
+    /// Generate a random data key
+    pub fn generate() -> Result<Self> {
+        Ok(Self(cipher::random()?))
+    }
+
+    /// Create a data key from raw bytes (e.g. returned by a KMS)
+    pub fn from_bytes(key: [u8; KEY_LEN]) -> Self {
+        Self(key)
+    }
+
+    /// Create a data key from a slice
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidKey` if the slice is not 32 bytes
+    pub fn from_slice(key: &[u8]) -> Result<Self> {
+        key.try_into().map(Self).map_err(|_| {
+            CryptoError::invalid_key(format!(
+                "data key must be {} bytes, got {}",
+                KEY_LEN,
+                key.len()
+            ))
+        })
+    }
+
+    /// Raw key bytes, for key providers that wrap keys remotely
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+}
+
+/// Never print key material
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DataKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_key_parse() {
+        let line = format!("2024-06:{}", BASE64.encode([7u8; KEY_LEN]));
+        let key: MasterKey = line.parse().unwrap();
+
+        assert_eq!(key.id(), "2024-06");
+        assert_eq!(key, MasterKey::new("2024-06", [7; KEY_LEN]).unwrap());
+
+        assert!("2024-06".parse::<MasterKey>().is_err());
+        assert!("2024-06:not-base64!".parse::<MasterKey>().is_err());
+        assert!(format!("2024-06:{}", BASE64.encode([0u8; 16]))
+            .parse::<MasterKey>()
+            .is_err());
+        assert!(format!(":{}", BASE64.encode([0u8; KEY_LEN]))
+            .parse::<MasterKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_id_validation() {
+        assert!(validate_key_id("2024-06").is_ok());
+        assert!(validate_key_id(&"k".repeat(MAX_KEY_ID_LEN)).is_ok());
+
+        assert!(validate_key_id("").is_err());
+        assert!(validate_key_id(&"k".repeat(MAX_KEY_ID_LEN + 1)).is_err());
+        assert!(validate_key_id("with space").is_err());
+        assert!(validate_key_id("a:b").is_err());
+        assert!(validate_key_id("clé").is_err());
+    }
+
+    #[test]
+    fn test_wrap_unwrap_roundtrip() {
+        let master = MasterKey::generate("primary").unwrap();
+        let data_key = DataKey::generate().unwrap();
+
+        let wrapped = master.wrap(&data_key).unwrap();
+
+        assert_eq!(master.unwrap(&wrapped).unwrap(), data_key);
+        assert!(MasterKey::generate("primary")
+            .unwrap()
+            .unwrap(&wrapped)
+            .is_err());
+    }
+
+    #[test]
+    fn test_wrapped_key_bound_to_key_id() {
+        let master = MasterKey::new("a", [1; KEY_LEN]).unwrap();
+        let renamed = MasterKey::new("b", [1; KEY_LEN]).unwrap();
+
+        let wrapped = master.wrap(&DataKey::generate().unwrap()).unwrap();
+
+        assert!(renamed.unwrap(&wrapped).is_err());
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let master = MasterKey::new("primary", [42; KEY_LEN]).unwrap();
+
+        assert_eq!(
+            format!("{:?}", master),
+            "MasterKey { id: \"primary\", key: \"<redacted>\" }"
+        );
+        assert_eq!(
+            format!("{:?}", DataKey::from_bytes([42; KEY_LEN])),
+            "DataKey(<redacted>)"
+        );
+    }
+}
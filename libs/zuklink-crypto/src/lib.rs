@@ -0,0 +1,41 @@
+//! # ZukLink Crypto
+//!
+//! Client-side envelope encryption of segment payloads.
+//!
+//! [`EncryptedStorageRepository`] wraps any [`StorageRepository`] and encrypts
+//! segments before they reach the backend, so the data at rest is protected by
+//! keys we control rather than by bucket-side settings alone.
+//!
+//! ## Envelope Encryption
+//!
+//! Every segment is encrypted with AES-256-GCM under its own random data key.
+//! The data key is wrapped (encrypted) by a master key held by a
+//! [`KeyProvider`] and stored next to the ciphertext, along with the id of the
+//! master key. Master keys never touch the storage backend.
+//!
+//! - [`LocalKeyProvider`] reads master keys from a local keyfile
+//! - Any KMS can be plugged in by implementing [`KeyProvider`]
+//!
+//! ## Key Rotation
+//!
+//! The id of the master key is recorded in each segment, so old segments stay
+//! readable after a rotation:
+//!
+//! 1. Add the new key at the top of the keyfile: it encrypts new segments
+//! 2. Keep the old keys below it for as long as segments sealed with them exist
+//!
+//! [`StorageRepository`]: zuklink_domain::ports::StorageRepository
+
+pub mod envelope;
+pub mod error;
+pub mod key;
+pub mod provider;
+pub mod repository;
+
+mod cipher;
+
+pub use cipher::KEY_LEN;
+pub use error::{CryptoError, Result};
+pub use key::{DataKey, MasterKey, MAX_KEY_ID_LEN};
+pub use provider::{KeyProvider, LocalKeyProvider, WrappedKey};
+pub use repository::EncryptedStorageRepository;
@@ -0,0 +1,283 @@
+//! Key providers
+//!
+//! A [`KeyProvider`] owns the master keys and hands out data keys. The
+//! interface follows the KMS model (`GenerateDataKey` / `Decrypt`), so a cloud
+//! KMS can be plugged in without the master key ever leaving it.
+
+use std::future::Future;
+use std::path::Path;
+
+use tracing::info;
+
+use crate::error::{CryptoError, Result};
+use crate::key::{validate_key_id, DataKey, MasterKey};
+
+/// A data key in the form stored next to the data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    /// Id of the master key that wrapped the data key
+    pub key_id: String,
+    /// The data key encrypted by the master key
+    pub wrapped: Vec<u8>,
+}
+
+/// Port for master key operations
+///
+/// Implementations must be able to unwrap data keys wrapped by any master key
+/// that still protects stored segments, not only the current one.
+pub trait KeyProvider: Send + Sync {
+    /// Generate a data key and wrap it with the current master key
+    ///
+    /// Returns the data key, used to encrypt one segment, and its wrapped form
+    /// to store with the segment.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::KeyProviderFailure` if the provider is unavailable
+    fn generate_data_key(&self) -> impl Future<Output = Result<(DataKey, WrappedKey)>> + Send;
+
+    /// Unwrap a data key with the master key it was wrapped by
+    ///
+    /// # Errors
+    ///
+    /// - `CryptoError::KeyNotFound` if the master key is unknown
+    /// - `CryptoError::DecryptionFailed` if the wrapped key does not verify
+    /// - `CryptoError::KeyProviderFailure` if the provider is unavailable
+    fn unwrap_data_key(
+        &self,
+        key_id: &str,
+        wrapped: &[u8],
+    ) -> impl Future<Output = Result<DataKey>> + Send;
+}
+
+/// Key provider backed by master keys held in memory
+///
+/// Usually loaded from a keyfile with one `<id>:<base64 key>` per line. The
+/// first key is the primary one, wrapping new data keys; the others are only
+/// used to unwrap data keys of older segments. Blank lines and lines starting
+/// with `#` are ignored.
+///
+/// ```text
+/// # Primary, since 2024-06
+/// 2024-06:q2mN...=
+/// # Retired, still protecting older segments
+/// 2024-01:Zx8f...=
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalKeyProvider {
+    /// Every known key, the primary one first
+    keys: Vec<MasterKey>,
+}
+
+impl LocalKeyProvider {
+    /// Build a provider, the first key being the primary one
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidKey` if `keys` is empty or two keys share
+    /// the same id.
+    pub fn new(keys: Vec<MasterKey>) -> Result<Self> {
+        if keys.is_empty() {
+            return Err(CryptoError::invalid_key("keyring must not be empty"));
+        }
+
+        for (index, key) in keys.iter().enumerate() {
+            if keys[..index].iter().any(|other| other.id() == key.id()) {
+                return Err(CryptoError::invalid_key(format!(
+                    "duplicate master key id '{}'",
+                    key.id()
+                )));
+            }
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Parse the content of a keyfile
+    ///
+    /// # Errors
+    ///
+    /// Returns `CryptoError::InvalidKey` with the offending line number if a
+    /// line is malformed, or if the keyring is invalid.
+    pub fn parse(content: &str) -> Result<Self> {
+        let keys = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                line.parse().map_err(|err| {
+                    CryptoError::invalid_key(format!("keyfile line {}: {}", number, err))
+                })
+            })
+            .collect::<Result<Vec<MasterKey>>>()?;
+
+        Self::new(keys)
+    }
+
+    /// Load master keys from a keyfile
+    ///
+    /// # Errors
+    ///
+    /// - `CryptoError::KeyProviderFailure` if the file cannot be read
+    /// - `CryptoError::InvalidKey` if its content is invalid
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|err| {
+            CryptoError::key_provider_failure(format!(
+                "failed to read keyfile {}: {}",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let provider = Self::parse(&content)?;
+        info!(
+            path = %path.display(),
+            primary = provider.primary().id(),
+            keys = provider.keys.len(),
+            "Loaded master keys"
+        );
+        Ok(provider)
+    }
+
+    /// Key wrapping new data keys
+    pub fn primary(&self) -> &MasterKey {
+        &self.keys[0]
+    }
+
+    /// Ids of every known key, the primary one first
+    pub fn key_ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(MasterKey::id)
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn generate_data_key(&self) -> impl Future<Output = Result<(DataKey, WrappedKey)>> + Send {
+        let result = DataKey::generate().and_then(|data_key| {
+            let primary = self.primary();
+            let wrapped = WrappedKey {
+                key_id: primary.id().to_string(),
+                wrapped: primary.wrap(&data_key)?,
+            };
+            Ok((data_key, wrapped))
+        });
+
+        async move { result }
+    }
+
+    fn unwrap_data_key(
+        &self,
+        key_id: &str,
+        wrapped: &[u8],
+    ) -> impl Future<Output = Result<DataKey>> + Send {
+        let result = validate_key_id(key_id).and_then(|()| {
+            self.keys
+                .iter()
+                .find(|key| key.id() == key_id)
+                .ok_or_else(|| CryptoError::key_not_found(key_id))?
+                .unwrap(wrapped)
+        });
+
+        async move { result }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(ids: &[&str]) -> LocalKeyProvider {
+        LocalKeyProvider::new(
+            ids.iter()
+                .map(|id| MasterKey::generate(*id).unwrap())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_generate_and_unwrap_data_key() {
+        let provider = provider(&["primary"]);
+
+        let (data_key, wrapped) = provider.generate_data_key().await.unwrap();
+
+        assert_eq!(wrapped.key_id, "primary");
+        assert_eq!(
+            provider
+                .unwrap_data_key(&wrapped.key_id, &wrapped.wrapped)
+                .await
+                .unwrap(),
+            data_key
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_id() {
+        let (_, wrapped) = provider(&["old"]).generate_data_key().await.unwrap();
+
+        let result = provider(&["new"])
+            .unwrap_data_key(&wrapped.key_id, &wrapped.wrapped)
+            .await;
+
+        assert!(matches!(result, Err(CryptoError::KeyNotFound(id)) if id == "old"));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_old_keys_readable() {
+        let old = MasterKey::generate("2024-01").unwrap();
+        let new = MasterKey::generate("2024-06").unwrap();
+
+        let before = LocalKeyProvider::new(vec![old.clone()]).unwrap();
+        let after = LocalKeyProvider::new(vec![new, old]).unwrap();
+
+        let (data_key, wrapped) = before.generate_data_key().await.unwrap();
+        assert_eq!(
+            after
+                .unwrap_data_key(&wrapped.key_id, &wrapped.wrapped)
+                .await
+                .unwrap(),
+            data_key
+        );
+
+        let (_, wrapped) = after.generate_data_key().await.unwrap();
+        assert_eq!(wrapped.key_id, "2024-06");
+    }
+
+    #[test]
+    fn test_parse_keyfile() {
+        let content = format!(
+            "# Primary\n{}\n\n  # Retired\n{}\n",
+            "2024-06:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            "2024-01:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+        );
+
+        let provider = LocalKeyProvider::parse(&content).unwrap();
+
+        assert_eq!(provider.primary().id(), "2024-06");
+        assert_eq!(
+            provider.key_ids().collect::<Vec<_>>(),
+            vec!["2024-06", "2024-01"]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_keyfile() {
+        assert!(LocalKeyProvider::parse("# only comments\n").is_err());
+
+        let err = LocalKeyProvider::parse("# header\nnot-a-key\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        let duplicate = "a:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\n\
+                         a:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+        assert!(LocalKeyProvider::parse(duplicate).is_err());
+    }
+
+    #[test]
+    fn test_from_missing_file() {
+        assert!(matches!(
+            LocalKeyProvider::from_file("/nonexistent/zuklink.keys"),
+            Err(CryptoError::KeyProviderFailure(_))
+        ));
+    }
+}
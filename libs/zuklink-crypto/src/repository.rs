@@ -0,0 +1,462 @@
+//! Encrypting storage decorator
+
+use std::future::Future;
+
+use tracing::{debug, warn};
+use zuklink_domain::{
+    ingestion::{
+        entity::{Segment, StoredSegment},
+        error::IngestionError,
+        idempotency::IdempotencyClaim,
+        ids::SegmentId,
+    },
+    ports::StorageRepository,
+};
+
+use crate::envelope;
+use crate::provider::KeyProvider;
+
+/// Storage repository encrypting segments before they reach another one
+///
+/// Wraps any [`StorageRepository`]: `save` and `save_if_absent` seal the data
+/// (after compression, which is useless on ciphertext) into an envelope and
+/// record the master key id on the segment, so adapters can store it as
+/// metadata. `get` and `load` open the envelope and return the data as it was
+/// before encryption, with the stored checksum and codec untouched.
+///
+/// Segments stored before encryption was enabled are returned as is, unless
+/// [`reject_plaintext`](Self::reject_plaintext) is set.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use zuklink_crypto::{EncryptedStorageRepository, LocalKeyProvider};
+/// # use zuklink_domain::ports::StorageRepository;
+///
+/// # fn example(repository: impl StorageRepository) -> zuklink_crypto::Result<()> {
+/// let keys = LocalKeyProvider::from_file("/etc/zuklink/master.keys")?;
+/// let repository = EncryptedStorageRepository::new(repository, keys);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncryptedStorageRepository<R, K> {
+    inner: R,
+    keys: K,
+    allow_plaintext: bool,
+}
+
+impl<R, K> EncryptedStorageRepository<R, K> {
+    /// Wrap a repository, encrypting with keys from `keys`
+    pub fn new(inner: R, keys: K) -> Self {
+        Self {
+            inner,
+            keys,
+            allow_plaintext: true,
+        }
+    }
+
+    /// Fail to read segments that are not encrypted
+    ///
+    /// Use once every stored segment has been written through this layer.
+    pub fn reject_plaintext(mut self) -> Self {
+        self.allow_plaintext = false;
+        self
+    }
+
+    /// Get the wrapped repository
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get the key provider
+    pub fn key_provider(&self) -> &K {
+        &self.keys
+    }
+}
+
+impl<R: StorageRepository, K: KeyProvider> EncryptedStorageRepository<R, K> {
+    /// Seal the data under a fresh data key
+    ///
+    /// Returns a copy of the segment recording the master key id, and the
+    /// envelope to store. For callers writing to the wrapped repository
+    /// themselves, e.g. to record the segment as stored.
+    pub async fn encrypt(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> Result<(Segment, Vec<u8>), IngestionError> {
+        let (data_key, wrapped_key) = self.keys.generate_data_key().await?;
+        let sealed = envelope::seal(segment.id(), &data_key, &wrapped_key, data)?;
+
+        debug!(
+            segment_id = %segment.id(),
+            key_id = %wrapped_key.key_id,
+            size = sealed.len(),
+            "Encrypted segment"
+        );
+
+        let mut segment = segment.clone();
+        segment.set_encryption_key_id(wrapped_key.key_id);
+        Ok((segment, sealed))
+    }
+
+    /// Open an envelope read from storage
+    async fn decrypt(
+        &self,
+        segment_id: &SegmentId,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, IngestionError> {
+        if !envelope::is_envelope(&data) {
+            if self.allow_plaintext {
+                debug!(segment_id = %segment_id, "Segment is not encrypted, returning as is");
+                return Ok(data);
+            }
+            warn!(segment_id = %segment_id, "Rejected unencrypted segment");
+            return Err(IngestionError::invalid_data(format!(
+                "Segment {} is not encrypted",
+                segment_id
+            )));
+        }
+
+        envelope::decrypt(&self.keys, segment_id, &data)
+            .await
+            .map_err(|err| {
+                warn!(segment_id = %segment_id, error = %err, "Failed to decrypt segment");
+                err.into()
+            })
+    }
+
+    /// Open the envelope of a segment loaded from the wrapped repository
+    ///
+    /// For callers reading segments another way than through
+    /// [`load`](StorageRepository::load), e.g. adapter-specific lookups. The
+    /// same rules apply: unencrypted data is returned as is only if plaintext
+    /// is allowed.
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the segment is not encrypted and
+    ///   plaintext is rejected
+    /// - Any error of the key provider or of the envelope if decryption fails
+    pub async fn open(
+        &self,
+        segment_id: &SegmentId,
+        mut stored: StoredSegment,
+    ) -> Result<StoredSegment, IngestionError> {
+        stored.data = self.decrypt(segment_id, stored.data).await?;
+        Ok(stored)
+    }
+}
+
+impl<R: StorageRepository, K: KeyProvider> StorageRepository for EncryptedStorageRepository<R, K> {
+    async fn save(&self, segment: &Segment, data: &[u8]) -> Result<String, IngestionError> {
+        let (segment, sealed) = self.encrypt(segment, data).await?;
+        self.inner.save(&segment, &sealed).await
+    }
+
+    async fn save_if_absent(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> Result<String, IngestionError> {
+        let (segment, sealed) = self.encrypt(segment, data).await?;
+        self.inner.save_if_absent(&segment, &sealed).await
+    }
+
+    fn claim_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send {
+        self.inner.claim_idempotency_key(claim)
+    }
+
+    fn commit_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl Future<Output = Result<(), IngestionError>> + Send {
+        self.inner.commit_idempotency_key(claim)
+    }
+
+    async fn get(&self, segment_id: &SegmentId) -> Result<Vec<u8>, IngestionError> {
+        let data = self.inner.get(segment_id).await?;
+        self.decrypt(segment_id, data).await
+    }
+
+    async fn load(&self, segment_id: &SegmentId) -> Result<StoredSegment, IngestionError> {
+        let stored = self.inner.load(segment_id).await?;
+        self.open(segment_id, stored).await
+    }
+
+    fn exists(
+        &self,
+        segment_id: &SegmentId,
+    ) -> impl Future<Output = Result<bool, IngestionError>> + Send {
+        self.inner.exists(segment_id)
+    }
+
+    fn delete(
+        &self,
+        segment_id: &SegmentId,
+    ) -> impl Future<Output = Result<(), IngestionError>> + Send {
+        self.inner.delete(segment_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::MasterKey;
+    use crate::provider::LocalKeyProvider;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use zuklink_domain::ingestion::service::IngestionService;
+
+    /// Segments as they reached the backend, with their stored bytes
+    type Segments = HashMap<SegmentId, (Segment, Vec<u8>)>;
+
+    /// In-memory storage recording what reaches the backend
+    #[derive(Clone, Default)]
+    struct MemoryRepository {
+        segments: Arc<Mutex<Segments>>,
+        claims: Arc<Mutex<HashMap<String, IdempotencyClaim>>>,
+    }
+
+    impl MemoryRepository {
+        fn stored(&self, segment_id: &SegmentId) -> (Segment, Vec<u8>) {
+            self.segments.lock().unwrap()[segment_id].clone()
+        }
+
+        fn put(&self, segment: &Segment, data: &[u8]) {
+            self.segments
+                .lock()
+                .unwrap()
+                .insert(*segment.id(), (segment.clone(), data.to_vec()));
+        }
+    }
+
+    impl StorageRepository for MemoryRepository {
+        fn save(
+            &self,
+            segment: &Segment,
+            data: &[u8],
+        ) -> impl Future<Output = Result<String, IngestionError>> + Send {
+            self.put(segment, data);
+            let key = format!("{}.zuk", segment.id());
+            async move { Ok(key) }
+        }
+
+        fn save_if_absent(
+            &self,
+            segment: &Segment,
+            data: &[u8],
+        ) -> impl Future<Output = Result<String, IngestionError>> + Send {
+            let result = if self.segments.lock().unwrap().contains_key(segment.id()) {
+                Err(IngestionError::segment_already_exists(segment.id()))
+            } else {
+                self.put(segment, data);
+                Ok(format!("{}.zuk", segment.id()))
+            };
+            async move { result }
+        }
+
+        fn claim_idempotency_key(
+            &self,
+            claim: &IdempotencyClaim,
+        ) -> impl Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send {
+            let mut claims = self.claims.lock().unwrap();
+            let existing = claims.get(&claim.key).cloned();
+            if existing.is_none() {
+                claims.insert(claim.key.clone(), claim.clone());
+            }
+            async move { Ok(existing) }
+        }
+
+        fn commit_idempotency_key(
+            &self,
+            claim: &IdempotencyClaim,
+        ) -> impl Future<Output = Result<(), IngestionError>> + Send {
+            self.claims
+                .lock()
+                .unwrap()
+                .insert(claim.key.clone(), claim.clone());
+            async { Ok(()) }
+        }
+
+        fn get(
+            &self,
+            segment_id: &SegmentId,
+        ) -> impl Future<Output = Result<Vec<u8>, IngestionError>> + Send {
+            let data = self.stored(segment_id).1;
+            async move { Ok(data) }
+        }
+
+        fn load(
+            &self,
+            segment_id: &SegmentId,
+        ) -> impl Future<Output = Result<StoredSegment, IngestionError>> + Send {
+            let (segment, data) = self.stored(segment_id);
+            async move {
+                Ok(StoredSegment::new(data, segment.checksum().copied())
+                    .with_compression(segment.compression()))
+            }
+        }
+
+        fn exists(
+            &self,
+            segment_id: &SegmentId,
+        ) -> impl Future<Output = Result<bool, IngestionError>> + Send {
+            let exists = self.segments.lock().unwrap().contains_key(segment_id);
+            async move { Ok(exists) }
+        }
+
+        fn delete(
+            &self,
+            segment_id: &SegmentId,
+        ) -> impl Future<Output = Result<(), IngestionError>> + Send {
+            self.segments.lock().unwrap().remove(segment_id);
+            async { Ok(()) }
+        }
+    }
+
+    fn keys(ids: &[&str]) -> (Vec<MasterKey>, LocalKeyProvider) {
+        let keys: Vec<MasterKey> = ids
+            .iter()
+            .map(|id| MasterKey::generate(*id).unwrap())
+            .collect();
+        let provider = LocalKeyProvider::new(keys.clone()).unwrap();
+        (keys, provider)
+    }
+
+    #[tokio::test]
+    async fn test_data_is_encrypted_at_rest() {
+        let backend = MemoryRepository::default();
+        let (_, provider) = keys(&["2024-06"]);
+        let repository = EncryptedStorageRepository::new(backend.clone(), provider);
+
+        let segment = Segment::new(b"personal data".to_vec());
+        repository.save(&segment, b"personal data").await.unwrap();
+
+        let (stored_segment, stored_data) = backend.stored(segment.id());
+        assert_eq!(stored_segment.encryption_key_id(), Some("2024-06"));
+        assert!(envelope::is_envelope(&stored_data));
+        assert!(!stored_data
+            .windows(b"personal data".len())
+            .any(|window| window == b"personal data"));
+
+        assert_eq!(
+            repository.get(segment.id()).await.unwrap(),
+            b"personal data"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_service_roundtrip_verifies_checksum() {
+        let backend = MemoryRepository::default();
+        let (_, provider) = keys(&["2024-06"]);
+        let service =
+            IngestionService::with_repository(EncryptedStorageRepository::new(backend, provider));
+
+        let segment_id = service.ingest_data(b"payload".to_vec()).await.unwrap();
+
+        assert_eq!(
+            service.get_segment_data(&segment_id).await.unwrap(),
+            b"payload"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_if_absent_is_preserved() {
+        let backend = MemoryRepository::default();
+        let (_, provider) = keys(&["2024-06"]);
+        let repository = EncryptedStorageRepository::new(backend, provider);
+
+        let segment = Segment::new(vec![1, 2, 3]);
+        repository
+            .save_if_absent(&segment, &[1, 2, 3])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            repository.save_if_absent(&segment, &[1, 2, 3]).await,
+            Err(IngestionError::SegmentAlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rotated_keys_still_decrypt() {
+        let backend = MemoryRepository::default();
+        let (old_keys, old_provider) = keys(&["2024-01"]);
+
+        let segment = Segment::new(vec![1, 2, 3]);
+        EncryptedStorageRepository::new(backend.clone(), old_provider)
+            .save(&segment, &[1, 2, 3])
+            .await
+            .unwrap();
+
+        // New primary key, old key kept for reads
+        let new_key = MasterKey::generate("2024-06").unwrap();
+        let rotated = LocalKeyProvider::new(vec![new_key, old_keys[0].clone()]).unwrap();
+        let repository = EncryptedStorageRepository::new(backend.clone(), rotated);
+
+        assert_eq!(repository.get(segment.id()).await.unwrap(), vec![1, 2, 3]);
+
+        let newer = Segment::new(vec![4, 5, 6]);
+        repository.save(&newer, &[4, 5, 6]).await.unwrap();
+        assert_eq!(
+            backend.stored(newer.id()).0.encryption_key_id(),
+            Some("2024-06")
+        );
+
+        // Without the old key, old segments can no longer be read
+        let (_, forgotten) = keys(&["2024-06"]);
+        let result = EncryptedStorageRepository::new(backend, forgotten)
+            .get(segment.id())
+            .await;
+        assert!(matches!(result, Err(IngestionError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_tampered_data_rejected() {
+        let backend = MemoryRepository::default();
+        let (_, provider) = keys(&["2024-06"]);
+        let repository = EncryptedStorageRepository::new(backend.clone(), provider);
+
+        let segment = Segment::new(vec![1, 2, 3]);
+        repository.save(&segment, &[1, 2, 3]).await.unwrap();
+
+        let (stored_segment, mut stored_data) = backend.stored(segment.id());
+        let last = stored_data.len() - 1;
+        stored_data[last] ^= 0x01;
+        backend.put(&stored_segment, &stored_data);
+
+        assert!(matches!(
+            repository.load(segment.id()).await,
+            Err(IngestionError::InvalidData(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_segments() {
+        let backend = MemoryRepository::default();
+        let legacy = Segment::new(vec![1, 2, 3]);
+        backend.put(&legacy, &[1, 2, 3]);
+
+        let (_, provider) = keys(&["2024-06"]);
+        let repository = EncryptedStorageRepository::new(backend, provider);
+        assert_eq!(repository.get(legacy.id()).await.unwrap(), vec![1, 2, 3]);
+
+        let strict = repository.reject_plaintext();
+        assert!(matches!(
+            strict.get(legacy.id()).await,
+            Err(IngestionError::InvalidData(_))
+        ));
+
+        // Segments loaded another way follow the same rule
+        let loaded = StoredSegment::new(vec![1, 2, 3], None);
+        assert!(matches!(
+            strict.open(legacy.id(), loaded).await,
+            Err(IngestionError::InvalidData(_))
+        ));
+    }
+}
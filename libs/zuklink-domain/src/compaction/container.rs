@@ -0,0 +1,475 @@
+//! Framed container of compacted segments
+//!
+//! A container holds the payloads of several segments, in order, each in a
+//! frame carrying the metadata the segment had as a standalone object:
+//!
+//! ```text
+//! +--------+-------------+---------+---------+-----+
+//! | "ZKC3" | count (u32) | frame 1 | frame 2 | ... |
+//! +--------+-------------+---------+---------+-----+
+//! ```
+//!
+//! Each frame is made of:
+//!
+//! 1. Segment id (16 bytes)
+//! 2. Codec name, prefixed with its length (u8)
+//! 3. Checksum flag (u8), followed by the SHA-256 checksum (32 bytes) if set
+//! 4. Encryption key id, prefixed with its length (u8), empty if unencrypted
+//! 5. Record flag (u8), followed for keyed segments by the record key,
+//!    prefixed with its length (u16), the creation time in milliseconds since
+//!    the epoch (i64) and the tombstone flag (u8)
+//! 6. Partition flag (u8), followed for partitioned segments by the partition
+//!    (u32)
+//! 7. Payload, prefixed with its length (u64)
+//!
+//! `ZKC2` containers, written before partitioned segments, have no partition
+//! flag, and `ZKC1` containers, written before keyed segments, no record flag
+//! either: both are still read. Integers are big-endian. Payloads are copied
+//! as stored (compressed and encrypted bytes are not touched), so each one can
+//! be read on its own with a range request at the offset recorded in its
+//! [`IndexEntry`].
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::ingestion::{
+    checksum::Checksum, compression::Compression, error::IngestionError, ids::SegmentId,
+    keyed::KeyedRecord, partition::PartitionId,
+};
+
+/// Magic bytes at the start of every container
+pub const MAGIC: &[u8; 4] = b"ZKC3";
+
+/// Magic bytes of containers written without partitions
+const MAGIC_V2: &[u8; 4] = b"ZKC2";
+
+/// Magic bytes of containers written without record keys
+const MAGIC_V1: &[u8; 4] = b"ZKC1";
+
+/// A segment stored in a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Original identifier of the segment
+    pub segment_id: SegmentId,
+    /// Codec the payload is compressed with
+    pub compression: Compression,
+    /// Checksum of the uncompressed data, if known
+    pub checksum: Option<Checksum>,
+    /// Master key the payload is encrypted with, if any
+    pub encryption_key_id: Option<String>,
+    /// Record key, for keyed segments
+    pub record: Option<KeyedRecord>,
+    /// Partition, for partitioned segments
+    pub partition: Option<PartitionId>,
+    /// The payload, as stored
+    pub data: Vec<u8>,
+}
+
+/// Location of a compacted segment
+///
+/// Written for every frame of a container, so a segment can be resolved by
+/// its original id once its standalone object is gone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Original identifier of the segment
+    pub segment_id: SegmentId,
+    /// Container holding the payload
+    pub container: SegmentId,
+    /// Offset of the payload in the container
+    pub offset: u64,
+    /// Length of the payload
+    pub length: u64,
+    /// Codec the payload is compressed with
+    #[serde(default)]
+    pub compression: Compression,
+    /// Checksum of the uncompressed data, if known
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+    /// Master key the payload is encrypted with, if any
+    #[serde(default)]
+    pub encryption_key_id: Option<String>,
+    /// Record key, for keyed segments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<KeyedRecord>,
+    /// Partition, for partitioned segments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<PartitionId>,
+    /// Topic of the segment, like the `topic` metadata of its container
+    ///
+    /// Missing from entries written before topics were indexed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+}
+
+/// Encode frames into a container
+///
+/// Returns the container and the index entry of every frame, in order. The
+/// entries have no topic: the caller sets the topic of the container.
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if a key id is longer than 255 bytes,
+/// a record key longer than 65535 bytes or there are more than `u32::MAX`
+/// frames
+pub fn encode(
+    container: SegmentId,
+    frames: &[Frame],
+) -> Result<(Vec<u8>, Vec<IndexEntry>), IngestionError> {
+    let count = u32::try_from(frames.len())
+        .map_err(|_| IngestionError::invalid_data("Too many frames in container"))?;
+    let size: usize = frames.iter().map(|frame| frame.data.len() + 128).sum();
+
+    let mut out = Vec::with_capacity(8 + size);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&count.to_be_bytes());
+
+    let mut entries = Vec::with_capacity(frames.len());
+    for frame in frames {
+        out.extend_from_slice(frame.segment_id.as_uuid().as_bytes());
+        write_short(&mut out, frame.compression.name().as_bytes(), "codec")?;
+        match &frame.checksum {
+            Some(checksum) => {
+                out.push(1);
+                out.extend_from_slice(checksum.as_bytes());
+            }
+            None => out.push(0),
+        }
+        let key_id = frame.encryption_key_id.as_deref().unwrap_or_default();
+        write_short(&mut out, key_id.as_bytes(), "key id")?;
+        match &frame.record {
+            Some(record) => {
+                out.push(1);
+                let len = u16::try_from(record.key.len()).map_err(|_| {
+                    IngestionError::invalid_data("Container record key longer than 65535 bytes")
+                })?;
+                out.extend_from_slice(&len.to_be_bytes());
+                out.extend_from_slice(record.key.as_bytes());
+                out.extend_from_slice(&record.created_at.timestamp_millis().to_be_bytes());
+                out.push(u8::from(record.tombstone));
+            }
+            None => out.push(0),
+        }
+        match frame.partition {
+            Some(partition) => {
+                out.push(1);
+                out.extend_from_slice(&partition.to_be_bytes());
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(&(frame.data.len() as u64).to_be_bytes());
+
+        entries.push(IndexEntry {
+            segment_id: frame.segment_id,
+            container,
+            offset: out.len() as u64,
+            length: frame.data.len() as u64,
+            compression: frame.compression,
+            checksum: frame.checksum,
+            encryption_key_id: frame.encryption_key_id.clone(),
+            record: frame.record.clone(),
+            partition: frame.partition,
+            topic: None,
+        });
+        out.extend_from_slice(&frame.data);
+    }
+
+    Ok((out, entries))
+}
+
+/// Decode every frame of a container
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the container is malformed
+pub fn decode(data: &[u8]) -> Result<Vec<Frame>, IngestionError> {
+    let mut reader = Reader { data, pos: 0 };
+
+    let (keyed, partitioned) = match reader.take(MAGIC.len())? {
+        magic if magic == MAGIC => (true, true),
+        magic if magic == MAGIC_V2 => (true, false),
+        magic if magic == MAGIC_V1 => (false, false),
+        _ => return Err(IngestionError::invalid_data("Not a segment container")),
+    };
+    let count = u32::from_be_bytes(reader.array()?);
+
+    let mut frames = Vec::new();
+    for _ in 0..count {
+        let segment_id = SegmentId::from_uuid(Uuid::from_bytes(reader.array()?));
+
+        let codec = reader.short()?;
+        let compression = std::str::from_utf8(codec)
+            .ok()
+            .and_then(|name| name.parse().ok())
+            .ok_or_else(|| invalid_frame(&segment_id, "unknown codec"))?;
+
+        let checksum = match reader.take(1)?[0] {
+            0 => None,
+            1 => Some(Checksum::from_bytes(reader.array()?)),
+            _ => return Err(invalid_frame(&segment_id, "invalid checksum flag")),
+        };
+
+        let key_id = reader.short()?;
+        let encryption_key_id = match key_id {
+            [] => None,
+            key_id => Some(
+                String::from_utf8(key_id.to_vec())
+                    .map_err(|_| invalid_frame(&segment_id, "key id is not UTF-8"))?,
+            ),
+        };
+
+        let record = match keyed.then(|| reader.take(1)).transpose()? {
+            None | Some([0]) => None,
+            Some([1]) => {
+                let len = u16::from_be_bytes(reader.array()?) as usize;
+                let key = String::from_utf8(reader.take(len)?.to_vec())
+                    .map_err(|_| invalid_frame(&segment_id, "record key is not UTF-8"))?;
+                let created_at =
+                    DateTime::from_timestamp_millis(i64::from_be_bytes(reader.array()?))
+                        .ok_or_else(|| invalid_frame(&segment_id, "invalid creation time"))?;
+                let tombstone = match reader.take(1)? {
+                    [0] => false,
+                    [1] => true,
+                    _ => return Err(invalid_frame(&segment_id, "invalid tombstone flag")),
+                };
+                Some(KeyedRecord {
+                    key,
+                    created_at,
+                    tombstone,
+                })
+            }
+            Some(_) => return Err(invalid_frame(&segment_id, "invalid record flag")),
+        };
+
+        let partition = match partitioned.then(|| reader.take(1)).transpose()? {
+            None | Some([0]) => None,
+            Some([1]) => Some(PartitionId::from_be_bytes(reader.array()?)),
+            Some(_) => return Err(invalid_frame(&segment_id, "invalid partition flag")),
+        };
+
+        let len = usize::try_from(u64::from_be_bytes(reader.array()?))
+            .map_err(|_| invalid_frame(&segment_id, "payload too large"))?;
+        let data = reader.take(len)?.to_vec();
+
+        frames.push(Frame {
+            segment_id,
+            compression,
+            checksum,
+            encryption_key_id,
+            record,
+            partition,
+            data,
+        });
+    }
+
+    if reader.pos != data.len() {
+        return Err(IngestionError::invalid_data(
+            "Trailing bytes after the last frame of the container",
+        ));
+    }
+
+    Ok(frames)
+}
+
+/// Write a field prefixed with its length on one byte
+fn write_short(out: &mut Vec<u8>, field: &[u8], name: &str) -> Result<(), IngestionError> {
+    let len = u8::try_from(field.len()).map_err(|_| {
+        IngestionError::invalid_data(format!("Container {} longer than 255 bytes", name))
+    })?;
+    out.push(len);
+    out.extend_from_slice(field);
+    Ok(())
+}
+
+fn invalid_frame(segment_id: &SegmentId, reason: &str) -> IngestionError {
+    IngestionError::invalid_data(format!(
+        "Invalid container frame for segment {}: {}",
+        segment_id, reason
+    ))
+}
+
+/// Bounds-checked cursor over a container
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], IngestionError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| IngestionError::invalid_data("Truncated segment container"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], IngestionError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+
+    fn short(&mut self) -> Result<&'a [u8], IngestionError> {
+        let len = self.take(1)?[0] as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(data: &[u8], compression: Compression, key_id: Option<&str>) -> Frame {
+        Frame {
+            segment_id: SegmentId::new(),
+            compression,
+            checksum: Some(Checksum::sha256(data)),
+            encryption_key_id: key_id.map(String::from),
+            record: None,
+            partition: None,
+            data: data.to_vec(),
+        }
+    }
+
+    fn keyed(frame: Frame, key: &str, tombstone: bool) -> Frame {
+        Frame {
+            record: Some(KeyedRecord {
+                key: key.to_string(),
+                created_at: DateTime::from_timestamp_millis(1_717_200_000_123).unwrap(),
+                tombstone,
+            }),
+            ..frame
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_keeps_order_and_metadata() {
+        let frames = vec![
+            frame(b"first", Compression::None, None),
+            frame(b"second", Compression::Gzip, Some("2024-06")),
+            Frame {
+                checksum: None,
+                ..frame(b"legacy", Compression::None, None)
+            },
+            keyed(frame(b"value", Compression::None, None), "user-42", false),
+            keyed(frame(b"", Compression::None, None), "user-42", true),
+            Frame {
+                partition: Some(7),
+                ..frame(b"partitioned", Compression::None, None)
+            },
+        ];
+
+        let (container, _) = encode(SegmentId::new(), &frames).unwrap();
+
+        assert!(container.starts_with(MAGIC));
+        assert_eq!(decode(&container).unwrap(), frames);
+    }
+
+    #[test]
+    fn test_index_entries_point_at_payloads() {
+        let container_id = SegmentId::new();
+        let frames = vec![
+            frame(b"first", Compression::None, None),
+            frame(b"second payload", Compression::Gzip, Some("2024-06")),
+        ];
+
+        let (container, entries) = encode(container_id, &frames).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        for (entry, frame) in entries.iter().zip(&frames) {
+            let start = entry.offset as usize;
+            let end = start + entry.length as usize;
+
+            assert_eq!(entry.segment_id, frame.segment_id);
+            assert_eq!(entry.container, container_id);
+            assert_eq!(&container[start..end], frame.data.as_slice());
+            assert_eq!(entry.compression, frame.compression);
+            assert_eq!(entry.checksum, frame.checksum);
+            assert_eq!(entry.encryption_key_id, frame.encryption_key_id);
+        }
+    }
+
+    #[test]
+    fn test_empty_container() {
+        let (container, entries) = encode(SegmentId::new(), &[]).unwrap();
+
+        assert!(entries.is_empty());
+        assert!(decode(&container).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed_containers_are_rejected() {
+        let (container, _) = encode(
+            SegmentId::new(),
+            &[frame(b"payload", Compression::None, None)],
+        )
+        .unwrap();
+
+        assert!(decode(b"ZKE1\0\0\0\0").is_err());
+        assert!(decode(&container[..container.len() - 1]).is_err());
+        assert!(decode(&[container.as_slice(), b"x"].concat()).is_err());
+
+        // Frame count larger than the content
+        let mut corrupted = container.clone();
+        corrupted[7] = 2;
+        assert!(decode(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_reads_containers_without_record_keys() {
+        let legacy = frame(b"payload", Compression::None, None);
+        let (mut container, _) = encode(SegmentId::new(), std::slice::from_ref(&legacy)).unwrap();
+
+        // Same frame in the ZKC1 layout: no record nor partition flag
+        container[..4].copy_from_slice(MAGIC_V1);
+        let record_flag = container.len() - legacy.data.len() - 10;
+        assert_eq!(container.remove(record_flag), 0);
+        assert_eq!(container.remove(record_flag), 0);
+
+        assert_eq!(decode(&container).unwrap(), vec![legacy]);
+    }
+
+    #[test]
+    fn test_reads_containers_without_partitions() {
+        let legacy = keyed(frame(b"payload", Compression::None, None), "user-42", false);
+        let (mut container, _) = encode(SegmentId::new(), std::slice::from_ref(&legacy)).unwrap();
+
+        // Same frame in the ZKC2 layout: no partition flag
+        container[..4].copy_from_slice(MAGIC_V2);
+        let partition_flag = container.len() - legacy.data.len() - 9;
+        assert_eq!(container.remove(partition_flag), 0);
+
+        assert_eq!(decode(&container).unwrap(), vec![legacy]);
+    }
+
+    #[test]
+    fn test_key_id_too_long() {
+        let frames = vec![frame(b"data", Compression::None, Some(&"k".repeat(256)))];
+
+        assert!(encode(SegmentId::new(), &frames).is_err());
+    }
+
+    #[test]
+    fn test_index_entry_serialization() {
+        let entry = IndexEntry {
+            segment_id: SegmentId::new(),
+            container: SegmentId::new(),
+            offset: 42,
+            length: 7,
+            compression: Compression::Gzip,
+            checksum: Some(Checksum::sha256(b"data")),
+            encryption_key_id: None,
+            record: None,
+            partition: Some(3),
+            topic: Some("billing".to_string()),
+        };
+
+        let json = serde_json::to_string(&entry).unwrap();
+
+        assert!(json.contains(r#""compression":"gzip""#));
+        assert_eq!(serde_json::from_str::<IndexEntry>(&json).unwrap(), entry);
+    }
+}
@@ -0,0 +1,262 @@
+//! Key compaction planning
+//!
+//! Keyed segments are versions of their record key within their topic: the
+//! same key under two topics names two different records. Key compaction
+//! keeps the newest version of every key and removes the older ones. A key whose
+//! newest version is a tombstone is deleted altogether once the tombstone is
+//! older than a grace period, long enough for consumers to see the delete.
+//!
+//! Unkeyed segments are never removed by key compaction.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::ingestion::{ids::SegmentId, keyed::KeyedRecord};
+
+/// A keyed segment, as listed from the storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedSegment {
+    /// Identifier of the segment
+    pub id: SegmentId,
+    /// Topic of the segment, if any
+    pub topic: Option<String>,
+    /// Record key of the segment
+    pub record: KeyedRecord,
+}
+
+/// Why key compaction removes a segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    /// A newer version of the key exists
+    Superseded,
+    /// The newest version is a tombstone older than the grace period
+    TombstoneExpired,
+}
+
+impl RemovalReason {
+    /// Name of the reason, as written in logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Superseded => "superseded",
+            Self::TombstoneExpired => "tombstone_expired",
+        }
+    }
+}
+
+impl fmt::Display for RemovalReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A keyed segment selected for removal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removed {
+    /// The removed segment
+    pub segment: KeyedSegment,
+    /// Why it is removed
+    pub reason: RemovalReason,
+}
+
+/// Rules of key compaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyCompactionPolicy {
+    /// How long a tombstone is kept as the newest version of its key
+    /// (default: 24 hours)
+    pub tombstone_grace: Duration,
+}
+
+impl Default for KeyCompactionPolicy {
+    fn default() -> Self {
+        Self {
+            tombstone_grace: Duration::from_secs(24 * 3600),
+        }
+    }
+}
+
+impl KeyCompactionPolicy {
+    /// Select the segments to remove, ordered by topic, key then creation time
+    ///
+    /// Versions of a key are grouped by topic and ordered by creation time,
+    /// then segment id to break ties.
+    pub fn select(&self, segments: &[KeyedSegment], now: DateTime<Utc>) -> Vec<Removed> {
+        let mut keys: HashMap<(Option<&str>, &str), Vec<&KeyedSegment>> = HashMap::new();
+        for segment in segments {
+            keys.entry((segment.topic.as_deref(), &segment.record.key))
+                .or_default()
+                .push(segment);
+        }
+
+        let mut keys: Vec<_> = keys.into_iter().collect();
+        keys.sort_by_key(|(key, _)| *key);
+
+        let mut removed = Vec::new();
+        for (_, mut versions) in keys {
+            versions.sort_by(|a, b| {
+                (a.record.created_at, a.id.as_uuid()).cmp(&(b.record.created_at, b.id.as_uuid()))
+            });
+            let Some((newest, older)) = versions.split_last() else {
+                continue;
+            };
+
+            removed.extend(older.iter().map(|segment| Removed {
+                segment: (*segment).clone(),
+                reason: RemovalReason::Superseded,
+            }));
+
+            let expired = newest.record.tombstone
+                && now
+                    .signed_duration_since(newest.record.created_at)
+                    .to_std()
+                    .is_ok_and(|age| age > self.tombstone_grace);
+            if expired {
+                removed.push(Removed {
+                    segment: (*newest).clone(),
+                    reason: RemovalReason::TombstoneExpired,
+                });
+            }
+        }
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, hour, 0, 0).unwrap()
+    }
+
+    fn version(key: &str, hour: u32, tombstone: bool) -> KeyedSegment {
+        version_in(None, key, hour, tombstone)
+    }
+
+    fn version_in(topic: Option<&str>, key: &str, hour: u32, tombstone: bool) -> KeyedSegment {
+        KeyedSegment {
+            id: SegmentId::new(),
+            topic: topic.map(String::from),
+            record: KeyedRecord {
+                key: key.to_string(),
+                created_at: at(hour),
+                tombstone,
+            },
+        }
+    }
+
+    fn removed(removed: &[Removed]) -> Vec<(&str, DateTime<Utc>, RemovalReason)> {
+        removed
+            .iter()
+            .map(|r| {
+                (
+                    r.segment.record.key.as_str(),
+                    r.segment.record.created_at,
+                    r.reason,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keeps_newest_version_per_key() {
+        let policy = KeyCompactionPolicy::default();
+        let segments = vec![
+            version("b", 3, false),
+            version("a", 2, false),
+            version("a", 5, false),
+            version("a", 1, false),
+            version("c", 1, false),
+        ];
+
+        let selected = policy.select(&segments, at(6));
+
+        assert_eq!(
+            removed(&selected),
+            vec![
+                ("a", at(1), RemovalReason::Superseded),
+                ("a", at(2), RemovalReason::Superseded),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keys_are_scoped_per_topic() {
+        let policy = KeyCompactionPolicy::default();
+        let billing = version_in(Some("billing"), "user-42", 3, false);
+        let audit = version_in(Some("audit"), "user-42", 2, false);
+        let segments = vec![
+            version_in(Some("billing"), "user-42", 1, false),
+            billing.clone(),
+            audit.clone(),
+            version_in(None, "user-42", 4, false),
+        ];
+
+        let selected = policy.select(&segments, at(6));
+
+        // Only the older billing version goes, the latest of each topic stays
+        assert_eq!(
+            removed(&selected),
+            vec![("user-42", at(1), RemovalReason::Superseded)]
+        );
+        assert_eq!(selected[0].segment.topic.as_deref(), Some("billing"));
+        assert!(selected
+            .iter()
+            .all(|r| r.segment.id != billing.id && r.segment.id != audit.id));
+    }
+
+    #[test]
+    fn test_tombstones_expire_after_grace_period() {
+        let policy = KeyCompactionPolicy {
+            tombstone_grace: Duration::from_secs(2 * 3600),
+        };
+        let segments = vec![
+            version("a", 1, false),
+            version("a", 2, true),
+            version("b", 1, false),
+            version("b", 5, true),
+        ];
+
+        let selected = policy.select(&segments, at(6));
+
+        assert_eq!(
+            removed(&selected),
+            vec![
+                ("a", at(1), RemovalReason::Superseded),
+                ("a", at(2), RemovalReason::TombstoneExpired),
+                ("b", at(1), RemovalReason::Superseded),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_value_after_tombstone_survives() {
+        let policy = KeyCompactionPolicy::default();
+        let segments = vec![version("a", 1, true), version("a", 2, false)];
+
+        let selected = policy.select(&segments, Utc::now());
+
+        assert_eq!(
+            removed(&selected),
+            vec![("a", at(1), RemovalReason::Superseded)]
+        );
+    }
+
+    #[test]
+    fn test_ties_are_broken_by_id() {
+        let policy = KeyCompactionPolicy::default();
+        let first = version("a", 1, false);
+        let second = version("a", 1, false);
+
+        let selected = policy.select(&[second.clone(), first.clone()], at(2));
+
+        // UUID v7 ids generated later sort after
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].segment.id, first.id);
+    }
+}
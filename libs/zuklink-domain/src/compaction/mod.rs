@@ -0,0 +1,13 @@
+//! Compaction domain module
+//!
+//! Every ingest request produces its own segment, so slow producers leave
+//! many tiny objects behind. Compaction merges small segments into a single
+//! [`container`] and records, for every original segment, where its payload
+//! now lives, so segments stay readable under their original `SegmentId`.
+//!
+//! Key compaction ([`keyed`]) removes the older versions of keyed segments,
+//! keeping only the latest value of every key.
+
+pub mod container;
+pub mod keyed;
+pub mod policy;
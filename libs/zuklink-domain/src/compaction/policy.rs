@@ -0,0 +1,175 @@
+//! Compaction planning
+//!
+//! Decides which small segments are merged together. Segments are grouped
+//! by time window, so a container only spans a bounded period, and kept in
+//! storage order within a container.
+
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+use crate::retention::policy::SegmentInfo;
+
+/// Rules deciding which segments are compacted together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Segments smaller than this are compacted (default: 1 MiB)
+    pub small_segment_bytes: u64,
+    /// Maximum size of a container (default: 64 MiB)
+    pub target_bytes: u64,
+    /// Only segments stored in the same window are merged (default: 1 hour)
+    pub window: Duration,
+    /// Segments younger than this are left alone, so consumers see them
+    /// first as standalone objects (default: 1 hour)
+    pub min_age: Duration,
+    /// Smallest number of segments worth a container (default: 2)
+    pub min_segments: usize,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            small_segment_bytes: 1024 * 1024,
+            target_bytes: 64 * 1024 * 1024,
+            window: Duration::from_secs(3600),
+            min_age: Duration::from_secs(3600),
+            min_segments: 2,
+        }
+    }
+}
+
+impl CompactionPolicy {
+    /// Group the segments to compact, each group making one container
+    ///
+    /// Groups are ordered by time, and segments within a group by storage
+    /// time then id.
+    pub fn plan(&self, segments: &[SegmentInfo], now: DateTime<Utc>) -> Vec<Vec<SegmentInfo>> {
+        let window_secs = self.window.as_secs().max(1) as i64;
+
+        let mut candidates: Vec<&SegmentInfo> = segments
+            .iter()
+            .filter(|segment| segment.size_bytes < self.small_segment_bytes)
+            .filter(|segment| {
+                now.signed_duration_since(segment.last_modified)
+                    .to_std()
+                    .is_ok_and(|age| age >= self.min_age)
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            (a.last_modified, a.id.as_uuid()).cmp(&(b.last_modified, b.id.as_uuid()))
+        });
+
+        let mut groups: Vec<Vec<SegmentInfo>> = Vec::new();
+        let mut current: Vec<SegmentInfo> = Vec::new();
+        let mut current_window = None;
+        let mut current_bytes = 0;
+
+        for segment in candidates {
+            let window = segment.last_modified.timestamp().div_euclid(window_secs);
+            let full = current_bytes + segment.size_bytes > self.target_bytes;
+
+            if current_window != Some(window) || full {
+                groups.push(std::mem::take(&mut current));
+                current_window = Some(window);
+                current_bytes = 0;
+            }
+            current_bytes += segment.size_bytes;
+            current.push(segment.clone());
+        }
+        groups.push(current);
+
+        groups.retain(|group| group.len() >= self.min_segments.max(1));
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingestion::ids::SegmentId;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, hour, minute, 0).unwrap()
+    }
+
+    fn segment(hour: u32, minute: u32, size_bytes: u64) -> SegmentInfo {
+        SegmentInfo {
+            id: SegmentId::new(),
+            size_bytes,
+            last_modified: at(hour, minute),
+            topic: None,
+        }
+    }
+
+    fn times(groups: &[Vec<SegmentInfo>]) -> Vec<Vec<DateTime<Utc>>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|s| s.last_modified).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_groups_by_window_in_order() {
+        let policy = CompactionPolicy::default();
+        let segments = vec![
+            segment(1, 30, 10),
+            segment(1, 10, 10),
+            segment(2, 5, 10),
+            segment(2, 50, 10),
+        ];
+
+        let groups = policy.plan(&segments, at(12, 0));
+
+        assert_eq!(
+            times(&groups),
+            vec![vec![at(1, 10), at(1, 30)], vec![at(2, 5), at(2, 50)]]
+        );
+    }
+
+    #[test]
+    fn test_skips_large_and_recent_segments() {
+        let policy = CompactionPolicy::default();
+        let segments = vec![
+            segment(1, 0, 10),
+            segment(1, 10, 2 * 1024 * 1024),
+            segment(1, 20, 10),
+            segment(11, 30, 10),
+            segment(11, 40, 10),
+        ];
+
+        let groups = policy.plan(&segments, at(12, 0));
+
+        assert_eq!(times(&groups), vec![vec![at(1, 0), at(1, 20)]]);
+    }
+
+    #[test]
+    fn test_splits_at_target_size() {
+        let policy = CompactionPolicy {
+            target_bytes: 25,
+            ..CompactionPolicy::default()
+        };
+        let segments = vec![
+            segment(1, 0, 10),
+            segment(1, 1, 10),
+            segment(1, 2, 10),
+            segment(1, 3, 10),
+            segment(1, 4, 10),
+        ];
+
+        let groups = policy.plan(&segments, at(12, 0));
+
+        assert_eq!(
+            times(&groups),
+            vec![vec![at(1, 0), at(1, 1)], vec![at(1, 2), at(1, 3)]]
+        );
+    }
+
+    #[test]
+    fn test_single_segments_are_left_alone() {
+        let policy = CompactionPolicy::default();
+        let segments = vec![segment(1, 0, 10), segment(3, 0, 10)];
+
+        assert!(policy.plan(&segments, at(12, 0)).is_empty());
+        assert!(policy.plan(&[], at(12, 0)).is_empty());
+    }
+}
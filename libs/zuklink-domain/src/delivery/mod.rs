@@ -0,0 +1,11 @@
+//! Delivery domain module
+//!
+//! Receivers deliver segments at least once: after a rebalance or a failure,
+//! a segment may be processed twice. For outputs written to idempotent
+//! targets, the exactly-once mode tags every output batch with its origin, an
+//! [`output::OutputTag`] (group, partition, segment ID, record offset), and
+//! stores the batch under a name derived from the tag. Committing the batch
+//! atomically under that name also records that the batch was delivered: a
+//! duplicate finds it committed and is dropped, invisible downstream.
+
+pub mod output;
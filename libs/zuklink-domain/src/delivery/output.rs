@@ -0,0 +1,184 @@
+//! Tagged output batches
+//!
+//! An output batch is what a processor writes for a range of records of a
+//! segment. Its [`OutputTag`] names it deterministically: processing the same
+//! records again, on the same node or another one, yields the same name. An
+//! [`OutputCommitter`] makes a batch visible atomically under that name, so
+//! the output and the record of its delivery are committed together.
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use crate::ingestion::{error::IngestionError, ids::SegmentId, partition::PartitionId};
+
+/// Maximum length of a group name in bytes
+pub const MAX_GROUP_LEN: usize = 128;
+
+/// Origin of an output batch
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OutputTag {
+    /// Consumer group writing the output
+    pub group: String,
+    /// Partition of the segment, if it was ingested with a partition key
+    pub partition: Option<PartitionId>,
+    /// Segment the batch was produced from
+    pub segment_id: SegmentId,
+    /// Offset of the first record of the batch in the segment
+    pub offset: u64,
+}
+
+impl OutputTag {
+    /// Tag of the batch starting at record `offset` of a segment
+    pub fn new(
+        group: impl Into<String>,
+        partition: Option<PartitionId>,
+        segment_id: SegmentId,
+        offset: u64,
+    ) -> Self {
+        Self {
+            group: group.into(),
+            partition,
+            segment_id,
+            offset,
+        }
+    }
+
+    /// Name of the batch, relative to the output root
+    ///
+    /// `<group>/<segment-id>-<offset>.out`, or
+    /// `<group>/<segment-id>.p<partition>-<offset>.out` for partitioned
+    /// segments.
+    pub fn name(&self) -> String {
+        match self.partition {
+            Some(partition) => format!(
+                "{}/{}.p{}-{}.out",
+                self.group, self.segment_id, partition, self.offset
+            ),
+            None => format!("{}/{}-{}.out", self.group, self.segment_id, self.offset),
+        }
+    }
+
+    /// Tag as object metadata, for outputs supporting it
+    pub fn metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::from([
+            ("group".to_string(), self.group.clone()),
+            ("segment-id".to_string(), self.segment_id.to_string()),
+            ("offset".to_string(), self.offset.to_string()),
+        ]);
+        if let Some(partition) = self.partition {
+            metadata.insert("partition".to_string(), partition.to_string());
+        }
+        metadata
+    }
+}
+
+/// Check that a group name is usable in output names
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the name is empty, longer than
+/// [`MAX_GROUP_LEN`] bytes, or holds anything but ASCII letters, digits,
+/// `-`, `_` and `.` (it must not start with a `.`)
+pub fn validate_group(group: &str) -> Result<(), IngestionError> {
+    if group.is_empty() {
+        return Err(IngestionError::invalid_data("Group name cannot be empty"));
+    }
+    if group.len() > MAX_GROUP_LEN {
+        return Err(IngestionError::invalid_data(format!(
+            "Group name length ({}) exceeds maximum ({})",
+            group.len(),
+            MAX_GROUP_LEN
+        )));
+    }
+    if group.starts_with('.')
+        || !group
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(IngestionError::invalid_data(format!(
+            "Invalid group name '{}'",
+            group
+        )));
+    }
+    Ok(())
+}
+
+/// Outcome of a commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitOutcome {
+    /// The batch is now visible
+    Committed,
+    /// A batch with the same tag was committed already, this one was dropped
+    Duplicate,
+}
+
+/// Port for committing output batches
+///
+/// In exactly-once mode, implementations make a batch visible atomically
+/// under the name of its tag, and never replace a committed batch: readers
+/// never see a partial batch nor a batch twice.
+pub trait OutputCommitter: Send + Sync {
+    /// Commit one output batch
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the batch cannot be written.
+    /// Nothing is visible then, and the batch can be committed again.
+    fn commit(
+        &self,
+        tag: &OutputTag,
+        data: &[u8],
+    ) -> impl Future<Output = Result<CommitOutcome, IngestionError>> + Send;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_names() {
+        let segment_id = SegmentId::new();
+        let tag = OutputTag::new("billing", None, segment_id, 0);
+        assert_eq!(tag.name(), format!("billing/{}-0.out", segment_id));
+
+        let tag = OutputTag::new("billing", Some(7), segment_id, 12);
+        assert_eq!(tag.name(), format!("billing/{}.p7-12.out", segment_id));
+    }
+
+    #[test]
+    fn test_same_records_same_name() {
+        let segment_id = SegmentId::new();
+
+        assert_eq!(
+            OutputTag::new("billing", Some(3), segment_id, 5).name(),
+            OutputTag::new("billing", Some(3), segment_id, 5).name()
+        );
+        assert_ne!(
+            OutputTag::new("billing", Some(3), segment_id, 5).name(),
+            OutputTag::new("audit", Some(3), segment_id, 5).name()
+        );
+    }
+
+    #[test]
+    fn test_tag_metadata() {
+        let segment_id = SegmentId::new();
+
+        let metadata = OutputTag::new("billing", None, segment_id, 0).metadata();
+        assert_eq!(metadata["segment-id"], segment_id.to_string());
+        assert!(!metadata.contains_key("partition"));
+
+        let metadata = OutputTag::new("billing", Some(7), segment_id, 0).metadata();
+        assert_eq!(metadata["partition"], "7");
+    }
+
+    #[test]
+    fn test_validate_group() {
+        assert!(validate_group("billing").is_ok());
+        assert!(validate_group("billing-v2.eu_west").is_ok());
+        assert!(validate_group("").is_err());
+        assert!(validate_group("a/b").is_err());
+        assert!(validate_group("..").is_err());
+        assert!(validate_group(".tmp").is_err());
+        assert!(validate_group(&"g".repeat(MAX_GROUP_LEN + 1)).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Content checksums
+//!
+//! Segments carry a SHA-256 checksum of their data, computed on ingest and
+//! stored alongside the data, so every reader can verify that what it reads
+//! is what the producer sent.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ingestion::error::IngestionError;
+
+/// SHA-256 checksum of a segment's data
+///
+/// Displayed and parsed as 64 lowercase hexadecimal characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Checksum([u8; 32]);
+
+impl Checksum {
+    /// Compute the checksum of `data`
+    pub fn sha256(data: &[u8]) -> Self {
+        Self(Sha256::digest(data).into())
+    }
+
+    /// Create a checksum from raw digest bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Get the raw digest bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Check that `data` matches this checksum
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::ChecksumMismatch` if it does not
+    pub fn verify(&self, data: &[u8]) -> Result<(), IngestionError> {
+        let actual = Self::sha256(data);
+        if actual != *self {
+            return Err(IngestionError::checksum_mismatch(self, actual));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = IngestionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            IngestionError::invalid_data(format!(
+                "Invalid SHA-256 checksum '{}': expected 64 hexadecimal characters",
+                s
+            ))
+        };
+
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(invalid());
+        }
+
+        let mut bytes = [0u8; 32];
+        for (byte, pair) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for Checksum {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Checksum {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `echo -n hello | sha256sum`
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_sha256_reference_value() {
+        assert_eq!(Checksum::sha256(b"hello").to_string(), HELLO_SHA256);
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let checksum: Checksum = HELLO_SHA256.parse().unwrap();
+        assert_eq!(checksum, Checksum::sha256(b"hello"));
+
+        // Uppercase hex is accepted
+        let upper: Checksum = HELLO_SHA256.to_uppercase().parse().unwrap();
+        assert_eq!(upper, checksum);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        assert!("abc".parse::<Checksum>().is_err());
+        assert!("zz".repeat(32).parse::<Checksum>().is_err());
+        assert!("é".repeat(32).parse::<Checksum>().is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let checksum = Checksum::sha256(b"hello");
+
+        assert!(checksum.verify(b"hello").is_ok());
+        assert!(matches!(
+            checksum.verify(b"hellO"),
+            Err(IngestionError::ChecksumMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+//! Segment compression
+//!
+//! Segments can be compressed before storage with one of the supported
+//! codecs. The codec is recorded with the stored segment and reversed
+//! automatically on read, so readers always get the data as ingested.
+//!
+//! Each codec is behind a cargo feature of the same name (all enabled by
+//! default). Selecting a codec that is not compiled in is a configuration
+//! error.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ingestion::error::IngestionError;
+
+/// Compression codec applied to stored segments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Data is stored as received
+    #[default]
+    None,
+    /// Gzip (DEFLATE), widely readable but slower
+    Gzip,
+    /// Zstandard at level 3, the best ratio for the CPU spent
+    Zstd,
+    /// LZ4 frame format, the fastest with a lower ratio
+    Lz4,
+}
+
+/// Zstandard compression level
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+impl Compression {
+    /// All codecs, including the ones not compiled in
+    pub const ALL: [Compression; 4] = [Self::None, Self::Gzip, Self::Zstd, Self::Lz4];
+
+    /// Name of the codec, as recorded with stored segments
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+            Self::Lz4 => "lz4",
+        }
+    }
+
+    /// Whether the codec is compiled in
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Gzip => cfg!(feature = "gzip"),
+            Self::Zstd => cfg!(feature = "zstd"),
+            Self::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Compress `data` with this codec
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::ConfigError` if the codec is not compiled in
+    /// - `IngestionError::InternalError` if the encoder fails
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, IngestionError> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                use std::io::Write;
+
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| self.failure("compress", e))
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                zstd::stream::encode_all(data, ZSTD_LEVEL).map_err(|e| self.failure("compress", e))
+            }
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => {
+                use std::io::Write;
+
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder
+                    .write_all(data)
+                    .map_err(|e| self.failure("compress", e))?;
+                encoder.finish().map_err(|e| self.failure("compress", e))
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unavailable()),
+        }
+    }
+
+    /// Decompress `data` previously compressed with this codec
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::ConfigError` if the codec is not compiled in
+    /// - `IngestionError::InvalidData` if the data is not valid for this codec
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, IngestionError> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => self.read_all(flate2::read::GzDecoder::new(data)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::stream::decode_all(data).map_err(|e| self.corrupted(e)),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => self.read_all(lz4_flex::frame::FrameDecoder::new(data)),
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unavailable()),
+        }
+    }
+
+    /// Drain a decoder into a buffer
+    #[cfg(any(feature = "gzip", feature = "lz4"))]
+    fn read_all(&self, mut decoder: impl std::io::Read) -> Result<Vec<u8>, IngestionError> {
+        let mut out = Vec::new();
+        decoder
+            .read_to_end(&mut out)
+            .map_err(|e| self.corrupted(e))?;
+        Ok(out)
+    }
+
+    /// Error of a failed encoder
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "lz4"))]
+    fn failure(&self, action: &str, err: impl fmt::Display) -> IngestionError {
+        IngestionError::internal_error(format!("Failed to {} with {}: {}", action, self, err))
+    }
+
+    /// Error of data the decoder cannot read
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "lz4"))]
+    fn corrupted(&self, err: impl fmt::Display) -> IngestionError {
+        IngestionError::invalid_data(format!("Failed to decompress {} data: {}", self, err))
+    }
+
+    /// Error of a codec that is not compiled in
+    fn unavailable(&self) -> IngestionError {
+        IngestionError::config_error(format!(
+            "Compression codec '{}' is not enabled in this build",
+            self
+        ))
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = IngestionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                IngestionError::config_error(format!(
+                    "Unknown compression codec '{}' (expected none, gzip, zstd or lz4)",
+                    s
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry() -> Vec<u8> {
+        (0..500)
+            .map(|i| {
+                format!(
+                    r#"{{"ts":{},"host":"web-{}","metric":"cpu","value":{}}}"#,
+                    1_700_000_000 + i,
+                    i % 4,
+                    i % 100
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_roundtrip_all_available_codecs() {
+        let data = telemetry();
+
+        for codec in Compression::ALL.into_iter().filter(|c| c.is_available()) {
+            let compressed = codec.compress(&data).unwrap();
+            assert_eq!(codec.decompress(&compressed).unwrap(), data, "{}", codec);
+
+            if codec != Compression::None {
+                assert!(
+                    compressed.len() * 4 < data.len(),
+                    "{} only reached {} -> {} bytes",
+                    codec,
+                    data.len(),
+                    compressed.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_none_is_identity() {
+        assert_eq!(Compression::None.compress(b"abc").unwrap(), b"abc");
+        assert_eq!(Compression::None.decompress(b"abc").unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_corrupted_data_is_rejected() {
+        for codec in Compression::ALL
+            .into_iter()
+            .filter(|c| c.is_available() && *c != Compression::None)
+        {
+            assert!(
+                matches!(
+                    codec.decompress(b"not compressed at all"),
+                    Err(IngestionError::InvalidData(_))
+                ),
+                "{}",
+                codec
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_names() {
+        for codec in Compression::ALL {
+            assert_eq!(codec.name().parse::<Compression>().unwrap(), codec);
+        }
+        assert_eq!("ZSTD".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!(matches!(
+            "brotli".parse::<Compression>(),
+            Err(IngestionError::ConfigError(_))
+        ));
+    }
+}
@@ -7,7 +7,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::ingestion::ids::SegmentId;
+use crate::ingestion::{
+    checksum::Checksum, compression::Compression, error::IngestionError, ids::SegmentId,
+    keyed::KeyedRecord, partition::PartitionId,
+};
 
 /// A Segment represents an immutable chunk of ingested data
 ///
@@ -39,6 +42,31 @@ pub struct Segment {
     /// Storage key/path where this segment is stored
     /// This is optional as it's set after storage, not at creation
     storage_key: Option<String>,
+
+    /// Checksum of the data, computed at creation
+    /// This is `None` for segments reconstructed without their checksum
+    #[serde(default)]
+    checksum: Option<Checksum>,
+
+    /// Codec the data is compressed with in storage
+    #[serde(default)]
+    compression: Compression,
+
+    /// Id of the master key protecting the data, if encrypted in storage
+    #[serde(default)]
+    encryption_key_id: Option<String>,
+
+    /// Record key, for segments that are versions of a key
+    #[serde(default)]
+    record_key: Option<String>,
+
+    /// Partition the segment was routed to by its partition key
+    #[serde(default)]
+    partition: Option<PartitionId>,
+
+    /// Topic the segment was ingested under
+    #[serde(default)]
+    topic: Option<String>,
 }
 
 impl Segment {
@@ -49,13 +77,27 @@ impl Segment {
     ///
     /// # Arguments
     ///
-    /// * `data` - The raw bytes to be stored (used only to calculate size)
+    /// * `data` - The raw bytes to be stored (used only to calculate size and checksum)
     pub fn new(data: Vec<u8>) -> Self {
+        Self::with_id(SegmentId::new(), &data)
+    }
+
+    /// Create a new Segment with a caller-chosen ID
+    ///
+    /// Used for idempotent ingestion, where the client picks the ID instead
+    /// of having it generated.
+    pub fn with_id(id: SegmentId, data: &[u8]) -> Self {
         Self {
-            id: SegmentId::new(),
+            id,
             size_bytes: data.len(),
             created_at: Utc::now(),
             storage_key: None,
+            checksum: Some(Checksum::sha256(data)),
+            compression: Compression::None,
+            encryption_key_id: None,
+            record_key: None,
+            partition: None,
+            topic: None,
         }
     }
 
@@ -71,6 +113,12 @@ impl Segment {
             size_bytes,
             created_at,
             storage_key,
+            checksum: None,
+            compression: Compression::None,
+            encryption_key_id: None,
+            record_key: None,
+            partition: None,
+            topic: None,
         }
     }
 
@@ -79,6 +127,11 @@ impl Segment {
         &self.id
     }
 
+    /// Take over the ID of an earlier attempt of the same request
+    pub(crate) fn set_id(&mut self, id: SegmentId) {
+        self.id = id;
+    }
+
     /// Get the size of the segment in bytes
     pub fn size(&self) -> usize {
         self.size_bytes
@@ -94,6 +147,75 @@ impl Segment {
         self.storage_key.as_deref()
     }
 
+    /// Get the checksum of the data (if known)
+    pub fn checksum(&self) -> Option<&Checksum> {
+        self.checksum.as_ref()
+    }
+
+    /// Get the codec the data is compressed with in storage
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Set the codec the data is compressed with before it is persisted
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Get the id of the master key the data is encrypted with (if encrypted)
+    pub fn encryption_key_id(&self) -> Option<&str> {
+        self.encryption_key_id.as_deref()
+    }
+
+    /// Record the master key the data is encrypted with before it is persisted
+    pub fn set_encryption_key_id(&mut self, key_id: impl Into<String>) {
+        self.encryption_key_id = Some(key_id.into());
+    }
+
+    /// Get the record key (if keyed)
+    pub fn record_key(&self) -> Option<&str> {
+        self.record_key.as_deref()
+    }
+
+    /// Make the segment a version of a record key
+    pub fn set_record_key(&mut self, key: impl Into<String>) {
+        self.record_key = Some(key.into());
+    }
+
+    /// Whether the segment deletes its record key (keyed and empty)
+    pub fn is_tombstone(&self) -> bool {
+        self.record_key.is_some() && self.size_bytes == 0
+    }
+
+    /// Get the record key with its creation time (if keyed)
+    pub fn keyed_record(&self) -> Option<KeyedRecord> {
+        self.record_key.as_ref().map(|key| KeyedRecord {
+            key: key.clone(),
+            created_at: self.created_at,
+            tombstone: self.is_tombstone(),
+        })
+    }
+
+    /// Get the partition (if partitioned)
+    pub fn partition(&self) -> Option<PartitionId> {
+        self.partition
+    }
+
+    /// Route the segment to a partition
+    pub fn set_partition(&mut self, partition: PartitionId) {
+        self.partition = Some(partition);
+    }
+
+    /// Get the topic (if ingested under one)
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
+    }
+
+    /// Set the topic the segment belongs to
+    pub fn set_topic(&mut self, topic: impl Into<String>) {
+        self.topic = Some(topic.into());
+    }
+
     /// Set the storage key after the segment has been persisted
     ///
     /// This is typically called by the infrastructure layer after successful storage.
@@ -107,6 +229,65 @@ impl Segment {
     }
 }
 
+/// A segment's data as read back from storage
+///
+/// Carries the checksum and codec stored alongside the data, so the domain
+/// can decompress and verify the data before handing it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredSegment {
+    /// The raw bytes read from storage, possibly compressed
+    pub data: Vec<u8>,
+    /// The checksum of the uncompressed data (`None` for legacy segments)
+    pub checksum: Option<Checksum>,
+    /// The codec the data is compressed with
+    pub compression: Compression,
+    /// The record key, for keyed segments
+    pub record: Option<KeyedRecord>,
+}
+
+impl StoredSegment {
+    /// Create an uncompressed stored segment from its data and stored checksum
+    pub fn new(data: Vec<u8>, checksum: Option<Checksum>) -> Self {
+        Self {
+            data,
+            checksum,
+            compression: Compression::None,
+            record: None,
+        }
+    }
+
+    /// Set the codec the data is compressed with
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the record key of a keyed segment
+    pub fn with_record(mut self, record: Option<KeyedRecord>) -> Self {
+        self.record = record;
+        self
+    }
+
+    /// Decompress the data, verify it against the stored checksum and return it
+    ///
+    /// Segments stored without a checksum are returned unverified.
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the data cannot be decompressed
+    /// - `IngestionError::ChecksumMismatch` if the data is corrupted
+    pub fn into_verified_data(self) -> Result<Vec<u8>, IngestionError> {
+        let data = match self.compression {
+            Compression::None => self.data,
+            codec => codec.decompress(&self.data)?,
+        };
+        if let Some(checksum) = &self.checksum {
+            checksum.verify(&data)?;
+        }
+        Ok(data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,6 +309,17 @@ mod tests {
         assert_eq!(display_str.len(), 36); // UUID string length with hyphens
     }
 
+    #[test]
+    fn test_segment_id_parse() {
+        let id = SegmentId::new();
+
+        assert_eq!(id.to_string().parse::<SegmentId>().unwrap(), id);
+        assert!(matches!(
+            "not-a-uuid".parse::<SegmentId>(),
+            Err(IngestionError::InvalidData(_))
+        ));
+    }
+
     #[test]
     fn test_segment_creation() {
         let data = vec![1, 2, 3, 4, 5];
@@ -136,6 +328,17 @@ mod tests {
         assert_eq!(segment.size(), data.len());
         assert!(!segment.is_persisted());
         assert!(segment.storage_key().is_none());
+        assert_eq!(segment.checksum(), Some(&Checksum::sha256(&data)));
+        assert!(segment.encryption_key_id().is_none());
+    }
+
+    #[test]
+    fn test_segment_set_encryption_key_id() {
+        let mut segment = Segment::new(vec![1, 2, 3]);
+
+        segment.set_encryption_key_id("2024-06");
+
+        assert_eq!(segment.encryption_key_id(), Some("2024-06"));
     }
 
     #[test]
@@ -151,6 +354,16 @@ mod tests {
         assert_eq!(segment.storage_key(), Some("data/abc-123.zuk"));
     }
 
+    #[test]
+    fn test_segment_with_id() {
+        let id = SegmentId::new();
+        let segment = Segment::with_id(id, &[1, 2, 3]);
+
+        assert_eq!(segment.id(), &id);
+        assert_eq!(segment.size(), 3);
+        assert!(!segment.is_persisted());
+    }
+
     #[test]
     fn test_segment_from_parts() {
         let id = SegmentId::new();
@@ -164,5 +377,55 @@ mod tests {
         assert_eq!(segment.created_at(), &now);
         assert_eq!(segment.storage_key(), Some("data/test.zuk"));
         assert!(segment.is_persisted());
+        assert!(segment.checksum().is_none());
+    }
+
+    #[test]
+    fn test_stored_segment_verification() {
+        let data = vec![1, 2, 3];
+        let checksum = Checksum::sha256(&data);
+
+        let stored = StoredSegment::new(data.clone(), Some(checksum));
+        assert_eq!(stored.into_verified_data().unwrap(), data);
+
+        let corrupted = StoredSegment::new(vec![1, 2, 4], Some(checksum));
+        assert!(matches!(
+            corrupted.into_verified_data(),
+            Err(IngestionError::ChecksumMismatch { .. })
+        ));
+
+        // Legacy segments without a checksum are not verified
+        let legacy = StoredSegment::new(vec![1, 2, 4], None);
+        assert_eq!(legacy.into_verified_data().unwrap(), vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_keyed_segment() {
+        let mut segment = Segment::new(vec![1, 2, 3]);
+        assert!(segment.keyed_record().is_none());
+
+        segment.set_record_key("user-42");
+        let record = segment.keyed_record().unwrap();
+        assert_eq!(record.key, "user-42");
+        assert_eq!(record.created_at, *segment.created_at());
+        assert!(!record.tombstone);
+
+        let mut tombstone = Segment::new(Vec::new());
+        assert!(!tombstone.is_tombstone());
+        tombstone.set_record_key("user-42");
+        assert!(tombstone.is_tombstone());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_stored_segment_decompression() {
+        let data = b"hello hello hello hello".to_vec();
+        let compressed = Compression::Gzip.compress(&data).unwrap();
+
+        let stored = StoredSegment::new(compressed, Some(Checksum::sha256(&data)))
+            .with_compression(Compression::Gzip);
+
+        // The checksum covers the uncompressed data
+        assert_eq!(stored.into_verified_data().unwrap(), data);
     }
 }
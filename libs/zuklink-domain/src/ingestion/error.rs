@@ -31,6 +31,14 @@ pub enum IngestionError {
     #[error("Segment {0} already exists")]
     SegmentAlreadyExists(String),
 
+    /// A retried request carries other data than the segment it targets
+    #[error("Segment {0} already exists with different data")]
+    IdempotencyConflict(String),
+
+    /// The data does not match its checksum
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
     /// Configuration error
     #[error("Configuration error: {0}")]
     ConfigError(String),
@@ -56,6 +64,24 @@ impl IngestionError {
         Self::SegmentTooLarge { size, max }
     }
 
+    /// Create a segment already exists error
+    pub fn segment_already_exists(segment_id: impl ToString) -> Self {
+        Self::SegmentAlreadyExists(segment_id.to_string())
+    }
+
+    /// Create an idempotency conflict error
+    pub fn idempotency_conflict(segment_id: impl ToString) -> Self {
+        Self::IdempotencyConflict(segment_id.to_string())
+    }
+
+    /// Create a checksum mismatch error
+    pub fn checksum_mismatch(expected: impl ToString, actual: impl ToString) -> Self {
+        Self::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+
     /// Create a config error with a message
     pub fn config_error(msg: impl Into<String>) -> Self {
         Self::ConfigError(msg.into())
@@ -98,6 +124,30 @@ mod tests {
         assert_eq!(err.to_string(), "Cannot ingest empty segment");
     }
 
+    #[test]
+    fn test_segment_already_exists_error() {
+        let err = IngestionError::segment_already_exists("abc-123");
+        assert!(matches!(err, IngestionError::SegmentAlreadyExists(_)));
+        assert_eq!(err.to_string(), "Segment abc-123 already exists");
+    }
+
+    #[test]
+    fn test_idempotency_conflict_error() {
+        let err = IngestionError::idempotency_conflict("abc-123");
+        assert!(matches!(err, IngestionError::IdempotencyConflict(_)));
+        assert_eq!(
+            err.to_string(),
+            "Segment abc-123 already exists with different data"
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch_error() {
+        let err = IngestionError::checksum_mismatch("abc", "def");
+        assert!(matches!(err, IngestionError::ChecksumMismatch { .. }));
+        assert_eq!(err.to_string(), "Checksum mismatch: expected abc, got def");
+    }
+
     #[test]
     fn test_invalid_data_error() {
         let err = IngestionError::invalid_data("Corrupted bytes");
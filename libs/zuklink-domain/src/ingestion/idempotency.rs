@@ -0,0 +1,118 @@
+//! Idempotency claims
+//!
+//! A request carrying an idempotency key first claims the key, recording the
+//! segment it is about to store. A retry finds the claim and gets the ID of
+//! the original segment back, whether that segment is still stored on its
+//! own, compacted into a container or already expired. The segment ID itself
+//! is generated like any other (UUID v7), so idempotent segments are ordered
+//! by creation time with the rest of their shard.
+//!
+//! Keys are scoped per topic: the same key under two topics names two
+//! different requests.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ingestion::{
+    checksum::Checksum, entity::Segment, ids::SegmentId, partition::PartitionId,
+};
+
+/// Record of the segment stored for a client idempotency key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdempotencyClaim {
+    /// Client idempotency key
+    pub key: String,
+    /// Topic the key is scoped to, `None` for segments without a topic
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Segment stored for the key
+    pub segment_id: SegmentId,
+    /// Partition the segment was routed to
+    #[serde(default)]
+    pub partition: Option<PartitionId>,
+    /// Checksum of the data of the segment
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+    /// Whether the segment is known to be stored
+    ///
+    /// A claim stays pending when the request that made it failed before
+    /// storing the segment; the next retry stores it.
+    pub committed: bool,
+    /// Time the key was claimed
+    pub claimed_at: DateTime<Utc>,
+}
+
+impl IdempotencyClaim {
+    /// Pending claim of `key` for `segment`, in the scope of its topic
+    pub fn new(key: impl Into<String>, segment: &Segment) -> Self {
+        Self {
+            key: key.into(),
+            topic: segment.topic().map(String::from),
+            segment_id: *segment.id(),
+            partition: segment.partition(),
+            checksum: segment.checksum().copied(),
+            committed: false,
+            claimed_at: Utc::now(),
+        }
+    }
+
+    /// Whether `segment` is a retry of the request that made this claim
+    ///
+    /// A retry carries the same data, routed to the same partition.
+    pub fn matches(&self, segment: &Segment) -> bool {
+        self.partition == segment.partition() && self.checksum.as_ref() == segment.checksum()
+    }
+
+    /// The claim once its segment is stored
+    pub fn committed(self) -> Self {
+        Self {
+            committed: true,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_records_segment() {
+        let mut segment = Segment::new(vec![1, 2, 3]);
+        segment.set_partition(7);
+        segment.set_topic("billing");
+
+        let claim = IdempotencyClaim::new("order-42", &segment);
+        assert_eq!(claim.segment_id, *segment.id());
+        assert_eq!(claim.topic.as_deref(), Some("billing"));
+        assert_eq!(claim.partition, Some(7));
+        assert!(!claim.committed);
+        assert!(claim.clone().committed().committed);
+
+        let json = serde_json::to_string(&claim).unwrap();
+        assert_eq!(
+            serde_json::from_str::<IdempotencyClaim>(&json).unwrap(),
+            claim
+        );
+    }
+
+    #[test]
+    fn test_claim_matches_same_data_and_partition() {
+        let mut segment = Segment::new(vec![1, 2, 3]);
+        segment.set_partition(7);
+        let claim = IdempotencyClaim::new("order-42", &segment);
+
+        let mut retry = Segment::new(vec![1, 2, 3]);
+        retry.set_partition(7);
+        assert!(claim.matches(&retry));
+
+        let mut other_data = Segment::new(vec![4, 5, 6]);
+        other_data.set_partition(7);
+        assert!(!claim.matches(&other_data));
+
+        let mut other_partition = Segment::new(vec![1, 2, 3]);
+        other_partition.set_partition(8);
+        assert!(!claim.matches(&other_partition));
+        assert!(!claim.matches(&Segment::new(vec![1, 2, 3])));
+    }
+}
@@ -1,12 +1,18 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use uuid::Uuid;
+use std::str::FromStr;
+use uuid::{Builder, Uuid};
+
+use crate::ingestion::error::IngestionError;
 
 /// Unique identifier for a Segment
 ///
-/// SegmentId is a wrapper around UUID v7 to provide type safety and prevent
-/// mixing up segment IDs with other UUIDs in the system.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// SegmentId is a wrapper around a UUID to provide type safety and prevent
+/// mixing up segment IDs with other UUIDs in the system. Generated IDs are
+/// UUID v7, which sort by creation time; IDs chosen by clients may be any
+/// UUID, and carry no time unless they are UUID v7 too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct SegmentId(Uuid);
 
 impl SegmentId {
@@ -15,6 +21,16 @@ impl SegmentId {
         Self(Uuid::now_v7())
     }
 
+    /// Smallest UUID v7 ID generated at `time`
+    ///
+    /// Every ID generated at or after `time` sorts at or after it, every ID
+    /// generated before sorts before it. Times are truncated to the
+    /// millisecond, the precision of UUID v7 timestamps.
+    pub fn first_at(time: DateTime<Utc>) -> Self {
+        let millis = u64::try_from(time.timestamp_millis()).unwrap_or(0);
+        Self(Builder::from_unix_timestamp_millis(millis, &[0; 10]).into_uuid())
+    }
+
     /// Create a SegmentId from an existing UUID
     pub fn from_uuid(uuid: Uuid) -> Self {
         Self(uuid)
@@ -24,6 +40,17 @@ impl SegmentId {
     pub fn as_uuid(&self) -> &Uuid {
         &self.0
     }
+
+    /// Time the ID was generated, for UUID v7 IDs
+    ///
+    /// Returns `None` for other versions, such as some IDs chosen by clients.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        if self.0.get_version_num() != 7 {
+            return None;
+        }
+        let (secs, nanos) = self.0.get_timestamp()?.to_unix();
+        DateTime::from_timestamp(i64::try_from(secs).ok()?, nanos)
+    }
 }
 
 impl Default for SegmentId {
@@ -38,6 +65,16 @@ impl fmt::Display for SegmentId {
     }
 }
 
+impl FromStr for SegmentId {
+    type Err = IngestionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s)
+            .map(Self)
+            .map_err(|_| IngestionError::invalid_data(format!("Invalid segment ID '{}'", s)))
+    }
+}
+
 impl From<Uuid> for SegmentId {
     fn from(uuid: Uuid) -> Self {
         Self(uuid)
@@ -0,0 +1,70 @@
+//! Keyed segments
+//!
+//! A segment may carry a record key, making it one version of that key: the
+//! newest version is the current value, and an empty one (a tombstone)
+//! deletes the key. Key compaction relies on this to keep only the latest
+//! value of every key.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ingestion::error::IngestionError;
+
+/// Maximum length of a record key in bytes
+pub const MAX_RECORD_KEY_LEN: usize = 1024;
+
+/// Record key of a segment, with what orders the versions of the key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyedRecord {
+    /// The record key
+    pub key: String,
+    /// Time the segment was ingested, the newest version of a key wins
+    pub created_at: DateTime<Utc>,
+    /// Whether the segment is empty, deleting the key
+    #[serde(default)]
+    pub tombstone: bool,
+}
+
+impl KeyedRecord {
+    /// Check that a record key is usable
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::InvalidData` if the key is empty or longer
+    /// than [`MAX_RECORD_KEY_LEN`] bytes
+    pub fn validate_key(key: &str) -> Result<(), IngestionError> {
+        if key.is_empty() {
+            return Err(IngestionError::invalid_data("Record key cannot be empty"));
+        }
+        if key.len() > MAX_RECORD_KEY_LEN {
+            return Err(IngestionError::invalid_data(format!(
+                "Record key length ({}) exceeds maximum ({})",
+                key.len(),
+                MAX_RECORD_KEY_LEN
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_key() {
+        assert!(KeyedRecord::validate_key("user-42").is_ok());
+        assert!(KeyedRecord::validate_key("").is_err());
+        assert!(KeyedRecord::validate_key(&"k".repeat(MAX_RECORD_KEY_LEN)).is_ok());
+        assert!(KeyedRecord::validate_key(&"k".repeat(MAX_RECORD_KEY_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_serialization_defaults() {
+        let json = r#"{"key":"user-42","created_at":"2024-06-01T00:00:00Z"}"#;
+        let record: KeyedRecord = serde_json::from_str(json).unwrap();
+
+        assert_eq!(record.key, "user-42");
+        assert!(!record.tombstone);
+    }
+}
@@ -0,0 +1,100 @@
+//! Ingestion metrics
+//!
+//! Counters of the data flowing through the ingestion service, used to
+//! follow the compression ratio and the CPU time spent on compression.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Live counters of an ingestion service
+#[derive(Debug, Default)]
+pub(crate) struct IngestionMetrics {
+    segments_ingested: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_stored: AtomicU64,
+    compression_nanos: AtomicU64,
+    segments_read: AtomicU64,
+    decompression_nanos: AtomicU64,
+}
+
+impl IngestionMetrics {
+    /// Record a segment stored by this service
+    pub(crate) fn record_ingest(&self, received: usize, stored: usize, elapsed: Duration) {
+        self.segments_ingested.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(received as u64, Ordering::Relaxed);
+        self.bytes_stored
+            .fetch_add(stored as u64, Ordering::Relaxed);
+        self.compression_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a segment read back and decompressed
+    pub(crate) fn record_read(&self, elapsed: Duration) {
+        self.segments_read.fetch_add(1, Ordering::Relaxed);
+        self.decompression_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Point-in-time copy of the counters
+    pub(crate) fn snapshot(&self) -> IngestionMetricsSnapshot {
+        IngestionMetricsSnapshot {
+            segments_ingested: self.segments_ingested.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_stored: self.bytes_stored.load(Ordering::Relaxed),
+            compression_time: Duration::from_nanos(self.compression_nanos.load(Ordering::Relaxed)),
+            segments_read: self.segments_read.load(Ordering::Relaxed),
+            decompression_time: Duration::from_nanos(
+                self.decompression_nanos.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+/// Point-in-time copy of the ingestion metrics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestionMetricsSnapshot {
+    /// Segments stored since startup
+    pub segments_ingested: u64,
+    /// Bytes received from producers, before compression
+    pub bytes_received: u64,
+    /// Bytes written to storage, after compression
+    pub bytes_stored: u64,
+    /// Time spent compressing segments
+    pub compression_time: Duration,
+    /// Segments read back through the service
+    pub segments_read: u64,
+    /// Time spent decompressing and verifying segments read back
+    pub decompression_time: Duration,
+}
+
+impl IngestionMetricsSnapshot {
+    /// Bytes received per byte stored (`1.0` before any ingestion)
+    pub fn compression_ratio(&self) -> f64 {
+        if self.bytes_stored == 0 {
+            return 1.0;
+        }
+        self.bytes_received as f64 / self.bytes_stored as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_ratio() {
+        let metrics = IngestionMetrics::default();
+        assert_eq!(metrics.snapshot().compression_ratio(), 1.0);
+
+        metrics.record_ingest(800, 100, Duration::from_millis(2));
+        metrics.record_ingest(800, 100, Duration::from_millis(3));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.segments_ingested, 2);
+        assert_eq!(snapshot.bytes_received, 1600);
+        assert_eq!(snapshot.bytes_stored, 200);
+        assert_eq!(snapshot.compression_time, Duration::from_millis(5));
+        assert_eq!(snapshot.compression_ratio(), 8.0);
+    }
+}
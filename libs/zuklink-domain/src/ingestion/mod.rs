@@ -3,8 +3,15 @@
 //! This module contains the core business logic and entities for data ingestion.
 //! It defines what a Segment is and how data flows through the ingestion pipeline.
 
+pub mod checksum;
+pub mod compression;
 pub mod entity;
 pub mod error;
+pub mod idempotency;
 pub mod ids;
+pub mod keyed;
+pub mod metrics;
+pub mod partition;
 pub mod ports;
 pub mod service;
+pub mod topic;
@@ -0,0 +1,119 @@
+//! Partitioned segments
+//!
+//! A segment may carry a partition key. Segments with the same key land in
+//! the same partition, and receivers are assigned whole partitions instead of
+//! individual segments: all the events of one key (a customer, a device) are
+//! processed by the same node.
+//!
+//! The partition of a key is a stable hash of the key modulo the number of
+//! partitions, FNV-1a with a SplitMix64 finalizer, the same on every node,
+//! version and platform. Changing the number of partitions moves keys between
+//! partitions: it must be the same on every sender.
+
+use crate::ingestion::error::IngestionError;
+
+/// Identifier of a partition, between 0 and the number of partitions - 1
+pub type PartitionId = u32;
+
+/// Default number of partitions keys are hashed into
+pub const DEFAULT_PARTITION_COUNT: u32 = 64;
+
+/// Maximum length of a partition key in bytes
+pub const MAX_PARTITION_KEY_LEN: usize = 1024;
+
+/// Partition a key belongs to, among `partitions` partitions
+///
+/// A count of 0 is treated as a single partition.
+pub fn partition_of(key: &str, partitions: u32) -> PartitionId {
+    (mix(hash(key.as_bytes())) % u64::from(partitions.max(1))) as PartitionId
+}
+
+/// Key a partition is assigned to receivers by
+///
+/// Hashed by the cluster sharding like a segment key, so every segment of the
+/// partition has the same owner.
+pub fn routing_key(partition: PartitionId) -> String {
+    format!("partition-{}", partition)
+}
+
+/// Check that a partition key is usable
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the key is empty or longer than
+/// [`MAX_PARTITION_KEY_LEN`] bytes
+pub fn validate_key(key: &str) -> Result<(), IngestionError> {
+    if key.is_empty() {
+        return Err(IngestionError::invalid_data(
+            "Partition key cannot be empty",
+        ));
+    }
+    if key.len() > MAX_PARTITION_KEY_LEN {
+        return Err(IngestionError::invalid_data(format!(
+            "Partition key length ({}) exceeds maximum ({})",
+            key.len(),
+            MAX_PARTITION_KEY_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Stable 64-bit hash (FNV-1a)
+fn hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// SplitMix64 finalizer, spreading FNV's weak low bits
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_of_is_stable_and_in_range() {
+        assert_eq!(
+            partition_of("customer-42", 64),
+            partition_of("customer-42", 64)
+        );
+        assert_eq!(partition_of("customer-42", 1), 0);
+        assert_eq!(partition_of("customer-42", 0), 0);
+
+        for i in 0..1_000 {
+            assert!(partition_of(&format!("customer-{}", i), 7) < 7);
+        }
+    }
+
+    #[test]
+    fn test_keys_spread_over_partitions() {
+        let mut counts = [0usize; 8];
+        for i in 0..8_000 {
+            counts[partition_of(&format!("customer-{}", i), 8) as usize] += 1;
+        }
+
+        // 1000 keys per partition on average
+        assert!(counts.iter().all(|count| (800..1_200).contains(count)));
+    }
+
+    #[test]
+    fn test_validate_key() {
+        assert!(validate_key("customer-42").is_ok());
+        assert!(validate_key("").is_err());
+        assert!(validate_key(&"k".repeat(MAX_PARTITION_KEY_LEN)).is_ok());
+        assert!(validate_key(&"k".repeat(MAX_PARTITION_KEY_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_routing_key() {
+        assert_eq!(routing_key(7), "partition-7");
+    }
+}
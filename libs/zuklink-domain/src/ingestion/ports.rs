@@ -6,7 +6,11 @@
 
 use std::future::Future;
 
-use crate::ingestion::{error::IngestionError, ids::SegmentId};
+use crate::ingestion::{
+    error::IngestionError,
+    ids::SegmentId,
+    service::{IngestOptions, IngestOutcome},
+};
 
 /// Port trait for ingestion operations
 ///
@@ -35,6 +39,75 @@ pub trait IngestionServicePort: Send + Sync {
         data: Vec<u8>,
     ) -> impl Future<Output = Result<SegmentId, IngestionError>> + Send;
 
+    /// Ingest raw data with request options
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes to ingest
+    /// * `options` - Idempotency key, segment ID and expected checksum
+    ///
+    /// # Returns
+    ///
+    /// `IngestOutcome::Created` for a new segment, `IngestOutcome::Duplicate`
+    /// with the original ID for a retry
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the options are invalid
+    /// - `IngestionError::ChecksumMismatch` if the data does not match the expected checksum
+    /// - Same errors as `ingest_data` otherwise
+    fn ingest(
+        &self,
+        data: Vec<u8>,
+        options: IngestOptions,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send;
+
+    /// Ingest raw data under a client idempotency key
+    ///
+    /// The key is claimed for the new segment, so retrying with the same key
+    /// returns the original segment instead of creating a duplicate.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes to ingest
+    /// * `idempotency_key` - Client-chosen key, unique per logical request
+    ///
+    /// # Returns
+    ///
+    /// `IngestOutcome::Created` for a new segment, `IngestOutcome::Duplicate`
+    /// with the original ID for a retry
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the key is empty or too long
+    /// - Same errors as `ingest_data` otherwise
+    fn ingest_idempotent(
+        &self,
+        data: Vec<u8>,
+        idempotency_key: &str,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send;
+
+    /// Ingest raw data under a client-chosen segment ID
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes to ingest
+    /// * `segment_id` - Client-chosen ID of the segment
+    ///
+    /// # Returns
+    ///
+    /// `IngestOutcome::Created` for a new segment, `IngestOutcome::Duplicate`
+    /// if a segment with this ID already exists
+    ///
+    /// # Errors
+    ///
+    /// Same errors as `ingest_data`
+    fn ingest_with_id(
+        &self,
+        data: Vec<u8>,
+        segment_id: SegmentId,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send;
+
     /// Retrieve a segment's data from storage
     ///
     /// # Arguments
@@ -47,7 +120,8 @@ pub trait IngestionServicePort: Send + Sync {
     ///
     /// # Errors
     ///
-    /// Returns `IngestionError::StorageFailure` if the segment doesn't exist or retrieval fails
+    /// - `IngestionError::StorageFailure` if the segment doesn't exist or retrieval fails
+    /// - `IngestionError::ChecksumMismatch` if the data doesn't match its stored checksum
     fn get_segment_data(
         &self,
         segment_id: &SegmentId,
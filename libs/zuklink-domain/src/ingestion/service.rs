@@ -3,11 +3,23 @@
 //! This module contains the core business logic for data ingestion.
 //! The service coordinates between the domain entities and the storage port.
 
+use std::borrow::Cow;
 use std::future::Future;
+use std::time::{Duration, Instant};
 
 use crate::{
     ingestion::{
-        entity::Segment, error::IngestionError, ids::SegmentId, ports::IngestionServicePort,
+        checksum::Checksum,
+        compression::Compression,
+        entity::Segment,
+        error::IngestionError,
+        idempotency::IdempotencyClaim,
+        ids::SegmentId,
+        keyed::KeyedRecord,
+        metrics::{IngestionMetrics, IngestionMetricsSnapshot},
+        partition::{self, DEFAULT_PARTITION_COUNT},
+        ports::IngestionServicePort,
+        topic,
     },
     ports::StorageRepository,
 };
@@ -19,6 +31,10 @@ pub struct IngestionConfig {
     pub max_segment_size: usize,
     /// Minimum segment size in bytes (default: 1 byte)
     pub min_segment_size: usize,
+    /// Codec applied to segments before storage (default: none)
+    pub compression: Compression,
+    /// Number of partitions partition keys are hashed into (default: 64)
+    pub partitions: u32,
 }
 
 impl Default for IngestionConfig {
@@ -26,10 +42,128 @@ impl Default for IngestionConfig {
         Self {
             max_segment_size: 100 * 1024 * 1024, // 100MB
             min_segment_size: 1,
+            compression: Compression::None,
+            partitions: DEFAULT_PARTITION_COUNT,
         }
     }
 }
 
+/// Maximum length of a client idempotency key in bytes
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Outcome of an idempotent ingestion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestOutcome {
+    /// The segment was stored by this request
+    Created(SegmentId),
+    /// The segment was already stored by an earlier request with the same key
+    Duplicate(SegmentId),
+}
+
+impl IngestOutcome {
+    /// ID of the stored segment
+    pub fn segment_id(&self) -> SegmentId {
+        match self {
+            Self::Created(id) | Self::Duplicate(id) => *id,
+        }
+    }
+
+    /// Whether this request stored the segment
+    pub fn is_created(&self) -> bool {
+        matches!(self, Self::Created(_))
+    }
+}
+
+/// Options of an ingestion request
+#[derive(Debug, Clone, Default)]
+pub struct IngestOptions {
+    /// Client idempotency key, claimed for the segment in its topic
+    pub idempotency_key: Option<String>,
+    /// Client-chosen segment ID
+    pub segment_id: Option<SegmentId>,
+    /// Checksum of the data as computed by the client
+    pub expected_checksum: Option<Checksum>,
+    /// Record key, making the segment a version of that key
+    pub record_key: Option<String>,
+    /// Partition key, routing the segment to the partition of that key
+    pub partition_key: Option<String>,
+    /// Topic, selecting per-topic storage settings
+    pub topic: Option<String>,
+}
+
+impl IngestOptions {
+    /// Set the client idempotency key
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Set the client-chosen segment ID
+    pub fn with_segment_id(mut self, segment_id: SegmentId) -> Self {
+        self.segment_id = Some(segment_id);
+        self
+    }
+
+    /// Set the checksum the data must match
+    pub fn with_expected_checksum(mut self, checksum: Checksum) -> Self {
+        self.expected_checksum = Some(checksum);
+        self
+    }
+
+    /// Set the record key of the segment
+    pub fn with_record_key(mut self, key: impl Into<String>) -> Self {
+        self.record_key = Some(key.into());
+        self
+    }
+
+    /// Set the partition key of the segment
+    pub fn with_partition_key(mut self, key: impl Into<String>) -> Self {
+        self.partition_key = Some(key.into());
+        self
+    }
+
+    /// Set the topic of the segment
+    pub fn with_topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Check the idempotency key and segment ID chosen by the client
+    fn validate_idempotency(&self) -> Result<(), IngestionError> {
+        let Some(key) = &self.idempotency_key else {
+            return Ok(());
+        };
+        if self.segment_id.is_some() {
+            return Err(IngestionError::invalid_data(
+                "Use either an idempotency key or a segment ID, not both",
+            ));
+        }
+        if key.is_empty() {
+            return Err(IngestionError::invalid_data(
+                "Idempotency key cannot be empty",
+            ));
+        }
+        if key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(IngestionError::invalid_data(format!(
+                "Idempotency key length ({}) exceeds maximum ({})",
+                key.len(),
+                MAX_IDEMPOTENCY_KEY_LEN
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Result of claiming an idempotency key
+enum Claimed {
+    /// The key is claimed for this request
+    New(IdempotencyClaim),
+    /// An earlier request claimed the key, its segment may not be stored
+    Pending(IdempotencyClaim),
+    /// An earlier request claimed the key and stored this segment
+    Committed(SegmentId),
+}
+
 /// Service for ingesting data into the ZukLink platform
 ///
 /// This service encapsulates the business rules for data ingestion:
@@ -47,6 +181,7 @@ impl Default for IngestionConfig {
 pub struct IngestionService<R> {
     repository: R,
     config: IngestionConfig,
+    metrics: IngestionMetrics,
 }
 
 impl<R> IngestionService<R>
@@ -55,7 +190,11 @@ where
 {
     /// Create a new IngestionService with the given repository and configuration
     pub fn new(repository: R, config: IngestionConfig) -> Self {
-        Self { repository, config }
+        Self {
+            repository,
+            config,
+            metrics: IngestionMetrics::default(),
+        }
     }
 
     /// Create a new IngestionService with default configuration
@@ -67,8 +206,8 @@ where
     ///
     /// This is the main entry point for data ingestion. It:
     /// 1. Validates the data according to business rules
-    /// 2. Creates a Segment entity
-    /// 3. Persists the data via the storage repository
+    /// 2. Creates a Segment entity, computing its checksum
+    /// 3. Persists the data and checksum via the storage repository
     /// 4. Returns the segment ID for tracking
     ///
     /// # Arguments
@@ -86,6 +225,241 @@ where
     /// - `IngestionError::StorageFailure` if storage operation fails
     ///
     pub async fn ingest_data(&self, data: Vec<u8>) -> Result<SegmentId, IngestionError> {
+        self.ingest(data, IngestOptions::default())
+            .await
+            .map(|outcome| outcome.segment_id())
+    }
+
+    /// Ingest raw data with request options
+    ///
+    /// Without a segment ID, a fresh segment ID is generated. With an
+    /// idempotency key, the key is claimed for that segment in its topic, and
+    /// a retried request returns `IngestOutcome::Duplicate` with the original
+    /// ID instead of creating a duplicate, even once the original segment is
+    /// compacted or expired. With a client-chosen segment ID, the segment is
+    /// written only if it does not exist yet. Either way, a retry carrying
+    /// other data, or routed to another partition, is rejected and the stored
+    /// segment is left untouched.
+    ///
+    /// If the client sent the checksum of its data, the data received is
+    /// verified against it before anything is stored.
+    ///
+    /// With a record key, the segment is a version of that key. Keyed
+    /// segments may be empty: an empty one is a tombstone deleting the key.
+    ///
+    /// With a partition key, the segment is routed to the partition of that
+    /// key, among the configured number of partitions.
+    ///
+    /// With a topic, the segment is stored with the settings of that topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes to ingest
+    /// * `options` - Idempotency key, segment ID, expected checksum, record and partition keys, topic
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the options are invalid
+    /// - `IngestionError::ChecksumMismatch` if the data does not match the expected checksum
+    /// - `IngestionError::IdempotencyConflict` if a retry differs from the request it repeats
+    /// - Same errors as [`ingest_data`](Self::ingest_data) otherwise
+    pub async fn ingest(
+        &self,
+        data: Vec<u8>,
+        options: IngestOptions,
+    ) -> Result<IngestOutcome, IngestionError> {
+        if let Some(key) = &options.record_key {
+            KeyedRecord::validate_key(key)?;
+        }
+        if let Some(key) = &options.partition_key {
+            partition::validate_key(key)?;
+        }
+        if let Some(topic) = &options.topic {
+            topic::validate(topic)?;
+        }
+        // Business rule: An empty keyed segment is a tombstone, not empty data
+        if !(data.is_empty() && options.record_key.is_some()) {
+            self.validate(&data)?;
+        }
+
+        options.validate_idempotency()?;
+        let client_id = options.segment_id;
+
+        // Create domain entity, computing the checksum of the data
+        let mut segment = Segment::with_id(client_id.unwrap_or_default(), &data);
+        if let Some(key) = options.record_key {
+            segment.set_record_key(key);
+        }
+        if let Some(key) = &options.partition_key {
+            segment.set_partition(partition::partition_of(key, self.config.partitions));
+        }
+        if let Some(topic) = options.topic {
+            segment.set_topic(topic);
+        }
+
+        // Business rule: The data must match the checksum sent by the client
+        if let (Some(expected), Some(actual)) = (&options.expected_checksum, segment.checksum()) {
+            if expected != actual {
+                return Err(IngestionError::checksum_mismatch(expected, actual));
+            }
+        }
+
+        // Compress for storage, the checksum still covers the data as received
+        let (stored, elapsed) = self.compress(&data)?;
+        segment.set_compression(self.config.compression);
+
+        if let Some(key) = &options.idempotency_key {
+            let claim = match self.claim(key, &segment).await? {
+                Claimed::New(claim) => claim,
+                Claimed::Pending(claim) => {
+                    // The request that claimed the key may have failed
+                    // before storing the segment: store it under its ID
+                    segment.set_id(claim.segment_id);
+                    claim
+                }
+                Claimed::Committed(segment_id) => return Ok(IngestOutcome::Duplicate(segment_id)),
+            };
+            let outcome = self
+                .store(&segment, &stored, data.len(), elapsed, true)
+                .await?;
+            self.repository
+                .commit_idempotency_key(&claim.committed())
+                .await?;
+            return Ok(outcome);
+        }
+
+        // Persist via repository (infrastructure concern)
+        let outcome = self
+            .store(&segment, &stored, data.len(), elapsed, client_id.is_some())
+            .await?;
+        if !outcome.is_created() {
+            self.check_retry(&segment).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Store a segment, only if it is absent with `if_absent`
+    ///
+    /// A segment stored already is reported as `IngestOutcome::Duplicate`.
+    async fn store(
+        &self,
+        segment: &Segment,
+        stored: &[u8],
+        received: usize,
+        elapsed: Duration,
+        if_absent: bool,
+    ) -> Result<IngestOutcome, IngestionError> {
+        let saved = if if_absent {
+            self.repository.save_if_absent(segment, stored).await
+        } else {
+            self.repository.save(segment, stored).await
+        };
+
+        match saved {
+            Ok(_) => {
+                self.metrics.record_ingest(received, stored.len(), elapsed);
+                Ok(IngestOutcome::Created(*segment.id()))
+            }
+            Err(IngestionError::SegmentAlreadyExists(_)) if if_absent => {
+                Ok(IngestOutcome::Duplicate(*segment.id()))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Claim an idempotency key for a segment
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::IdempotencyConflict` if the key was claimed
+    /// for other data or another partition
+    async fn claim(&self, key: &str, segment: &Segment) -> Result<Claimed, IngestionError> {
+        let claim = IdempotencyClaim::new(key, segment);
+        let Some(existing) = self.repository.claim_idempotency_key(&claim).await? else {
+            return Ok(Claimed::New(claim));
+        };
+        if !existing.matches(segment) {
+            return Err(IngestionError::idempotency_conflict(existing.segment_id));
+        }
+        Ok(if existing.committed {
+            Claimed::Committed(existing.segment_id)
+        } else {
+            Claimed::Pending(existing)
+        })
+    }
+
+    /// Check that a retry carries the data of the segment it targets
+    ///
+    /// Segments stored without a checksum cannot be compared, and are taken
+    /// as the same data.
+    async fn check_retry(&self, segment: &Segment) -> Result<(), IngestionError> {
+        let stored = self.repository.load(segment.id()).await?;
+        match (stored.checksum, segment.checksum()) {
+            (Some(stored), Some(retried)) if stored != *retried => {
+                Err(IngestionError::idempotency_conflict(segment.id()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compress data with the configured codec, timing the work
+    fn compress<'a>(&self, data: &'a [u8]) -> Result<(Cow<'a, [u8]>, Duration), IngestionError> {
+        let started = Instant::now();
+        let stored = match self.config.compression {
+            Compression::None => Cow::Borrowed(data),
+            codec => Cow::Owned(codec.compress(data)?),
+        };
+        Ok((stored, started.elapsed()))
+    }
+
+    /// Ingest raw data under a client idempotency key
+    ///
+    /// The key is claimed for the new segment, so a retried request with the
+    /// same key returns the ID of the segment stored by the first attempt
+    /// instead of creating a duplicate. See [`ingest`](Self::ingest).
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The raw bytes to ingest
+    /// * `idempotency_key` - Client-chosen key, unique per logical request
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::InvalidData` if the key is empty or too long
+    /// - Same errors as [`ingest_data`](Self::ingest_data) otherwise
+    pub async fn ingest_idempotent(
+        &self,
+        data: Vec<u8>,
+        idempotency_key: &str,
+    ) -> Result<IngestOutcome, IngestionError> {
+        self.ingest(
+            data,
+            IngestOptions::default().with_idempotency_key(idempotency_key),
+        )
+        .await
+    }
+
+    /// Ingest raw data under a client-chosen segment ID
+    ///
+    /// Like [`ingest_idempotent`](Self::ingest_idempotent), but the client
+    /// picks the segment ID directly. If a segment with this ID already
+    /// exists, it is left untouched and `IngestOutcome::Duplicate` is returned,
+    /// or `IngestionError::IdempotencyConflict` if its data differs.
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`ingest_data`](Self::ingest_data)
+    pub async fn ingest_with_id(
+        &self,
+        data: Vec<u8>,
+        segment_id: SegmentId,
+    ) -> Result<IngestOutcome, IngestionError> {
+        self.ingest(data, IngestOptions::default().with_segment_id(segment_id))
+            .await
+    }
+
+    /// Check the business rules on the size of ingested data
+    fn validate(&self, data: &[u8]) -> Result<(), IngestionError> {
         // Business rule: Cannot ingest empty data
         if data.is_empty() {
             return Err(IngestionError::EmptySegment);
@@ -108,17 +482,7 @@ where
             )));
         }
 
-        // Create domain entity
-        let mut segment = Segment::new(data.clone());
-
-        // Persist via repository (infrastructure concern)
-        let storage_key = self.repository.save(&segment, &data).await?;
-
-        // Update segment with storage location
-        segment.set_storage_key(storage_key);
-
-        // Return segment ID for tracking
-        Ok(*segment.id())
+        Ok(())
     }
 
     /// Retrieve a segment's data from storage
@@ -133,12 +497,19 @@ where
     ///
     /// # Errors
     ///
-    /// Returns `IngestionError::StorageFailure` if the segment doesn't exist or retrieval fails
+    /// - `IngestionError::StorageFailure` if the segment doesn't exist or retrieval fails
+    /// - `IngestionError::ChecksumMismatch` if the data doesn't match its stored checksum
     pub async fn get_segment_data(
         &self,
         segment_id: &SegmentId,
     ) -> Result<Vec<u8>, IngestionError> {
-        self.repository.get(segment_id).await
+        let stored = self.repository.load(segment_id).await?;
+
+        let started = Instant::now();
+        let data = stored.into_verified_data()?;
+        self.metrics.record_read(started.elapsed());
+
+        Ok(data)
     }
 
     /// Check if a segment exists
@@ -171,6 +542,11 @@ where
     pub fn config(&self) -> &IngestionConfig {
         &self.config
     }
+
+    /// Get a snapshot of the ingestion metrics
+    pub fn metrics(&self) -> IngestionMetricsSnapshot {
+        self.metrics.snapshot()
+    }
 }
 
 // Implement the IngestionServicePort trait for IngestionService
@@ -185,6 +561,30 @@ where
         self.ingest_data(data)
     }
 
+    fn ingest(
+        &self,
+        data: Vec<u8>,
+        options: IngestOptions,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send {
+        self.ingest(data, options)
+    }
+
+    fn ingest_idempotent(
+        &self,
+        data: Vec<u8>,
+        idempotency_key: &str,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send {
+        self.ingest_idempotent(data, idempotency_key)
+    }
+
+    fn ingest_with_id(
+        &self,
+        data: Vec<u8>,
+        segment_id: SegmentId,
+    ) -> impl Future<Output = Result<IngestOutcome, IngestionError>> + Send {
+        self.ingest_with_id(data, segment_id)
+    }
+
     fn get_segment_data(
         &self,
         segment_id: &SegmentId,
@@ -210,18 +610,35 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ingestion::entity::StoredSegment;
     use std::collections::HashMap;
     use std::future::Future;
     use std::sync::{Arc, Mutex};
 
+    /// Expectation of a mocked write
+    type SaveFn = Arc<dyn Fn(&Segment, &[u8]) -> Result<String, IngestionError> + Send + Sync>;
+
+    /// Expectation of a mocked lookup of a segment
+    type LookupFn<T> = Arc<dyn Fn(&SegmentId) -> Result<T, IngestionError> + Send + Sync>;
+
+    /// Idempotency claims by topic and key
+    type Claims = HashMap<(Option<String>, String), IdempotencyClaim>;
+
+    /// Expectation of a mocked write of an idempotency claim
+    type ClaimFn<T> = Arc<dyn Fn(&IdempotencyClaim) -> Result<T, IngestionError> + Send + Sync>;
+
     /// Mock StorageRepository using builder pattern for testing
     /// Compatible with RPITIT (Return Position Impl Trait In Trait)
     #[derive(Clone)]
     struct MockStorageRepo {
-        save_fn: Arc<dyn Fn(&Segment, &[u8]) -> Result<String, IngestionError> + Send + Sync>,
-        get_fn: Arc<dyn Fn(&SegmentId) -> Result<Vec<u8>, IngestionError> + Send + Sync>,
-        exists_fn: Arc<dyn Fn(&SegmentId) -> Result<bool, IngestionError> + Send + Sync>,
-        delete_fn: Arc<dyn Fn(&SegmentId) -> Result<(), IngestionError> + Send + Sync>,
+        save_fn: SaveFn,
+        save_if_absent_fn: SaveFn,
+        claim_fn: ClaimFn<Option<IdempotencyClaim>>,
+        commit_fn: ClaimFn<()>,
+        get_fn: LookupFn<Vec<u8>>,
+        load_fn: LookupFn<StoredSegment>,
+        exists_fn: LookupFn<bool>,
+        delete_fn: LookupFn<()>,
     }
 
     impl MockStorageRepo {
@@ -230,7 +647,13 @@ mod tests {
                 save_fn: Arc::new(|_, _| {
                     Err(IngestionError::storage_failure("No expectation set"))
                 }),
+                save_if_absent_fn: Arc::new(|_, _| {
+                    Err(IngestionError::storage_failure("No expectation set"))
+                }),
+                claim_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
+                commit_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
                 get_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
+                load_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
                 exists_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
                 delete_fn: Arc::new(|_| Err(IngestionError::storage_failure("No expectation set"))),
             }
@@ -244,11 +667,44 @@ mod tests {
             self
         }
 
+        fn with_save_if_absent<F>(mut self, f: F) -> Self
+        where
+            F: Fn(&Segment, &[u8]) -> Result<String, IngestionError> + Send + Sync + 'static,
+        {
+            self.save_if_absent_fn = Arc::new(f);
+            self
+        }
+
+        fn with_claims<F, G>(mut self, claim: F, commit: G) -> Self
+        where
+            F: Fn(&IdempotencyClaim) -> Result<Option<IdempotencyClaim>, IngestionError>
+                + Send
+                + Sync
+                + 'static,
+            G: Fn(&IdempotencyClaim) -> Result<(), IngestionError> + Send + Sync + 'static,
+        {
+            self.claim_fn = Arc::new(claim);
+            self.commit_fn = Arc::new(commit);
+            self
+        }
+
         fn with_get<F>(mut self, f: F) -> Self
         where
             F: Fn(&SegmentId) -> Result<Vec<u8>, IngestionError> + Send + Sync + 'static,
         {
-            self.get_fn = Arc::new(f);
+            // Data returned by `get` is also returned by `load`, without checksum
+            let f = Arc::new(f);
+            let get_fn = f.clone();
+            self.get_fn = Arc::new(move |id| get_fn(id));
+            self.load_fn = Arc::new(move |id| f(id).map(|data| StoredSegment::new(data, None)));
+            self
+        }
+
+        fn with_load<F>(mut self, f: F) -> Self
+        where
+            F: Fn(&SegmentId) -> Result<StoredSegment, IngestionError> + Send + Sync + 'static,
+        {
+            self.load_fn = Arc::new(f);
             self
         }
 
@@ -279,6 +735,31 @@ mod tests {
             async move { result }
         }
 
+        fn save_if_absent(
+            &self,
+            segment: &Segment,
+            data: &[u8],
+        ) -> impl Future<Output = Result<String, IngestionError>> + Send {
+            let result = (self.save_if_absent_fn)(segment, data);
+            async move { result }
+        }
+
+        fn claim_idempotency_key(
+            &self,
+            claim: &IdempotencyClaim,
+        ) -> impl Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send {
+            let result = (self.claim_fn)(claim);
+            async move { result }
+        }
+
+        fn commit_idempotency_key(
+            &self,
+            claim: &IdempotencyClaim,
+        ) -> impl Future<Output = Result<(), IngestionError>> + Send {
+            let result = (self.commit_fn)(claim);
+            async move { result }
+        }
+
         fn get(
             &self,
             segment_id: &SegmentId,
@@ -287,6 +768,14 @@ mod tests {
             async move { result }
         }
 
+        fn load(
+            &self,
+            segment_id: &SegmentId,
+        ) -> impl Future<Output = Result<StoredSegment, IngestionError>> + Send {
+            let result = (self.load_fn)(segment_id);
+            async move { result }
+        }
+
         fn exists(
             &self,
             segment_id: &SegmentId,
@@ -363,20 +852,48 @@ mod tests {
         }
 
         fn with_in_memory_storage(mut self) -> Self {
-            let data: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::new()));
+            let data: Arc<Mutex<HashMap<String, StoredSegment>>> =
+                Arc::new(Mutex::new(HashMap::new()));
 
             let data_clone = data.clone();
             self.storage = self.storage.with_save(move |seg, bytes| {
                 let key = format!("data/{}.zuk", seg.id());
+                data_clone.lock().unwrap().insert(
+                    key.clone(),
+                    StoredSegment::new(bytes.to_vec(), seg.checksum().copied())
+                        .with_compression(seg.compression()),
+                );
+                Ok(key)
+            });
+
+            let data_clone = data.clone();
+            self.storage = self.storage.with_save_if_absent(move |seg, bytes| {
+                let key = format!("data/{}.zuk", seg.id());
+                let mut data = data_clone.lock().unwrap();
+                if data.contains_key(&key) {
+                    return Err(IngestionError::segment_already_exists(seg.id()));
+                }
+                data.insert(
+                    key.clone(),
+                    StoredSegment::new(bytes.to_vec(), seg.checksum().copied())
+                        .with_compression(seg.compression()),
+                );
+                Ok(key)
+            });
+
+            let data_clone = data.clone();
+            self.storage = self.storage.with_get(move |seg_id| {
+                let key = format!("data/{}.zuk", seg_id);
                 data_clone
                     .lock()
                     .unwrap()
-                    .insert(key.clone(), bytes.to_vec());
-                Ok(key)
+                    .get(&key)
+                    .map(|stored| stored.data.clone())
+                    .ok_or_else(|| IngestionError::storage_failure("Segment not found"))
             });
 
             let data_clone = data.clone();
-            self.storage = self.storage.with_get(move |seg_id| {
+            self.storage = self.storage.with_load(move |seg_id| {
                 let key = format!("data/{}.zuk", seg_id);
                 data_clone
                     .lock()
@@ -399,6 +916,26 @@ mod tests {
                 Ok(())
             });
 
+            // Claims are scoped per topic and outlive their segments
+            let claims: Arc<Mutex<Claims>> = Arc::new(Mutex::new(HashMap::new()));
+            let claims_clone = claims.clone();
+            self.storage = self.storage.with_claims(
+                move |claim| {
+                    let scope = (claim.topic.clone(), claim.key.clone());
+                    let mut claims = claims_clone.lock().unwrap();
+                    if let Some(existing) = claims.get(&scope) {
+                        return Ok(Some(existing.clone()));
+                    }
+                    claims.insert(scope, claim.clone());
+                    Ok(None)
+                },
+                move |claim| {
+                    let scope = (claim.topic.clone(), claim.key.clone());
+                    claims.lock().unwrap().insert(scope, claim.clone());
+                    Ok(())
+                },
+            );
+
             self
         }
 
@@ -414,6 +951,13 @@ mod tests {
             self
         }
 
+        fn with_compression(mut self, compression: Compression) -> Self {
+            let mut config = self.config.take().unwrap_or_default();
+            config.compression = compression;
+            self.config = Some(config);
+            self
+        }
+
         fn with_min_segment_size(mut self, min_size: usize) -> Self {
             let mut config = self.config.take().unwrap_or_default();
             config.min_segment_size = min_size;
@@ -589,6 +1133,435 @@ mod tests {
         ));
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_returns_original_segment() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let first = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+        let retry = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+
+        assert!(first.is_created());
+        assert_eq!(retry, IngestOutcome::Duplicate(first.segment_id()));
+        // Generated like any other ID, ordered by creation time
+        assert!(first.segment_id().timestamp().is_some());
+
+        // A different key creates a different segment
+        let other = service
+            .ingest_idempotent(vec![1, 2, 3], "order-43")
+            .await
+            .unwrap();
+        assert!(other.is_created());
+        assert_ne!(other.segment_id(), first.segment_id());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_rejects_conflicting_retry() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let first = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+        let retry = service.ingest_idempotent(vec![4, 5, 6], "order-42").await;
+        assert!(matches!(retry, Err(IngestionError::IdempotencyConflict(_))));
+
+        let data = service.get_segment_data(&first.segment_id()).await.unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+
+        let conflicting = service
+            .ingest_with_id(vec![4, 5, 6], first.segment_id())
+            .await;
+        assert!(matches!(
+            conflicting,
+            Err(IngestionError::IdempotencyConflict(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_with_id_accepts_retry_of_legacy_segment() {
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder
+            .storage
+            .with_save_if_absent(|seg, _| Err(IngestionError::segment_already_exists(seg.id())))
+            .with_load(|_| Ok(StoredSegment::new(vec![9, 9, 9], None)));
+        let service = builder.build();
+
+        // Stored without a checksum: nothing to compare
+        let segment_id = SegmentId::new();
+        let outcome = service
+            .ingest_with_id(vec![1, 2, 3], segment_id)
+            .await
+            .unwrap();
+        assert_eq!(outcome, IngestOutcome::Duplicate(segment_id));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_survives_expired_segment() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let first = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+        service.delete_segment(&first.segment_id()).await.unwrap();
+
+        // The claim outlives the segment: the retry stores nothing
+        let retry = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+        assert_eq!(retry, IngestOutcome::Duplicate(first.segment_id()));
+        assert!(!service.segment_exists(&first.segment_id()).await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_scopes_keys() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+        let ingest = |options: IngestOptions| service.ingest(vec![1, 2, 3], options);
+        let keyed = || IngestOptions::default().with_idempotency_key("order-42");
+
+        let first = ingest(keyed().with_partition_key("customer-1"))
+            .await
+            .unwrap();
+
+        // Another partition is another request
+        let moved = ingest(keyed().with_partition_key("customer-2")).await;
+        assert!(matches!(moved, Err(IngestionError::IdempotencyConflict(_))));
+
+        // Another topic is another scope
+        let billing = ingest(
+            keyed()
+                .with_partition_key("customer-1")
+                .with_topic("billing"),
+        )
+        .await
+        .unwrap();
+        assert!(billing.is_created());
+        assert_ne!(billing.segment_id(), first.segment_id());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_completes_pending_claim() {
+        let pending = IdempotencyClaim::new("order-42", &Segment::new(vec![1, 2, 3]));
+        let claimed = pending.clone();
+        let committed = Arc::new(Mutex::new(None));
+        let committed_clone = committed.clone();
+
+        let mut builder = IngestionServiceTestBuilder::new().with_in_memory_storage();
+        builder.storage = builder.storage.with_claims(
+            move |_| Ok(Some(claimed.clone())),
+            move |claim| {
+                *committed_clone.lock().unwrap() = Some(claim.clone());
+                Ok(())
+            },
+        );
+        let service = builder.build();
+
+        // The first attempt claimed the key but never stored the segment
+        let retry = service
+            .ingest_idempotent(vec![1, 2, 3], "order-42")
+            .await
+            .unwrap();
+        assert_eq!(retry, IngestOutcome::Created(pending.segment_id));
+        assert_eq!(
+            service.get_segment_data(&pending.segment_id).await.unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(committed.lock().unwrap().clone(), Some(pending.committed()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_idempotent_rejects_invalid_keys() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let result = service.ingest_idempotent(vec![1, 2, 3], "").await;
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+
+        let long_key = "k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1);
+        let result = service.ingest_idempotent(vec![1, 2, 3], &long_key).await;
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_with_matching_checksum() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let data = vec![1, 2, 3];
+        let options = IngestOptions::default().with_expected_checksum(Checksum::sha256(&data));
+        let outcome = service.ingest(data.clone(), options).await.unwrap();
+
+        assert!(outcome.is_created());
+        assert_eq!(
+            service
+                .get_segment_data(&outcome.segment_id())
+                .await
+                .unwrap(),
+            data
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_keyed_segment_and_tombstone() {
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let saved_clone = saved.clone();
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder.storage.with_save(move |seg, _| {
+            saved_clone.lock().unwrap().push(seg.keyed_record());
+            Ok(format!("{}.zuk", seg.id()))
+        });
+        let service = builder.build();
+
+        let options = IngestOptions::default().with_record_key("user-42");
+        service
+            .ingest(vec![1, 2, 3], options.clone())
+            .await
+            .unwrap();
+        service.ingest(Vec::new(), options).await.unwrap();
+
+        let saved = saved.lock().unwrap();
+        let records: Vec<_> = saved.iter().flatten().collect();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.key == "user-42"));
+        assert!(!records[0].tombstone);
+        assert!(records[1].tombstone);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_rejects_invalid_record_keys() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_successful_save()
+            .build();
+
+        let empty_key = IngestOptions::default().with_record_key("");
+        let result = service.ingest(vec![1, 2, 3], empty_key).await;
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+
+        // Only keyed segments may be empty
+        let result = service.ingest(Vec::new(), IngestOptions::default()).await;
+        assert!(matches!(result, Err(IngestionError::EmptySegment)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_routes_partitioned_segments() {
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let saved_clone = saved.clone();
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder.storage.with_save(move |seg, _| {
+            saved_clone.lock().unwrap().push(seg.partition());
+            Ok(format!("{}.zuk", seg.id()))
+        });
+        let service = builder
+            .with_config(IngestionConfig {
+                partitions: 8,
+                ..IngestionConfig::default()
+            })
+            .build();
+
+        let customer = IngestOptions::default().with_partition_key("customer-42");
+        service
+            .ingest(vec![1, 2, 3], customer.clone())
+            .await
+            .unwrap();
+        service.ingest(vec![4, 5, 6], customer).await.unwrap();
+        service
+            .ingest(vec![7, 8, 9], IngestOptions::default())
+            .await
+            .unwrap();
+
+        let expected = partition::partition_of("customer-42", 8);
+        assert_eq!(
+            *saved.lock().unwrap(),
+            vec![Some(expected), Some(expected), None]
+        );
+
+        let empty_key = IngestOptions::default().with_partition_key("");
+        let result = service.ingest(vec![1, 2, 3], empty_key).await;
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_sets_topic() {
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let saved_clone = saved.clone();
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder.storage.with_save(move |seg, _| {
+            saved_clone
+                .lock()
+                .unwrap()
+                .push(seg.topic().map(String::from));
+            Ok(format!("{}.zuk", seg.id()))
+        });
+        let service = builder.build();
+
+        let billing = IngestOptions::default().with_topic("billing");
+        service.ingest(vec![1, 2, 3], billing).await.unwrap();
+        service
+            .ingest(vec![4, 5, 6], IngestOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *saved.lock().unwrap(),
+            vec![Some("billing".to_string()), None]
+        );
+
+        let invalid = IngestOptions::default().with_topic("billing/eu");
+        let result = service.ingest(vec![1, 2, 3], invalid).await;
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+        assert_eq!(saved.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_with_checksum_mismatch_stores_nothing() {
+        let stored = Arc::new(Mutex::new(false));
+        let stored_clone = stored.clone();
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder.storage.with_save(move |seg, _| {
+            *stored_clone.lock().unwrap() = true;
+            Ok(format!("data/{}.zuk", seg.id()))
+        });
+        let service = builder.build();
+
+        let options = IngestOptions::default().with_expected_checksum(Checksum::sha256(&[1, 2, 4]));
+        let result = service.ingest(vec![1, 2, 3], options).await;
+
+        assert!(matches!(
+            result,
+            Err(IngestionError::ChecksumMismatch { .. })
+        ));
+        assert!(!*stored.lock().unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_rejects_key_and_segment_id() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let options = IngestOptions::default()
+            .with_idempotency_key("order-42")
+            .with_segment_id(SegmentId::new());
+        let result = service.ingest(vec![1, 2, 3], options).await;
+
+        assert!(matches!(result, Err(IngestionError::InvalidData(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_segment_data_detects_corruption() {
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder.storage.with_load(|_| {
+            Ok(StoredSegment::new(
+                vec![1, 2, 4],
+                Some(Checksum::sha256(&[1, 2, 3])),
+            ))
+        });
+        let service = builder.build();
+
+        let result = service.get_segment_data(&SegmentId::new()).await;
+
+        assert!(matches!(
+            result,
+            Err(IngestionError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_compressed_segment_roundtrip() {
+        let stored = Arc::new(Mutex::new(Vec::new()));
+        let stored_clone = stored.clone();
+        let mut builder = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .with_compression(Compression::Gzip);
+        let save_if_absent = builder.storage.save_if_absent_fn.clone();
+        builder.storage = builder.storage.with_save_if_absent(move |seg, bytes| {
+            assert_eq!(seg.compression(), Compression::Gzip);
+            *stored_clone.lock().unwrap() = bytes.to_vec();
+            save_if_absent(seg, bytes)
+        });
+        let service = builder.build();
+
+        let data = "{\"metric\":\"cpu\",\"value\":42}\n"
+            .repeat(200)
+            .into_bytes();
+        let segment_id = service
+            .ingest_idempotent(data.clone(), "batch-1")
+            .await
+            .unwrap()
+            .segment_id();
+
+        // Stored compressed, read back as ingested
+        assert!(stored.lock().unwrap().len() < data.len() / 4);
+        assert_eq!(service.get_segment_data(&segment_id).await.unwrap(), data);
+
+        let metrics = service.metrics();
+        assert_eq!(metrics.segments_ingested, 1);
+        assert_eq!(metrics.bytes_received, data.len() as u64);
+        assert!(metrics.compression_ratio() > 4.0);
+        assert_eq!(metrics.segments_read, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_duplicates_are_not_counted_in_metrics() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        for _ in 0..3 {
+            service
+                .ingest_idempotent(vec![1, 2, 3], "order-42")
+                .await
+                .unwrap();
+        }
+
+        let metrics = service.metrics();
+        assert_eq!(metrics.segments_ingested, 1);
+        assert_eq!(metrics.bytes_stored, 3);
+        assert_eq!(metrics.compression_ratio(), 1.0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_with_id_validates_data() {
+        let service = IngestionServiceTestBuilder::new()
+            .with_in_memory_storage()
+            .build();
+
+        let result = service.ingest_with_id(vec![], SegmentId::new()).await;
+        assert!(matches!(result, Err(IngestionError::EmptySegment)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ingest_with_id_storage_failure() {
+        let mut builder = IngestionServiceTestBuilder::new();
+        builder.storage = builder
+            .storage
+            .with_save_if_absent(|_, _| Err(IngestionError::storage_failure("S3 down")));
+        let service = builder.build();
+
+        let result = service
+            .ingest_with_id(vec![1, 2, 3], SegmentId::new())
+            .await;
+        assert!(matches!(result, Err(IngestionError::StorageFailure(_))));
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_full_lifecycle_with_in_memory_storage() {
         let service = IngestionServiceTestBuilder::new()
@@ -654,6 +1627,7 @@ mod tests {
         let config = IngestionConfig {
             max_segment_size: 1024,
             min_segment_size: 10,
+            ..IngestionConfig::default()
         };
 
         let service = IngestionServiceTestBuilder::new()
@@ -0,0 +1,65 @@
+//! Topics
+//!
+//! A segment may be ingested under a topic, naming the stream it belongs to
+//! (`billing`, `audit.events`). Topics select per-topic storage settings, such
+//! as the S3 options of the segments carrying personal data; segments without
+//! a topic use the global settings.
+//!
+//! Topic names are short ASCII identifiers, so they can be used in
+//! environment variable names and object metadata as is.
+
+use crate::ingestion::error::IngestionError;
+
+/// Maximum length of a topic name in bytes
+pub const MAX_TOPIC_LEN: usize = 128;
+
+/// Check that a topic name is usable
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the name is empty, longer than
+/// [`MAX_TOPIC_LEN`] bytes, or contains anything but ASCII letters, digits,
+/// `-`, `_` and `.`
+pub fn validate(topic: &str) -> Result<(), IngestionError> {
+    if topic.is_empty() {
+        return Err(IngestionError::invalid_data("Topic cannot be empty"));
+    }
+    if topic.len() > MAX_TOPIC_LEN {
+        return Err(IngestionError::invalid_data(format!(
+            "Topic length ({}) exceeds maximum ({})",
+            topic.len(),
+            MAX_TOPIC_LEN
+        )));
+    }
+    if !topic
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+    {
+        return Err(IngestionError::invalid_data(format!(
+            "Invalid topic '{}': only ASCII letters, digits, '-', '_' and '.' are allowed",
+            topic
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_topics() {
+        assert!(validate("billing").is_ok());
+        assert!(validate("audit.events-v2_EU").is_ok());
+        assert!(validate(&"t".repeat(MAX_TOPIC_LEN)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_topics() {
+        assert!(validate("").is_err());
+        assert!(validate(&"t".repeat(MAX_TOPIC_LEN + 1)).is_err());
+        assert!(validate("billing/eu").is_err());
+        assert!(validate("factures-é").is_err());
+        assert!(validate("with space").is_err());
+    }
+}
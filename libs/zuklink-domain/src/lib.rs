@@ -6,6 +6,12 @@
 //! - **Entities**: Core domain models (Segment)
 //! - **Ports**: Trait definitions for external dependencies (StorageRepository)
 //! - **Services**: Business logic orchestration
+//! - **Retention**: Rules deciding when stored segments are deleted
+//! - **Compaction**: Merging of small segments into framed containers
+//! - **Ordering**: Processing of the segments of a shard in creation order
+//! - **Delivery**: Tagged output batches committed exactly once
+//! - **Manifests**: Per-window records of the segments stored, for discovery
+//! - **Replay**: Resets of the positions of consumer groups
 //!
 //! ## Architecture
 //!
@@ -20,6 +26,7 @@
 /// use zuklink_domain::ingestion::service::IngestionService;
 /// use zuklink_domain::ports::StorageRepository;
 /// use zuklink_domain::ingestion::error::IngestionError;
+/// use zuklink_domain::ingestion::idempotency::IdempotencyClaim;
 /// use std::future::Future;
 ///
 /// // Mock implementation for doc-test
@@ -29,9 +36,21 @@
 ///         let key = format!("mock/{}", segment.id());
 ///         async move { Ok(key) }
 ///     }
+///     fn save_if_absent(&self, segment: &Segment, data: &[u8]) -> impl Future<Output = Result<String, IngestionError>> + Send {
+///         self.save(segment, data)
+///     }
+///     fn claim_idempotency_key(&self, _claim: &IdempotencyClaim) -> impl Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send {
+///         async { Ok(None) }
+///     }
+///     fn commit_idempotency_key(&self, _claim: &IdempotencyClaim) -> impl Future<Output = Result<(), IngestionError>> + Send {
+///         async { Ok(()) }
+///     }
 ///     fn get(&self, _id: &zuklink_domain::ingestion::ids::SegmentId) -> impl Future<Output = Result<Vec<u8>, IngestionError>> + Send {
 ///         async { Ok(vec![]) }
 ///     }
+///     fn load(&self, _id: &zuklink_domain::ingestion::ids::SegmentId) -> impl Future<Output = Result<zuklink_domain::ingestion::entity::StoredSegment, IngestionError>> + Send {
+///         async { Ok(zuklink_domain::ingestion::entity::StoredSegment::new(vec![], None)) }
+///     }
 ///     fn exists(&self, _id: &zuklink_domain::ingestion::ids::SegmentId) -> impl Future<Output = Result<bool, IngestionError>> + Send {
 ///         async { Ok(true) }
 ///     }
@@ -49,7 +68,13 @@
 ///     println!("Ingested segment: {}", segment_id);
 /// }
 /// ```
+pub mod compaction;
+pub mod delivery;
 pub mod ingestion;
+pub mod manifest;
+pub mod ordering;
+pub mod replay;
+pub mod retention;
 pub mod storage;
 
 // Re-export ports for convenient access
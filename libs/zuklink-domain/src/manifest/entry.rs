@@ -0,0 +1,144 @@
+//! Manifest entries
+//!
+//! A [`ManifestEntry`] describes one stored segment. A [`Manifest`] holds the
+//! entries of a closed window, sorted by segment ID and without duplicates:
+//! the same window always reads the same way.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ingestion::{
+    checksum::Checksum, entity::Segment, ids::SegmentId, partition::PartitionId,
+};
+
+/// A segment recorded in a manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Identifier of the segment
+    pub segment_id: SegmentId,
+    /// Partition of the segment, if it was ingested with a partition key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition: Option<PartitionId>,
+    /// Size of the stored payload in bytes, after compression and encryption
+    pub size: u64,
+    /// Checksum of the data as received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+    /// Id of the master key the segment is encrypted with, if encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key_id: Option<String>,
+    /// Number of records in the segment
+    pub records: u64,
+    /// Time the segment was created
+    pub created_at: DateTime<Utc>,
+}
+
+impl ManifestEntry {
+    /// Entry of a segment stored with `size` bytes of payload
+    ///
+    /// A segment holds the payload of one ingest request: a single record.
+    pub fn of(segment: &Segment, size: usize) -> Self {
+        Self {
+            segment_id: *segment.id(),
+            partition: segment.partition(),
+            size: size as u64,
+            checksum: segment.checksum().copied(),
+            encryption_key_id: segment.encryption_key_id().map(String::from),
+            records: 1,
+            created_at: *segment.created_at(),
+        }
+    }
+}
+
+/// Sealed manifest of a window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Name of the window, e.g. `2024-06-01T12:05`
+    pub window: String,
+    /// Entries of the window, sorted by segment ID
+    pub entries: Vec<ManifestEntry>,
+    /// Time the manifest was sealed
+    pub sealed_at: DateTime<Utc>,
+}
+
+impl Manifest {
+    /// Seal the entries of a window
+    ///
+    /// Entries recorded more than once, by retried requests, are kept once.
+    pub fn seal(
+        window: impl Into<String>,
+        entries: impl IntoIterator<Item = ManifestEntry>,
+        sealed_at: DateTime<Utc>,
+    ) -> Self {
+        let mut entries: Vec<ManifestEntry> = entries.into_iter().collect();
+        entries.sort_by_key(|entry| (entry.segment_id, entry.partition));
+        entries.dedup_by_key(|entry| (entry.segment_id, entry.partition));
+
+        Self {
+            window: window.into(),
+            entries,
+            sealed_at,
+        }
+    }
+
+    /// Total number of records in the window
+    pub fn records(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.records).sum()
+    }
+
+    /// Total size of the segments of the window in bytes
+    pub fn size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_of_segment() {
+        let mut segment = Segment::new(b"hello".to_vec());
+        segment.set_partition(7);
+        segment.set_encryption_key_id("2024-06");
+
+        let entry = ManifestEntry::of(&segment, 3);
+
+        assert_eq!(entry.segment_id, *segment.id());
+        assert_eq!(entry.partition, Some(7));
+        assert_eq!(entry.size, 3);
+        assert_eq!(entry.checksum, Some(Checksum::sha256(b"hello")));
+        assert_eq!(entry.encryption_key_id.as_deref(), Some("2024-06"));
+        assert_eq!(entry.records, 1);
+    }
+
+    #[test]
+    fn test_seal_sorts_and_deduplicates() {
+        let mut first = ManifestEntry::of(&Segment::new(b"a".to_vec()), 1);
+        let mut second = ManifestEntry::of(&Segment::new(b"b".to_vec()), 1);
+        if second.segment_id < first.segment_id {
+            std::mem::swap(&mut first, &mut second);
+        }
+
+        let manifest = Manifest::seal(
+            "2024-06-01T12:05",
+            [second.clone(), first.clone(), second.clone()],
+            Utc::now(),
+        );
+
+        assert_eq!(manifest.entries, vec![first, second]);
+        assert_eq!(manifest.records(), 2);
+        assert_eq!(manifest.size(), 2);
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let entry = ManifestEntry::of(&Segment::new(b"a".to_vec()), 1);
+        let manifest = Manifest::seal("2024-06-01T12", [entry], Utc::now());
+
+        let json = serde_json::to_string(&manifest).unwrap();
+
+        assert!(!json.contains("partition"));
+        assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+    }
+}
@@ -0,0 +1,12 @@
+//! Manifest domain module
+//!
+//! Senders record every segment they store in a manifest of the time window
+//! (a minute or an hour) it was written in: its ID, partition, size, checksum
+//! and record count. While a [`window::Window`] is open, each segment is one
+//! entry object; once it is closed, its entries are sealed into a single
+//! [`entry::Manifest`], sorted by segment ID. Receivers discover segments by
+//! reading manifests instead of listing the whole bucket, and replays or
+//! audits of a window read the same segments in the same order every time.
+
+pub mod entry;
+pub mod window;
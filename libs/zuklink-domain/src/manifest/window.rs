@@ -0,0 +1,217 @@
+//! Manifest time windows
+//!
+//! Windows are aligned on UTC minutes or hours and named after their start:
+//! `2024-06-01T12:05` for a minute, `2024-06-01T12` for an hour. Names sort in
+//! time order, so manifests list in time order too.
+
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::ingestion::error::IngestionError;
+
+/// Format of the name of a minute window
+const MINUTE_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// Format of the name of an hour window
+const HOUR_FORMAT: &str = "%Y-%m-%dT%H";
+
+/// Length of the windows segments are recorded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum WindowSize {
+    /// One manifest per minute
+    #[default]
+    Minute,
+    /// One manifest per hour
+    Hour,
+}
+
+impl WindowSize {
+    /// Name of the size, as parsed by `FromStr`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+        }
+    }
+
+    /// Length of a window
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Minute => Duration::from_secs(60),
+            Self::Hour => Duration::from_secs(3600),
+        }
+    }
+
+    fn format(&self) -> &'static str {
+        match self {
+            Self::Minute => MINUTE_FORMAT,
+            Self::Hour => HOUR_FORMAT,
+        }
+    }
+}
+
+impl fmt::Display for WindowSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for WindowSize {
+    type Err = IngestionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "minute" => Ok(Self::Minute),
+            "hour" => Ok(Self::Hour),
+            _ => Err(IngestionError::config_error(format!(
+                "Unknown manifest window '{}' (expected minute or hour)",
+                s
+            ))),
+        }
+    }
+}
+
+/// A time window of a given size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Window {
+    /// Start of the window, included
+    start: DateTime<Utc>,
+    /// Length of the window
+    size: WindowSize,
+}
+
+impl Window {
+    /// Window of `size` containing `time`
+    pub fn containing(size: WindowSize, time: DateTime<Utc>) -> Self {
+        let start = match size {
+            WindowSize::Minute => time.with_second(0),
+            WindowSize::Hour => time.with_minute(0).and_then(|time| time.with_second(0)),
+        }
+        .and_then(|time| time.with_nanosecond(0))
+        .unwrap_or(time);
+
+        Self { start, size }
+    }
+
+    /// Parse a window from its name
+    ///
+    /// Returns `None` if the name is not a window name.
+    pub fn parse(name: &str) -> Option<Self> {
+        let size = match name.len() {
+            16 => WindowSize::Minute,
+            13 => WindowSize::Hour,
+            _ => return None,
+        };
+        // Hours alone are not a parseable datetime, pad them with minutes
+        let padded = match size {
+            WindowSize::Minute => name.to_string(),
+            WindowSize::Hour => format!("{}:00", name),
+        };
+        let start = NaiveDateTime::parse_from_str(&padded, MINUTE_FORMAT)
+            .ok()?
+            .and_utc();
+
+        let window = Self { start, size };
+        (window.name() == name).then_some(window)
+    }
+
+    /// Start of the window, included
+    pub fn start(&self) -> DateTime<Utc> {
+        self.start
+    }
+
+    /// End of the window, excluded
+    pub fn end(&self) -> DateTime<Utc> {
+        self.start + chrono::Duration::from_std(self.size.duration()).unwrap_or_default()
+    }
+
+    /// Length of the window
+    pub fn size(&self) -> WindowSize {
+        self.size
+    }
+
+    /// Name of the window, e.g. `2024-06-01T12:05`
+    pub fn name(&self) -> String {
+        self.start.format(self.size.format()).to_string()
+    }
+
+    /// Whether no entry is expected in the window anymore at `now`
+    ///
+    /// `grace` covers requests still in flight at the end of the window and
+    /// clock skew between senders.
+    pub fn is_closed(&self, now: DateTime<Utc>, grace: Duration) -> bool {
+        match chrono::Duration::from_std(grace) {
+            Ok(grace) => self.end() + grace <= now,
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn test_windows_containing() {
+        let minute = Window::containing(WindowSize::Minute, at(12, 5, 42));
+        assert_eq!(minute.start(), at(12, 5, 0));
+        assert_eq!(minute.end(), at(12, 6, 0));
+        assert_eq!(minute.name(), "2024-06-01T12:05");
+
+        let hour = Window::containing(WindowSize::Hour, at(12, 5, 42));
+        assert_eq!(hour.start(), at(12, 0, 0));
+        assert_eq!(hour.end(), at(13, 0, 0));
+        assert_eq!(hour.name(), "2024-06-01T12");
+    }
+
+    #[test]
+    fn test_parse_window_names() {
+        for size in [WindowSize::Minute, WindowSize::Hour] {
+            let window = Window::containing(size, at(23, 59, 59));
+            assert_eq!(Window::parse(&window.name()), Some(window));
+        }
+
+        assert_eq!(Window::parse("2024-06-01T12:5"), None);
+        assert_eq!(Window::parse("2024-13-01T12"), None);
+        assert_eq!(Window::parse("not-a-window-name"), None);
+    }
+
+    #[test]
+    fn test_names_sort_in_time_order() {
+        let earlier = Window::containing(WindowSize::Minute, at(9, 59, 0));
+        let later = Window::containing(WindowSize::Minute, at(10, 0, 0));
+
+        assert!(earlier.name() < later.name());
+        assert!(earlier < later);
+    }
+
+    #[test]
+    fn test_window_closes_after_grace() {
+        let window = Window::containing(WindowSize::Minute, at(12, 5, 0));
+        let grace = Duration::from_secs(30);
+
+        assert!(!window.is_closed(at(12, 6, 0), grace));
+        assert!(!window.is_closed(at(12, 6, 29), grace));
+        assert!(window.is_closed(at(12, 6, 30), grace));
+    }
+
+    #[test]
+    fn test_parse_window_size() {
+        assert_eq!("minute".parse::<WindowSize>().unwrap(), WindowSize::Minute);
+        assert_eq!(" Hour ".parse::<WindowSize>().unwrap(), WindowSize::Hour);
+        assert!("day".parse::<WindowSize>().is_err());
+    }
+}
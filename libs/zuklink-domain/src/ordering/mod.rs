@@ -0,0 +1,10 @@
+//! Ordering domain module
+//!
+//! Receivers may process the segments of a shard strictly in creation order.
+//! Segment IDs are UUID v7, so they sort by the time `zuk-bolt` created them,
+//! but uploads land in S3 in any order: a segment is only processed once the
+//! lateness window past its creation has elapsed, so slightly delayed uploads
+//! still take their place. The [`policy::ShardProgress`] of every shard is
+//! persisted, so the order survives restarts and rebalances.
+
+pub mod policy;
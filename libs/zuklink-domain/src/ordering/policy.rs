@@ -0,0 +1,303 @@
+//! Ordering policy
+//!
+//! Decides which segment of a shard is processed next. Segments are ordered
+//! by [`Position`]: the creation time carried by their UUID v7 ID, then the ID
+//! itself. IDs without a timestamp (chosen by clients, not UUID v7) are
+//! placed at their upload time instead.
+//!
+//! A segment is ready once the lateness window past its creation has elapsed:
+//! until then, a segment created earlier may still be on its way. A segment
+//! uploaded after the shard progressed past it is skipped: processing it
+//! would break the order.
+//!
+//! Planning is pure: the caller lists the pending segments of a shard, then
+//! processes what the policy returns.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::ingestion::ids::SegmentId;
+
+/// Place of a segment in the processing order of its shard
+///
+/// Ordered by time, then by segment ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Position {
+    /// Time the segment was created (or uploaded, for IDs without a timestamp)
+    pub time: DateTime<Utc>,
+    /// Identifier of the segment
+    pub segment_id: SegmentId,
+}
+
+impl Position {
+    /// Position of a segment uploaded at `uploaded_at`
+    ///
+    /// The creation time comes from the UUID v7 ID when it has one.
+    pub fn of(segment_id: SegmentId, uploaded_at: DateTime<Utc>) -> Self {
+        Self {
+            time: segment_id.timestamp().unwrap_or(uploaded_at),
+            segment_id,
+        }
+    }
+}
+
+/// Persisted progress of a shard
+///
+/// Every segment of the shard up to `position` is processed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardProgress {
+    /// Virtual shard of the cluster
+    pub shard: u16,
+    /// Last segment processed
+    pub position: Position,
+    /// Reset generation of the consumer group the progress was saved under
+    ///
+    /// Progress saved before the last reset of the group is ignored. Absent
+    /// from progress saved before resets existed, read as 0.
+    #[serde(default)]
+    pub generation: u64,
+    /// Time the progress was saved
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A segment of a shard waiting to be processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// Position of the segment
+    pub position: Position,
+    /// Time the segment was uploaded
+    pub uploaded_at: DateTime<Utc>,
+}
+
+/// What to do with the pending segments of a shard
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShardPlan {
+    /// Segment to process now, if the first one in order is ready
+    pub next: Option<Position>,
+    /// Segments waiting for the ones before them or for the lateness window
+    pub waiting: usize,
+    /// Segments behind the progress, processed already
+    pub done: Vec<Position>,
+    /// Segments behind the progress uploaded after the lateness window:
+    /// skipped, they arrived too late to be processed in order
+    pub late: Vec<Position>,
+}
+
+/// How long receivers wait for delayed uploads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderingPolicy {
+    /// Time after its creation a segment waits for earlier ones (default: 5s)
+    pub lateness: Duration,
+}
+
+impl Default for OrderingPolicy {
+    fn default() -> Self {
+        Self {
+            lateness: Duration::from_secs(5),
+        }
+    }
+}
+
+impl OrderingPolicy {
+    /// Whether the lateness window past a position has elapsed at `now`
+    pub fn is_ready(&self, position: &Position, now: DateTime<Utc>) -> bool {
+        match chrono::Duration::from_std(self.lateness) {
+            Ok(lateness) => position.time + lateness <= now,
+            Err(_) => false,
+        }
+    }
+
+    /// Plan the pending segments of a shard
+    ///
+    /// `progress` is the last segment processed in the shard, if any.
+    pub fn plan(
+        &self,
+        candidates: &[Candidate],
+        progress: Option<&Position>,
+        now: DateTime<Utc>,
+    ) -> ShardPlan {
+        let mut plan = ShardPlan::default();
+        let mut ahead = Vec::with_capacity(candidates.len());
+
+        for candidate in candidates {
+            match progress {
+                Some(progress) if candidate.position <= *progress => {
+                    // Uploaded in time, the shard processed it before progressing:
+                    // otherwise it missed its turn
+                    if self.is_ready(&candidate.position, candidate.uploaded_at) {
+                        plan.late.push(candidate.position);
+                    } else {
+                        plan.done.push(candidate.position);
+                    }
+                }
+                _ => ahead.push(candidate.position),
+            }
+        }
+
+        ahead.sort();
+        plan.next = ahead
+            .first()
+            .filter(|first| self.is_ready(first, now))
+            .copied();
+        plan.waiting = ahead.len() - usize::from(plan.next.is_some());
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn at(secs: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap() + chrono::Duration::seconds(secs.into())
+    }
+
+    /// Segment created at `secs`, with a UUID v7 ID
+    fn segment(secs: u32) -> Position {
+        let id = SegmentId::from_uuid(Uuid::new_v7(uuid::Timestamp::from_unix(
+            uuid::NoContext,
+            at(secs).timestamp() as u64,
+            0,
+        )));
+        Position {
+            time: at(secs),
+            segment_id: id,
+        }
+    }
+
+    /// Client-chosen ID that is not a UUID v7
+    fn timeless_id() -> SegmentId {
+        SegmentId::from_uuid(Uuid::from_u128(0x6f9619ff_8b86_4011_b42d_00c04fc964ff))
+    }
+
+    fn candidate(position: Position, uploaded: u32) -> Candidate {
+        Candidate {
+            position,
+            uploaded_at: at(uploaded),
+        }
+    }
+
+    fn policy() -> OrderingPolicy {
+        OrderingPolicy {
+            lateness: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_position_from_uuid_v7() {
+        let position = segment(10);
+
+        assert_eq!(position.segment_id.timestamp(), Some(at(10)));
+        assert_eq!(Position::of(position.segment_id, at(99)), position);
+    }
+
+    #[test]
+    fn test_position_without_timestamp_uses_upload_time() {
+        let id = timeless_id();
+
+        assert_eq!(id.timestamp(), None);
+        assert_eq!(Position::of(id, at(7)).time, at(7));
+    }
+
+    #[test]
+    fn test_positions_order_by_time_then_id() {
+        let early = segment(1);
+        let late = segment(2);
+        let tie = Position {
+            time: early.time,
+            segment_id: timeless_id(),
+        };
+
+        assert!(early < late);
+        assert!(tie < late);
+        assert_ne!(tie.cmp(&early), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn test_plan_processes_oldest_ready_segment() {
+        let (first, second) = (segment(10), segment(12));
+        let candidates = [candidate(second, 12), candidate(first, 10)];
+
+        let plan = policy().plan(&candidates, None, at(20));
+
+        assert_eq!(plan.next, Some(first));
+        assert_eq!(plan.waiting, 1);
+    }
+
+    #[test]
+    fn test_plan_waits_out_the_lateness_window() {
+        let position = segment(10);
+        let candidates = [candidate(position, 10)];
+
+        let plan = policy().plan(&candidates, None, at(14));
+        assert_eq!(plan.next, None);
+        assert_eq!(plan.waiting, 1);
+
+        let plan = policy().plan(&candidates, None, at(15));
+        assert_eq!(plan.next, Some(position));
+    }
+
+    #[test]
+    fn test_plan_never_skips_an_unready_earlier_segment() {
+        // The earlier segment arrived late but within the window: it goes first
+        let (earlier, later) = (segment(10), segment(11));
+        let candidates = [candidate(later, 11), candidate(earlier, 13)];
+
+        let plan = policy().plan(&candidates, None, at(16));
+
+        assert_eq!(plan.next, Some(earlier));
+    }
+
+    #[test]
+    fn test_plan_skips_segments_behind_the_progress() {
+        let progress = segment(20);
+        let processed = segment(10);
+        let late = segment(12);
+        let next = segment(25);
+        let candidates = [
+            candidate(processed, 11),
+            candidate(late, 30),
+            candidate(next, 25),
+        ];
+
+        let plan = policy().plan(&candidates, Some(&progress), at(40));
+
+        assert_eq!(plan.done, vec![processed]);
+        assert_eq!(plan.late, vec![late]);
+        assert_eq!(plan.next, Some(next));
+        assert_eq!(plan.waiting, 0);
+    }
+
+    #[test]
+    fn test_shard_progress_serialization() {
+        let progress = ShardProgress {
+            shard: 42,
+            position: segment(10),
+            generation: 3,
+            updated_at: at(20),
+        };
+
+        let json = serde_json::to_string(&progress).unwrap();
+
+        assert!(json.contains(r#""shard":42"#));
+        assert_eq!(
+            serde_json::from_str::<ShardProgress>(&json).unwrap(),
+            progress
+        );
+    }
+
+    #[test]
+    fn test_shard_progress_without_generation() {
+        let json = format!(
+            r#"{{"shard":42,"position":{},"updated_at":"2024-06-01T00:00:20Z"}}"#,
+            serde_json::to_string(&segment(10)).unwrap()
+        );
+
+        let progress: ShardProgress = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(progress.generation, 0);
+    }
+}
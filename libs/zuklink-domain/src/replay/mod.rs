@@ -0,0 +1,12 @@
+//! Replay domain module
+//!
+//! Receivers save the progress of every shard they own for their consumer
+//! group (see [`crate::ordering::policy::ShardProgress`]), so a restart or a
+//! rebalance resumes where processing stopped. A [`reset::GroupReset`] moves
+//! the whole group to a new position: the earliest segment, the latest one, a
+//! wall-clock time or a given segment. Segment IDs are UUID v7, so a time maps
+//! to a place in the segment order. Every reset bumps the generation of the
+//! group, and progress saved under an older generation is ignored from then
+//! on.
+
+pub mod reset;
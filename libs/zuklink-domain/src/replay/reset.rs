@@ -0,0 +1,303 @@
+//! Resets of consumer group positions
+//!
+//! A reset is resolved once, when it is requested: "latest" means the time of
+//! the request, not the time each receiver reads it. The resolved position is
+//! stored as progress, the last position counted as processed, so shards
+//! resume from a reset exactly like from their saved progress.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::ingestion::{error::IngestionError, ids::SegmentId};
+use crate::ordering::policy::{Position, ShardProgress};
+
+/// Where a consumer group starts processing after a reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum StartFrom {
+    /// Every stored segment is processed again
+    Earliest,
+    /// Only segments created after the reset are processed
+    Latest,
+    /// Segments created at or after this time are processed
+    Time(DateTime<Utc>),
+    /// This segment and the ones created after it are processed
+    Segment(SegmentId),
+}
+
+impl StartFrom {
+    /// Last position counted as processed once reset, `None` for earliest
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::InvalidData` for a segment whose ID carries no
+    /// creation time (chosen by a client, not a UUID v7): its place in the
+    /// order is only known once listed.
+    pub fn progress(&self, now: DateTime<Utc>) -> Result<Option<Position>, IngestionError> {
+        let first = match self {
+            Self::Earliest => return Ok(None),
+            Self::Latest => SegmentId::first_at(now),
+            Self::Time(time) => SegmentId::first_at(*time),
+            Self::Segment(segment_id) => *segment_id,
+        };
+        let time = first.timestamp().ok_or_else(|| {
+            IngestionError::invalid_data(format!(
+                "Segment {} has no creation time, start from a time instead",
+                first
+            ))
+        })?;
+
+        Ok(Some(just_before(Position {
+            time,
+            segment_id: first,
+        })))
+    }
+}
+
+/// Greatest position before `position`
+///
+/// Positions are ordered by time, then by ID: the ID right before at the same
+/// time is the greatest one.
+fn just_before(position: Position) -> Position {
+    let id = position.segment_id.as_uuid().as_u128().saturating_sub(1);
+    Position {
+        time: position.time,
+        segment_id: SegmentId::from_uuid(Uuid::from_u128(id)),
+    }
+}
+
+impl fmt::Display for StartFrom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Earliest => f.write_str("earliest"),
+            Self::Latest => f.write_str("latest"),
+            Self::Time(time) => f.write_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            Self::Segment(segment_id) => write!(f, "{}", segment_id),
+        }
+    }
+}
+
+impl FromStr for StartFrom {
+    type Err = IngestionError;
+
+    /// Parse `earliest`, `latest`, an RFC 3339 time or a segment ID
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "earliest" => return Ok(Self::Earliest),
+            "latest" => return Ok(Self::Latest),
+            _ => {}
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Time(time.with_timezone(&Utc)));
+        }
+        s.parse().map(Self::Segment).map_err(|_| {
+            IngestionError::invalid_data(format!(
+                "Invalid start position '{}' (expected earliest, latest, an RFC 3339 time or a segment ID)",
+                s
+            ))
+        })
+    }
+}
+
+impl From<StartFrom> for String {
+    fn from(start: StartFrom) -> Self {
+        start.to_string()
+    }
+}
+
+impl TryFrom<String> for StartFrom {
+    type Error = IngestionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Reset of the positions of a consumer group
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupReset {
+    /// Name of the consumer group
+    pub group: String,
+    /// Number of resets of the group, this one included
+    pub generation: u64,
+    /// Requested start
+    pub start: StartFrom,
+    /// Last position counted as processed in every shard, `None` to process
+    /// every segment
+    pub progress: Option<Position>,
+    /// Time the reset was requested
+    pub requested_at: DateTime<Utc>,
+}
+
+impl GroupReset {
+    /// Reset a group to `start`, following its previous reset if any
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`StartFrom::progress`]
+    pub fn after(
+        previous: Option<&GroupReset>,
+        group: impl Into<String>,
+        start: StartFrom,
+        now: DateTime<Utc>,
+    ) -> Result<Self, IngestionError> {
+        Ok(Self {
+            group: group.into(),
+            generation: generation(previous) + 1,
+            start,
+            progress: start.progress(now)?,
+            requested_at: now,
+        })
+    }
+}
+
+/// Generation of a group, 0 if it was never reset
+pub fn generation(reset: Option<&GroupReset>) -> u64 {
+    reset.map_or(0, |reset| reset.generation)
+}
+
+/// Progress a shard resumes from, given the last reset of its group
+///
+/// Progress saved since the reset wins; older progress is replaced by the
+/// position of the reset.
+pub fn resume_from(reset: Option<&GroupReset>, saved: Option<ShardProgress>) -> Option<Position> {
+    match (reset, saved) {
+        (Some(reset), Some(saved)) if saved.generation < reset.generation => reset.progress,
+        (Some(reset), None) => reset.progress,
+        (_, saved) => saved.map(|saved| saved.position),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn at(secs: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap() + chrono::Duration::seconds(secs.into())
+    }
+
+    fn saved(position: Position, generation: u64) -> ShardProgress {
+        ShardProgress {
+            shard: 1,
+            position,
+            generation,
+            updated_at: at(0),
+        }
+    }
+
+    #[test]
+    fn test_parse_start() {
+        assert_eq!(
+            "earliest".parse::<StartFrom>().unwrap(),
+            StartFrom::Earliest
+        );
+        assert_eq!(" LATEST ".parse::<StartFrom>().unwrap(), StartFrom::Latest);
+        assert_eq!(
+            "2024-06-01T00:00:10Z".parse::<StartFrom>().unwrap(),
+            StartFrom::Time(at(10))
+        );
+        assert_eq!(
+            "2024-06-01T02:00:10+02:00".parse::<StartFrom>().unwrap(),
+            StartFrom::Time(at(10))
+        );
+
+        let segment_id = SegmentId::new();
+        let start: StartFrom = segment_id.to_string().parse().unwrap();
+        assert_eq!(start, StartFrom::Segment(segment_id));
+        assert_eq!(start.to_string().parse::<StartFrom>().unwrap(), start);
+
+        assert!("yesterday".parse::<StartFrom>().is_err());
+    }
+
+    #[test]
+    fn test_first_id_at_time() {
+        let first = SegmentId::first_at(at(10));
+
+        assert_eq!(first.timestamp(), Some(at(10)));
+        assert!(first < SegmentId::first_at(at(11)));
+        assert!(SegmentId::first_at(at(9)) < first);
+    }
+
+    #[test]
+    fn test_time_start_splits_segments_by_creation() {
+        let progress = StartFrom::Time(at(10)).progress(at(99)).unwrap().unwrap();
+
+        let before = Position::of(SegmentId::first_at(at(9)), at(9));
+        let first = Position::of(SegmentId::first_at(at(10)), at(10));
+        let after = Position::of(SegmentId::first_at(at(11)), at(11));
+
+        assert!(before <= progress);
+        assert!(first > progress);
+        assert!(after > progress);
+    }
+
+    #[test]
+    fn test_segment_start_includes_the_segment() {
+        let segment_id = SegmentId::new();
+        let position = Position::of(segment_id, Utc::now());
+
+        let progress = StartFrom::Segment(segment_id)
+            .progress(Utc::now())
+            .unwrap()
+            .unwrap();
+
+        assert!(position > progress);
+        assert!(StartFrom::Segment(SegmentId::from_uuid(Uuid::from_u128(
+            0x6f9619ff_8b86_4011_b42d_00c04fc964ff
+        )))
+        .progress(Utc::now())
+        .is_err());
+    }
+
+    #[test]
+    fn test_earliest_and_latest() {
+        assert_eq!(StartFrom::Earliest.progress(at(10)).unwrap(), None);
+
+        let progress = StartFrom::Latest.progress(at(10)).unwrap().unwrap();
+        assert!(Position::of(SegmentId::first_at(at(9)), at(9)) <= progress);
+        assert!(Position::of(SegmentId::first_at(at(10)), at(10)) > progress);
+    }
+
+    #[test]
+    fn test_resets_bump_the_generation() {
+        let first = GroupReset::after(None, "billing", StartFrom::Earliest, at(10)).unwrap();
+        let second = GroupReset::after(Some(&first), "billing", StartFrom::Latest, at(20)).unwrap();
+
+        assert_eq!(generation(None), 0);
+        assert_eq!(first.generation, 1);
+        assert_eq!(second.generation, 2);
+
+        let json = serde_json::to_string(&second).unwrap();
+        assert!(json.contains(r#""start":"latest""#));
+        assert_eq!(serde_json::from_str::<GroupReset>(&json).unwrap(), second);
+    }
+
+    #[test]
+    fn test_resume_from_latest_of_reset_and_progress() {
+        let reset = GroupReset::after(None, "billing", StartFrom::Time(at(10)), at(30)).unwrap();
+        let position = Position::of(SegmentId::first_at(at(20)), at(20));
+
+        // Never reset: the saved progress
+        assert_eq!(resume_from(None, Some(saved(position, 0))), Some(position));
+        assert_eq!(resume_from(None, None), None);
+
+        // Saved before the reset: the reset wins
+        assert_eq!(
+            resume_from(Some(&reset), Some(saved(position, 0))),
+            reset.progress
+        );
+        assert_eq!(resume_from(Some(&reset), None), reset.progress);
+
+        // Saved since the reset
+        assert_eq!(
+            resume_from(Some(&reset), Some(saved(position, 1))),
+            Some(position)
+        );
+    }
+}
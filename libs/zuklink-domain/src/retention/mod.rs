@@ -0,0 +1,7 @@
+//! Retention domain module
+//!
+//! Rules deciding when a stored segment may be deleted. Segments are never
+//! removed otherwise: the garbage collector of the storage adapter lists the
+//! stored segments and deletes the ones a [`policy::RetentionPolicy`] selects.
+
+pub mod policy;
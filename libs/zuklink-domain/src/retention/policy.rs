@@ -0,0 +1,594 @@
+//! Retention policies
+//!
+//! A policy combines up to three rules, each of which expires segments on its
+//! own:
+//!
+//! - **Max age**: segments stored longer ago than the limit
+//! - **Max bytes**: the oldest segments, until the retained total fits the limit
+//! - **Checkpoints**: segments every consumer group has checkpointed past
+//!
+//! A topic can have its own policy, replacing the global one for its
+//! segments: the limits of each policy apply to its own segments only, so a
+//! busy topic cannot push the segments of another one out.
+//!
+//! Selection is pure: the caller lists the stored segments and checkpoints,
+//! then deletes what the policy returns.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::ingestion::ids::SegmentId;
+use crate::ordering::policy::ShardProgress;
+use crate::replay::reset::{self, GroupReset};
+
+/// A stored segment, as listed from the storage backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// Identifier of the segment
+    pub id: SegmentId,
+    /// Size of the stored object in bytes
+    pub size_bytes: u64,
+    /// Time the object was stored
+    pub last_modified: DateTime<Utc>,
+    /// Topic of the segment, `None` if it has none or it is not known
+    pub topic: Option<String>,
+}
+
+/// Progress of a consumer group
+///
+/// The group has processed every segment stored before `position`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Name of the consumer group
+    pub group: String,
+    /// Segments stored before this time are processed
+    pub position: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    /// Checkpoint of a group, from the saved progress of its shards
+    ///
+    /// The group is past the oldest position among its shards. Progress saved
+    /// before the last reset of the group counts as the position of the reset,
+    /// which shards without progress also resume from. Returns `None` when a
+    /// shard has no position, e.g. after a reset to the earliest segment, or
+    /// the group has none at all: nothing is known to be processed.
+    pub fn of_group(
+        group: impl Into<String>,
+        reset: Option<&GroupReset>,
+        progress: &[ShardProgress],
+    ) -> Option<Self> {
+        let mut positions = Vec::with_capacity(progress.len() + 1);
+        for saved in progress {
+            positions.push(reset::resume_from(reset, Some(saved.clone()))?);
+        }
+        positions.extend(reset.and_then(|reset| reset.progress));
+
+        let position = positions.into_iter().min()?;
+        Some(Self {
+            group: group.into(),
+            position: position.time,
+        })
+    }
+}
+
+/// Rule that expired a segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiryReason {
+    /// Older than the maximum age
+    MaxAge,
+    /// Among the oldest segments beyond the maximum total size
+    MaxBytes,
+    /// Processed by every consumer group
+    Checkpointed,
+}
+
+impl ExpiryReason {
+    /// Name of the rule, as written in audit logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MaxAge => "max_age",
+            Self::MaxBytes => "max_bytes",
+            Self::Checkpointed => "checkpointed",
+        }
+    }
+}
+
+impl fmt::Display for ExpiryReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A segment selected for deletion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expired {
+    /// The expired segment
+    pub segment: SegmentInfo,
+    /// First rule that expired it
+    pub reason: ExpiryReason,
+}
+
+/// Rules deciding when segments are deleted
+///
+/// Every rule is disabled by default, so the default policy keeps everything.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use zuklink_domain::retention::policy::RetentionPolicy;
+///
+/// let policy = RetentionPolicy {
+///     max_age: Some(Duration::from_secs(7 * 24 * 3600)),
+///     max_bytes: Some(500 * 1024 * 1024 * 1024),
+///     ..RetentionPolicy::default()
+/// };
+/// assert!(policy.is_enabled());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Maximum age of a segment
+    pub max_age: Option<Duration>,
+    /// Maximum total size of the retained segments
+    pub max_bytes: Option<u64>,
+    /// Delete segments once every consumer group has checkpointed past them
+    pub delete_checkpointed: bool,
+    /// Policies of the segments of a topic, replacing this one
+    pub topics: BTreeMap<String, RetentionPolicy>,
+}
+
+impl RetentionPolicy {
+    /// Whether any rule is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some()
+            || self.max_bytes.is_some()
+            || self.delete_checkpointed
+            || self.topics.values().any(RetentionPolicy::is_enabled)
+    }
+
+    /// Whether the checkpoint rule is enabled, globally or for a topic
+    pub fn uses_checkpoints(&self) -> bool {
+        self.delete_checkpointed
+            || self
+                .topics
+                .values()
+                .any(|policy| policy.delete_checkpointed)
+    }
+
+    /// Policy of the segments of a topic, the global policy if it has none
+    pub fn for_topic(&self, topic: Option<&str>) -> &Self {
+        topic
+            .and_then(|topic| self.topics.get(topic))
+            .unwrap_or(self)
+    }
+
+    /// Select the segments to delete, oldest first
+    ///
+    /// Every segment is selected by the policy of its topic. The checkpoint
+    /// rule only applies when at least one checkpoint exists: without
+    /// consumer groups, nothing is known to be processed.
+    pub fn select(
+        &self,
+        segments: &[SegmentInfo],
+        checkpoints: &[Checkpoint],
+        now: DateTime<Utc>,
+    ) -> Vec<Expired> {
+        let mut by_topic: BTreeMap<Option<&str>, Vec<&SegmentInfo>> = BTreeMap::new();
+        for segment in segments {
+            let topic = segment
+                .topic
+                .as_deref()
+                .filter(|topic| self.topics.contains_key(*topic));
+            by_topic.entry(topic).or_default().push(segment);
+        }
+
+        let watermark = checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.position)
+            .min();
+
+        let mut expired: Vec<Expired> = by_topic
+            .into_iter()
+            .flat_map(|(topic, segments)| {
+                self.for_topic(topic).select_own(segments, watermark, now)
+            })
+            .collect();
+        expired.sort_by(|a, b| {
+            (a.segment.last_modified, a.segment.id.as_uuid())
+                .cmp(&(b.segment.last_modified, b.segment.id.as_uuid()))
+        });
+        expired
+    }
+
+    /// Select the segments to delete among the segments of this policy
+    ///
+    /// Topic policies are ignored: `segments` are all governed by this one.
+    fn select_own(
+        &self,
+        mut segments: Vec<&SegmentInfo>,
+        watermark: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Vec<Expired> {
+        segments.sort_by(|a, b| {
+            (a.last_modified, a.id.as_uuid()).cmp(&(b.last_modified, b.id.as_uuid()))
+        });
+        let watermark = watermark.filter(|_| self.delete_checkpointed);
+
+        let mut reasons: Vec<Option<ExpiryReason>> = segments
+            .iter()
+            .map(|segment| {
+                let age = now.signed_duration_since(segment.last_modified).to_std();
+                if self
+                    .max_age
+                    .is_some_and(|max_age| age.is_ok_and(|age| age > max_age))
+                {
+                    Some(ExpiryReason::MaxAge)
+                } else if watermark.is_some_and(|watermark| segment.last_modified < watermark) {
+                    Some(ExpiryReason::Checkpointed)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        if let Some(max_bytes) = self.max_bytes {
+            let mut retained: u64 = segments
+                .iter()
+                .zip(&reasons)
+                .filter(|(_, reason)| reason.is_none())
+                .map(|(segment, _)| segment.size_bytes)
+                .sum();
+
+            for (segment, reason) in segments.iter().zip(reasons.iter_mut()) {
+                if retained <= max_bytes {
+                    break;
+                }
+                if reason.is_none() {
+                    *reason = Some(ExpiryReason::MaxBytes);
+                    retained -= segment.size_bytes;
+                }
+            }
+        }
+
+        segments
+            .into_iter()
+            .zip(reasons)
+            .filter_map(|(segment, reason)| {
+                reason.map(|reason| Expired {
+                    segment: segment.clone(),
+                    reason,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ordering::policy::Position;
+    use crate::replay::reset::StartFrom;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, hour, 0, 0).unwrap()
+    }
+
+    fn segment(hour: u32, size_bytes: u64) -> SegmentInfo {
+        SegmentInfo {
+            id: SegmentId::new(),
+            size_bytes,
+            last_modified: at(hour),
+            topic: None,
+        }
+    }
+
+    fn topic_segment(topic: &str, hour: u32, size_bytes: u64) -> SegmentInfo {
+        SegmentInfo {
+            topic: Some(topic.to_string()),
+            ..segment(hour, size_bytes)
+        }
+    }
+
+    fn checkpoint(group: &str, hour: u32) -> Checkpoint {
+        Checkpoint {
+            group: group.to_string(),
+            position: at(hour),
+        }
+    }
+
+    fn reasons(expired: &[Expired]) -> Vec<(DateTime<Utc>, ExpiryReason)> {
+        expired
+            .iter()
+            .map(|expired| (expired.segment.last_modified, expired.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_policy_keeps_everything() {
+        let policy = RetentionPolicy::default();
+        let segments = vec![segment(1, 10), segment(2, 10)];
+
+        assert!(!policy.is_enabled());
+        assert!(policy
+            .select(&segments, &[checkpoint("billing", 23)], at(23))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_max_age() {
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(2 * 3600)),
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![
+            segment(9, 10),
+            segment(1, 10),
+            segment(7, 10),
+            segment(8, 10),
+        ];
+
+        let expired = policy.select(&segments, &[], at(10));
+
+        assert_eq!(
+            reasons(&expired),
+            vec![(at(1), ExpiryReason::MaxAge), (at(7), ExpiryReason::MaxAge)]
+        );
+    }
+
+    #[test]
+    fn test_max_bytes_expires_oldest_first() {
+        let policy = RetentionPolicy {
+            max_bytes: Some(20),
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![
+            segment(3, 10),
+            segment(1, 10),
+            segment(2, 10),
+            segment(4, 5),
+        ];
+
+        let expired = policy.select(&segments, &[], at(5));
+
+        assert_eq!(
+            reasons(&expired),
+            vec![
+                (at(1), ExpiryReason::MaxBytes),
+                (at(2), ExpiryReason::MaxBytes)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_bytes_counts_only_retained_segments() {
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(3600)),
+            max_bytes: Some(20),
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![segment(1, 100), segment(8, 10), segment(9, 10)];
+
+        let expired = policy.select(&segments, &[], at(9));
+
+        assert_eq!(reasons(&expired), vec![(at(1), ExpiryReason::MaxAge)]);
+    }
+
+    #[test]
+    fn test_checkpoints_use_slowest_group() {
+        let policy = RetentionPolicy {
+            delete_checkpointed: true,
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![segment(1, 10), segment(2, 10), segment(3, 10)];
+        let checkpoints = vec![checkpoint("billing", 3), checkpoint("audit", 2)];
+
+        let expired = policy.select(&segments, &checkpoints, at(23));
+
+        assert_eq!(reasons(&expired), vec![(at(1), ExpiryReason::Checkpointed)]);
+    }
+
+    #[test]
+    fn test_checkpoints_required() {
+        let policy = RetentionPolicy {
+            delete_checkpointed: true,
+            ..RetentionPolicy::default()
+        };
+
+        assert!(policy.select(&[segment(1, 10)], &[], at(23)).is_empty());
+    }
+
+    #[test]
+    fn test_topic_policies_replace_global_policy() {
+        let audit = RetentionPolicy::default();
+        let billing = RetentionPolicy {
+            max_age: Some(Duration::from_secs(3600)),
+            ..RetentionPolicy::default()
+        };
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(5 * 3600)),
+            topics: BTreeMap::from([
+                ("audit".to_string(), audit),
+                ("billing".to_string(), billing.clone()),
+            ]),
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![
+            topic_segment("audit", 1, 10),
+            topic_segment("billing", 7, 10),
+            topic_segment("billing", 9, 10),
+            topic_segment("metrics", 2, 10),
+            segment(3, 10),
+            segment(6, 10),
+        ];
+
+        let expired = policy.select(&segments, &[], at(9));
+
+        assert_eq!(
+            reasons(&expired),
+            vec![
+                (at(2), ExpiryReason::MaxAge),
+                (at(3), ExpiryReason::MaxAge),
+                (at(7), ExpiryReason::MaxAge)
+            ]
+        );
+        assert_eq!(policy.for_topic(Some("billing")), &billing);
+        assert_eq!(policy.for_topic(Some("metrics")), &policy);
+        assert_eq!(policy.for_topic(None), &policy);
+    }
+
+    #[test]
+    fn test_max_bytes_applies_per_topic() {
+        let policy = RetentionPolicy {
+            max_bytes: Some(20),
+            topics: BTreeMap::from([(
+                "billing".to_string(),
+                RetentionPolicy {
+                    max_bytes: Some(10),
+                    ..RetentionPolicy::default()
+                },
+            )]),
+            ..RetentionPolicy::default()
+        };
+        let segments = vec![
+            segment(1, 10),
+            topic_segment("billing", 2, 10),
+            topic_segment("billing", 3, 10),
+            segment(4, 10),
+        ];
+
+        let expired = policy.select(&segments, &[], at(5));
+
+        // The billing segments do not count against the global limit
+        assert_eq!(reasons(&expired), vec![(at(2), ExpiryReason::MaxBytes)]);
+    }
+
+    #[test]
+    fn test_topic_policy_enables_collection() {
+        let policy = RetentionPolicy {
+            topics: BTreeMap::from([(
+                "billing".to_string(),
+                RetentionPolicy {
+                    delete_checkpointed: true,
+                    ..RetentionPolicy::default()
+                },
+            )]),
+            ..RetentionPolicy::default()
+        };
+
+        assert!(policy.is_enabled());
+        assert!(!RetentionPolicy {
+            topics: BTreeMap::from([("billing".to_string(), RetentionPolicy::default())]),
+            ..RetentionPolicy::default()
+        }
+        .is_enabled());
+    }
+
+    #[test]
+    fn test_future_segments_are_not_aged() {
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(60)),
+            ..RetentionPolicy::default()
+        };
+
+        // Clock skew between the storage backend and this node
+        assert!(policy.select(&[segment(12, 10)], &[], at(10)).is_empty());
+    }
+
+    fn progress(shard: u16, hour: u32, generation: u64) -> ShardProgress {
+        ShardProgress {
+            shard,
+            position: Position::of(SegmentId::first_at(at(hour)), at(hour)),
+            generation,
+            updated_at: at(hour),
+        }
+    }
+
+    fn reset(generation: u64, progress: Option<u32>) -> GroupReset {
+        GroupReset {
+            group: "billing".to_string(),
+            generation,
+            start: StartFrom::Latest,
+            progress: progress.map(|hour| Position::of(SegmentId::first_at(at(hour)), at(hour))),
+            requested_at: at(0),
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_of_group_uses_slowest_shard() {
+        let shards = vec![progress(1, 5, 0), progress(2, 3, 0), progress(3, 8, 0)];
+
+        assert_eq!(
+            Checkpoint::of_group("billing", None, &shards),
+            Some(checkpoint("billing", 3))
+        );
+        assert_eq!(Checkpoint::of_group("billing", None, &[]), None);
+    }
+
+    #[test]
+    fn test_checkpoint_of_reset_group() {
+        // Progress saved before the reset resumes from the reset
+        let shards = vec![progress(1, 2, 0), progress(2, 6, 1)];
+        assert_eq!(
+            Checkpoint::of_group("billing", Some(&reset(1, Some(4))), &shards),
+            Some(checkpoint("billing", 4))
+        );
+
+        // Shards without progress resume from the reset too
+        assert_eq!(
+            Checkpoint::of_group("billing", Some(&reset(1, Some(4))), &[]),
+            Some(checkpoint("billing", 4))
+        );
+
+        // Reset to the earliest segment: stale shards have no position
+        assert_eq!(
+            Checkpoint::of_group("billing", Some(&reset(2, None)), &shards),
+            None
+        );
+        assert_eq!(
+            Checkpoint::of_group("billing", Some(&reset(1, None)), &[progress(2, 6, 1)]),
+            Some(checkpoint("billing", 6))
+        );
+    }
+
+    #[test]
+    fn test_uses_checkpoints() {
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(60)),
+            ..RetentionPolicy::default()
+        };
+        assert!(!policy.uses_checkpoints());
+
+        let policy = RetentionPolicy {
+            topics: BTreeMap::from([(
+                "billing".to_string(),
+                RetentionPolicy {
+                    delete_checkpointed: true,
+                    ..RetentionPolicy::default()
+                },
+            )]),
+            ..policy
+        };
+        assert!(policy.uses_checkpoints());
+    }
+
+    #[test]
+    fn test_checkpoint_serialization() {
+        let checkpoint = checkpoint("billing", 3);
+        let json = serde_json::to_string(&checkpoint).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"group":"billing","position":"2024-06-01T03:00:00Z"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&json).unwrap(),
+            checkpoint
+        );
+    }
+}
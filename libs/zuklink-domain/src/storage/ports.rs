@@ -11,7 +11,12 @@
 
 use std::future::Future;
 
-use crate::ingestion::{entity::Segment, error::IngestionError, ids::SegmentId};
+use crate::ingestion::{
+    entity::{Segment, StoredSegment},
+    error::IngestionError,
+    idempotency::IdempotencyClaim,
+    ids::SegmentId,
+};
 
 /// Port for storage operations
 ///
@@ -34,8 +39,9 @@ pub trait StorageRepository: Send + Sync {
     /// The implementation should:
     /// 1. Generate or use the segment's ID to create a storage key
     /// 2. Store the bytes in the backend (S3, filesystem, etc.)
-    /// 3. Return the full storage key/path
-    /// 4. Convert any infrastructure errors to `IngestionError::StorageFailure`
+    /// 3. Store the segment's checksum and compression codec alongside the data
+    /// 4. Return the full storage key/path
+    /// 5. Convert any infrastructure errors to `IngestionError::StorageFailure`
     ///
     /// # Arguments
     ///
@@ -55,8 +61,69 @@ pub trait StorageRepository: Send + Sync {
         data: &[u8],
     ) -> impl Future<Output = Result<String, IngestionError>> + Send;
 
+    /// Save a segment's data only if no segment with the same ID exists
+    ///
+    /// Used by idempotent ingestion, where the segment ID is chosen by the
+    /// client or recorded in an idempotency claim. The existence check and
+    /// the write must be a single atomic operation in the backend (e.g. an S3
+    /// conditional write with `If-None-Match: *`), so concurrent retries
+    /// cannot both succeed. A segment with the same ID stored under another
+    /// partition, or compacted, exists too.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment` - The segment metadata
+    /// * `data` - The raw bytes to store
+    ///
+    /// # Returns
+    ///
+    /// The storage key where the data was stored
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::SegmentAlreadyExists` if the segment is already stored
+    /// - `IngestionError::StorageFailure` if the storage operation fails
+    fn save_if_absent(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> impl Future<Output = Result<String, IngestionError>> + Send;
+
+    /// Claim a client idempotency key, unless an earlier request claimed it
+    ///
+    /// Keys are scoped to the topic of the claim. The existence check and
+    /// the write must be a single atomic operation in the backend, so
+    /// concurrent requests with the same key cannot both claim it. Claims are
+    /// kept after their segment expires, so late retries are still
+    /// recognized.
+    ///
+    /// # Returns
+    ///
+    /// `None` if this call claimed the key, the existing claim otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the storage operation fails
+    fn claim_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send;
+
+    /// Record that the segment of a claim is stored
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the storage operation fails
+    fn commit_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl Future<Output = Result<(), IngestionError>> + Send;
+
     /// Retrieve a segment's data from storage
     ///
+    /// Returns the bytes as stored, still compressed if the segment was
+    /// compressed on ingest. Use `load` to get the codec and checksum.
+    ///
     /// # Arguments
     ///
     /// * `segment_id` - The unique identifier of the segment to retrieve
@@ -73,6 +140,24 @@ pub trait StorageRepository: Send + Sync {
         segment_id: &SegmentId,
     ) -> impl Future<Output = Result<Vec<u8>, IngestionError>> + Send;
 
+    /// Retrieve a segment's data along with its stored checksum
+    ///
+    /// Unlike `get`, this returns the checksum and codec stored by `save`, so
+    /// the caller can decompress and verify the data. Segments stored without
+    /// a checksum are returned with `checksum: None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_id` - The unique identifier of the segment to retrieve
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the segment doesn't exist or retrieval fails
+    fn load(
+        &self,
+        segment_id: &SegmentId,
+    ) -> impl Future<Output = Result<StoredSegment, IngestionError>> + Send;
+
     /// Check if a segment exists in storage
     ///
     /// # Arguments
@@ -0,0 +1,491 @@
+//! Compaction of small segments
+//!
+//! Applies a [`CompactionPolicy`] to the bucket: small segments of the same
+//! time window are read, as stored, and written again into a framed
+//! container (`_compacted/<id>.zkc`), then their standalone objects are
+//! deleted.
+//!
+//! A container only holds segments of one topic, recorded in its `topic`
+//! metadata like on segments, so retention applies the policy of the topic
+//! to the container as a whole. Containers and their index entries are
+//! written with the write options of their topic (see
+//! [`S3WriteOptions::for_topic`](super::S3WriteOptions::for_topic)): objects
+//! are never copied server-side, so nothing keeps the settings of the
+//! segments and compacted data gets the encryption and storage class of its
+//! topic.
+//!
+//! ## Atomicity
+//!
+//! A compaction goes through three steps, each safe to interrupt:
+//!
+//! 1. The container is written. Until its index entries exist, nothing
+//!    refers to it.
+//! 2. An index entry (`_index/<segment-id>.json`) is written for every
+//!    segment. Readers fall back to it once the standalone object is gone.
+//! 3. The standalone objects are deleted.
+//!
+//! A segment is readable at any time, standalone or through its index entry.
+//! A run interrupted after step 2 leaves segments stored twice: the next run
+//! finishes their retirement first. A run interrupted before leaves an
+//! unreferenced container, deleted by retention like any other.
+
+use aws_sdk_s3::types::ChecksumMode;
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+use zuklink_domain::{
+    compaction::{
+        container::{self, Frame},
+        policy::CompactionPolicy,
+    },
+    ingestion::{error::IngestionError, ids::SegmentId},
+    retention::policy::SegmentInfo,
+};
+
+use super::s3_repository::{
+    container_key, delete_keys, index_key, list_segments, listed_segment, listed_segment_id,
+    segment_id_from_key, stored_checksum, stored_compression, stored_record, S3StorageRepository,
+    WriteCondition, WriteOutcome, ENCRYPTION_KEY_METADATA_KEY, INDEX_PREFIX, TOPIC_METADATA_KEY,
+};
+
+/// Segments read for compaction, with their listed key, by topic
+type TopicFrames = BTreeMap<Option<String>, Vec<(String, Frame)>>;
+
+/// Outcome of a compaction run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Standalone segments listed in the bucket
+    pub scanned: usize,
+    /// Containers written
+    pub containers: usize,
+    /// Segments moved into a container
+    pub compacted: usize,
+    /// Bytes moved into containers
+    pub bytes: u64,
+}
+
+/// Merges small segments into containers
+///
+/// Containers are written with the write options of the repository, those of
+/// their topic if it has its own.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use zuklink_domain::compaction::policy::CompactionPolicy;
+/// use zuklink_s3::infrastructure::{S3Compactor, S3StorageRepository};
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let repo = S3StorageRepository::new(Client::new(&config), "my-bucket".to_string());
+/// let compactor = S3Compactor::new(repo, CompactionPolicy::default());
+/// let report = compactor.run_once().await.unwrap();
+/// println!("{} segments compacted", report.compacted);
+/// # }
+/// ```
+pub struct S3Compactor {
+    repository: S3StorageRepository,
+    policy: CompactionPolicy,
+}
+
+impl S3Compactor {
+    /// Create a compactor for the bucket of a repository
+    pub fn new(repository: S3StorageRepository, policy: CompactionPolicy) -> Self {
+        Self { repository, policy }
+    }
+
+    /// Get the compaction policy
+    pub fn policy(&self) -> &CompactionPolicy {
+        &self.policy
+    }
+
+    /// Compact the bucket once
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if listing the bucket fails.
+    /// A group that fails to compact is logged and left for the next run.
+    pub async fn run_once(&self) -> Result<CompactionReport, IngestionError> {
+        let client = self.repository.client();
+        let bucket = self.repository.bucket();
+
+        let listed = list_segments(client, bucket, None, segment_id_from_key).await?;
+        let indexed: HashSet<SegmentId> =
+            list_segments(client, bucket, Some(INDEX_PREFIX), listed_segment_id)
+                .await?
+                .into_iter()
+                .map(|(_, segment)| segment.id)
+                .collect();
+
+        // Segments indexed but still stored: an earlier run was interrupted
+        let (retired, segments): (Vec<_>, Vec<_>) = listed
+            .into_iter()
+            .partition(|(_, segment)| indexed.contains(&segment.id));
+        if !retired.is_empty() {
+            let keys: Vec<String> = retired.into_iter().map(|(key, _)| key).collect();
+            let failures = delete_keys(client, bucket, &keys).await;
+            info!(
+                retired = keys.len() - failures.len(),
+                "Finished retiring segments of an interrupted compaction"
+            );
+        }
+
+        let keys: HashMap<SegmentId, String> = segments
+            .iter()
+            .map(|(key, segment)| (segment.id, key.clone()))
+            .collect();
+        let segments: Vec<SegmentInfo> = segments.into_iter().map(|(_, segment)| segment).collect();
+        let mut report = CompactionReport {
+            scanned: segments.len(),
+            ..CompactionReport::default()
+        };
+
+        for group in self.policy.plan(&segments, Utc::now()) {
+            let topics = match self.read_group(&group, &keys).await {
+                Ok(topics) => topics,
+                Err(err) => {
+                    warn!(
+                        segments = group.len(),
+                        error = %err,
+                        "Failed to read segment group, will retry"
+                    );
+                    continue;
+                }
+            };
+
+            for (topic, frames) in topics {
+                let segments = frames.len();
+                match self.compact(topic.as_deref(), frames).await {
+                    Ok(bytes) => {
+                        report.containers += 1;
+                        report.compacted += segments;
+                        report.bytes += bytes;
+                    }
+                    Err(err) => warn!(
+                        topic = topic.as_deref().unwrap_or_default(),
+                        segments,
+                        error = %err,
+                        "Failed to compact segment group, will retry"
+                    ),
+                }
+            }
+        }
+
+        info!(
+            bucket = %bucket,
+            scanned = report.scanned,
+            containers = report.containers,
+            compacted = report.compacted,
+            bytes = report.bytes,
+            "Compaction finished"
+        );
+
+        Ok(report)
+    }
+
+    /// Read a group of segments, split by topic
+    ///
+    /// `keys` holds the listed key of every segment.
+    async fn read_group(
+        &self,
+        group: &[SegmentInfo],
+        keys: &HashMap<SegmentId, String>,
+    ) -> Result<TopicFrames, IngestionError> {
+        let mut topics = TopicFrames::new();
+        for key in group.iter().filter_map(|segment| keys.get(&segment.id)) {
+            let (frame, topic) = self.read_frame(key).await?;
+            topics.entry(topic).or_default().push((key.clone(), frame));
+        }
+        Ok(topics)
+    }
+
+    /// Move segments of a topic into a new container
+    ///
+    /// `frames` holds every segment with its listed key. Returns the size of
+    /// the moved payloads.
+    async fn compact(
+        &self,
+        topic: Option<&str>,
+        frames: Vec<(String, Frame)>,
+    ) -> Result<u64, IngestionError> {
+        let (sources, frames): (Vec<String>, Vec<Frame>) = frames.into_iter().unzip();
+
+        let container = SegmentId::new();
+        let (data, entries) = container::encode(container, &frames)?;
+        let key = container_key(&container);
+        let bytes = entries.iter().map(|entry| entry.length).sum();
+
+        let metadata = topic
+            .map(|topic| HashMap::from([(TOPIC_METADATA_KEY.to_string(), topic.to_string())]))
+            .unwrap_or_default();
+        let outcome = self
+            .repository
+            .put_topic_object(
+                topic,
+                &key,
+                metadata,
+                data.into(),
+                &WriteCondition::IfAbsent,
+            )
+            .await?;
+        if outcome == WriteOutcome::PreconditionFailed {
+            return Err(IngestionError::StorageFailure(format!(
+                "Container '{}' already exists",
+                key
+            )));
+        }
+        debug!(key = %key, segments = entries.len(), "Wrote container");
+
+        // Commit: from here on, every segment is readable through its index
+        let mut writes = JoinSet::new();
+        for mut entry in entries {
+            let repository = self.repository.clone();
+            entry.topic = topic.map(String::from);
+            writes.spawn(async move {
+                let json = serde_json::to_vec(&entry).map_err(|e| {
+                    IngestionError::internal_error(format!("Failed to encode index entry: {}", e))
+                })?;
+                repository
+                    .put_topic_object(
+                        entry.topic.as_deref(),
+                        &index_key(&entry.segment_id, entry.partition),
+                        HashMap::new(),
+                        json.into(),
+                        &WriteCondition::Always,
+                    )
+                    .await
+                    .map(drop)
+            });
+        }
+        while let Some(joined) = writes.join_next().await {
+            joined.map_err(|e| {
+                IngestionError::internal_error(format!("Index write task failed: {}", e))
+            })??;
+        }
+
+        let failures =
+            delete_keys(self.repository.client(), self.repository.bucket(), &sources).await;
+        if !failures.is_empty() {
+            warn!(
+                failed = failures.len(),
+                "Failed to delete compacted segments, retrying on the next run"
+            );
+        }
+
+        info!(
+            container = %container,
+            topic = topic.unwrap_or_default(),
+            segments = sources.len(),
+            bytes,
+            "Compacted segments"
+        );
+        Ok(bytes)
+    }
+
+    /// Read a standalone segment as stored, with its metadata and topic
+    async fn read_frame(&self, key: &str) -> Result<(Frame, Option<String>), IngestionError> {
+        let (segment_id, partition) = listed_segment(key).ok_or_else(|| {
+            IngestionError::internal_error(format!("Invalid segment key '{}'", key))
+        })?;
+        let output = self
+            .repository
+            .write_options()
+            .apply_to_get(self.repository.client().get_object())
+            .bucket(self.repository.bucket())
+            .key(key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+            .map_err(|e| {
+                IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, e
+                ))
+            })?;
+
+        let checksum = stored_checksum(output.metadata(), output.checksum_sha256())?;
+        let compression = stored_compression(output.metadata())?;
+        let record = stored_record(output.metadata())?;
+        let encryption_key_id = output
+            .metadata()
+            .and_then(|metadata| metadata.get(ENCRYPTION_KEY_METADATA_KEY))
+            .cloned();
+        let topic = output
+            .metadata()
+            .and_then(|metadata| metadata.get(TOPIC_METADATA_KEY))
+            .cloned();
+        let data = output.body.collect().await.map_err(|e| {
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, e
+            ))
+        })?;
+
+        let frame = Frame {
+            segment_id,
+            compression,
+            checksum,
+            encryption_key_id,
+            record,
+            partition,
+            data: data.into_bytes().to_vec(),
+        };
+        Ok((frame, topic))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::s3_repository::{container_id_from_key, segment_key};
+    use super::super::write_options::{S3WriteOptions, ServerSideEncryption};
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_sdk_s3::types::StorageClass;
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+    use std::sync::{Arc, Mutex};
+    use zuklink_domain::ingestion::compression::Compression;
+
+    /// Key, KMS key id and storage class of an object written to S3
+    type Write = (String, Option<String>, Option<String>);
+
+    /// Compactor of a mocked bucket recording every `PutObject`
+    fn recording_compactor(options: S3WriteOptions, writes: Arc<Mutex<Vec<Write>>>) -> S3Compactor {
+        let http_client = infallible_client_fn(move |request| {
+            let header = |name: &str| {
+                request
+                    .headers()
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+            };
+            let response = http::Response::builder().status(200);
+
+            if request.method() == "PUT" {
+                writes.lock().unwrap().push((
+                    request.uri().path().to_string(),
+                    header("x-amz-server-side-encryption-aws-kms-key-id"),
+                    header("x-amz-storage-class"),
+                ));
+                return response
+                    .header("ETag", "\"v1\"")
+                    .body(SdkBody::empty())
+                    .unwrap();
+            }
+
+            // DeleteObjects of the compacted segments
+            response
+                .body(SdkBody::from("<DeleteResult></DeleteResult>"))
+                .unwrap()
+        });
+
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .force_path_style(true)
+            .http_client(http_client)
+            .build();
+        let repository =
+            S3StorageRepository::new(aws_sdk_s3::Client::from_conf(config), "zuklink".to_string())
+                .with_write_options(options);
+        S3Compactor::new(repository, CompactionPolicy::default())
+    }
+
+    #[tokio::test]
+    async fn test_container_keeps_topic_options() {
+        let billing = S3WriteOptions {
+            encryption: Some(ServerSideEncryption::Kms {
+                key_id: Some("alias/billing".to_string()),
+                bucket_key: false,
+            }),
+            storage_class: Some(StorageClass::GlacierIr),
+            ..S3WriteOptions::default()
+        };
+        let options = S3WriteOptions {
+            storage_class: Some(StorageClass::StandardIa),
+            topics: BTreeMap::from([("billing".to_string(), billing)]),
+            ..S3WriteOptions::default()
+        };
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let compactor = recording_compactor(options, writes.clone());
+
+        let segment_id = SegmentId::new();
+        let frame = Frame {
+            segment_id,
+            compression: Compression::None,
+            checksum: None,
+            encryption_key_id: None,
+            record: None,
+            partition: None,
+            data: b"invoice".to_vec(),
+        };
+        compactor
+            .compact(
+                Some("billing"),
+                vec![(segment_key(&segment_id, None), frame)],
+            )
+            .await
+            .unwrap();
+
+        // The container and its index entry, both with the options of the topic
+        let writes = writes.lock().unwrap();
+        assert_eq!(writes.len(), 2);
+        assert!(writes
+            .iter()
+            .any(|(key, _, _)| key.contains("/_compacted/")));
+        assert!(writes.iter().any(|(key, _, _)| key.contains("/_index/")));
+        for (key, kms_key_id, storage_class) in writes.iter() {
+            assert_eq!(kms_key_id.as_deref(), Some("alias/billing"), "{}", key);
+            assert_eq!(storage_class.as_deref(), Some("GLACIER_IR"), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_listed_segment_keys() {
+        let id = SegmentId::new();
+
+        assert_eq!(listed_segment_id(&format!("{}.zuk", id)), Some(id));
+        assert_eq!(listed_segment_id(&index_key(&id, None)), Some(id));
+        assert_eq!(listed_segment_id(&container_key(&id)), None);
+        assert_eq!(listed_segment_id(&format!("{}.json", id)), None);
+        assert_eq!(listed_segment_id("_index/not-a-uuid.json"), None);
+    }
+
+    #[test]
+    fn test_container_keys() {
+        let id = SegmentId::new();
+
+        assert_eq!(container_key(&id), format!("_compacted/{}.zkc", id));
+        assert_eq!(container_id_from_key(&container_key(&id)), Some(id));
+        assert_eq!(container_id_from_key(&format!("{}.zuk", id)), None);
+        assert_eq!(container_id_from_key(&index_key(&id, None)), None);
+    }
+
+    #[test]
+    fn test_partitioned_keys() {
+        let id = SegmentId::new();
+
+        assert_eq!(segment_key(&id, None), format!("{}.zuk", id));
+        assert_eq!(segment_key(&id, Some(7)), format!("{}.p7.zuk", id));
+        assert_eq!(index_key(&id, Some(7)), format!("_index/{}.p7.json", id));
+
+        assert_eq!(listed_segment(&segment_key(&id, None)), Some((id, None)));
+        assert_eq!(
+            listed_segment(&segment_key(&id, Some(7))),
+            Some((id, Some(7)))
+        );
+        assert_eq!(
+            listed_segment(&index_key(&id, Some(7))),
+            Some((id, Some(7)))
+        );
+        assert_eq!(segment_id_from_key(&segment_key(&id, Some(7))), Some(id));
+
+        // Only the canonical form of a partition is a segment key
+        assert_eq!(listed_segment(&format!("{}.p07.zuk", id)), None);
+        assert_eq!(listed_segment(&format!("{}.p.zuk", id)), None);
+        assert_eq!(listed_segment(&format!("{}.x7.zuk", id)), None);
+        assert_eq!(listed_segment(&format!("_checkpoints/{}.p7.zuk", id)), None);
+    }
+}
@@ -0,0 +1,498 @@
+//! Segment garbage collection
+//!
+//! Applies a [`RetentionPolicy`] to the bucket: lists the stored segments and
+//! the consumer checkpoints, selects the expired segments and deletes them
+//! with batched `DeleteObjects` requests (up to 1000 keys each).
+//!
+//! ## Topics
+//!
+//! When the policy has topic policies, the topic of every listed segment is
+//! read from its `topic` metadata, with one `HeadObject` request per object,
+//! so each segment is selected by the policy of its topic.
+//!
+//! ## Containers
+//!
+//! Containers of compacted segments expire as a whole, aged from the time they
+//! were written, under the policy of the topic in their metadata. Containers
+//! written before compaction split topics have none and follow the global
+//! policy. Before a container is deleted, the index entries of its segments
+//! are, so a segment is never resolved to a deleted container.
+//!
+//! ## Checkpoints
+//!
+//! The checkpoint of a consumer group is derived from the progress its
+//! receivers save under `_progress/<group>/` (see [`S3ProgressStore`]): every
+//! segment stored before the oldest position among its shards is processed by
+//! that group. If any group has no position, the checkpoint rule deletes
+//! nothing. A malformed progress aborts the run rather than being skipped,
+//! since ignoring a slow group could delete data it has not processed yet.
+//! A retired group keeps holding segments back until its `_progress/<group>/`
+//! objects are deleted.
+//!
+//! ## Audit Log
+//!
+//! Every expired segment produces one audit entry, deleted or not (dry run,
+//! failure). Entries are logged under the `zuklink::audit` tracing target and,
+//! if configured, appended as JSON lines to an audit file.
+
+use aws_sdk_s3::Client;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+use zuklink_domain::{
+    compaction::container,
+    ingestion::{error::IngestionError, ids::SegmentId},
+    retention::policy::{Checkpoint, ExpiryReason, RetentionPolicy, SegmentInfo},
+};
+
+use super::progress_store::S3ProgressStore;
+use super::s3_repository::{
+    container_id_from_key, delete_keys, index_key, list_segments, segment_id_from_key,
+    S3StorageRepository, CONTAINER_PREFIX, TOPIC_METADATA_KEY,
+};
+use super::write_options::S3WriteOptions;
+
+/// Number of expired segments handled per audit batch
+const AUDIT_BATCH_SIZE: usize = 1000;
+
+/// Number of concurrent requests reading the topics of listed segments
+const TOPIC_BATCH_SIZE: usize = 64;
+
+/// Outcome of a garbage collection run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Segments and containers listed in the bucket
+    pub scanned: usize,
+    /// Segments selected by the policy
+    pub expired: usize,
+    /// Segments deleted (always 0 in dry run)
+    pub deleted: usize,
+    /// Segments whose deletion failed
+    pub failed: usize,
+    /// Bytes freed by the deleted segments
+    pub freed_bytes: u64,
+}
+
+/// What happened to an expired segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AuditOutcome {
+    /// Dry run: the segment was kept
+    WouldDelete,
+    Deleted,
+    Failed,
+}
+
+/// One line of the audit log
+#[derive(Debug, Serialize)]
+struct AuditEntry<'a> {
+    time: DateTime<Utc>,
+    segment_id: String,
+    size_bytes: u64,
+    last_modified: DateTime<Utc>,
+    reason: ExpiryReason,
+    outcome: AuditOutcome,
+    /// Number of segments in the container, for containers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segments: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Deletes the segments expired by a retention policy
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use std::time::Duration;
+/// use zuklink_domain::retention::policy::RetentionPolicy;
+/// use zuklink_s3::infrastructure::S3GarbageCollector;
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let policy = RetentionPolicy {
+///     max_age: Some(Duration::from_secs(7 * 24 * 3600)),
+///     ..RetentionPolicy::default()
+/// };
+/// let gc = S3GarbageCollector::new(Client::new(&config), "my-bucket".to_string(), policy)
+///     .with_dry_run(true);
+/// let report = gc.run_once().await.unwrap();
+/// println!("{} segments would be deleted", report.expired);
+/// # }
+/// ```
+pub struct S3GarbageCollector {
+    client: Client,
+    bucket: String,
+    policy: RetentionPolicy,
+    write_options: S3WriteOptions,
+    dry_run: bool,
+    audit_log: Option<PathBuf>,
+}
+
+impl S3GarbageCollector {
+    /// Create a garbage collector for a bucket
+    pub fn new(client: Client, bucket: String, policy: RetentionPolicy) -> Self {
+        Self {
+            client,
+            bucket,
+            policy,
+            write_options: S3WriteOptions::default(),
+            dry_run: false,
+            audit_log: None,
+        }
+    }
+
+    /// Read the progress of consumer groups with these options (SSE-C key)
+    pub fn with_write_options(mut self, write_options: S3WriteOptions) -> Self {
+        self.write_options = write_options;
+        self
+    }
+
+    /// Only report what would be deleted
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Append the audit entries to this file, as JSON lines
+    pub fn with_audit_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// Get the retention policy
+    pub fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    /// Whether the collector only reports what would be deleted
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Apply the retention policy once
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if listing the bucket fails or the
+    ///   audit log cannot be written
+    /// - `IngestionError::InvalidData` if the progress of a shard is malformed
+    ///
+    /// Failed deletions are counted in the report, not returned as errors:
+    /// they are retried on the next run.
+    pub async fn run_once(&self) -> Result<GcReport, IngestionError> {
+        if !self.policy.is_enabled() {
+            debug!("No retention rule enabled, skipping garbage collection");
+            return Ok(GcReport::default());
+        }
+
+        let mut listed =
+            list_segments(&self.client, &self.bucket, None, segment_id_from_key).await?;
+        listed.extend(
+            list_segments(
+                &self.client,
+                &self.bucket,
+                Some(CONTAINER_PREFIX),
+                container_id_from_key,
+            )
+            .await?,
+        );
+        if !self.policy.topics.is_empty() {
+            listed = self.read_topics(listed).await?;
+        }
+        let keys: HashMap<SegmentId, String> = listed
+            .iter()
+            .map(|(key, segment)| (segment.id, key.clone()))
+            .collect();
+        let segments: Vec<_> = listed.into_iter().map(|(_, segment)| segment).collect();
+
+        let checkpoints = if self.policy.uses_checkpoints() {
+            self.read_checkpoints().await?
+        } else {
+            Vec::new()
+        };
+        let expired = self.policy.select(&segments, &checkpoints, Utc::now());
+
+        let mut report = GcReport {
+            scanned: segments.len(),
+            expired: expired.len(),
+            ..GcReport::default()
+        };
+
+        for batch in expired.chunks(AUDIT_BATCH_SIZE) {
+            let mut failures = HashMap::new();
+            let mut contents = HashMap::new();
+            let mut deletable = Vec::with_capacity(batch.len());
+
+            for expired in batch {
+                let key = &keys[&expired.segment.id];
+                if container_id_from_key(key).is_none() {
+                    deletable.push(key.clone());
+                } else if !self.dry_run {
+                    match self.retire_index(key).await {
+                        Ok(count) => {
+                            contents.insert(key.clone(), count);
+                            deletable.push(key.clone());
+                        }
+                        Err(err) => {
+                            warn!(key = %key, error = %err, "Failed to retire container index");
+                            failures.insert(key.clone(), err.to_string());
+                        }
+                    }
+                }
+            }
+            if !self.dry_run {
+                failures.extend(delete_keys(&self.client, &self.bucket, &deletable).await);
+            }
+
+            let mut lines = String::new();
+            for expired in batch {
+                let key = &keys[&expired.segment.id];
+                let error = failures.get(key).map(String::as_str);
+                let outcome = match (self.dry_run, error) {
+                    (true, _) => AuditOutcome::WouldDelete,
+                    (false, None) => {
+                        report.deleted += 1;
+                        report.freed_bytes += expired.segment.size_bytes;
+                        AuditOutcome::Deleted
+                    }
+                    (false, Some(_)) => {
+                        report.failed += 1;
+                        AuditOutcome::Failed
+                    }
+                };
+
+                let entry = AuditEntry {
+                    time: Utc::now(),
+                    segment_id: expired.segment.id.to_string(),
+                    size_bytes: expired.segment.size_bytes,
+                    last_modified: expired.segment.last_modified,
+                    reason: expired.reason,
+                    outcome,
+                    segments: contents.get(key).copied(),
+                    error,
+                };
+                let line = serde_json::to_string(&entry).map_err(|e| {
+                    IngestionError::internal_error(format!("Failed to encode audit entry: {}", e))
+                })?;
+                info!(target: "zuklink::audit", "{}", line);
+                lines.push_str(&line);
+                lines.push('\n');
+            }
+
+            self.append_audit(&lines).await?;
+        }
+
+        info!(
+            bucket = %self.bucket,
+            dry_run = self.dry_run,
+            scanned = report.scanned,
+            expired = report.expired,
+            deleted = report.deleted,
+            failed = report.failed,
+            freed_bytes = report.freed_bytes,
+            "Garbage collection finished"
+        );
+
+        Ok(report)
+    }
+
+    /// Delete the index entries of the segments of a container
+    ///
+    /// Returns the number of segments in the container.
+    async fn retire_index(&self, key: &str) -> Result<usize, IngestionError> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, e
+                ))
+            })?;
+        let data = output.body.collect().await.map_err(|e| {
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, e
+            ))
+        })?;
+        let frames = container::decode(&data.into_bytes())?;
+
+        let index_keys: Vec<String> = frames
+            .iter()
+            .map(|frame| index_key(&frame.segment_id, frame.partition))
+            .collect();
+        let failures = delete_keys(&self.client, &self.bucket, &index_keys).await;
+        if let Some((index_key, error)) = failures.into_iter().next() {
+            return Err(IngestionError::StorageFailure(format!(
+                "Failed to delete index entry '{}': {}",
+                index_key, error
+            )));
+        }
+
+        Ok(frames.len())
+    }
+
+    /// Fill in the topic of listed segments from their metadata
+    ///
+    /// Segments deleted since they were listed are dropped.
+    async fn read_topics(
+        &self,
+        listed: Vec<(String, SegmentInfo)>,
+    ) -> Result<Vec<(String, SegmentInfo)>, IngestionError> {
+        let mut segments = Vec::with_capacity(listed.len());
+        let mut listed = listed.into_iter().peekable();
+
+        while listed.peek().is_some() {
+            let mut heads = JoinSet::new();
+            for (key, segment) in listed.by_ref().take(TOPIC_BATCH_SIZE) {
+                let request = self.client.head_object().bucket(&self.bucket).key(&key);
+                heads.spawn(async move {
+                    let topic = match request.send().await {
+                        Ok(output) => output
+                            .metadata()
+                            .and_then(|metadata| metadata.get(TOPIC_METADATA_KEY))
+                            .cloned(),
+                        Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                            return Ok(None)
+                        }
+                        Err(err) => {
+                            return Err(IngestionError::StorageFailure(format!(
+                                "S3 head_object failed for key '{}': {}",
+                                key, err
+                            )))
+                        }
+                    };
+                    Ok(Some((key, SegmentInfo { topic, ..segment })))
+                });
+            }
+
+            while let Some(joined) = heads.join_next().await {
+                let head = joined.map_err(|e| {
+                    IngestionError::internal_error(format!("Head task failed: {}", e))
+                })??;
+                segments.extend(head);
+            }
+        }
+
+        Ok(segments)
+    }
+
+    /// Derive the checkpoint of every consumer group from its progress
+    ///
+    /// Returns no checkpoint at all if a group has no position, so that the
+    /// checkpoint rule deletes nothing.
+    async fn read_checkpoints(&self) -> Result<Vec<Checkpoint>, IngestionError> {
+        let repository = S3StorageRepository::new(self.client.clone(), self.bucket.clone())
+            .with_write_options(self.write_options.clone());
+
+        let mut checkpoints = Vec::new();
+        for group in S3ProgressStore::list_groups(&repository).await? {
+            let store = S3ProgressStore::new(repository.clone(), &group);
+            let reset = store.load_reset().await?;
+            let progress = store.load_all().await?;
+
+            match Checkpoint::of_group(&group, reset.as_ref(), &progress) {
+                Some(checkpoint) => {
+                    debug!(group = %group, position = %checkpoint.position, "Read checkpoint");
+                    checkpoints.push(checkpoint);
+                }
+                None => {
+                    warn!(group = %group, "Consumer group has no position, keeping checkpointed segments");
+                    return Ok(Vec::new());
+                }
+            }
+        }
+
+        Ok(checkpoints)
+    }
+
+    /// Append lines to the audit file, if configured
+    async fn append_audit(&self, lines: &str) -> Result<(), IngestionError> {
+        let Some(path) = &self.audit_log else {
+            return Ok(());
+        };
+
+        let write = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(lines.as_bytes()).await?;
+            file.flush().await
+        };
+
+        write.await.map_err(|e| {
+            IngestionError::StorageFailure(format!(
+                "Failed to write audit log '{}': {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::progress_store::PROGRESS_PREFIX;
+    use super::*;
+    use chrono::TimeZone;
+    use zuklink_domain::ingestion::ids::SegmentId;
+
+    #[test]
+    fn test_only_segment_keys_are_collected() {
+        let id = SegmentId::new();
+
+        assert_eq!(segment_id_from_key(&format!("{}.zuk", id)), Some(id));
+        assert_eq!(segment_id_from_key(&format!("{}.json", id)), None);
+        assert_eq!(
+            segment_id_from_key(&format!("{}{}.zuk", PROGRESS_PREFIX, id)),
+            None
+        );
+        assert_eq!(segment_id_from_key("not-a-uuid.zuk"), None);
+    }
+
+    #[test]
+    fn test_audit_entry_format() {
+        let id: SegmentId = "0190c5a4-1f2e-7c3d-8a4b-5c6d7e8f9a0b".parse().unwrap();
+        let time = Utc.with_ymd_and_hms(2024, 6, 8, 0, 0, 0).unwrap();
+        let entry = AuditEntry {
+            time,
+            segment_id: id.to_string(),
+            size_bytes: 42,
+            last_modified: Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+            reason: ExpiryReason::MaxAge,
+            outcome: AuditOutcome::WouldDelete,
+            segments: None,
+            error: None,
+        };
+
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            concat!(
+                r#"{"time":"2024-06-08T00:00:00Z","segment_id":"0190c5a4-1f2e-7c3d-8a4b-5c6d7e8f9a0b","#,
+                r#""size_bytes":42,"last_modified":"2024-06-01T00:00:00Z","reason":"max_age","#,
+                r#""outcome":"would_delete"}"#
+            )
+        );
+
+        let failed = AuditEntry {
+            outcome: AuditOutcome::Failed,
+            error: Some("AccessDenied: denied"),
+            ..entry
+        };
+        assert!(serde_json::to_string(&failed)
+            .unwrap()
+            .ends_with(r#""outcome":"failed","error":"AccessDenied: denied"}"#));
+    }
+}
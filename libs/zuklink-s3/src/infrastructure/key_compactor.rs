@@ -0,0 +1,300 @@
+//! Key compaction of keyed segments
+//!
+//! Applies a [`KeyCompactionPolicy`] to the bucket: reads the record key and
+//! topic of every segment, standalone (from its user metadata) or compacted
+//! (from its index entry, or its container for entries written without a
+//! topic), then deletes the segments the policy removes.
+//!
+//! Removing a segment deletes every key it was listed under. Removing a
+//! compacted segment deletes its index entry: it is no longer
+//! readable, and its payload is reclaimed when its container expires.
+//!
+//! Record keys are not listed by S3, so every run reads the metadata of every
+//! segment, with up to [`DESCRIBE_CONCURRENCY`] requests in flight.
+
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use chrono::Utc;
+use std::collections::HashMap;
+use tokio::task::JoinSet;
+use tracing::{debug, info};
+use zuklink_domain::{
+    compaction::keyed::{KeyCompactionPolicy, KeyedSegment, RemovalReason},
+    ingestion::{error::IngestionError, ids::SegmentId},
+};
+
+use super::s3_repository::{
+    container_key, delete_keys, list_segments, listed_segment_id, segment_id_from_key,
+    stored_record, S3StorageRepository, INDEX_PREFIX, TOPIC_METADATA_KEY,
+};
+
+/// Maximum number of segments described concurrently
+pub const DESCRIBE_CONCURRENCY: usize = 32;
+
+/// Outcome of a key compaction run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyCompactionReport {
+    /// Segments listed in the bucket, standalone or compacted
+    pub scanned: usize,
+    /// Segments carrying a record key
+    pub keyed: usize,
+    /// Older versions removed
+    pub superseded: usize,
+    /// Expired tombstones removed
+    pub tombstones_expired: usize,
+    /// Segments whose removal failed
+    pub failed: usize,
+}
+
+/// Keeps only the latest version of every record key
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use zuklink_domain::compaction::keyed::KeyCompactionPolicy;
+/// use zuklink_s3::infrastructure::{S3KeyCompactor, S3StorageRepository};
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let repo = S3StorageRepository::new(Client::new(&config), "my-bucket".to_string());
+/// let compactor = S3KeyCompactor::new(repo, KeyCompactionPolicy::default());
+/// let report = compactor.run_once().await.unwrap();
+/// println!("{} older versions removed", report.superseded);
+/// # }
+/// ```
+pub struct S3KeyCompactor {
+    repository: S3StorageRepository,
+    policy: KeyCompactionPolicy,
+}
+
+impl S3KeyCompactor {
+    /// Create a key compactor for the bucket of a repository
+    pub fn new(repository: S3StorageRepository, policy: KeyCompactionPolicy) -> Self {
+        Self { repository, policy }
+    }
+
+    /// Get the key compaction policy
+    pub fn policy(&self) -> &KeyCompactionPolicy {
+        &self.policy
+    }
+
+    /// Compact the keyed segments of the bucket once
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if listing the bucket or reading
+    ///   the metadata of a segment fails
+    /// - `IngestionError::InvalidData` if the record metadata of a segment is
+    ///   malformed
+    ///
+    /// Failed removals are counted in the report, not returned as errors:
+    /// they are retried on the next run.
+    pub async fn run_once(&self) -> Result<KeyCompactionReport, IngestionError> {
+        let client = self.repository.client();
+        let bucket = self.repository.bucket();
+
+        // Listed keys of every segment, standalone first: a segment both
+        // stored and indexed (interrupted compaction) is described once
+        let mut listed: HashMap<SegmentId, Vec<String>> = HashMap::new();
+        for (key, segment) in list_segments(client, bucket, None, segment_id_from_key).await? {
+            listed.entry(segment.id).or_default().push(key);
+        }
+        for (key, segment) in
+            list_segments(client, bucket, Some(INDEX_PREFIX), listed_segment_id).await?
+        {
+            listed.entry(segment.id).or_default().push(key);
+        }
+
+        let segments = self.describe(&listed).await?;
+        let removed = self.policy.select(&segments, Utc::now());
+
+        let mut report = KeyCompactionReport {
+            scanned: listed.len(),
+            keyed: segments.len(),
+            ..KeyCompactionReport::default()
+        };
+
+        let keys: Vec<String> = removed
+            .iter()
+            .flat_map(|removed| listed[&removed.segment.id].iter().cloned())
+            .collect();
+        let failures = delete_keys(client, bucket, &keys).await;
+
+        for removed in &removed {
+            let id = &removed.segment.id;
+            let failed = listed[id].iter().any(|key| failures.contains_key(key));
+            match (failed, removed.reason) {
+                (true, _) => report.failed += 1,
+                (false, RemovalReason::Superseded) => report.superseded += 1,
+                (false, RemovalReason::TombstoneExpired) => report.tombstones_expired += 1,
+            }
+            debug!(
+                segment_id = %id,
+                topic = removed.segment.topic.as_deref().unwrap_or_default(),
+                record_key = %removed.segment.record.key,
+                reason = %removed.reason,
+                failed,
+                "Removed keyed segment"
+            );
+        }
+
+        info!(
+            bucket = %bucket,
+            scanned = report.scanned,
+            keyed = report.keyed,
+            superseded = report.superseded,
+            tombstones_expired = report.tombstones_expired,
+            failed = report.failed,
+            "Key compaction finished"
+        );
+
+        Ok(report)
+    }
+
+    /// Read the record key and topic of every segment, keeping the keyed ones
+    ///
+    /// Segments are described from their first listed key: their user
+    /// metadata if stored, their index entry if compacted.
+    async fn describe(
+        &self,
+        segments: &HashMap<SegmentId, Vec<String>>,
+    ) -> Result<Vec<KeyedSegment>, IngestionError> {
+        let mut keyed = Vec::new();
+        let mut tasks = JoinSet::new();
+
+        for (id, keys) in segments {
+            if tasks.len() >= DESCRIBE_CONCURRENCY {
+                if let Some(segment) = join(tasks.join_next().await)? {
+                    keyed.push(segment);
+                }
+            }
+
+            let repository = self.repository.clone();
+            let (id, key) = (*id, keys[0].clone());
+            tasks.spawn(async move {
+                let described = if key.starts_with(INDEX_PREFIX) {
+                    match repository.read_index(&key).await? {
+                        Some(entry) => match (entry.record, entry.topic) {
+                            (Some(record), Some(topic)) => Some((record, Some(topic))),
+                            // Written before topics were indexed: the container has it
+                            (Some(record), None) => {
+                                let container = container_key(&entry.container);
+                                let topic = head(&repository, &container)
+                                    .await?
+                                    .and_then(|output| stored_topic(&output));
+                                Some((record, topic))
+                            }
+                            (None, _) => None,
+                        },
+                        None => None,
+                    }
+                } else {
+                    match head(&repository, &key).await? {
+                        Some(output) => stored_record(output.metadata())?
+                            .map(|record| (record, stored_topic(&output))),
+                        None => None,
+                    }
+                };
+                Ok(described.map(|(record, topic)| KeyedSegment { id, topic, record }))
+            });
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            if let Some(segment) = join(Some(joined))? {
+                keyed.push(segment);
+            }
+        }
+
+        Ok(keyed)
+    }
+}
+
+/// Unwrap the result of a describe task
+fn join(
+    joined: Option<Result<Result<Option<KeyedSegment>, IngestionError>, tokio::task::JoinError>>,
+) -> Result<Option<KeyedSegment>, IngestionError> {
+    match joined {
+        Some(joined) => joined
+            .map_err(|e| IngestionError::internal_error(format!("Describe task failed: {}", e)))?,
+        None => Ok(None),
+    }
+}
+
+/// Read the user metadata of an object
+///
+/// An object deleted since it was listed has none.
+async fn head(
+    repository: &S3StorageRepository,
+    key: &str,
+) -> Result<Option<HeadObjectOutput>, IngestionError> {
+    let request = repository
+        .write_options()
+        .apply_to_head(repository.client().head_object());
+
+    match request.bucket(repository.bucket()).key(key).send().await {
+        Ok(output) => Ok(Some(output)),
+        Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+        Err(err) => Err(IngestionError::StorageFailure(format!(
+            "S3 head_object failed for key '{}': {}",
+            key, err
+        ))),
+    }
+}
+
+/// Topic of a segment or container, from its user metadata
+fn stored_topic(output: &HeadObjectOutput) -> Option<String> {
+    output
+        .metadata()
+        .and_then(|metadata| metadata.get(TOPIC_METADATA_KEY))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::s3_repository::{
+        CREATED_AT_METADATA_KEY, RECORD_KEY_METADATA_KEY, TOMBSTONE_METADATA_KEY,
+    };
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metadata(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_stored_record() {
+        // "clé/42" in base64: keys are not limited to ASCII
+        let stored = metadata(&[
+            (RECORD_KEY_METADATA_KEY, "Y2zDqS80Mg=="),
+            (CREATED_AT_METADATA_KEY, "2024-06-01T03:00:00+00:00"),
+            (TOMBSTONE_METADATA_KEY, "true"),
+        ]);
+
+        let record = stored_record(Some(&stored)).unwrap().unwrap();
+
+        assert_eq!(record.key, "clé/42");
+        assert_eq!(
+            record.created_at,
+            Utc.with_ymd_and_hms(2024, 6, 1, 3, 0, 0).unwrap()
+        );
+        assert!(record.tombstone);
+    }
+
+    #[test]
+    fn test_unkeyed_and_malformed_records() {
+        assert_eq!(stored_record(None).unwrap(), None);
+        assert_eq!(stored_record(Some(&metadata(&[]))).unwrap(), None);
+
+        let invalid_key = metadata(&[
+            (RECORD_KEY_METADATA_KEY, "not base64!"),
+            (CREATED_AT_METADATA_KEY, "2024-06-01T03:00:00+00:00"),
+        ]);
+        assert!(stored_record(Some(&invalid_key)).is_err());
+
+        let missing_time = metadata(&[(RECORD_KEY_METADATA_KEY, "dXNlci00Mg==")]);
+        assert!(stored_record(Some(&missing_time)).is_err());
+    }
+}
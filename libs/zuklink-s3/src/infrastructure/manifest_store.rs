@@ -0,0 +1,656 @@
+//! Per-window manifests of stored segments
+//!
+//! Senders record every segment they store as an entry object of the window
+//! it was created in, `_manifests/<window>/<id>[.p<partition>].json`, holding
+//! a [`ManifestEntry`]. Once the window is closed, a sealer merges its entries
+//! into `_manifests/<window>.json`, holding a [`Manifest`], and deletes them.
+//!
+//! Receivers only list `_manifests/`: one object per sealed window plus the
+//! entries of the windows still open, instead of every object of the bucket.
+//!
+//! Sealed manifests are written conditionally, on the ETag of the manifest
+//! they were merged with (`If-Match`), or only if the window has none yet
+//! (`If-None-Match: *`). Of two sealers merging into the same manifest, the
+//! second one reads it again and merges into the new version, so no entry is
+//! lost.
+
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use zuklink_domain::{
+    ingestion::{error::IngestionError, ids::SegmentId, partition::PartitionId},
+    manifest::{
+        entry::{Manifest, ManifestEntry},
+        window::{Window, WindowSize},
+    },
+};
+
+use super::s3_repository::{
+    delete_keys, parse_segment_name, segment_name, S3StorageRepository, WriteCondition,
+    WriteOutcome,
+};
+
+/// Prefix of the manifests and their entries
+pub const MANIFEST_PREFIX: &str = "_manifests/";
+
+/// Attempts to write the manifest of a window that keeps changing
+const SEAL_ATTEMPTS: usize = 5;
+
+/// S3 key of the sealed manifest of a window
+pub fn manifest_key(window: &Window) -> String {
+    format!("{}{}.json", MANIFEST_PREFIX, window.name())
+}
+
+/// S3 key of the entry of a segment in an open window
+pub fn entry_key(
+    window: &Window,
+    segment_id: &SegmentId,
+    partition: Option<PartitionId>,
+) -> String {
+    format!(
+        "{}{}/{}.json",
+        MANIFEST_PREFIX,
+        window.name(),
+        segment_name(segment_id, partition)
+    )
+}
+
+/// What a key under [`MANIFEST_PREFIX`] holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListedManifest {
+    /// The sealed manifest of a window
+    Sealed(Window),
+    /// The entry of a segment in a window not sealed yet
+    Entry {
+        window: Window,
+        segment_id: SegmentId,
+        partition: Option<PartitionId>,
+    },
+}
+
+impl ListedManifest {
+    /// Window the object belongs to
+    pub fn window(&self) -> Window {
+        match self {
+            Self::Sealed(window) | Self::Entry { window, .. } => *window,
+        }
+    }
+}
+
+/// Read what a listed S3 key holds, see [`ListedManifest`]
+///
+/// Returns `None` for keys that are not manifests or manifest entries.
+pub fn listed_manifest(key: &str) -> Option<ListedManifest> {
+    let name = key.strip_prefix(MANIFEST_PREFIX)?.strip_suffix(".json")?;
+    match name.split_once('/') {
+        Some((window, segment)) => {
+            let (segment_id, partition) = parse_segment_name(segment)?;
+            Some(ListedManifest::Entry {
+                window: Window::parse(window)?,
+                segment_id,
+                partition,
+            })
+        }
+        None => Window::parse(name).map(ListedManifest::Sealed),
+    }
+}
+
+/// An object listed under [`MANIFEST_PREFIX`]
+#[derive(Debug, Clone)]
+pub struct ManifestObject {
+    /// S3 key of the object
+    pub key: String,
+    /// What the object holds
+    pub listed: ListedManifest,
+    /// ETag of the object, changing whenever it is rewritten
+    pub etag: Option<String>,
+    /// Time the object was last written
+    pub last_modified: DateTime<Utc>,
+}
+
+/// Result of a sealing pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SealReport {
+    /// Number of windows sealed
+    pub windows: usize,
+    /// Number of entries merged into sealed manifests
+    pub entries: usize,
+}
+
+/// Records, lists, reads and seals manifests
+///
+/// Manifests are written with the write options of the repository.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use std::time::Duration;
+/// use zuklink_domain::manifest::window::WindowSize;
+/// use zuklink_s3::infrastructure::{S3ManifestStore, S3StorageRepository};
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let repo = S3StorageRepository::new(Client::new(&config), "my-bucket".to_string());
+/// let store = S3ManifestStore::new(repo, WindowSize::Minute);
+/// let report = store
+///     .seal(chrono::Utc::now(), Duration::from_secs(60))
+///     .await
+///     .unwrap();
+/// println!("Sealed {} windows", report.windows);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct S3ManifestStore {
+    repository: S3StorageRepository,
+    window_size: WindowSize,
+}
+
+impl S3ManifestStore {
+    /// Create a manifest store recording entries in windows of `window_size`
+    ///
+    /// The size only applies to recording: windows of any size are listed,
+    /// read and sealed.
+    pub fn new(repository: S3StorageRepository, window_size: WindowSize) -> Self {
+        Self {
+            repository,
+            window_size,
+        }
+    }
+
+    /// Size of the windows entries are recorded in
+    pub fn window_size(&self) -> WindowSize {
+        self.window_size
+    }
+
+    /// Record a stored segment in the window it was created in
+    ///
+    /// Recording the same segment again rewrites the same entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the entry cannot be written
+    pub async fn record(&self, entry: &ManifestEntry) -> Result<(), IngestionError> {
+        let window = Window::containing(self.window_size, entry.created_at);
+        let key = entry_key(&window, &entry.segment_id, entry.partition);
+        let json = serde_json::to_vec(entry).map_err(|err| {
+            IngestionError::internal_error(format!("Failed to encode manifest entry: {}", err))
+        })?;
+
+        self.repository
+            .put_object(
+                &key,
+                HashMap::new(),
+                None,
+                json.into(),
+                &WriteCondition::Always,
+            )
+            .await?;
+
+        debug!(key = %key, segment_id = %entry.segment_id, "Recorded manifest entry");
+        Ok(())
+    }
+
+    /// List the sealed manifests and the entries of open windows
+    ///
+    /// Objects are returned in key order: windows in time order, each
+    /// sealed manifest before the entries of its window.
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the listing fails
+    pub async fn list(&self) -> Result<Vec<ManifestObject>, IngestionError> {
+        let mut objects = Vec::new();
+        let mut pages = self
+            .repository
+            .client()
+            .list_objects_v2()
+            .bucket(self.repository.bucket())
+            .prefix(MANIFEST_PREFIX)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|err| {
+                IngestionError::StorageFailure(format!(
+                    "S3 list_objects_v2 failed for prefix '{}': {}",
+                    MANIFEST_PREFIX, err
+                ))
+            })?;
+
+            for object in page.contents() {
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                let Some(listed) = listed_manifest(key) else {
+                    continue;
+                };
+                let Some(last_modified) = object
+                    .last_modified()
+                    .and_then(|time| DateTime::from_timestamp(time.secs(), time.subsec_nanos()))
+                else {
+                    warn!(key = %key, "Object listed without modification time, skipping");
+                    continue;
+                };
+
+                objects.push(ManifestObject {
+                    key: key.to_string(),
+                    listed,
+                    etag: object.e_tag().map(String::from),
+                    last_modified,
+                });
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Read the sealed manifest of a window, if it has one
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if the object cannot be read
+    /// - `IngestionError::InvalidData` if the manifest is malformed
+    pub async fn read(&self, window: &Window) -> Result<Option<Manifest>, IngestionError> {
+        self.get_json(&manifest_key(window)).await
+    }
+
+    /// Read a manifest entry from its key, if it still exists
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if the object cannot be read
+    /// - `IngestionError::InvalidData` if the entry is malformed
+    pub async fn read_entry(&self, key: &str) -> Result<Option<ManifestEntry>, IngestionError> {
+        self.get_json(key).await
+    }
+
+    /// Seal the windows closed at `now`
+    ///
+    /// The entries of every closed window are merged into its manifest, with
+    /// the entries it already holds if it was sealed before (entries
+    /// recorded late), then deleted. A window whose manifest cannot be
+    /// written keeps its entries for the next pass; entries that cannot be
+    /// deleted are merged again, which changes nothing.
+    ///
+    /// A manifest changed by another sealer since it was read is read and
+    /// merged again, up to a few times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the listing fails, or if a closed window cannot be
+    /// sealed
+    pub async fn seal(
+        &self,
+        now: DateTime<Utc>,
+        grace: Duration,
+    ) -> Result<SealReport, IngestionError> {
+        let mut sealed = BTreeSet::new();
+        let mut open: BTreeMap<Window, Vec<String>> = BTreeMap::new();
+        for object in self.list().await? {
+            match object.listed {
+                ListedManifest::Sealed(window) => {
+                    sealed.insert(window);
+                }
+                ListedManifest::Entry { window, .. } => {
+                    open.entry(window).or_default().push(object.key);
+                }
+            }
+        }
+
+        let mut report = SealReport::default();
+        for (window, keys) in open {
+            if !window.is_closed(now, grace) {
+                continue;
+            }
+
+            let mut entries = Vec::with_capacity(keys.len());
+            for key in &keys {
+                // Gone if another sealer got to it first
+                if let Some(entry) = self.read_entry(key).await? {
+                    entries.push(entry);
+                }
+            }
+
+            let manifest = self
+                .merge(&window, entries, sealed.contains(&window), now)
+                .await?;
+
+            let failures =
+                delete_keys(self.repository.client(), self.repository.bucket(), &keys).await;
+            if !failures.is_empty() {
+                warn!(window = %window, failed = failures.len(), "Failed to delete sealed manifest entries");
+            }
+
+            info!(window = %window, entries = manifest.entries.len(), "Sealed manifest");
+            report.windows += 1;
+            report.entries += keys.len();
+        }
+
+        Ok(report)
+    }
+
+    /// Merge entries into the sealed manifest of a window
+    ///
+    /// `sealed` tells whether the window was listed with a manifest. The
+    /// manifest is read with its ETag and written only if it is unchanged;
+    /// otherwise it is read again, up to [`SEAL_ATTEMPTS`] times.
+    async fn merge(
+        &self,
+        window: &Window,
+        entries: Vec<ManifestEntry>,
+        mut sealed: bool,
+        now: DateTime<Utc>,
+    ) -> Result<Manifest, IngestionError> {
+        for attempt in 1..=SEAL_ATTEMPTS {
+            let (mut merged, condition) = match self.read_sealed(window, sealed).await? {
+                Some((manifest, etag)) => (manifest.entries, WriteCondition::IfMatch(etag)),
+                None => (Vec::new(), WriteCondition::IfAbsent),
+            };
+            merged.extend(entries.iter().cloned());
+
+            let manifest = Manifest::seal(window.name(), merged, now);
+            match self.write(window, &manifest, &condition).await? {
+                WriteOutcome::Written => return Ok(manifest),
+                WriteOutcome::PreconditionFailed => {
+                    debug!(window = %window, attempt, "Manifest changed while sealing, merging again");
+                    sealed = true;
+                }
+            }
+        }
+
+        Err(IngestionError::StorageFailure(format!(
+            "Manifest of window {} changed {} times while sealing",
+            window, SEAL_ATTEMPTS
+        )))
+    }
+
+    /// Read the sealed manifest of a window with its ETag, if `sealed`
+    async fn read_sealed(
+        &self,
+        window: &Window,
+        sealed: bool,
+    ) -> Result<Option<(Manifest, String)>, IngestionError> {
+        if !sealed {
+            return Ok(None);
+        }
+        let key = manifest_key(window);
+        match self.get_json_versioned(&key).await? {
+            Some((manifest, Some(etag))) => Ok(Some((manifest, etag))),
+            Some((_, None)) => Err(IngestionError::StorageFailure(format!(
+                "S3 get_object returned no ETag for key '{}'",
+                key
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Write the sealed manifest of a window, if `condition` holds
+    ///
+    /// Returns `WriteOutcome::PreconditionFailed` if S3 rejects the condition.
+    async fn write(
+        &self,
+        window: &Window,
+        manifest: &Manifest,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        let json = serde_json::to_vec(manifest).map_err(|err| {
+            IngestionError::internal_error(format!("Failed to encode manifest: {}", err))
+        })?;
+
+        self.repository
+            .put_object(
+                &manifest_key(window),
+                HashMap::new(),
+                None,
+                json.into(),
+                condition,
+            )
+            .await
+    }
+
+    /// Read and decode a JSON object, if it exists
+    async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, IngestionError> {
+        Ok(self.get_json_versioned(key).await?.map(|(value, _)| value))
+    }
+
+    /// Read and decode a JSON object with its ETag, if it exists
+    async fn get_json_versioned<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<(T, Option<String>)>, IngestionError> {
+        let output = match self
+            .repository
+            .write_options()
+            .apply_to_get(self.repository.client().get_object())
+            .bucket(self.repository.bucket())
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(GetObjectError::is_no_such_key) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => {
+                return Err(IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, err
+                )))
+            }
+        };
+
+        let etag = output.e_tag().map(String::from);
+        let body = output.body.collect().await.map_err(|err| {
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, err
+            ))
+        })?;
+        serde_json::from_slice(&body.into_bytes())
+            .map(|value| Some((value, etag)))
+            .map_err(|err| {
+                IngestionError::invalid_data(format!("Invalid manifest '{}': {}", key, err))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::s3_repository::listed_segment;
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_smithy_http_client::test_util::infallible_client_fn;
+    use std::sync::{Arc, Mutex};
+
+    /// Sealed manifest of a mocked bucket, with the version of its ETag
+    #[derive(Default)]
+    struct Bucket {
+        manifest: Option<Vec<u8>>,
+        version: u32,
+        /// Whether another sealer rewrote the manifest already
+        raced: bool,
+        /// `If-Match` header of every write
+        writes: Vec<Option<String>>,
+    }
+
+    impl Bucket {
+        fn etag(&self) -> String {
+            format!("\"v{}\"", self.version)
+        }
+    }
+
+    fn entry(created_at: DateTime<Utc>) -> ManifestEntry {
+        ManifestEntry {
+            segment_id: SegmentId::new(),
+            partition: None,
+            size: 1,
+            checksum: None,
+            encryption_key_id: None,
+            records: 1,
+            created_at,
+        }
+    }
+
+    /// Store of a bucket where another sealer adds `raced` to the manifest
+    /// right before the first write
+    fn racing_store(bucket: Arc<Mutex<Bucket>>, raced: ManifestEntry) -> S3ManifestStore {
+        let http_client = infallible_client_fn(move |request| {
+            let mut bucket = bucket.lock().unwrap();
+            let response = http::Response::builder();
+
+            if request.method() == "GET" {
+                return match bucket.manifest.clone() {
+                    Some(body) => response
+                        .status(200)
+                        .header("ETag", bucket.etag())
+                        .body(SdkBody::from(body)),
+                    None => response.status(404).body(SdkBody::from(
+                        "<Error><Code>NoSuchKey</Code><Message>Not found</Message></Error>",
+                    )),
+                }
+                .unwrap();
+            }
+
+            if !bucket.raced {
+                bucket.raced = true;
+                let mut manifest: Manifest =
+                    serde_json::from_slice(bucket.manifest.as_deref().unwrap()).unwrap();
+                manifest.entries.push(raced.clone());
+                bucket.manifest = Some(serde_json::to_vec(&manifest).unwrap());
+                bucket.version += 1;
+            }
+
+            let if_match = request
+                .headers()
+                .get("If-Match")
+                .map(|value| value.to_str().unwrap().to_string());
+            bucket.writes.push(if_match.clone());
+            if if_match != Some(bucket.etag()) {
+                return response
+                    .status(412)
+                    .body(SdkBody::from(
+                        "<Error><Code>PreconditionFailed</Code><Message>Changed</Message></Error>",
+                    ))
+                    .unwrap();
+            }
+
+            bucket.manifest = Some(request.body().bytes().unwrap().to_vec());
+            bucket.version += 1;
+            response
+                .status(200)
+                .header("ETag", bucket.etag())
+                .body(SdkBody::empty())
+                .unwrap()
+        });
+
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .http_client(http_client)
+            .build();
+        let repository =
+            S3StorageRepository::new(aws_sdk_s3::Client::from_conf(config), "zuklink".to_string());
+        S3ManifestStore::new(repository, WindowSize::Hour)
+    }
+
+    #[tokio::test]
+    async fn test_merge_retries_changed_manifest() {
+        let window = Window::parse("2024-06-01T12").unwrap();
+        let now = Utc::now();
+        let (sealed, raced, late) = (entry(now), entry(now), entry(now));
+
+        let bucket = Arc::new(Mutex::new(Bucket::default()));
+        bucket.lock().unwrap().manifest = Some(
+            serde_json::to_vec(&Manifest::seal(window.name(), [sealed.clone()], now)).unwrap(),
+        );
+        let store = racing_store(bucket.clone(), raced.clone());
+
+        let manifest = store
+            .merge(&window, vec![late.clone()], true, now)
+            .await
+            .unwrap();
+
+        // Merged into the manifest rewritten by the other sealer
+        let bucket = bucket.lock().unwrap();
+        assert_eq!(
+            manifest,
+            Manifest::seal(window.name(), [sealed, raced, late], now)
+        );
+        assert_eq!(
+            serde_json::from_slice::<Manifest>(bucket.manifest.as_deref().unwrap()).unwrap(),
+            manifest
+        );
+        assert_eq!(
+            bucket.writes,
+            vec![Some("\"v0\"".to_string()), Some("\"v1\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_manifest_keys() {
+        let window = Window::parse("2024-06-01T12:05").unwrap();
+        let segment_id = SegmentId::new();
+
+        assert_eq!(manifest_key(&window), "_manifests/2024-06-01T12:05.json");
+        assert_eq!(
+            entry_key(&window, &segment_id, Some(3)),
+            format!("_manifests/2024-06-01T12:05/{}.p3.json", segment_id)
+        );
+
+        // Never mistaken for segments
+        assert_eq!(listed_segment(&manifest_key(&window)), None);
+        assert_eq!(listed_segment(&entry_key(&window, &segment_id, None)), None);
+    }
+
+    #[test]
+    fn test_listed_manifest() {
+        let window = Window::parse("2024-06-01T12").unwrap();
+        let segment_id = SegmentId::new();
+
+        assert_eq!(
+            listed_manifest(&manifest_key(&window)),
+            Some(ListedManifest::Sealed(window))
+        );
+        assert_eq!(
+            listed_manifest(&entry_key(&window, &segment_id, Some(3))),
+            Some(ListedManifest::Entry {
+                window,
+                segment_id,
+                partition: Some(3),
+            })
+        );
+        assert_eq!(
+            listed_manifest(&entry_key(&window, &segment_id, None))
+                .unwrap()
+                .window(),
+            window
+        );
+    }
+
+    #[test]
+    fn test_listed_manifest_rejects_other_keys() {
+        let segment_id = SegmentId::new();
+
+        assert_eq!(listed_manifest(&format!("{}.zuk", segment_id)), None);
+        assert_eq!(listed_manifest("_manifests/not-a-window.json"), None);
+        assert_eq!(
+            listed_manifest("_manifests/2024-06-01T12:05/oops.json"),
+            None
+        );
+        assert_eq!(
+            listed_manifest(&format!("_manifests/2024-06-01T12:05/{}.txt", segment_id)),
+            None
+        );
+        assert_eq!(listed_manifest("_progress/2024-06-01T12:05.json"), None);
+    }
+}
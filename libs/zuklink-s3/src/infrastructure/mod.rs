@@ -1,5 +1,35 @@
 //! Infrastructure adapters for S3 storage
 
+pub mod compactor;
+pub mod garbage_collector;
+pub mod key_compactor;
+pub mod manifest_store;
+pub mod notifications;
+pub mod output_committer;
+pub mod progress_store;
 pub mod s3_repository;
+pub mod write_options;
 
-pub use s3_repository::S3StorageRepository;
+pub use compactor::{CompactionReport, S3Compactor};
+pub use garbage_collector::{GcReport, S3GarbageCollector};
+pub use key_compactor::{KeyCompactionReport, S3KeyCompactor};
+pub use manifest_store::{
+    entry_key, listed_manifest, manifest_key, ListedManifest, ManifestObject, S3ManifestStore,
+    SealReport, MANIFEST_PREFIX,
+};
+pub use notifications::{
+    created_event_message, parse_created_objects, CreatedObject, EVENTS_URL_METADATA_KEY,
+};
+pub use output_committer::{output_key, S3OutputCommitter};
+pub use progress_store::{progress_key, reset_key, S3ProgressStore, PROGRESS_PREFIX};
+pub use s3_repository::{
+    assignment_key, claim_key, listed_segment, listed_segment_id, segment_key, stored_checksum,
+    stored_compression, stored_record, S3StorageRepository, CHECKSUM_METADATA_KEY,
+    COMPRESSION_METADATA_KEY, CONTAINER_PREFIX, CREATED_AT_METADATA_KEY,
+    ENCRYPTION_KEY_METADATA_KEY, IDEMPOTENCY_PREFIX, INDEX_PREFIX, RECORD_KEY_METADATA_KEY,
+    TOMBSTONE_METADATA_KEY, TOPIC_METADATA_KEY,
+};
+pub use write_options::{
+    CustomerKey, S3WriteOptions, ServerSideEncryption, DEFAULT_MULTIPART_THRESHOLD,
+    DEFAULT_PART_SIZE, MIN_PART_SIZE,
+};
@@ -0,0 +1,262 @@
+//! S3 event notifications
+//!
+//! Parses the object-created events S3 and MinIO send to webhooks, so that
+//! receivers learn about new segments without listing the bucket. Both use
+//! the S3 event message format:
+//!
+//! ```json
+//! {"Records":[{"eventName":"ObjectCreated:Put","eventTime":"2024-06-01T00:00:00.000Z",
+//!   "s3":{"bucket":{"name":"zuklink"},"object":{"key":"<id>.zuk","size":1024}}}]}
+//! ```
+//!
+//! MinIO prefixes event names with `s3:` and adds top-level fields, which are
+//! ignored. Object keys are URL-encoded in events and decoded here.
+//!
+//! Senders pushing new segments straight to their receiver post the same
+//! message, built by [`created_event_message`], to the endpoint the receiver
+//! advertises under [`EVENTS_URL_METADATA_KEY`].
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use zuklink_domain::ingestion::error::IngestionError;
+
+/// Yellowpage metadata key under which receivers advertise their event endpoint
+pub const EVENTS_URL_METADATA_KEY: &str = "events_url";
+
+/// Event name of the messages built by [`created_event_message`]
+const PUT_EVENT_NAME: &str = "ObjectCreated:Put";
+
+/// Object reported created by an event notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedObject {
+    /// Bucket holding the object
+    pub bucket: String,
+    /// Key of the object, decoded
+    pub key: String,
+    /// Size of the object in bytes
+    pub size: i64,
+    /// Time of the event, if reported
+    pub event_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventMessage {
+    #[serde(rename = "Records", default)]
+    records: Vec<EventRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventRecord {
+    event_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event_time: Option<DateTime<Utc>>,
+    s3: EventEntity,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventEntity {
+    bucket: EventBucket,
+    object: EventObject,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventBucket {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EventObject {
+    key: String,
+    #[serde(default)]
+    size: i64,
+}
+
+/// Objects created according to an event notification
+///
+/// Other events (removals, test events without records) are ignored.
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the message is not an S3 event
+/// message or holds an invalid key
+pub fn parse_created_objects(body: &[u8]) -> Result<Vec<CreatedObject>, IngestionError> {
+    let message: EventMessage = serde_json::from_slice(body).map_err(|err| {
+        IngestionError::invalid_data(format!("Invalid event notification: {}", err))
+    })?;
+
+    message
+        .records
+        .into_iter()
+        .filter(|record| {
+            record
+                .event_name
+                .trim_start_matches("s3:")
+                .starts_with("ObjectCreated:")
+        })
+        .map(|record| {
+            Ok(CreatedObject {
+                bucket: record.s3.bucket.name,
+                key: decode_key(&record.s3.object.key)?,
+                size: record.s3.object.size,
+                event_time: record.event_time,
+            })
+        })
+        .collect()
+}
+
+/// S3 event message reporting objects created
+///
+/// The message is what [`parse_created_objects`] reads back.
+///
+/// # Errors
+///
+/// Returns `IngestionError::InternalError` if the message cannot be encoded
+pub fn created_event_message(objects: &[CreatedObject]) -> Result<Vec<u8>, IngestionError> {
+    let message = EventMessage {
+        records: objects
+            .iter()
+            .map(|object| EventRecord {
+                event_name: PUT_EVENT_NAME.to_string(),
+                event_time: object.event_time,
+                s3: EventEntity {
+                    bucket: EventBucket {
+                        name: object.bucket.clone(),
+                    },
+                    object: EventObject {
+                        key: encode_key(&object.key),
+                        size: object.size,
+                    },
+                },
+            })
+            .collect(),
+    };
+    serde_json::to_vec(&message).map_err(|err| {
+        IngestionError::internal_error(format!("Failed to encode event notification: {}", err))
+    })
+}
+
+/// URL-encode an object key, as S3 does in events
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decode a URL-encoded object key (`+` is a space)
+fn decode_key(key: &str) -> Result<String, IngestionError> {
+    let invalid = || IngestionError::invalid_data(format!("Invalid object key '{}'", key));
+
+    let mut bytes = Vec::with_capacity(key.len());
+    let mut input = key.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [
+                    input.next().ok_or_else(invalid)?,
+                    input.next().ok_or_else(invalid)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_aws_event() {
+        let body = br#"{"Records":[{"eventVersion":"2.1","eventSource":"aws:s3",
+            "eventTime":"2024-06-01T00:00:00.000Z","eventName":"ObjectCreated:Put",
+            "s3":{"bucket":{"name":"zuklink"},"object":{"key":"abc.zuk","size":1024,"eTag":"x"}}}]}"#;
+
+        let objects = parse_created_objects(body).unwrap();
+
+        assert_eq!(
+            objects,
+            vec![CreatedObject {
+                bucket: "zuklink".to_string(),
+                key: "abc.zuk".to_string(),
+                size: 1024,
+                event_time: Some(Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_minio_event() {
+        let body = br#"{"EventName":"s3:ObjectCreated:CompleteMultipartUpload","Key":"zuklink/_index/abc.json",
+            "Records":[{"eventName":"s3:ObjectCreated:CompleteMultipartUpload",
+            "s3":{"bucket":{"name":"zuklink"},"object":{"key":"_index%2Fabc.json","size":12}}}]}"#;
+
+        let objects = parse_created_objects(body).unwrap();
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].key, "_index/abc.json");
+        assert_eq!(objects[0].event_time, None);
+    }
+
+    #[test]
+    fn test_other_events_are_ignored() {
+        let body = br#"{"Records":[{"eventName":"s3:ObjectRemoved:Delete",
+            "s3":{"bucket":{"name":"zuklink"},"object":{"key":"abc.zuk"}}}]}"#;
+        assert!(parse_created_objects(body).unwrap().is_empty());
+
+        // Test event sent when a notification is configured
+        assert!(parse_created_objects(br#"{"Event":"s3:TestEvent"}"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_invalid_events() {
+        assert!(parse_created_objects(b"not json").is_err());
+
+        let body = br#"{"Records":[{"eventName":"ObjectCreated:Put",
+            "s3":{"bucket":{"name":"zuklink"},"object":{"key":"abc%2"}}}]}"#;
+        assert!(parse_created_objects(body).is_err());
+    }
+
+    #[test]
+    fn test_decode_key() {
+        assert_eq!(decode_key("a+b%2Fc.zuk").unwrap(), "a b/c.zuk");
+        assert_eq!(decode_key("caf%C3%A9").unwrap(), "café");
+        assert!(decode_key("%zz").is_err());
+    }
+
+    #[test]
+    fn test_created_event_message_round_trip() {
+        let objects = vec![
+            CreatedObject {
+                bucket: "zuklink".to_string(),
+                key: "abc.p7.zuk".to_string(),
+                size: 1024,
+                event_time: Some(Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap()),
+            },
+            CreatedObject {
+                bucket: "zuklink".to_string(),
+                key: "_index/a b+é.json".to_string(),
+                size: 0,
+                event_time: None,
+            },
+        ];
+
+        let message = created_event_message(&objects).unwrap();
+
+        assert_eq!(parse_created_objects(&message).unwrap(), objects);
+        assert_eq!(encode_key("_index/a b"), "_index%2Fa%20b");
+    }
+}
@@ -0,0 +1,121 @@
+//! S3 output committer
+//!
+//! Writes the output batches of receivers as `<prefix><group>/<name>.out`,
+//! tagged with their origin in the object metadata. In exactly-once mode the
+//! write is conditional (`If-None-Match: *`): the object appears atomically,
+//! and the first commit of a tag wins. A duplicate commit, after a rebalance
+//! or a retry, is rejected by S3 and dropped.
+//!
+//! Large batches are uploaded in parts, only visible once complete.
+
+use bytes::Bytes;
+use std::future::Future;
+use tracing::debug;
+use zuklink_domain::{
+    delivery::output::{CommitOutcome, OutputCommitter, OutputTag},
+    ingestion::error::IngestionError,
+};
+
+use super::s3_repository::{S3StorageRepository, WriteCondition, WriteOutcome};
+
+/// S3 key of an output batch under `prefix`
+pub fn output_key(prefix: &str, tag: &OutputTag) -> String {
+    format!("{}{}", prefix, tag.name())
+}
+
+/// Commits output batches to an S3 bucket
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use zuklink_domain::delivery::output::{OutputCommitter, OutputTag};
+/// use zuklink_domain::ingestion::ids::SegmentId;
+/// use zuklink_s3::infrastructure::{S3OutputCommitter, S3StorageRepository};
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let repo = S3StorageRepository::new(Client::new(&config), "my-output".to_string());
+/// let committer = S3OutputCommitter::new(repo, "exports/", true);
+/// let tag = OutputTag::new("billing", None, SegmentId::new(), 0);
+/// committer.commit(&tag, b"processed").await.unwrap();
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct S3OutputCommitter {
+    repository: S3StorageRepository,
+    prefix: String,
+    exactly_once: bool,
+}
+
+impl S3OutputCommitter {
+    /// Create a committer writing under `prefix` in the bucket of a repository
+    ///
+    /// Without `exactly_once`, a duplicate commit replaces the batch.
+    pub fn new(
+        repository: S3StorageRepository,
+        prefix: impl Into<String>,
+        exactly_once: bool,
+    ) -> Self {
+        Self {
+            repository,
+            prefix: prefix.into(),
+            exactly_once,
+        }
+    }
+}
+
+impl OutputCommitter for S3OutputCommitter {
+    fn commit(
+        &self,
+        tag: &OutputTag,
+        data: &[u8],
+    ) -> impl Future<Output = Result<CommitOutcome, IngestionError>> + Send {
+        let key = output_key(&self.prefix, tag);
+        let metadata = tag.metadata();
+        let data = Bytes::copy_from_slice(data);
+        let condition = if self.exactly_once {
+            WriteCondition::IfAbsent
+        } else {
+            WriteCondition::Always
+        };
+
+        async move {
+            match self
+                .repository
+                .put_object(&key, metadata, None, data, &condition)
+                .await?
+            {
+                WriteOutcome::Written => {
+                    debug!(key = %key, "Committed output batch");
+                    Ok(CommitOutcome::Committed)
+                }
+                WriteOutcome::PreconditionFailed => {
+                    debug!(key = %key, "Output batch committed already, dropping duplicate");
+                    Ok(CommitOutcome::Duplicate)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zuklink_domain::ingestion::ids::SegmentId;
+
+    #[test]
+    fn test_output_keys() {
+        let segment_id = SegmentId::new();
+        let tag = OutputTag::new("billing", Some(7), segment_id, 0);
+
+        assert_eq!(
+            output_key("exports/", &tag),
+            format!("exports/billing/{}.p7-0.out", segment_id)
+        );
+        assert_eq!(
+            output_key("", &tag),
+            format!("billing/{}.p7-0.out", segment_id)
+        );
+    }
+}
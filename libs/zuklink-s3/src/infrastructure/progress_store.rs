@@ -0,0 +1,327 @@
+//! Persisted progress of shards
+//!
+//! Receivers record the progress of every shard they own for their consumer
+//! group as `_progress/<group>/<shard>.json`, holding a [`ShardProgress`], e.g.
+//! `{"shard":42,"position":{"time":"...","segment_id":"..."},"generation":1,"updated_at":"..."}`.
+//! The node taking over a shard, after a restart or a rebalance, resumes
+//! from there. Progress saved before it was kept per group
+//! (`_progress/<shard>.json`) is still read when a group has none of its own.
+//!
+//! The last reset of a group is stored as `_progress/<group>/reset.json`,
+//! holding a [`GroupReset`]. Receivers watch it to rewind or fast-forward.
+//!
+//! The groups are the folders of `_progress/`: retention reads the progress
+//! of every shard of every group to find what all of them processed.
+
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::debug;
+use zuklink_domain::{
+    ingestion::error::IngestionError, ordering::policy::ShardProgress, replay::reset::GroupReset,
+};
+
+use super::s3_repository::{S3StorageRepository, WriteCondition};
+
+/// Prefix of the progress objects of shards
+pub const PROGRESS_PREFIX: &str = "_progress/";
+
+/// S3 key of the progress of a shard for a consumer group
+pub fn progress_key(group: &str, shard: u16) -> String {
+    format!("{}{}/{}.json", PROGRESS_PREFIX, group, shard)
+}
+
+/// S3 key of the last reset of a consumer group
+pub fn reset_key(group: &str) -> String {
+    format!("{}{}/reset.json", PROGRESS_PREFIX, group)
+}
+
+/// S3 key of the progress of a shard saved before groups were tracked
+fn legacy_progress_key(shard: u16) -> String {
+    format!("{}{}.json", PROGRESS_PREFIX, shard)
+}
+
+/// Shard of a progress key listed under `prefix`
+fn shard_of_key(prefix: &str, key: &str) -> Option<u16> {
+    key.strip_prefix(prefix)?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
+
+/// Reads and writes the progress and resets of a consumer group
+///
+/// Progress objects are written with the write options of the repository.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use aws_sdk_s3::Client;
+/// use zuklink_s3::infrastructure::{S3ProgressStore, S3StorageRepository};
+///
+/// # async fn example() {
+/// let config = aws_config::load_from_env().await;
+/// let repo = S3StorageRepository::new(Client::new(&config), "my-bucket".to_string());
+/// let store = S3ProgressStore::new(repo, "billing");
+/// if let Some(progress) = store.load(42).await.unwrap() {
+///     println!("Shard 42 processed up to {}", progress.position.segment_id);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct S3ProgressStore {
+    repository: S3StorageRepository,
+    group: String,
+}
+
+impl S3ProgressStore {
+    /// Create a store for the progress of `group`, in the bucket of a repository
+    pub fn new(repository: S3StorageRepository, group: impl Into<String>) -> Self {
+        Self {
+            repository,
+            group: group.into(),
+        }
+    }
+
+    /// Consumer group of the progress
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// List the consumer groups with progress or resets in the bucket of a repository
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if listing the bucket fails
+    pub async fn list_groups(
+        repository: &S3StorageRepository,
+    ) -> Result<Vec<String>, IngestionError> {
+        let (_, folders) = list(repository, PROGRESS_PREFIX).await?;
+        Ok(folders
+            .iter()
+            .filter_map(|folder| folder.strip_prefix(PROGRESS_PREFIX)?.strip_suffix('/'))
+            .map(String::from)
+            .collect())
+    }
+
+    /// Read the progress of every shard of the group
+    ///
+    /// Shards without progress of their own fall back to their progress
+    /// saved before groups were tracked, like [`load`](Self::load).
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if the bucket cannot be listed or
+    ///   an object cannot be read
+    /// - `IngestionError::InvalidData` if a progress is malformed
+    pub async fn load_all(&self) -> Result<Vec<ShardProgress>, IngestionError> {
+        let prefix = format!("{}{}/", PROGRESS_PREFIX, self.group);
+        let (keys, _) = list(&self.repository, &prefix).await?;
+        let (legacy_keys, _) = list(&self.repository, PROGRESS_PREFIX).await?;
+
+        let mut shards: BTreeMap<u16, String> = legacy_keys
+            .into_iter()
+            .filter_map(|key| Some((shard_of_key(PROGRESS_PREFIX, &key)?, key)))
+            .collect();
+        shards.extend(
+            keys.into_iter()
+                .filter_map(|key| Some((shard_of_key(&prefix, &key)?, key))),
+        );
+
+        let mut progress = Vec::with_capacity(shards.len());
+        for key in shards.values() {
+            progress.extend(self.get_json::<ShardProgress>(key).await?);
+        }
+        Ok(progress)
+    }
+
+    /// Read the progress of a shard, if it has one
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if the object cannot be read
+    /// - `IngestionError::InvalidData` if the progress is malformed
+    pub async fn load(&self, shard: u16) -> Result<Option<ShardProgress>, IngestionError> {
+        let mut progress: Option<ShardProgress> =
+            self.get_json(&progress_key(&self.group, shard)).await?;
+        if progress.is_none() {
+            progress = self.get_json(&legacy_progress_key(shard)).await?;
+        }
+
+        if let Some(progress) = &progress {
+            debug!(shard, segment_id = %progress.position.segment_id, "Read shard progress");
+        }
+        Ok(progress)
+    }
+
+    /// Record the progress of a shard, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the object cannot be written
+    pub async fn save(&self, progress: &ShardProgress) -> Result<(), IngestionError> {
+        self.put_json(&progress_key(&self.group, progress.shard), progress)
+            .await?;
+
+        debug!(shard = progress.shard, segment_id = %progress.position.segment_id, "Saved shard progress");
+        Ok(())
+    }
+
+    /// Read the last reset of the group, if it was ever reset
+    ///
+    /// # Errors
+    ///
+    /// - `IngestionError::StorageFailure` if the object cannot be read
+    /// - `IngestionError::InvalidData` if the reset is malformed
+    pub async fn load_reset(&self) -> Result<Option<GroupReset>, IngestionError> {
+        self.get_json(&reset_key(&self.group)).await
+    }
+
+    /// Record a reset of the group, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the object cannot be written
+    pub async fn save_reset(&self, reset: &GroupReset) -> Result<(), IngestionError> {
+        self.put_json(&reset_key(&self.group), reset).await?;
+
+        debug!(group = %self.group, generation = reset.generation, start = %reset.start, "Saved group reset");
+        Ok(())
+    }
+
+    /// Write a JSON object, replacing the previous one
+    async fn put_json<T: Serialize>(&self, key: &str, value: &T) -> Result<(), IngestionError> {
+        let json = serde_json::to_vec(value).map_err(|err| {
+            IngestionError::internal_error(format!("Failed to encode '{}': {}", key, err))
+        })?;
+
+        self.repository
+            .put_object(
+                key,
+                HashMap::new(),
+                None,
+                json.into(),
+                &WriteCondition::Always,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Read and decode a JSON object, if it exists
+    async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, IngestionError> {
+        let output = match self
+            .repository
+            .write_options()
+            .apply_to_get(self.repository.client().get_object())
+            .bucket(self.repository.bucket())
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(GetObjectError::is_no_such_key) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => {
+                return Err(IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, err
+                )))
+            }
+        };
+
+        let body = output.body.collect().await.map_err(|err| {
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, err
+            ))
+        })?;
+        serde_json::from_slice(&body.into_bytes())
+            .map(Some)
+            .map_err(|err| {
+                IngestionError::invalid_data(format!("Invalid progress '{}': {}", key, err))
+            })
+    }
+}
+
+/// List the objects and folders right under `prefix`
+async fn list(
+    repository: &S3StorageRepository,
+    prefix: &str,
+) -> Result<(Vec<String>, Vec<String>), IngestionError> {
+    let mut keys = Vec::new();
+    let mut folders = Vec::new();
+    let mut pages = repository
+        .client()
+        .list_objects_v2()
+        .bucket(repository.bucket())
+        .prefix(prefix)
+        .delimiter("/")
+        .into_paginator()
+        .send();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            IngestionError::StorageFailure(format!(
+                "S3 list_objects_v2 failed for prefix '{}': {}",
+                prefix, err
+            ))
+        })?;
+        keys.extend(
+            page.contents()
+                .iter()
+                .filter_map(|o| o.key())
+                .map(String::from),
+        );
+        folders.extend(
+            page.common_prefixes()
+                .iter()
+                .filter_map(|p| p.prefix())
+                .map(String::from),
+        );
+    }
+
+    Ok((keys, folders))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::s3_repository::listed_segment;
+    use super::*;
+
+    #[test]
+    fn test_progress_keys() {
+        assert_eq!(progress_key("billing", 42), "_progress/billing/42.json");
+        assert_eq!(reset_key("billing"), "_progress/billing/reset.json");
+        assert_eq!(legacy_progress_key(42), "_progress/42.json");
+
+        // Never mistaken for a segment
+        assert_eq!(listed_segment(&progress_key("billing", 42)), None);
+        assert_eq!(listed_segment(&reset_key("billing")), None);
+        assert_eq!(listed_segment(&legacy_progress_key(42)), None);
+    }
+
+    #[test]
+    fn test_shard_of_key() {
+        assert_eq!(
+            shard_of_key("_progress/billing/", &progress_key("billing", 42)),
+            Some(42)
+        );
+        assert_eq!(
+            shard_of_key(PROGRESS_PREFIX, &legacy_progress_key(42)),
+            Some(42)
+        );
+        assert_eq!(
+            shard_of_key("_progress/billing/", &reset_key("billing")),
+            None
+        );
+        assert_eq!(
+            shard_of_key(PROGRESS_PREFIX, &progress_key("billing", 42)),
+            None
+        );
+    }
+}
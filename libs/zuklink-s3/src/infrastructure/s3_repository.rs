@@ -3,14 +3,356 @@
 //! This module implements the `StorageRepository` trait using AWS S3 as the backend.
 //! It handles all S3 operations and converts AWS errors to domain errors.
 
-use aws_sdk_s3::{primitives::ByteStream, Client};
+use aws_sdk_s3::{
+    config::http::HttpResponse,
+    error::SdkError,
+    operation::get_object::GetObjectError,
+    primitives::ByteStream,
+    types::{
+        ChecksumAlgorithm, ChecksumMode, CompletedMultipartUpload, CompletedPart, Delete,
+        ObjectIdentifier,
+    },
+    Client,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Arc;
 use tracing::{debug, error, info, instrument, warn};
 use zuklink_domain::{
-    ingestion::{entity::Segment, error::IngestionError, ids::SegmentId},
+    compaction::container::IndexEntry,
+    ingestion::{
+        checksum::Checksum,
+        compression::Compression,
+        entity::{Segment, StoredSegment},
+        error::IngestionError,
+        idempotency::IdempotencyClaim,
+        ids::SegmentId,
+        keyed::KeyedRecord,
+        partition::{self, PartitionId},
+    },
     ports::StorageRepository,
+    retention::policy::SegmentInfo,
 };
 
+use super::write_options::{S3WriteOptions, ServerSideEncryption};
+
+/// User metadata key holding the hex SHA-256 checksum of a segment
+///
+/// Stored as `x-amz-meta-sha256`, readable by any S3 client.
+pub const CHECKSUM_METADATA_KEY: &str = "sha256";
+
+/// User metadata key holding the codec a segment is compressed with
+///
+/// Absent for uncompressed segments.
+pub const COMPRESSION_METADATA_KEY: &str = "compression";
+
+/// User metadata key holding the id of the master key a segment is encrypted with
+///
+/// Absent for unencrypted segments. Lists which segments still depend on a
+/// key before it is retired.
+pub const ENCRYPTION_KEY_METADATA_KEY: &str = "key-id";
+
+/// User metadata key holding the record key of a keyed segment, base64 encoded
+///
+/// Encoded because S3 metadata travels as HTTP headers, which cannot carry
+/// arbitrary UTF-8.
+pub const RECORD_KEY_METADATA_KEY: &str = "record-key";
+
+/// User metadata key holding the ingestion time of a keyed segment (RFC 3339)
+pub const CREATED_AT_METADATA_KEY: &str = "created-at";
+
+/// User metadata key marking keyed segments that are tombstones
+pub const TOMBSTONE_METADATA_KEY: &str = "tombstone";
+
+/// User metadata key holding the topic a segment was ingested under
+///
+/// Absent for segments without a topic.
+pub const TOPIC_METADATA_KEY: &str = "topic";
+
+/// Precondition of a write
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum WriteCondition {
+    /// Write unconditionally, replacing any object under the key
+    Always,
+    /// Write only if no object exists under the key (`If-None-Match: *`)
+    IfAbsent,
+    /// Write only if the object under the key has this ETag (`If-Match`)
+    IfMatch(String),
+}
+
+impl WriteCondition {
+    /// Whether S3 may reject the write with 412 Precondition Failed
+    fn is_conditional(&self) -> bool {
+        *self != Self::Always
+    }
+}
+
+/// Result of a write that S3 did not fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WriteOutcome {
+    /// The object was written
+    Written,
+    /// S3 rejected the [`WriteCondition`] (412 Precondition Failed): an
+    /// object already exists under the key, or it changed
+    PreconditionFailed,
+}
+
+/// Set the precondition headers of a write
+///
+/// `PutObject` and `CompleteMultipartUpload` builders have the same setters
+/// but no common trait.
+macro_rules! apply_condition {
+    ($condition:expr, $request:expr) => {{
+        let request = $request;
+        match $condition {
+            WriteCondition::Always => request,
+            WriteCondition::IfAbsent => request.if_none_match("*"),
+            WriteCondition::IfMatch(etag) => request.if_match(etag.clone()),
+        }
+    }};
+}
+
+/// Read the record key stored with an S3 object
+///
+/// Returns `None` for unkeyed segments.
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the record metadata is malformed
+pub fn stored_record(
+    metadata: Option<&HashMap<String, String>>,
+) -> Result<Option<KeyedRecord>, IngestionError> {
+    let Some(metadata) = metadata else {
+        return Ok(None);
+    };
+    let Some(encoded) = metadata.get(RECORD_KEY_METADATA_KEY) else {
+        return Ok(None);
+    };
+
+    let key = BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| {
+            IngestionError::invalid_data(format!("Invalid stored record key '{}'", encoded))
+        })?;
+    let created_at = metadata
+        .get(CREATED_AT_METADATA_KEY)
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| {
+            IngestionError::invalid_data(format!(
+                "Missing or invalid creation time for record key '{}'",
+                key
+            ))
+        })?;
+
+    Ok(Some(KeyedRecord {
+        key,
+        created_at,
+        tombstone: metadata
+            .get(TOMBSTONE_METADATA_KEY)
+            .is_some_and(|value| value == "true"),
+    }))
+}
+
+/// Read the codec an S3 object is compressed with
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the codec is unknown
+pub fn stored_compression(
+    metadata: Option<&HashMap<String, String>>,
+) -> Result<Compression, IngestionError> {
+    match metadata.and_then(|metadata| metadata.get(COMPRESSION_METADATA_KEY)) {
+        Some(name) => name.parse().map_err(|_| {
+            IngestionError::invalid_data(format!("Unknown stored compression '{}'", name))
+        }),
+        None => Ok(Compression::None),
+    }
+}
+
+/// Read the checksum stored with an S3 object
+///
+/// Prefers the `sha256` user metadata and falls back to the S3 native
+/// `ChecksumSHA256` field (returned by `GetObject` with checksum mode
+/// enabled). Returns `None` for objects stored without a checksum.
+///
+/// # Errors
+///
+/// Returns `IngestionError::InvalidData` if the stored checksum is malformed
+pub fn stored_checksum(
+    metadata: Option<&HashMap<String, String>>,
+    checksum_sha256: Option<&str>,
+) -> Result<Option<Checksum>, IngestionError> {
+    if let Some(hex) = metadata.and_then(|metadata| metadata.get(CHECKSUM_METADATA_KEY)) {
+        return hex.parse().map(Some);
+    }
+
+    match checksum_sha256 {
+        // Multipart uploads report a checksum of checksums ("<base64>-<parts>"),
+        // which cannot be compared with the data
+        Some(encoded) if !encoded.contains('-') => {
+            let bytes = BASE64
+                .decode(encoded)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .ok_or_else(|| {
+                    IngestionError::invalid_data(format!(
+                        "Invalid S3 SHA-256 checksum '{}'",
+                        encoded
+                    ))
+                })?;
+            Ok(Some(Checksum::from_bytes(bytes)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read the segment ID from an S3 key
+///
+/// Returns `None` for keys that are not segments (other extension, prefixed
+/// keys such as progress).
+pub(crate) fn segment_id_from_key(key: &str) -> Option<SegmentId> {
+    key.strip_suffix(".zuk")
+        .and_then(parse_segment_name)
+        .map(|(id, _)| id)
+}
+
+/// Read the ID and partition from the name of a segment
+///
+/// `<id>` for unpartitioned segments, `<id>.p<partition>` for partitioned
+/// ones. Only the canonical form is accepted, so a segment has a single key.
+pub(crate) fn parse_segment_name(name: &str) -> Option<(SegmentId, Option<PartitionId>)> {
+    if name.contains('/') {
+        return None;
+    }
+    let (id, partition) = match name.split_once('.') {
+        Some((id, raw)) => {
+            let partition: PartitionId = raw.strip_prefix('p')?.parse().ok()?;
+            if format!("p{}", partition) != raw {
+                return None;
+            }
+            (id, Some(partition))
+        }
+        None => (name, None),
+    };
+    Some((id.parse().ok()?, partition))
+}
+
+/// Name of a segment in its keys, see [`parse_segment_name`]
+pub(crate) fn segment_name(segment_id: &SegmentId, partition: Option<PartitionId>) -> String {
+    match partition {
+        Some(partition) => format!("{}.p{}", segment_id, partition),
+        None => segment_id.to_string(),
+    }
+}
+
+/// S3 key of a segment: `<id>.zuk`, or `<id>.p<partition>.zuk` if partitioned
+///
+/// The partition is part of the key so receivers can assign segments by
+/// partition from a listing alone.
+pub fn segment_key(segment_id: &SegmentId, partition: Option<PartitionId>) -> String {
+    format!("{}.zuk", segment_name(segment_id, partition))
+}
+
+/// Key deciding which receiver owns a segment
+///
+/// Segments of a partition all go to the owner of the partition, so they are
+/// processed by a single receiver; other segments are spread by their key.
+/// Receivers and senders pushing notifications share this assignment.
+pub fn assignment_key(segment_id: &SegmentId, partition: Option<PartitionId>) -> String {
+    match partition {
+        Some(partition) => partition::routing_key(partition),
+        None => segment_key(segment_id, partition),
+    }
+}
+
+/// Prefix of the index entries of compacted segments
+///
+/// `_index/<segment-id>.json` locates a segment in its container once the
+/// standalone object is gone.
+pub const INDEX_PREFIX: &str = "_index/";
+
+/// Prefix of the containers of compacted segments (`_compacted/<id>.zkc`)
+pub const CONTAINER_PREFIX: &str = "_compacted/";
+
+/// S3 key of the index entry of a compacted segment
+///
+/// Partitioned like the key of the segment: `_index/<id>.p<partition>.json`.
+pub(crate) fn index_key(segment_id: &SegmentId, partition: Option<PartitionId>) -> String {
+    format!(
+        "{}{}.json",
+        INDEX_PREFIX,
+        segment_name(segment_id, partition)
+    )
+}
+
+/// Prefix of the claims of client idempotency keys
+pub const IDEMPOTENCY_PREFIX: &str = "_idempotency/";
+
+/// S3 key of the claim of a client idempotency key
+///
+/// Keys are hashed, so any key maps to a valid object name, and scoped per
+/// topic: `_idempotency/[<topic>/]<sha256 of the key>.json`.
+pub fn claim_key(topic: Option<&str>, key: &str) -> String {
+    let hash = Checksum::sha256(key.as_bytes());
+    match topic {
+        Some(topic) => format!("{}{}/{}.json", IDEMPOTENCY_PREFIX, topic, hash),
+        None => format!("{}{}.json", IDEMPOTENCY_PREFIX, hash),
+    }
+}
+
+/// S3 key of a container
+pub(crate) fn container_key(container: &SegmentId) -> String {
+    format!("{}{}.zkc", CONTAINER_PREFIX, container)
+}
+
+/// Read the container ID from an S3 key
+pub(crate) fn container_id_from_key(key: &str) -> Option<SegmentId> {
+    key.strip_prefix(CONTAINER_PREFIX)
+        .and_then(|key| key.strip_suffix(".zkc"))
+        .and_then(|id| id.parse().ok())
+}
+
+/// Read the ID and partition of a readable segment from a listed S3 key
+///
+/// Both standalone segments (`<id>.zuk`) and the index entries of compacted
+/// segments (`_index/<id>.json`) are segments `load` can read, partitioned or
+/// not. Returns `None` for any other key.
+pub fn listed_segment(key: &str) -> Option<(SegmentId, Option<PartitionId>)> {
+    key.strip_suffix(".zuk")
+        .or_else(|| {
+            key.strip_prefix(INDEX_PREFIX)
+                .and_then(|key| key.strip_suffix(".json"))
+        })
+        .and_then(parse_segment_name)
+}
+
+/// Read the ID of a readable segment from a listed S3 key
+///
+/// See [`listed_segment`].
+pub fn listed_segment_id(key: &str) -> Option<SegmentId> {
+    listed_segment(key).map(|(id, _)| id)
+}
+
+/// Whether a failed GET means the object does not exist
+fn is_no_such_key(err: &SdkError<GetObjectError, HttpResponse>) -> bool {
+    err.as_service_error()
+        .is_some_and(GetObjectError::is_no_such_key)
+}
+
+/// Error of a segment found under none of its keys
+fn not_found(segment_id: &SegmentId) -> IngestionError {
+    IngestionError::StorageFailure(format!(
+        "Segment {} not found in S3 (neither stored nor compacted)",
+        segment_id
+    ))
+}
+
 /// S3-based implementation of the StorageRepository port
 ///
 /// This adapter translates domain storage operations into AWS S3 API calls.
@@ -23,14 +365,65 @@ use zuklink_domain::{
 /// - An S3 bucket name
 /// - An AWS SDK S3 Client (configured with region, credentials, endpoint)
 ///
+/// Server-side encryption, storage class, tags and headers are set with
+/// [`with_write_options`](Self::with_write_options). Segments larger than the
+/// multipart threshold are uploaded in parts, with the same options. Segments
+/// ingested under a topic are written with the options of their topic, and
+/// their topic is recorded as `topic` user metadata.
+///
+/// ## Checksums and Compression
+///
+/// Segments are written with the SHA-256 checksum of their uncompressed data
+/// as `sha256` user metadata, and segments stored as is (neither compressed
+/// nor encrypted) in a single request also as S3 native checksum (S3 rejects
+/// the upload if the body does not match). The codec of compressed segments
+/// is recorded as `compression` user metadata, and the master key of
+/// encrypted segments as `key-id`. `load` returns the checksum and codec so
+/// the domain can decompress and verify the data.
+///
+/// ## Keyed Segments
+///
+/// The record key of keyed segments is recorded as `record-key` user
+/// metadata (base64), with the ingestion time as `created-at` and, for
+/// tombstones, `tombstone: true`. `load` returns them with the data.
+///
+/// ## Compacted Segments
+///
+/// Segments merged into a container by the
+/// [`S3Compactor`](super::S3Compactor) are no longer stored at their own key.
+/// When a segment is missing, `get` and `load` look up its index entry
+/// (`_index/<id>.json`) and read the payload from the container with a range
+/// request. `delete` removes the index entry, the payload stays in the
+/// container until the container expires.
+///
+/// ## Idempotency Keys
+///
+/// Idempotency keys are claimed with a conditional write of a JSON
+/// [`IdempotencyClaim`] at [`claim_key`], with the options of the topic of the
+/// claim. Claims are never deleted: a retry is recognized after its segment
+/// is compacted or expired. `save_if_absent` also finds segments with the
+/// same ID stored under another partition or compacted.
+///
+/// ## Partitioned Segments
+///
+/// Segments routed to a partition are stored at `<id>.p<partition>.zuk`
+/// (and indexed at `_index/<id>.p<partition>.json` once compacted), so the
+/// partition of every segment is known from a listing alone. Their key cannot
+/// be derived from the ID: `get`, `load`, `exists` and `delete` find it by
+/// listing the ID prefix, after the unpartitioned key.
+/// [`load_partitioned`](Self::load_partitioned) reads a segment whose
+/// partition is known without listing.
+///
 /// ## Error Handling
 ///
 /// All AWS SDK errors are converted to `IngestionError::StorageFailure` with
-/// descriptive error messages for debugging.
+/// descriptive error messages for debugging, except rejected conditional
+/// segment writes, which become `IngestionError::SegmentAlreadyExists`.
 #[derive(Clone)]
 pub struct S3StorageRepository {
     client: Client,
     bucket: String,
+    options: Arc<S3WriteOptions>,
 }
 
 impl S3StorageRepository {
@@ -55,7 +448,48 @@ impl S3StorageRepository {
     /// ```
     pub fn new(client: Client, bucket: String) -> Self {
         info!(bucket = %bucket, "Initializing S3StorageRepository");
-        Self { client, bucket }
+        Self {
+            client,
+            bucket,
+            options: Arc::new(S3WriteOptions::default()),
+        }
+    }
+
+    /// Set the options applied to every object written
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use aws_sdk_s3::Client;
+    /// use zuklink_s3::infrastructure::{S3StorageRepository, S3WriteOptions, ServerSideEncryption};
+    ///
+    /// # async fn example() {
+    /// let config = aws_config::load_from_env().await;
+    /// let options = S3WriteOptions {
+    ///     encryption: Some(ServerSideEncryption::S3),
+    ///     ..S3WriteOptions::default()
+    /// };
+    /// let repo = S3StorageRepository::new(Client::new(&config), "my-bucket".to_string())
+    ///     .with_write_options(options);
+    /// # }
+    /// ```
+    pub fn with_write_options(mut self, options: S3WriteOptions) -> Self {
+        info!(
+            encryption = ?options.encryption.as_ref().map(|sse| match sse {
+                ServerSideEncryption::S3 => "sse-s3",
+                ServerSideEncryption::Kms { .. } => "sse-kms",
+                ServerSideEncryption::Customer(_) => "sse-c",
+            }),
+            storage_class = ?options.storage_class,
+            "Configured S3 write options"
+        );
+        self.options = Arc::new(options);
+        self
+    }
+
+    /// Get the options applied to every object written
+    pub fn write_options(&self) -> &S3WriteOptions {
+        &self.options
     }
 
     /// Get the bucket name
@@ -63,67 +497,795 @@ impl S3StorageRepository {
         &self.bucket
     }
 
-    /// Generate the S3 key for a segment
+    /// Get the S3 client
+    pub(crate) fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Generate the S3 key for an unpartitioned segment
     ///
-    /// Follows the flat storage pattern: just the segment UUID with .zuk extension
-    fn generate_key(segment_id: &SegmentId) -> String {
+    /// Follows the flat storage pattern: just the segment UUID with .zuk
+    /// extension. See [`segment_key`] for partitioned segments.
+    pub fn generate_key(segment_id: &SegmentId) -> String {
         format!("{}.zuk", segment_id)
     }
-}
 
-impl StorageRepository for S3StorageRepository {
-    #[instrument(skip(self, segment, data), fields(segment_id = %segment.id(), data_size = data.len()))]
-    fn save(
+    /// User metadata of a segment: checksum, codec, encryption key, record and topic
+    fn object_metadata(segment: &Segment) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        if segment.compression() != Compression::None {
+            metadata.insert(
+                COMPRESSION_METADATA_KEY.to_string(),
+                segment.compression().name().to_string(),
+            );
+        }
+        if let Some(key_id) = segment.encryption_key_id() {
+            metadata.insert(ENCRYPTION_KEY_METADATA_KEY.to_string(), key_id.to_string());
+        }
+        if let Some(checksum) = segment.checksum() {
+            metadata.insert(CHECKSUM_METADATA_KEY.to_string(), checksum.to_string());
+        }
+        if let Some(record) = segment.keyed_record() {
+            metadata.insert(
+                RECORD_KEY_METADATA_KEY.to_string(),
+                BASE64.encode(record.key.as_bytes()),
+            );
+            metadata.insert(
+                CREATED_AT_METADATA_KEY.to_string(),
+                record.created_at.to_rfc3339(),
+            );
+            if record.tombstone {
+                metadata.insert(TOMBSTONE_METADATA_KEY.to_string(), "true".to_string());
+            }
+        }
+        if let Some(topic) = segment.topic() {
+            metadata.insert(TOPIC_METADATA_KEY.to_string(), topic.to_string());
+        }
+        metadata
+    }
+
+    /// S3 native checksum of the stored bytes, if known
+    ///
+    /// The checksum covers the original data, so it only matches the body of
+    /// segments stored as is.
+    fn native_checksum(segment: &Segment) -> Option<String> {
+        let stored_as_is =
+            segment.compression() == Compression::None && segment.encryption_key_id().is_none();
+        segment
+            .checksum()
+            .filter(|_| stored_as_is)
+            .map(|checksum| BASE64.encode(checksum.as_bytes()))
+    }
+
+    /// Write a segment with the options of its topic, in parts if it is large
+    ///
+    /// With `if_absent`, the write is conditional (`If-None-Match: *`): S3
+    /// rejects it with 412 Precondition Failed if an object already exists
+    /// under this key.
+    fn put(
         &self,
         segment: &Segment,
         data: &[u8],
+        if_absent: bool,
     ) -> impl std::future::Future<Output = Result<String, IngestionError>> + Send {
-        let client = self.client.clone();
-        let bucket = self.bucket.clone();
-        let key = Self::generate_key(segment.id());
+        let repo = self.clone();
+        let key = segment_key(segment.id(), segment.partition());
+        let segment_id = *segment.id();
+        let metadata = Self::object_metadata(segment);
+        let native_checksum = Self::native_checksum(segment);
+        let topic = segment.topic().map(String::from);
         let data = Bytes::copy_from_slice(data);
 
+        let condition = if if_absent {
+            WriteCondition::IfAbsent
+        } else {
+            WriteCondition::Always
+        };
+
         async move {
-            debug!(key = %key, bucket = %bucket, "Saving segment to S3");
+            debug!(key = %key, bucket = %repo.bucket, if_absent, topic = ?topic, "Saving segment to S3");
 
-            let body = ByteStream::from(data);
+            // The conditional write only covers this key: a segment with the
+            // same ID may be stored under another partition, or compacted
+            if if_absent && repo.exists(&segment_id).await? {
+                info!(key = %key, "Segment already exists in S3");
+                return Err(IngestionError::segment_already_exists(segment_id));
+            }
 
-            match client
-                .put_object()
-                .bucket(&bucket)
-                .key(&key)
-                .body(body)
-                .send()
+            let options = repo.options.for_topic(topic.as_deref());
+            match repo
+                .write_object(options, &key, metadata, native_checksum, data, &condition)
                 .await
             {
-                Ok(_) => {
+                Ok(WriteOutcome::Written) => {
                     info!(key = %key, "Successfully saved segment to S3");
                     Ok(key)
                 }
+                Ok(WriteOutcome::PreconditionFailed) => {
+                    info!(key = %key, "Segment already exists in S3");
+                    Err(IngestionError::segment_already_exists(segment_id))
+                }
                 Err(err) => {
-                    error!(key = %key, error = ?err, "Failed to save segment to S3");
-                    Err(IngestionError::StorageFailure(format!(
-                        "S3 put_object failed for key '{}': {}",
+                    error!(key = %key, error = %err, "Failed to save segment to S3");
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    /// Write an object with the global options, in parts if it is large
+    ///
+    /// Returns `WriteOutcome::PreconditionFailed` when S3 rejects the
+    /// `condition`, which never happens to `WriteCondition::Always`.
+    pub(crate) async fn put_object(
+        &self,
+        key: &str,
+        metadata: HashMap<String, String>,
+        native_checksum: Option<String>,
+        data: Bytes,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        self.write_object(
+            &self.options,
+            key,
+            metadata,
+            native_checksum,
+            data,
+            condition,
+        )
+        .await
+    }
+
+    /// Write an object of a topic with the options of the topic, in parts if it is large
+    pub(crate) async fn put_topic_object(
+        &self,
+        topic: Option<&str>,
+        key: &str,
+        metadata: HashMap<String, String>,
+        data: Bytes,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        let options = self.options.for_topic(topic);
+        self.write_object(options, key, metadata, None, data, condition)
+            .await
+    }
+
+    /// Write an object with the given options, in parts if it is large
+    async fn write_object(
+        &self,
+        options: &S3WriteOptions,
+        key: &str,
+        metadata: HashMap<String, String>,
+        native_checksum: Option<String>,
+        data: Bytes,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        if options.is_multipart(data.len()) {
+            return self
+                .put_multipart(options, key, metadata, data, condition)
+                .await;
+        }
+
+        let request = options
+            .apply_to_put(self.client.put_object())
+            .bucket(&self.bucket)
+            .key(key)
+            .set_metadata(Some(metadata));
+        let request = match native_checksum {
+            Some(checksum) => request
+                .checksum_algorithm(ChecksumAlgorithm::Sha256)
+                .checksum_sha256(checksum),
+            None => request,
+        };
+        let request = apply_condition!(condition, request);
+
+        match request.body(ByteStream::from(data)).send().await {
+            Ok(_) => Ok(WriteOutcome::Written),
+            Err(err) => write_failed("put_object", key, condition, err),
+        }
+    }
+
+    /// Upload a segment in parts, aborting the upload on failure
+    ///
+    /// The object only becomes visible on completion, so readers never see a
+    /// partial segment. The `condition` applies to the completion.
+    async fn put_multipart(
+        &self,
+        options: &S3WriteOptions,
+        key: &str,
+        metadata: HashMap<String, String>,
+        data: Bytes,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        let upload = options
+            .apply_to_create_multipart(self.client.create_multipart_upload())
+            .bucket(&self.bucket)
+            .key(key)
+            .set_metadata(Some(metadata))
+            .send()
+            .await
+            .map_err(|err| storage_failure("create_multipart_upload", key, err))?;
+        let upload_id = upload.upload_id().ok_or_else(|| {
+            IngestionError::StorageFailure(format!(
+                "S3 create_multipart_upload returned no upload id for key '{}'",
+                key
+            ))
+        })?;
+
+        let result = async {
+            let mut parts = Vec::new();
+            for (index, start) in (0..data.len()).step_by(options.part_size).enumerate() {
+                let part_number = index as i32 + 1;
+                let end = (start + options.part_size).min(data.len());
+
+                let part = options
+                    .apply_to_upload_part(self.client.upload_part())
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(data.slice(start..end)))
+                    .send()
+                    .await
+                    .map_err(|err| storage_failure("upload_part", key, err))?;
+
+                parts.push(
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(part.e_tag().map(String::from))
+                        .build(),
+                );
+            }
+            debug!(key = %key, parts = parts.len(), "Uploaded segment parts");
+
+            let request = self
+                .client
+                .complete_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                );
+            let request = apply_condition!(condition, request);
+
+            match request.send().await {
+                Ok(_) => Ok(WriteOutcome::Written),
+                Err(err) => write_failed("complete_multipart_upload", key, condition, err),
+            }
+        }
+        .await;
+
+        if !matches!(result, Ok(WriteOutcome::Written)) {
+            // Parts of an unfinished upload are billed until it is aborted
+            if let Err(err) = self
+                .client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                warn!(key = %key, upload_id, error = ?err, "Failed to abort multipart upload");
+            }
+        }
+
+        result
+    }
+
+    /// Check whether an object exists
+    async fn head(&self, key: &str) -> Result<bool, IngestionError> {
+        let request = self.options.apply_to_head(self.client.head_object());
+        match request.bucket(&self.bucket).key(key).send().await {
+            Ok(_) => Ok(true),
+            Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+            Err(err) => {
+                error!(key = %key, error = ?err, "Failed to check object existence in S3");
+                Err(IngestionError::StorageFailure(format!(
+                    "S3 head_object failed for key '{}': {}",
+                    key, err
+                )))
+            }
+        }
+    }
+
+    /// Find the key of a partitioned segment or index entry by listing
+    ///
+    /// The key of a partitioned segment cannot be derived from its ID alone:
+    /// objects under `<prefix><id>.p` are listed instead. `prefix` is empty
+    /// for standalone segments and [`INDEX_PREFIX`] for index entries.
+    async fn find_partitioned(
+        &self,
+        prefix: &str,
+        segment_id: &SegmentId,
+    ) -> Result<Option<String>, IngestionError> {
+        let search = format!("{}{}.p", prefix, segment_id);
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&search)
+            .send()
+            .await
+            .map_err(|err| {
+                IngestionError::StorageFailure(format!(
+                    "S3 list_objects_v2 failed for prefix '{}': {}",
+                    search, err
+                ))
+            })?;
+
+        Ok(output
+            .contents()
+            .iter()
+            .filter_map(|object| object.key())
+            .find(|key| listed_segment_id(key) == Some(*segment_id))
+            .map(String::from))
+    }
+
+    /// Load a standalone segment from its key
+    ///
+    /// Returns `None` if no object exists under this key.
+    async fn load_key(&self, key: &str) -> Result<Option<StoredSegment>, IngestionError> {
+        debug!(key = %key, bucket = %self.bucket, "Loading segment from S3");
+
+        let output = match self
+            .options
+            .apply_to_get(self.client.get_object())
+            .bucket(&self.bucket)
+            .key(key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(err) if is_no_such_key(&err) => return Ok(None),
+            Err(err) => {
+                warn!(key = %key, error = ?err, "Failed to load segment from S3");
+                return Err(IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, err
+                )));
+            }
+        };
+
+        let checksum = stored_checksum(output.metadata(), output.checksum_sha256())?;
+        let compression = stored_compression(output.metadata())?;
+        let record = stored_record(output.metadata())?;
+
+        let data = output.body.collect().await.map_err(|err| {
+            error!(key = %key, error = ?err, "Failed to read S3 object body");
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, err
+            ))
+        })?;
+
+        let bytes = data.into_bytes().to_vec();
+        info!(key = %key, size = bytes.len(), "Successfully loaded segment from S3");
+        Ok(Some(
+            StoredSegment::new(bytes, checksum)
+                .with_compression(compression)
+                .with_record(record),
+        ))
+    }
+
+    /// Load a segment whose partition is known, standalone or compacted
+    ///
+    /// Cheaper than [`load`](StorageRepository::load) for partitioned
+    /// segments, whose keys are then known without listing, e.g. when the
+    /// segment was found by listing the bucket.
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`load`](StorageRepository::load)
+    pub async fn load_partitioned(
+        &self,
+        segment_id: &SegmentId,
+        partition: Option<PartitionId>,
+    ) -> Result<StoredSegment, IngestionError> {
+        if let Some(stored) = self.load_key(&segment_key(segment_id, partition)).await? {
+            return Ok(stored);
+        }
+        let entry = self
+            .read_index(&index_key(segment_id, partition))
+            .await?
+            .ok_or_else(|| not_found(segment_id))?;
+        self.load_compacted(segment_id, entry).await
+    }
+
+    /// Check whether a segment whose partition is known still exists
+    ///
+    /// Cheaper than [`exists`](StorageRepository::exists) for partitioned
+    /// segments, for the same reason as [`load_partitioned`](Self::load_partitioned).
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::StorageFailure` if the check fails
+    pub async fn exists_partitioned(
+        &self,
+        segment_id: &SegmentId,
+        partition: Option<PartitionId>,
+    ) -> Result<bool, IngestionError> {
+        Ok(self.head(&segment_key(segment_id, partition)).await?
+            || self.head(&index_key(segment_id, partition)).await?)
+    }
+
+    /// Load a segment that is not stored under its unpartitioned key
+    ///
+    /// Partitioned segments are found by listing, compacted ones through
+    /// their index entry.
+    async fn load_moved(&self, segment_id: &SegmentId) -> Result<StoredSegment, IngestionError> {
+        if let Some(key) = self.find_partitioned("", segment_id).await? {
+            if let Some(stored) = self.load_key(&key).await? {
+                return Ok(stored);
+            }
+        }
+
+        let mut entry = self.read_index(&index_key(segment_id, None)).await?;
+        if entry.is_none() {
+            if let Some(key) = self.find_partitioned(INDEX_PREFIX, segment_id).await? {
+                entry = self.read_index(&key).await?;
+            }
+        }
+        let entry = entry.ok_or_else(|| not_found(segment_id))?;
+        self.load_compacted(segment_id, entry).await
+    }
+
+    /// Read the index entry of a compacted segment from its key, if it exists
+    pub(crate) async fn read_index(&self, key: &str) -> Result<Option<IndexEntry>, IngestionError> {
+        self.read_json(key, "index entry").await
+    }
+
+    /// Read and decode a JSON object, if it exists
+    ///
+    /// `kind` names the object in the error of a malformed one.
+    async fn read_json<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        kind: &str,
+    ) -> Result<Option<T>, IngestionError> {
+        let output = match self
+            .options
+            .apply_to_get(self.client.get_object())
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(err) if is_no_such_key(&err) => return Ok(None),
+            Err(err) => {
+                return Err(IngestionError::StorageFailure(format!(
+                    "S3 get_object failed for key '{}': {}",
+                    key, err
+                )))
+            }
+        };
+
+        let body = output.body.collect().await.map_err(|err| {
+            IngestionError::StorageFailure(format!(
+                "Failed to read S3 object body for key '{}': {}",
+                key, err
+            ))
+        })?;
+        serde_json::from_slice(&body.into_bytes())
+            .map(Some)
+            .map_err(|err| {
+                IngestionError::invalid_data(format!("Invalid {} '{}': {}", kind, key, err))
+            })
+    }
+
+    /// Write an idempotency claim with the options of its topic
+    async fn write_claim(
+        &self,
+        claim: &IdempotencyClaim,
+        condition: &WriteCondition,
+    ) -> Result<WriteOutcome, IngestionError> {
+        let key = claim_key(claim.topic.as_deref(), &claim.key);
+        let json = serde_json::to_vec(claim).map_err(|err| {
+            IngestionError::internal_error(format!("Failed to encode idempotency claim: {}", err))
+        })?;
+
+        let options = self.options.for_topic(claim.topic.as_deref());
+        self.write_object(options, &key, HashMap::new(), None, json.into(), condition)
+            .await
+    }
+
+    /// Load a segment from the container it was compacted into
+    ///
+    /// Only the payload is read, with a range request.
+    async fn load_compacted(
+        &self,
+        segment_id: &SegmentId,
+        entry: IndexEntry,
+    ) -> Result<StoredSegment, IngestionError> {
+        let key = container_key(&entry.container);
+        debug!(key = %key, offset = entry.offset, length = entry.length, "Loading compacted segment");
+
+        let range = format!(
+            "bytes={}-{}",
+            entry.offset,
+            (entry.offset + entry.length).saturating_sub(1)
+        );
+        let data = if entry.length == 0 {
+            Vec::new()
+        } else {
+            let output = self
+                .options
+                .apply_to_get(self.client.get_object())
+                .bucket(&self.bucket)
+                .key(&key)
+                .range(range)
+                .send()
+                .await
+                .map_err(|err| {
+                    IngestionError::StorageFailure(format!(
+                        "S3 get_object failed for key '{}': {}",
                         key, err
-                    )))
+                    ))
+                })?;
+            let body = output.body.collect().await.map_err(|err| {
+                IngestionError::StorageFailure(format!(
+                    "Failed to read S3 object body for key '{}': {}",
+                    key, err
+                ))
+            })?;
+            body.into_bytes().to_vec()
+        };
+
+        if data.len() as u64 != entry.length {
+            return Err(IngestionError::invalid_data(format!(
+                "Compacted segment {} is truncated in container '{}'",
+                segment_id, key
+            )));
+        }
+
+        info!(segment_id = %segment_id, container = %entry.container, size = data.len(), "Loaded compacted segment from S3");
+        Ok(StoredSegment::new(data, entry.checksum)
+            .with_compression(entry.compression)
+            .with_record(entry.record))
+    }
+}
+
+/// Convert a failed write into its outcome
+///
+/// A 412 Precondition Failed on a conditional write means an object already
+/// exists under this key (`If-None-Match`), or that it changed (`If-Match`).
+/// Anything else is a storage failure, including 409
+/// ConditionalRequestConflict, returned when a concurrent conditional write to
+/// the same key is in flight: retrying resolves it.
+fn write_failed<E>(
+    operation: &str,
+    key: &str,
+    condition: &WriteCondition,
+    err: SdkError<E, HttpResponse>,
+) -> Result<WriteOutcome, IngestionError>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if condition.is_conditional() && err.raw_response().map(|r| r.status().as_u16()) == Some(412) {
+        return Ok(WriteOutcome::PreconditionFailed);
+    }
+    Err(storage_failure(operation, key, err))
+}
+
+/// Error of a failed S3 request on an object
+fn storage_failure<E>(operation: &str, key: &str, err: SdkError<E, HttpResponse>) -> IngestionError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    IngestionError::StorageFailure(format!(
+        "S3 {} failed for key '{}': {}",
+        operation, key, err
+    ))
+}
+
+/// Maximum number of keys in one `DeleteObjects` request
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// List the objects under `prefix` whose key `id_of` maps to an ID
+///
+/// Returns the key of every listed object with its size and modification
+/// time. Objects listed without a modification time are skipped. Topics are
+/// not listed: they are left unknown.
+pub(crate) async fn list_segments(
+    client: &Client,
+    bucket: &str,
+    prefix: Option<&str>,
+    id_of: fn(&str) -> Option<SegmentId>,
+) -> Result<Vec<(String, SegmentInfo)>, IngestionError> {
+    let mut segments = Vec::new();
+    let mut pages = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .set_prefix(prefix.map(String::from))
+        .into_paginator()
+        .send();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|e| {
+            IngestionError::StorageFailure(format!(
+                "S3 list_objects_v2 failed for bucket '{}': {}",
+                bucket, e
+            ))
+        })?;
+
+        for object in page.contents() {
+            let Some(key) = object.key() else {
+                continue;
+            };
+            let Some(id) = id_of(key) else {
+                continue;
+            };
+            let Some(last_modified) = object
+                .last_modified()
+                .and_then(|time| DateTime::from_timestamp(time.secs(), time.subsec_nanos()))
+            else {
+                warn!(key = %key, "Object listed without modification time, skipping");
+                continue;
+            };
+
+            segments.push((
+                key.to_string(),
+                SegmentInfo {
+                    id,
+                    size_bytes: object.size().unwrap_or(0).max(0) as u64,
+                    last_modified,
+                    topic: None,
+                },
+            ));
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Delete objects with batched `DeleteObjects` requests
+///
+/// Returns the error message of every key that could not be deleted.
+pub(crate) async fn delete_keys(
+    client: &Client,
+    bucket: &str,
+    keys: &[String],
+) -> HashMap<String, String> {
+    let mut failures = HashMap::new();
+
+    for batch in keys.chunks(DELETE_BATCH_SIZE) {
+        let fail_all = |error: String| {
+            warn!(error = %error, keys = batch.len(), "Failed to delete object batch");
+            batch
+                .iter()
+                .map(|key| (key.clone(), error.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>();
+        let delete = objects.and_then(|objects| {
+            Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+        });
+        let delete = match delete {
+            Ok(delete) => delete,
+            Err(e) => {
+                failures.extend(fail_all(format!("Invalid DeleteObjects request: {}", e)));
+                continue;
+            }
+        };
+
+        match client
+            .delete_objects()
+            .bucket(bucket)
+            .delete(delete)
+            .send()
+            .await
+        {
+            // Quiet mode: only the failed keys are reported
+            Ok(output) => failures.extend(output.errors().iter().filter_map(|error| {
+                let key = error.key()?.to_string();
+                let message = format!(
+                    "{}: {}",
+                    error.code().unwrap_or("Unknown"),
+                    error.message().unwrap_or_default()
+                );
+                warn!(key = %key, error = %message, "Failed to delete object");
+                Some((key, message))
+            })),
+            Err(e) => failures.extend(fail_all(format!("S3 delete_objects failed: {}", e))),
+        }
+    }
+
+    failures
+}
+
+impl StorageRepository for S3StorageRepository {
+    #[instrument(skip(self, segment, data), fields(segment_id = %segment.id(), data_size = data.len()))]
+    fn save(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> impl std::future::Future<Output = Result<String, IngestionError>> + Send {
+        self.put(segment, data, false)
+    }
+
+    #[instrument(skip(self, segment, data), fields(segment_id = %segment.id(), data_size = data.len()))]
+    fn save_if_absent(
+        &self,
+        segment: &Segment,
+        data: &[u8],
+    ) -> impl std::future::Future<Output = Result<String, IngestionError>> + Send {
+        self.put(segment, data, true)
+    }
+
+    #[instrument(skip(self, claim), fields(segment_id = %claim.segment_id, topic = ?claim.topic))]
+    fn claim_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl std::future::Future<Output = Result<Option<IdempotencyClaim>, IngestionError>> + Send
+    {
+        let repo = self.clone();
+        let claim = claim.clone();
+
+        async move {
+            match repo.write_claim(&claim, &WriteCondition::IfAbsent).await? {
+                WriteOutcome::Written => {
+                    debug!(segment_id = %claim.segment_id, "Claimed idempotency key");
+                    Ok(None)
+                }
+                WriteOutcome::PreconditionFailed => {
+                    let key = claim_key(claim.topic.as_deref(), &claim.key);
+                    let existing = repo
+                        .read_json::<IdempotencyClaim>(&key, "idempotency claim")
+                        .await?
+                        .ok_or_else(|| {
+                            IngestionError::StorageFailure(format!(
+                                "Idempotency claim '{}' disappeared after a conflicting write",
+                                key
+                            ))
+                        })?;
+                    info!(segment_id = %existing.segment_id, committed = existing.committed, "Idempotency key already claimed");
+                    Ok(Some(existing))
                 }
             }
         }
     }
 
+    #[instrument(skip(self, claim), fields(segment_id = %claim.segment_id, topic = ?claim.topic))]
+    fn commit_idempotency_key(
+        &self,
+        claim: &IdempotencyClaim,
+    ) -> impl std::future::Future<Output = Result<(), IngestionError>> + Send {
+        let repo = self.clone();
+        let claim = claim.clone().committed();
+
+        async move {
+            repo.write_claim(&claim, &WriteCondition::Always).await?;
+            debug!(segment_id = %claim.segment_id, "Committed idempotency claim");
+            Ok(())
+        }
+    }
+
     #[instrument(skip(self), fields(segment_id = %segment_id))]
     fn get(
         &self,
         segment_id: &SegmentId,
     ) -> impl std::future::Future<Output = Result<Vec<u8>, IngestionError>> + Send {
-        let client = self.client.clone();
-        let bucket = self.bucket.clone();
-        let key = Self::generate_key(segment_id);
+        let repo = self.clone();
+        let segment_id = *segment_id;
+        let key = Self::generate_key(&segment_id);
 
         async move {
-            debug!(key = %key, bucket = %bucket, "Retrieving segment from S3");
+            debug!(key = %key, bucket = %repo.bucket, "Retrieving segment from S3");
 
-            match client.get_object().bucket(&bucket).key(&key).send().await {
+            let request = repo.options.apply_to_get(repo.client.get_object());
+            match request.bucket(&repo.bucket).key(&key).send().await {
                 Ok(output) => match output.body.collect().await {
                     Ok(data) => {
                         let bytes = data.into_bytes().to_vec();
@@ -138,6 +1300,9 @@ impl StorageRepository for S3StorageRepository {
                         )))
                     }
                 },
+                Err(err) if is_no_such_key(&err) => {
+                    repo.load_moved(&segment_id).await.map(|stored| stored.data)
+                }
                 Err(err) => {
                     warn!(key = %key, error = ?err, "Failed to retrieve segment from S3");
                     Err(IngestionError::StorageFailure(format!(
@@ -149,38 +1314,52 @@ impl StorageRepository for S3StorageRepository {
         }
     }
 
+    #[instrument(skip(self), fields(segment_id = %segment_id))]
+    fn load(
+        &self,
+        segment_id: &SegmentId,
+    ) -> impl std::future::Future<Output = Result<StoredSegment, IngestionError>> + Send {
+        let repo = self.clone();
+        let segment_id = *segment_id;
+        let key = Self::generate_key(&segment_id);
+
+        async move {
+            match repo.load_key(&key).await? {
+                Some(stored) => Ok(stored),
+                None => repo.load_moved(&segment_id).await,
+            }
+        }
+    }
+
     #[instrument(skip(self), fields(segment_id = %segment_id))]
     fn exists(
         &self,
         segment_id: &SegmentId,
     ) -> impl std::future::Future<Output = Result<bool, IngestionError>> + Send {
-        let client = self.client.clone();
-        let bucket = self.bucket.clone();
-        let key = Self::generate_key(segment_id);
+        let repo = self.clone();
+        let segment_id = *segment_id;
 
         async move {
-            debug!(key = %key, bucket = %bucket, "Checking if segment exists in S3");
+            debug!(segment_id = %segment_id, bucket = %repo.bucket, "Checking if segment exists in S3");
 
-            match client.head_object().bucket(&bucket).key(&key).send().await {
-                Ok(_) => {
+            // A compacted segment only exists through its index entry
+            let keys = [
+                (Self::generate_key(&segment_id), ""),
+                (index_key(&segment_id, None), INDEX_PREFIX),
+            ];
+            for (key, prefix) in keys {
+                if repo.head(&key).await? {
                     debug!(key = %key, "Segment exists in S3");
-                    Ok(true)
+                    return Ok(true);
                 }
-                Err(err) => {
-                    // Check if it's a "Not Found" error (404)
-                    let err_str = err.to_string();
-                    if err_str.contains("NotFound") || err_str.contains("404") {
-                        debug!(key = %key, "Segment does not exist in S3");
-                        Ok(false)
-                    } else {
-                        error!(key = %key, error = ?err, "Failed to check segment existence in S3");
-                        Err(IngestionError::StorageFailure(format!(
-                            "S3 head_object failed for key '{}': {}",
-                            key, err
-                        )))
-                    }
+                if let Some(key) = repo.find_partitioned(prefix, &segment_id).await? {
+                    debug!(key = %key, "Partitioned segment exists in S3");
+                    return Ok(true);
                 }
             }
+
+            debug!(segment_id = %segment_id, "Segment does not exist in S3");
+            Ok(false)
         }
     }
 
@@ -189,32 +1368,62 @@ impl StorageRepository for S3StorageRepository {
         &self,
         segment_id: &SegmentId,
     ) -> impl std::future::Future<Output = Result<(), IngestionError>> + Send {
-        let client = self.client.clone();
-        let bucket = self.bucket.clone();
-        let key = Self::generate_key(segment_id);
+        let repo = self.clone();
+        let segment_id = *segment_id;
 
         async move {
-            debug!(key = %key, bucket = %bucket, "Deleting segment from S3");
+            debug!(segment_id = %segment_id, bucket = %repo.bucket, "Deleting segment from S3");
 
-            match client
-                .delete_object()
-                .bucket(&bucket)
-                .key(&key)
-                .send()
-                .await
-            {
-                Ok(_) => {
-                    info!(key = %key, "Successfully deleted segment from S3");
-                    Ok(())
-                }
-                Err(err) => {
+            // Deleting a missing key succeeds, so both the standalone object
+            // and the index entry of a compacted segment can be removed. The
+            // payload stays in its container until the container expires.
+            let mut keys = vec![
+                Self::generate_key(&segment_id),
+                index_key(&segment_id, None),
+            ];
+            for prefix in ["", INDEX_PREFIX] {
+                keys.extend(repo.find_partitioned(prefix, &segment_id).await?);
+            }
+
+            for key in keys {
+                if let Err(err) = repo
+                    .client
+                    .delete_object()
+                    .bucket(&repo.bucket)
+                    .key(&key)
+                    .send()
+                    .await
+                {
                     error!(key = %key, error = ?err, "Failed to delete segment from S3");
-                    Err(IngestionError::StorageFailure(format!(
+                    return Err(IngestionError::StorageFailure(format!(
                         "S3 delete_object failed for key '{}': {}",
                         key, err
-                    )))
+                    )));
                 }
             }
+
+            info!(segment_id = %segment_id, "Successfully deleted segment from S3");
+            Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_keys_are_scoped_per_topic() {
+        let hash = Checksum::sha256(b"order/42");
+
+        assert_eq!(
+            claim_key(None, "order/42"),
+            format!("_idempotency/{}.json", hash)
+        );
+        assert_eq!(
+            claim_key(Some("billing"), "order/42"),
+            format!("_idempotency/billing/{}.json", hash)
+        );
+        assert_eq!(listed_segment(&claim_key(None, "order/42")), None);
+    }
+}
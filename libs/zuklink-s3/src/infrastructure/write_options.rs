@@ -0,0 +1,632 @@
+//! S3 object write options
+//!
+//! Server-side encryption, storage class, tags and HTTP headers applied to
+//! every object the adapter writes. The same options are applied to single
+//! `PutObject` requests and multipart uploads, so an object gets the same
+//! settings whatever its size.
+//!
+//! Segments ingested under a topic can be written with their own options:
+//! the options of the topic are resolved when the segment is saved, with
+//! [`S3WriteOptions::for_topic`]. Containers and index entries written by
+//! compaction get the options of their topic too. Manifests and progress are
+//! always written with the global options.
+//!
+//! SSE-C (customer-provided keys) also has to be sent when reading: the
+//! `apply_to_get` and `apply_to_head` helpers take care of it for readers of
+//! the raw objects.
+//!
+//! ## Environment
+//!
+//! | Variable | Values |
+//! |----------|--------|
+//! | `ZUKLINK_S3_SSE` | `none`, `AES256` (SSE-S3), `aws:kms` (SSE-KMS), `sse-c` |
+//! | `ZUKLINK_S3_SSE_KMS_KEY_ID` | KMS key id or ARN (SSE-KMS, bucket default if unset) |
+//! | `ZUKLINK_S3_SSE_BUCKET_KEY` | `true` to use an S3 Bucket Key (SSE-KMS) |
+//! | `ZUKLINK_S3_SSE_C_KEY` | Base64 256-bit key (SSE-C) |
+//! | `ZUKLINK_S3_STORAGE_CLASS` | e.g. `STANDARD_IA`, `INTELLIGENT_TIERING` |
+//! | `ZUKLINK_S3_TAGS` | `key=value` pairs, comma-separated |
+//! | `ZUKLINK_S3_CACHE_CONTROL` | `Cache-Control` header |
+//! | `ZUKLINK_S3_CONTENT_TYPE` | `Content-Type` header |
+//! | `ZUKLINK_S3_MULTIPART_THRESHOLD_BYTES` | Size from which uploads are multipart |
+//! | `ZUKLINK_S3_PART_SIZE_BYTES` | Size of multipart parts (at least 5 MiB) |
+//! | `ZUKLINK_S3_TOPICS` | Topics with their own options, comma-separated |
+//!
+//! The options of a topic listed in `ZUKLINK_S3_TOPICS` are set with the same
+//! variables, prefixed with `ZUKLINK_S3_TOPIC_<TOPIC>_` instead of
+//! `ZUKLINK_S3_`, where `<TOPIC>` is the topic name in upper case with `-`
+//! and `.` replaced by `_` (`ZUKLINK_S3_TOPIC_AUDIT_EVENTS_STORAGE_CLASS` for
+//! `audit.events`). Settings left unset are those of the global options;
+//! tags replace the global tags. A topic setting `SSE` also reads the KMS or
+//! SSE-C key from its own variables.
+//!
+//! Readers only send the global SSE-C key, so a topic cannot use SSE-C with
+//! another key than the global options, nor drop or add SSE-C.
+
+use aws_sdk_s3::{
+    operation::{
+        create_multipart_upload::builders::CreateMultipartUploadFluentBuilder,
+        get_object::builders::GetObjectFluentBuilder,
+        head_object::builders::HeadObjectFluentBuilder,
+        put_object::builders::PutObjectFluentBuilder,
+        upload_part::builders::UploadPartFluentBuilder,
+    },
+    types::{ServerSideEncryption as SdkServerSideEncryption, StorageClass},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use md5::{Digest, Md5};
+use std::collections::BTreeMap;
+use std::fmt;
+use zuklink_domain::ingestion::{error::IngestionError, topic};
+
+/// Default size from which segments are uploaded in parts
+pub const DEFAULT_MULTIPART_THRESHOLD: usize = 16 * 1024 * 1024;
+
+/// Default size of multipart parts
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Smallest part size accepted by S3 (except for the last part)
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Algorithm of SSE-C keys, the only one S3 supports
+const SSE_C_ALGORITHM: &str = "AES256";
+
+/// Prefix of the variables of the global options
+const ENV_PREFIX: &str = "ZUKLINK_S3";
+
+/// Apply every object option to a request creating an object
+///
+/// The SDK builders share method names but no trait, hence the macro.
+macro_rules! apply_object_options {
+    ($options:expr, $request:expr) => {{
+        let options = $options;
+        let mut request = $request;
+
+        request = match &options.encryption {
+            None => request,
+            Some(ServerSideEncryption::S3) => {
+                request.server_side_encryption(SdkServerSideEncryption::Aes256)
+            }
+            Some(ServerSideEncryption::Kms { key_id, bucket_key }) => request
+                .server_side_encryption(SdkServerSideEncryption::AwsKms)
+                .set_ssekms_key_id(key_id.clone())
+                .bucket_key_enabled(*bucket_key),
+            Some(ServerSideEncryption::Customer(key)) => request
+                .sse_customer_algorithm(SSE_C_ALGORITHM)
+                .sse_customer_key(key.encoded())
+                .sse_customer_key_md5(key.encoded_md5()),
+        };
+
+        request
+            .set_storage_class(options.storage_class.clone())
+            .set_tagging(options.tagging())
+            .set_cache_control(options.cache_control.clone())
+            .set_content_type(options.content_type.clone())
+    }};
+}
+
+/// Apply the SSE-C key to a request reading or extending an object
+macro_rules! apply_customer_key {
+    ($options:expr, $request:expr) => {{
+        let request = $request;
+        match $options.customer_key() {
+            Some(key) => request
+                .sse_customer_algorithm(SSE_C_ALGORITHM)
+                .sse_customer_key(key.encoded())
+                .sse_customer_key_md5(key.encoded_md5()),
+            None => request,
+        }
+    }};
+}
+
+/// Server-side encryption mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerSideEncryption {
+    /// SSE-S3: keys managed by S3
+    S3,
+    /// SSE-KMS: keys managed by AWS KMS
+    Kms {
+        /// KMS key id or ARN, the bucket default key if `None`
+        key_id: Option<String>,
+        /// Use an S3 Bucket Key to reduce KMS requests
+        bucket_key: bool,
+    },
+    /// SSE-C: key provided with every request
+    Customer(CustomerKey),
+}
+
+/// 256-bit key for SSE-C
+///
+/// S3 never stores the key: it must be sent again to read the object.
+#[derive(Clone, PartialEq, Eq)]
+pub struct CustomerKey {
+    key: [u8; 32],
+}
+
+impl CustomerKey {
+    /// Create a key from raw bytes
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    /// Parse a base64-encoded key
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::ConfigError` if the key is not 32 bytes of base64
+    pub fn from_base64(encoded: &str) -> Result<Self, IngestionError> {
+        let key = BASE64.decode(encoded.trim()).map_err(|e| {
+            IngestionError::config_error(format!("SSE-C key is not valid base64: {}", e))
+        })?;
+        let key = key.try_into().map_err(|key: Vec<u8>| {
+            IngestionError::config_error(format!("SSE-C key must be 32 bytes, got {}", key.len()))
+        })?;
+        Ok(Self { key })
+    }
+
+    /// Key as sent in `x-amz-server-side-encryption-customer-key`
+    fn encoded(&self) -> String {
+        BASE64.encode(self.key)
+    }
+
+    /// Digest as sent in `x-amz-server-side-encryption-customer-key-MD5`
+    fn encoded_md5(&self) -> String {
+        BASE64.encode(Md5::digest(self.key))
+    }
+}
+
+/// Never print key material
+impl fmt::Debug for CustomerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomerKey(<redacted>)")
+    }
+}
+
+/// Options applied to every object written to S3
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3WriteOptions {
+    /// Server-side encryption, the bucket default if `None`
+    pub encryption: Option<ServerSideEncryption>,
+    /// Storage class, `STANDARD` if `None`
+    pub storage_class: Option<StorageClass>,
+    /// Object tags
+    pub tags: Vec<(String, String)>,
+    /// `Cache-Control` header
+    pub cache_control: Option<String>,
+    /// `Content-Type` header
+    pub content_type: Option<String>,
+    /// Size from which objects are uploaded in parts
+    pub multipart_threshold: usize,
+    /// Size of multipart parts
+    pub part_size: usize,
+    /// Options of the segments of a topic, replacing these ones
+    pub topics: BTreeMap<String, S3WriteOptions>,
+}
+
+impl Default for S3WriteOptions {
+    fn default() -> Self {
+        Self {
+            encryption: None,
+            storage_class: None,
+            tags: Vec::new(),
+            cache_control: None,
+            content_type: None,
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            part_size: DEFAULT_PART_SIZE,
+            topics: BTreeMap::new(),
+        }
+    }
+}
+
+impl S3WriteOptions {
+    /// Load the options from `ZUKLINK_S3_*` environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::ConfigError` if a variable is invalid
+    pub fn from_env() -> Result<Self, IngestionError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Load the options from a variable lookup
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Result<Self, IngestionError> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+
+        let mut options = Self::default().overridden(ENV_PREFIX, var)?;
+        options.validate()?;
+
+        let topics = var("ZUKLINK_S3_TOPICS").unwrap_or_default();
+        for name in topics
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            topic::validate(name).map_err(|err| {
+                IngestionError::config_error(format!("Invalid ZUKLINK_S3_TOPICS: {}", err))
+            })?;
+
+            let overrides = options.overridden(&topic_prefix(name), var)?;
+            overrides.validate()?;
+            if overrides.customer_key() != options.customer_key() {
+                return Err(IngestionError::config_error(format!(
+                    "Topic '{}' must use the same SSE-C key as the global options",
+                    name
+                )));
+            }
+            options.topics.insert(name.to_string(), overrides);
+        }
+
+        Ok(options)
+    }
+
+    /// Copy of these options, with the settings of the variables set under `prefix`
+    fn overridden(
+        &self,
+        prefix: &str,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, IngestionError> {
+        let name = |setting: &str| format!("{}_{}", prefix, setting);
+        let var = |setting: &str| var(&name(setting));
+
+        let encryption = match var("SSE").as_deref().map(str::trim) {
+            None => self.encryption.clone(),
+            Some("none") => None,
+            Some("AES256") | Some("aes256") | Some("sse-s3") => Some(ServerSideEncryption::S3),
+            Some("aws:kms") | Some("sse-kms") => Some(ServerSideEncryption::Kms {
+                key_id: var("SSE_KMS_KEY_ID"),
+                bucket_key: var("SSE_BUCKET_KEY")
+                    .map(|value| parse_bool(&name("SSE_BUCKET_KEY"), &value))
+                    .transpose()?
+                    .unwrap_or(false),
+            }),
+            Some("sse-c") => {
+                let key = var("SSE_C_KEY").ok_or_else(|| {
+                    IngestionError::config_error(format!(
+                        "{}=sse-c requires {}",
+                        name("SSE"),
+                        name("SSE_C_KEY")
+                    ))
+                })?;
+                Some(ServerSideEncryption::Customer(CustomerKey::from_base64(
+                    &key,
+                )?))
+            }
+            Some(other) => {
+                return Err(IngestionError::config_error(format!(
+                    "Unknown {} '{}', expected none, AES256, aws:kms or sse-c",
+                    name("SSE"),
+                    other
+                )))
+            }
+        };
+
+        Ok(Self {
+            encryption,
+            storage_class: var("STORAGE_CLASS")
+                .map(|class| StorageClass::from(class.trim()))
+                .or_else(|| self.storage_class.clone()),
+            tags: var("TAGS")
+                .map(|tags| parse_tags(&name("TAGS"), &tags))
+                .transpose()?
+                .unwrap_or_else(|| self.tags.clone()),
+            cache_control: var("CACHE_CONTROL").or_else(|| self.cache_control.clone()),
+            content_type: var("CONTENT_TYPE").or_else(|| self.content_type.clone()),
+            multipart_threshold: var("MULTIPART_THRESHOLD_BYTES")
+                .map(|value| parse_size(&name("MULTIPART_THRESHOLD_BYTES"), &value))
+                .transpose()?
+                .unwrap_or(self.multipart_threshold),
+            part_size: var("PART_SIZE_BYTES")
+                .map(|value| parse_size(&name("PART_SIZE_BYTES"), &value))
+                .transpose()?
+                .unwrap_or(self.part_size),
+            topics: BTreeMap::new(),
+        })
+    }
+
+    /// Options of the segments of a topic, the global options if it has none
+    pub fn for_topic(&self, topic: Option<&str>) -> &Self {
+        topic
+            .and_then(|topic| self.topics.get(topic))
+            .unwrap_or(self)
+    }
+
+    /// Check the multipart settings
+    ///
+    /// # Errors
+    ///
+    /// Returns `IngestionError::ConfigError` if parts are smaller than S3 allows
+    pub fn validate(&self) -> Result<(), IngestionError> {
+        if self.part_size < MIN_PART_SIZE {
+            return Err(IngestionError::config_error(format!(
+                "Multipart part size ({} bytes) is below the S3 minimum ({} bytes)",
+                self.part_size, MIN_PART_SIZE
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether an object of this size is uploaded in parts
+    pub fn is_multipart(&self, size: usize) -> bool {
+        size > self.multipart_threshold
+    }
+
+    /// Whether objects are encrypted with a customer-provided key
+    pub fn customer_key(&self) -> Option<&CustomerKey> {
+        match &self.encryption {
+            Some(ServerSideEncryption::Customer(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Tags in the `x-amz-tagging` format (URL-encoded query string)
+    fn tagging(&self) -> Option<String> {
+        if self.tags.is_empty() {
+            return None;
+        }
+        let pairs: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+            .collect();
+        Some(pairs.join("&"))
+    }
+
+    /// Apply the options to a `PutObject` request
+    pub fn apply_to_put(&self, request: PutObjectFluentBuilder) -> PutObjectFluentBuilder {
+        apply_object_options!(self, request)
+    }
+
+    /// Apply the options to a `CreateMultipartUpload` request
+    pub fn apply_to_create_multipart(
+        &self,
+        request: CreateMultipartUploadFluentBuilder,
+    ) -> CreateMultipartUploadFluentBuilder {
+        apply_object_options!(self, request)
+    }
+
+    /// Apply the SSE-C key to an `UploadPart` request
+    pub fn apply_to_upload_part(
+        &self,
+        request: UploadPartFluentBuilder,
+    ) -> UploadPartFluentBuilder {
+        apply_customer_key!(self, request)
+    }
+
+    /// Apply the SSE-C key to a `GetObject` request
+    pub fn apply_to_get(&self, request: GetObjectFluentBuilder) -> GetObjectFluentBuilder {
+        apply_customer_key!(self, request)
+    }
+
+    /// Apply the SSE-C key to a `HeadObject` request
+    pub fn apply_to_head(&self, request: HeadObjectFluentBuilder) -> HeadObjectFluentBuilder {
+        apply_customer_key!(self, request)
+    }
+}
+
+/// Prefix of the variables of the options of a topic
+fn topic_prefix(topic: &str) -> String {
+    let name: String = topic
+        .chars()
+        .map(|c| match c {
+            '-' | '.' => '_',
+            c => c.to_ascii_uppercase(),
+        })
+        .collect();
+    format!("{}_TOPIC_{}", ENV_PREFIX, name)
+}
+
+/// Parse `key=value` pairs separated by commas
+fn parse_tags(name: &str, tags: &str) -> Result<Vec<(String, String)>, IngestionError> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            tag.split_once('=')
+                .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| {
+                    IngestionError::config_error(format!(
+                        "Invalid tag '{}' in {}, expected key=value",
+                        tag, name
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn parse_size(name: &str, value: &str) -> Result<usize, IngestionError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| IngestionError::config_error(format!("Invalid {}: '{}'", name, value)))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, IngestionError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| IngestionError::config_error(format!("Invalid {}: '{}'", name, value)))
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986)
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<S3WriteOptions, IngestionError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        S3WriteOptions::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = from_vars(&[]).unwrap();
+
+        assert_eq!(options, S3WriteOptions::default());
+        assert!(options.tagging().is_none());
+        assert!(!options.is_multipart(DEFAULT_MULTIPART_THRESHOLD));
+        assert!(options.is_multipart(DEFAULT_MULTIPART_THRESHOLD + 1));
+    }
+
+    #[test]
+    fn test_encryption_modes() {
+        assert_eq!(
+            from_vars(&[("ZUKLINK_S3_SSE", "AES256")])
+                .unwrap()
+                .encryption,
+            Some(ServerSideEncryption::S3)
+        );
+        assert_eq!(
+            from_vars(&[
+                ("ZUKLINK_S3_SSE", "aws:kms"),
+                ("ZUKLINK_S3_SSE_KMS_KEY_ID", "alias/zuklink"),
+                ("ZUKLINK_S3_SSE_BUCKET_KEY", "true"),
+            ])
+            .unwrap()
+            .encryption,
+            Some(ServerSideEncryption::Kms {
+                key_id: Some("alias/zuklink".to_string()),
+                bucket_key: true,
+            })
+        );
+
+        let key = BASE64.encode([7u8; 32]);
+        let options =
+            from_vars(&[("ZUKLINK_S3_SSE", "sse-c"), ("ZUKLINK_S3_SSE_C_KEY", &key)]).unwrap();
+        assert_eq!(options.customer_key(), Some(&CustomerKey::new([7; 32])));
+
+        assert!(from_vars(&[("ZUKLINK_S3_SSE", "sse-c")]).is_err());
+        assert!(from_vars(&[("ZUKLINK_S3_SSE", "rot13")]).is_err());
+    }
+
+    #[test]
+    fn test_customer_key() {
+        // Reference: base64(md5(32 zero bytes))
+        let key = CustomerKey::new([0; 32]);
+        assert_eq!(key.encoded_md5(), "cLyPS3KoaSFGi/joRB3OUQ==");
+        assert_eq!(format!("{:?}", key), "CustomerKey(<redacted>)");
+
+        assert!(CustomerKey::from_base64("not base64!").is_err());
+        assert!(CustomerKey::from_base64(&BASE64.encode([0u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_tags_and_headers() {
+        let options = from_vars(&[
+            ("ZUKLINK_S3_TAGS", "team=data, pii=true,note=a b&c"),
+            ("ZUKLINK_S3_STORAGE_CLASS", "STANDARD_IA"),
+            ("ZUKLINK_S3_CACHE_CONTROL", "no-store"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            options.tagging().unwrap(),
+            "team=data&pii=true&note=a%20b%26c"
+        );
+        assert_eq!(options.storage_class, Some(StorageClass::StandardIa));
+        assert_eq!(options.cache_control.as_deref(), Some("no-store"));
+
+        assert!(from_vars(&[("ZUKLINK_S3_TAGS", "no-value")]).is_err());
+        assert!(from_vars(&[("ZUKLINK_S3_TAGS", "=value")]).is_err());
+    }
+
+    #[test]
+    fn test_multipart_settings() {
+        let options = from_vars(&[
+            ("ZUKLINK_S3_MULTIPART_THRESHOLD_BYTES", "1048576"),
+            ("ZUKLINK_S3_PART_SIZE_BYTES", "6291456"),
+        ])
+        .unwrap();
+
+        assert_eq!(options.multipart_threshold, 1_048_576);
+        assert_eq!(options.part_size, 6_291_456);
+
+        assert!(from_vars(&[("ZUKLINK_S3_PART_SIZE_BYTES", "1024")]).is_err());
+        assert!(from_vars(&[("ZUKLINK_S3_PART_SIZE_BYTES", "big")]).is_err());
+    }
+
+    #[test]
+    fn test_topic_overrides() {
+        let options = from_vars(&[
+            ("ZUKLINK_S3_SSE", "AES256"),
+            ("ZUKLINK_S3_STORAGE_CLASS", "STANDARD_IA"),
+            ("ZUKLINK_S3_TAGS", "team=data"),
+            ("ZUKLINK_S3_TOPICS", "billing, audit.events"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE", "aws:kms"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE_KMS_KEY_ID", "alias/billing"),
+            ("ZUKLINK_S3_TOPIC_BILLING_TAGS", "pii=true"),
+            ("ZUKLINK_S3_TOPIC_AUDIT_EVENTS_STORAGE_CLASS", "GLACIER_IR"),
+        ])
+        .unwrap();
+
+        let billing = options.for_topic(Some("billing"));
+        assert_eq!(
+            billing.encryption,
+            Some(ServerSideEncryption::Kms {
+                key_id: Some("alias/billing".to_string()),
+                bucket_key: false,
+            })
+        );
+        assert_eq!(billing.storage_class, Some(StorageClass::StandardIa));
+        assert_eq!(billing.tagging().unwrap(), "pii=true");
+
+        let audit = options.for_topic(Some("audit.events"));
+        assert_eq!(audit.encryption, Some(ServerSideEncryption::S3));
+        assert_eq!(audit.storage_class, Some(StorageClass::GlacierIr));
+        assert_eq!(audit.tagging().unwrap(), "team=data");
+
+        // Segments without a topic, or of a topic without overrides
+        assert_eq!(options.for_topic(None), &options);
+        assert_eq!(options.for_topic(Some("metrics")), &options);
+    }
+
+    #[test]
+    fn test_topic_overrides_rejected() {
+        assert!(from_vars(&[("ZUKLINK_S3_TOPICS", "billing/eu")]).is_err());
+        assert!(from_vars(&[
+            ("ZUKLINK_S3_TOPICS", "billing"),
+            ("ZUKLINK_S3_TOPIC_BILLING_PART_SIZE_BYTES", "1024"),
+        ])
+        .is_err());
+
+        // Readers only know the global SSE-C key
+        let key = BASE64.encode([7u8; 32]);
+        let other_key = BASE64.encode([8u8; 32]);
+        assert!(from_vars(&[
+            ("ZUKLINK_S3_TOPICS", "billing"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE", "sse-c"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE_C_KEY", &key),
+        ])
+        .is_err());
+        assert!(from_vars(&[
+            ("ZUKLINK_S3_SSE", "sse-c"),
+            ("ZUKLINK_S3_SSE_C_KEY", &key),
+            ("ZUKLINK_S3_TOPICS", "billing"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE", "sse-c"),
+            ("ZUKLINK_S3_TOPIC_BILLING_SSE_C_KEY", &other_key),
+        ])
+        .is_err());
+        assert!(from_vars(&[
+            ("ZUKLINK_S3_SSE", "sse-c"),
+            ("ZUKLINK_S3_SSE_C_KEY", &key),
+            ("ZUKLINK_S3_TOPICS", "billing"),
+            ("ZUKLINK_S3_TOPIC_BILLING_STORAGE_CLASS", "STANDARD_IA"),
+        ])
+        .is_ok());
+    }
+}
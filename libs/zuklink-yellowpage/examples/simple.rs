@@ -4,15 +4,13 @@
 //! - Initialize a Yellowpage instance
 //! - Set node metadata
 //! - Monitor cluster membership
-//! - Compute consistent hashing for file distribution
+//! - Resolve file ownership from the cluster view
 //!
 //! Run with:
 //! ```bash
 //! cargo run --example simple
 //! ```
 
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
 use std::time::Duration;
 use zuklink_yellowpage::Yellowpage;
 
@@ -42,9 +40,9 @@ async fn main() -> anyhow::Result<()> {
     let yellowpage = Yellowpage::new(node_id.clone(), listen_addr, seeds).await?;
 
     // Set node metadata
-    yellowpage.set_metadata("role", "receiver").await;
-    yellowpage.set_metadata("status", "ready").await;
-    yellowpage.set_metadata("version", "0.1.0").await;
+    yellowpage.set_metadata("role", "receiver").await?;
+    yellowpage.set_metadata("status", "ready").await?;
+    yellowpage.set_metadata("version", "0.1.0").await?;
 
     println!("✅ Yellowpage initialized\n");
 
@@ -114,24 +112,15 @@ async fn print_cluster_status(yellowpage: &Yellowpage) {
     }
 }
 
-/// Demonstrate consistent hashing for file distribution
+/// Demonstrate file distribution through the cluster view
 async fn demonstrate_sharding(yellowpage: &Yellowpage) {
-    let live_nodes = yellowpage.get_live_nodes().await;
-    let cluster_size = live_nodes.len();
+    let view = yellowpage.cluster_view().await;
 
-    if cluster_size == 0 {
-        println!("⚠️  No nodes in cluster - skipping sharding demo");
+    if view.my_index().is_none() {
+        println!("⚠️  Not a member of the cluster view - skipping sharding demo");
         return;
     }
 
-    let my_index = match yellowpage.my_index().await {
-        Some(idx) => idx,
-        None => {
-            println!("⚠️  Cannot determine my index - skipping sharding demo");
-            return;
-        }
-    };
-
     println!("🔀 Sharding Demo:");
 
     // Simulate some files
@@ -148,7 +137,7 @@ async fn demonstrate_sharding(yellowpage: &Yellowpage) {
     let mut other_files = Vec::new();
 
     for filename in &test_files {
-        if should_process_file(filename, my_index, cluster_size) {
+        if view.owns(filename) {
             my_files.push(*filename);
         } else {
             other_files.push(*filename);
@@ -165,16 +154,3 @@ async fn demonstrate_sharding(yellowpage: &Yellowpage) {
         println!("  Other nodes' files: {:?}", other_files);
     }
 }
-
-/// Determine if this node should process a file based on consistent hashing
-fn should_process_file(filename: &str, my_index: usize, cluster_size: usize) -> bool {
-    if cluster_size == 0 {
-        return false;
-    }
-
-    let mut hasher = DefaultHasher::new();
-    filename.hash(&mut hasher);
-    let hash = hasher.finish();
-
-    (hash as usize % cluster_size) == my_index
-}
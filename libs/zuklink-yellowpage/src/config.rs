@@ -0,0 +1,839 @@
+//! Yellowpage configuration
+//!
+//! Gossip timings and failure detection must be tuned per deployment: cross-AZ
+//! clusters need a looser failure detector to tolerate latency spikes, while
+//! local tests want the cluster to converge in a few hundred milliseconds.
+//!
+//! ## Sources
+//!
+//! A [`YellowpageConfig`] can be built in code, loaded from a TOML file or read
+//! from `YELLOWPAGE_*` environment variables. [`YellowpageConfig::load`]
+//! combines both: the file named by `YELLOWPAGE_CONFIG_FILE` (if any) is read
+//! first, then environment variables override individual settings.
+//!
+//! Durations are written in human-readable form (`"500ms"`, `"10s"`, `"24h"`).
+//!
+//! ```toml
+//! node_id = "receiver-1"
+//! cluster_id = "zuklink-prod"
+//! listen_addr = "0.0.0.0:7000"
+//! seeds = ["receiver-2:7000", "receiver-3:7000"]
+//! gossip_interval = "1s"
+//! phi_threshold = 12.0
+//! max_interval = "20s"
+//! generation = "timestamp"
+//! seed_provider = "dns:zuk-sink.default.svc.cluster.local:7000"
+//! gossip_keys = ["2:q2mN7V1x...=", "1:Zk8Rt0aH...="]
+//!
+//! [rebalance]
+//! interval = "10s"
+//! threshold = 1.5
+//! ```
+
+use chitchat::FailureDetectorConfig;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encryption::{GossipKey, Keyring};
+use crate::error::{GossipError, Result};
+use crate::rebalance::RebalanceConfig;
+use crate::seeds::{parse_list, SeedSource};
+use crate::{sharding, view};
+
+/// Environment variable naming the configuration file read by `load()`
+pub const CONFIG_FILE_ENV: &str = "YELLOWPAGE_CONFIG_FILE";
+
+/// Source of the generation id announced with the node id
+///
+/// Peers use the generation id to tell a restarted node from its previous
+/// incarnation: state gossiped by an older generation is discarded. The oldest
+/// generation also wins the leader election.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationSource {
+    /// Seconds since the Unix epoch at startup
+    #[default]
+    Timestamp,
+    /// A fixed value, for deterministic tests
+    ///
+    /// A node restarted with the same generation id is not recognized as a
+    /// new incarnation by its peers, so never reuse a value in production.
+    Fixed(u64),
+}
+
+impl GenerationSource {
+    /// Compute the generation id for a node starting now
+    pub fn generation_id(&self) -> u64 {
+        match self {
+            Self::Timestamp => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("System time should be after Unix epoch")
+                .as_secs(),
+            Self::Fixed(generation_id) => *generation_id,
+        }
+    }
+}
+
+impl FromStr for GenerationSource {
+    type Err = String;
+
+    /// Parse `timestamp` or a fixed numeric generation id
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "timestamp" => Ok(Self::Timestamp),
+            other => other
+                .parse()
+                .map(Self::Fixed)
+                .map_err(|_| format!("expected 'timestamp' or a number, got '{}'", other)),
+        }
+    }
+}
+
+/// Configuration of a Yellowpage node
+///
+/// Every field has a default matching the previous hardcoded values, so only
+/// the settings that differ need to be provided.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use zuklink_yellowpage::{Yellowpage, YellowpageConfig};
+/// # async fn example() -> zuklink_yellowpage::Result<()> {
+/// let config = YellowpageConfig::new("receiver-1", "0.0.0.0:7000".parse().unwrap())
+///     .with_seeds(vec!["receiver-2:7000".to_string()])
+///     .with_gossip_interval(Duration::from_secs(1))
+///     .with_phi_threshold(12.0);
+///
+/// let yellowpage = Yellowpage::with_config(config).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct YellowpageConfig {
+    /// Unique identifier of this node (`YELLOWPAGE_NODE_ID`)
+    pub node_id: String,
+    /// Cluster identifier, nodes ignore gossip from other clusters (`YELLOWPAGE_CLUSTER_ID`)
+    pub cluster_id: String,
+    /// Address to bind to, also advertised to peers (`YELLOWPAGE_LISTEN_ADDR`)
+    pub listen_addr: SocketAddr,
+    /// Seed nodes to bootstrap the cluster (`YELLOWPAGE_SEEDS`, comma-separated)
+    pub seeds: Vec<String>,
+    /// Provider discovering additional seeds, queried again once started (`YELLOWPAGE_SEED_PROVIDER`)
+    ///
+    /// One of `env:<VAR>`, `file:<path>`, `dns:<host>:<port>` or `srv:<name>`.
+    pub seed_provider: Option<SeedSource>,
+    /// Time spent waiting for the seed provider to return a peer (`YELLOWPAGE_SEED_DISCOVERY_TIMEOUT`)
+    #[serde(with = "humantime_serde")]
+    pub seed_discovery_timeout: Duration,
+    /// Interval between two queries of the seed provider once started (`YELLOWPAGE_SEED_REFRESH_INTERVAL`)
+    #[serde(with = "humantime_serde")]
+    pub seed_refresh_interval: Duration,
+    /// Interval between two gossip rounds (`YELLOWPAGE_GOSSIP_INTERVAL`)
+    #[serde(with = "humantime_serde")]
+    pub gossip_interval: Duration,
+    /// Phi accrual threshold above which a node is suspected dead (`YELLOWPAGE_PHI_THRESHOLD`)
+    ///
+    /// Higher values tolerate more latency jitter but detect failures later.
+    pub phi_threshold: f64,
+    /// Number of heartbeat intervals kept to estimate the distribution (`YELLOWPAGE_SAMPLING_WINDOW_SIZE`)
+    pub sampling_window_size: usize,
+    /// Heartbeat interval assumed before any sample is collected (`YELLOWPAGE_INITIAL_INTERVAL`)
+    #[serde(with = "humantime_serde")]
+    pub initial_interval: Duration,
+    /// Heartbeat intervals longer than this are clamped (`YELLOWPAGE_MAX_INTERVAL`)
+    #[serde(with = "humantime_serde")]
+    pub max_interval: Duration,
+    /// Time a dead node is kept before being forgotten (`YELLOWPAGE_DEAD_NODE_GRACE_PERIOD`)
+    #[serde(with = "humantime_serde")]
+    pub dead_node_grace_period: Duration,
+    /// Time deleted keys are kept as tombstones (`YELLOWPAGE_MARKED_FOR_DELETION_GRACE_PERIOD`)
+    #[serde(with = "humantime_serde")]
+    pub marked_for_deletion_grace_period: Duration,
+    /// Shared keys encrypting gossip packets, the first one sealing outgoing
+    /// packets (`YELLOWPAGE_GOSSIP_KEYS`, comma-separated `<id>:<base64 key>`)
+    ///
+    /// Gossip is sent in plaintext when empty.
+    pub gossip_keys: Vec<GossipKey>,
+    /// Number of nodes the cluster is expected to have (`YELLOWPAGE_EXPECTED_CLUSTER_SIZE`)
+    ///
+    /// When set, a node whose view holds fewer than `expected / 2 + 1` nodes
+    /// considers itself on the minority side of a partition and reports no
+    /// quorum. Quorum checks are disabled when unset.
+    pub expected_cluster_size: Option<usize>,
+    /// Capacity weight advertised for shard assignment (`YELLOWPAGE_CAPACITY`)
+    ///
+    /// Nodes own a share of the keys proportional to their capacity. Defaults
+    /// to the number of available CPUs; `0` takes no shards.
+    pub capacity: Option<u32>,
+    /// Join as an observer (`YELLOWPAGE_OBSERVER`: `true` or `false`)
+    ///
+    /// Observers follow the cluster view, e.g. to find the owner of a key,
+    /// but own no shards and take no part in quorum and leader election.
+    pub observer: bool,
+    /// Load-aware rebalancing run by the leader (`YELLOWPAGE_REBALANCE`: `true` or `false`)
+    ///
+    /// Disabled when unset: shards stay with their hash-based owner.
+    pub rebalance: Option<RebalanceConfig>,
+    /// Source of the generation id (`YELLOWPAGE_GENERATION`: `timestamp` or a number)
+    pub generation: GenerationSource,
+    /// Time a leader candidate must stay stable before taking over (`YELLOWPAGE_LEADER_SETTLE_DELAY`)
+    #[serde(with = "humantime_serde")]
+    pub leader_settle_delay: Duration,
+    /// Time given to gossip to propagate a departure before stopping (`YELLOWPAGE_DEPARTURE_GRACE`)
+    #[serde(with = "humantime_serde")]
+    pub departure_grace: Duration,
+}
+
+impl Default for YellowpageConfig {
+    fn default() -> Self {
+        Self {
+            node_id: String::new(),
+            cluster_id: "zuklink-cluster".to_string(),
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 7000)),
+            seeds: Vec::new(),
+            seed_provider: None,
+            seed_discovery_timeout: Duration::from_secs(10),
+            seed_refresh_interval: Duration::from_secs(30),
+            gossip_interval: Duration::from_millis(500),
+            phi_threshold: 8.0,
+            sampling_window_size: 1000,
+            initial_interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(10),
+            dead_node_grace_period: Duration::from_secs(24 * 60 * 60),
+            marked_for_deletion_grace_period: Duration::from_secs(60),
+            gossip_keys: Vec::new(),
+            expected_cluster_size: None,
+            capacity: None,
+            observer: false,
+            rebalance: None,
+            generation: GenerationSource::Timestamp,
+            leader_settle_delay: Duration::from_secs(5),
+            departure_grace: Duration::from_secs(2),
+        }
+    }
+}
+
+impl YellowpageConfig {
+    /// Create a configuration with default timings
+    pub fn new(node_id: impl Into<String>, listen_addr: SocketAddr) -> Self {
+        Self {
+            node_id: node_id.into(),
+            listen_addr,
+            ..Self::default()
+        }
+    }
+
+    /// Create a configuration tuned for fast convergence on a single host
+    ///
+    /// Gossip runs every 50ms and the failure detector assumes short heartbeat
+    /// intervals, so a local cluster converges and detects a killed node within
+    /// a few hundred milliseconds. Not suitable for real networks.
+    pub fn local(node_id: impl Into<String>, listen_addr: SocketAddr) -> Self {
+        Self {
+            gossip_interval: Duration::from_millis(50),
+            initial_interval: Duration::from_millis(100),
+            max_interval: Duration::from_millis(500),
+            leader_settle_delay: Duration::from_millis(200),
+            departure_grace: Duration::from_millis(200),
+            ..Self::new(node_id, listen_addr)
+        }
+    }
+
+    /// Load the configuration from `YELLOWPAGE_*` environment variables
+    ///
+    /// Unset variables keep their default value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` if a variable cannot be parsed.
+    pub fn from_env() -> Result<Self> {
+        Self::default().with_env_overrides(|key| std::env::var(key).ok())
+    }
+
+    /// Load the configuration from a TOML file
+    ///
+    /// Missing settings keep their default value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` if the file cannot be read, is not
+    /// valid TOML or contains unknown settings.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            GossipError::config_error(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+
+        Self::from_toml(&content)
+            .map_err(|e| GossipError::config_error(format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    /// Load the configuration file named by `YELLOWPAGE_CONFIG_FILE`, then
+    /// apply `YELLOWPAGE_*` environment overrides
+    ///
+    /// Falls back to the defaults if no file is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` if the file or a variable is invalid.
+    pub fn load() -> Result<Self> {
+        let base = match std::env::var(CONFIG_FILE_ENV) {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) => Self::default(),
+        };
+
+        base.with_env_overrides(|key| std::env::var(key).ok())
+    }
+
+    /// Set the node identifier
+    pub fn with_node_id(mut self, node_id: impl Into<String>) -> Self {
+        self.node_id = node_id.into();
+        self
+    }
+
+    /// Set the cluster identifier
+    pub fn with_cluster_id(mut self, cluster_id: impl Into<String>) -> Self {
+        self.cluster_id = cluster_id.into();
+        self
+    }
+
+    /// Set the gossip listen address
+    pub fn with_listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = listen_addr;
+        self
+    }
+
+    /// Set the seed nodes
+    pub fn with_seeds(mut self, seeds: Vec<String>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    /// Set the provider discovering seeds at startup
+    pub fn with_seed_provider(mut self, seed_provider: SeedSource) -> Self {
+        self.seed_provider = Some(seed_provider);
+        self
+    }
+
+    /// Set the time spent waiting for the seed provider to return a peer
+    pub fn with_seed_discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.seed_discovery_timeout = timeout;
+        self
+    }
+
+    /// Set the interval between two queries of the seed provider once started
+    pub fn with_seed_refresh_interval(mut self, interval: Duration) -> Self {
+        self.seed_refresh_interval = interval;
+        self
+    }
+
+    /// Set the interval between two gossip rounds
+    pub fn with_gossip_interval(mut self, gossip_interval: Duration) -> Self {
+        self.gossip_interval = gossip_interval;
+        self
+    }
+
+    /// Set the phi accrual failure threshold
+    pub fn with_phi_threshold(mut self, phi_threshold: f64) -> Self {
+        self.phi_threshold = phi_threshold;
+        self
+    }
+
+    /// Set the failure detector sampling window size
+    pub fn with_sampling_window_size(mut self, sampling_window_size: usize) -> Self {
+        self.sampling_window_size = sampling_window_size;
+        self
+    }
+
+    /// Set the initial and maximum heartbeat intervals of the failure detector
+    pub fn with_heartbeat_intervals(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_interval = initial;
+        self.max_interval = max;
+        self
+    }
+
+    /// Set the time a dead node is kept before being forgotten
+    pub fn with_dead_node_grace_period(mut self, grace_period: Duration) -> Self {
+        self.dead_node_grace_period = grace_period;
+        self
+    }
+
+    /// Set the time deleted keys are kept as tombstones
+    pub fn with_marked_for_deletion_grace_period(mut self, grace_period: Duration) -> Self {
+        self.marked_for_deletion_grace_period = grace_period;
+        self
+    }
+
+    /// Set the gossip encryption keys, the first one being the primary key
+    pub fn with_gossip_keys(mut self, gossip_keys: Vec<GossipKey>) -> Self {
+        self.gossip_keys = gossip_keys;
+        self
+    }
+
+    /// Set the generation id source
+    pub fn with_generation(mut self, generation: GenerationSource) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Set the expected cluster size, enabling quorum checks
+    pub fn with_expected_cluster_size(mut self, expected_cluster_size: usize) -> Self {
+        self.expected_cluster_size = Some(expected_cluster_size);
+        self
+    }
+
+    /// Set the capacity weight advertised for shard assignment
+    pub fn with_capacity(mut self, capacity: u32) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Join as an observer, owning no shards
+    pub fn with_observer(mut self, observer: bool) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Enable load-aware rebalancing
+    pub fn with_rebalance(mut self, rebalance: RebalanceConfig) -> Self {
+        self.rebalance = Some(rebalance);
+        self
+    }
+
+    /// Set the leader election settle delay
+    pub fn with_leader_settle_delay(mut self, settle_delay: Duration) -> Self {
+        self.leader_settle_delay = settle_delay;
+        self
+    }
+
+    /// Set the departure grace period of `leave()`
+    pub fn with_departure_grace(mut self, departure_grace: Duration) -> Self {
+        self.departure_grace = departure_grace;
+        self
+    }
+
+    /// Check the configuration for inconsistent values
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` describing the first invalid setting.
+    pub fn validate(&self) -> Result<()> {
+        if self.node_id.is_empty() {
+            return Err(GossipError::config_error("node_id must not be empty"));
+        }
+        if self.cluster_id.is_empty() {
+            return Err(GossipError::config_error("cluster_id must not be empty"));
+        }
+        if self.seed_refresh_interval.is_zero() {
+            return Err(GossipError::config_error(
+                "seed_refresh_interval must be greater than zero",
+            ));
+        }
+        if self.gossip_interval.is_zero() {
+            return Err(GossipError::config_error(
+                "gossip_interval must be greater than zero",
+            ));
+        }
+        if !self.phi_threshold.is_finite() || self.phi_threshold <= 0.0 {
+            return Err(GossipError::config_error(format!(
+                "phi_threshold must be positive, got {}",
+                self.phi_threshold
+            )));
+        }
+        if self.sampling_window_size == 0 {
+            return Err(GossipError::config_error(
+                "sampling_window_size must be greater than zero",
+            ));
+        }
+        if !self.gossip_keys.is_empty() {
+            Keyring::new(&self.cluster_id, &self.gossip_keys)?;
+        }
+        if self.expected_cluster_size == Some(0) {
+            return Err(GossipError::config_error(
+                "expected_cluster_size must be greater than zero",
+            ));
+        }
+        if let Some(rebalance) = &self.rebalance {
+            if rebalance.interval.is_zero() {
+                return Err(GossipError::config_error(
+                    "rebalance.interval must be greater than zero",
+                ));
+            }
+            if !rebalance.threshold.is_finite() || rebalance.threshold < 1.0 {
+                return Err(GossipError::config_error(format!(
+                    "rebalance.threshold must be at least 1.0, got {}",
+                    rebalance.threshold
+                )));
+            }
+            if rebalance.sustain_rounds == 0 {
+                return Err(GossipError::config_error(
+                    "rebalance.sustain_rounds must be greater than zero",
+                ));
+            }
+        }
+        if self.initial_interval > self.max_interval {
+            return Err(GossipError::config_error(format!(
+                "initial_interval ({:?}) must not exceed max_interval ({:?})",
+                self.initial_interval, self.max_interval
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Build the Chitchat failure detector configuration
+    pub(crate) fn failure_detector_config(&self) -> FailureDetectorConfig {
+        FailureDetectorConfig {
+            phi_threshold: self.phi_threshold,
+            sampling_window_size: self.sampling_window_size,
+            max_interval: self.max_interval,
+            initial_interval: self.initial_interval,
+            dead_node_grace_period: self.dead_node_grace_period,
+        }
+    }
+
+    /// Capacity advertised by this node, defaulting to its CPU count
+    pub(crate) fn effective_capacity(&self) -> u32 {
+        self.capacity.unwrap_or_else(sharding::local_capacity)
+    }
+
+    /// Minimum number of live nodes required, if quorum checks are enabled
+    pub(crate) fn quorum(&self) -> Option<usize> {
+        self.expected_cluster_size.map(view::quorum_for)
+    }
+
+    fn from_toml(content: &str) -> std::result::Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Override settings with the variables returned by `lookup`
+    fn with_env_overrides(mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        if let Some(node_id) = lookup("YELLOWPAGE_NODE_ID") {
+            self.node_id = node_id;
+        }
+        if let Some(cluster_id) = lookup("YELLOWPAGE_CLUSTER_ID") {
+            self.cluster_id = cluster_id;
+        }
+        if let Some(listen_addr) = lookup("YELLOWPAGE_LISTEN_ADDR") {
+            self.listen_addr = parse_var("YELLOWPAGE_LISTEN_ADDR", &listen_addr)?;
+        }
+        if let Some(seeds) = lookup("YELLOWPAGE_SEEDS") {
+            self.seeds = parse_list(&seeds);
+        }
+        if let Some(value) = lookup("YELLOWPAGE_SEED_PROVIDER") {
+            self.seed_provider = Some(parse_var("YELLOWPAGE_SEED_PROVIDER", &value)?);
+        }
+        if let Some(value) = lookup("YELLOWPAGE_SEED_DISCOVERY_TIMEOUT") {
+            self.seed_discovery_timeout =
+                parse_duration("YELLOWPAGE_SEED_DISCOVERY_TIMEOUT", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_SEED_REFRESH_INTERVAL") {
+            self.seed_refresh_interval =
+                parse_duration("YELLOWPAGE_SEED_REFRESH_INTERVAL", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_GOSSIP_INTERVAL") {
+            self.gossip_interval = parse_duration("YELLOWPAGE_GOSSIP_INTERVAL", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_PHI_THRESHOLD") {
+            self.phi_threshold = parse_var("YELLOWPAGE_PHI_THRESHOLD", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_SAMPLING_WINDOW_SIZE") {
+            self.sampling_window_size = parse_var("YELLOWPAGE_SAMPLING_WINDOW_SIZE", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_INITIAL_INTERVAL") {
+            self.initial_interval = parse_duration("YELLOWPAGE_INITIAL_INTERVAL", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_MAX_INTERVAL") {
+            self.max_interval = parse_duration("YELLOWPAGE_MAX_INTERVAL", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_DEAD_NODE_GRACE_PERIOD") {
+            self.dead_node_grace_period =
+                parse_duration("YELLOWPAGE_DEAD_NODE_GRACE_PERIOD", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_MARKED_FOR_DELETION_GRACE_PERIOD") {
+            self.marked_for_deletion_grace_period =
+                parse_duration("YELLOWPAGE_MARKED_FOR_DELETION_GRACE_PERIOD", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_GOSSIP_KEYS") {
+            self.gossip_keys = parse_list(&value)
+                .iter()
+                .map(|key| parse_var("YELLOWPAGE_GOSSIP_KEYS", key))
+                .collect::<Result<_>>()?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_EXPECTED_CLUSTER_SIZE") {
+            self.expected_cluster_size =
+                Some(parse_var("YELLOWPAGE_EXPECTED_CLUSTER_SIZE", &value)?);
+        }
+        if let Some(value) = lookup("YELLOWPAGE_CAPACITY") {
+            self.capacity = Some(parse_var("YELLOWPAGE_CAPACITY", &value)?);
+        }
+        if let Some(value) = lookup("YELLOWPAGE_OBSERVER") {
+            self.observer = parse_var("YELLOWPAGE_OBSERVER", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE_INTERVAL") {
+            self.rebalance
+                .get_or_insert_with(RebalanceConfig::default)
+                .interval = parse_duration("YELLOWPAGE_REBALANCE_INTERVAL", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE_THRESHOLD") {
+            self.rebalance
+                .get_or_insert_with(RebalanceConfig::default)
+                .threshold = parse_var("YELLOWPAGE_REBALANCE_THRESHOLD", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE_MIN_BACKLOG") {
+            self.rebalance
+                .get_or_insert_with(RebalanceConfig::default)
+                .min_backlog = parse_var("YELLOWPAGE_REBALANCE_MIN_BACKLOG", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE_SUSTAIN_ROUNDS") {
+            self.rebalance
+                .get_or_insert_with(RebalanceConfig::default)
+                .sustain_rounds = parse_var("YELLOWPAGE_REBALANCE_SUSTAIN_ROUNDS", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE_SHARD_COOLDOWN") {
+            self.rebalance
+                .get_or_insert_with(RebalanceConfig::default)
+                .shard_cooldown = parse_duration("YELLOWPAGE_REBALANCE_SHARD_COOLDOWN", &value)?;
+        }
+        // Tuning variables enable rebalancing, unless explicitly disabled
+        if let Some(value) = lookup("YELLOWPAGE_REBALANCE") {
+            if parse_var::<bool>("YELLOWPAGE_REBALANCE", &value)? {
+                self.rebalance.get_or_insert_with(RebalanceConfig::default);
+            } else {
+                self.rebalance = None;
+            }
+        }
+        if let Some(value) = lookup("YELLOWPAGE_GENERATION") {
+            self.generation = parse_var("YELLOWPAGE_GENERATION", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_LEADER_SETTLE_DELAY") {
+            self.leader_settle_delay = parse_duration("YELLOWPAGE_LEADER_SETTLE_DELAY", &value)?;
+        }
+        if let Some(value) = lookup("YELLOWPAGE_DEPARTURE_GRACE") {
+            self.departure_grace = parse_duration("YELLOWPAGE_DEPARTURE_GRACE", &value)?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// Parse an environment variable value
+fn parse_var<T>(key: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| GossipError::config_error(format!("Invalid {} '{}': {}", key, value, e)))
+}
+
+/// Parse a human-readable duration (`500ms`, `10s`, `1h`)
+fn parse_duration(key: &str, value: &str) -> Result<Duration> {
+    humantime::parse_duration(value.trim())
+        .map_err(|e| GossipError::config_error(format!("Invalid {} '{}': {}", key, value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup<'a>(vars: &'a HashMap<&str, &str>) -> impl Fn(&str) -> Option<String> + 'a {
+        move |key| vars.get(key).map(|value| value.to_string())
+    }
+
+    #[test]
+    fn test_default_matches_previous_hardcoded_values() {
+        let config = YellowpageConfig::default();
+
+        assert_eq!(config.cluster_id, "zuklink-cluster");
+        assert_eq!(config.gossip_interval, Duration::from_millis(500));
+        assert_eq!(
+            config.marked_for_deletion_grace_period,
+            Duration::from_secs(60)
+        );
+        assert_eq!(config.phi_threshold, 8.0);
+        assert_eq!(config.generation, GenerationSource::Timestamp);
+        assert!(!config.observer);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = HashMap::from([
+            ("YELLOWPAGE_NODE_ID", "receiver-1"),
+            ("YELLOWPAGE_LISTEN_ADDR", "0.0.0.0:7100"),
+            ("YELLOWPAGE_SEEDS", "receiver-2:7000, receiver-3:7000,"),
+            ("YELLOWPAGE_GOSSIP_INTERVAL", "1s"),
+            ("YELLOWPAGE_PHI_THRESHOLD", "12.5"),
+            ("YELLOWPAGE_MAX_INTERVAL", "20s"),
+            ("YELLOWPAGE_GENERATION", "42"),
+            ("YELLOWPAGE_SEED_PROVIDER", "dns:zuk-sink:7000"),
+            ("YELLOWPAGE_EXPECTED_CLUSTER_SIZE", "5"),
+            ("YELLOWPAGE_CAPACITY", "16"),
+            ("YELLOWPAGE_OBSERVER", "true"),
+        ]);
+
+        let config = YellowpageConfig::default()
+            .with_env_overrides(lookup(&vars))
+            .unwrap();
+
+        assert_eq!(config.node_id, "receiver-1");
+        assert_eq!(config.listen_addr, "0.0.0.0:7100".parse().unwrap());
+        assert_eq!(config.seeds, vec!["receiver-2:7000", "receiver-3:7000"]);
+        assert_eq!(config.gossip_interval, Duration::from_secs(1));
+        assert_eq!(config.phi_threshold, 12.5);
+        assert_eq!(config.max_interval, Duration::from_secs(20));
+        assert_eq!(config.generation, GenerationSource::Fixed(42));
+        assert_eq!(config.expected_cluster_size, Some(5));
+        assert_eq!(config.quorum(), Some(3));
+        assert_eq!(config.effective_capacity(), 16);
+        assert!(config.observer);
+        assert_eq!(
+            config.seed_provider,
+            Some(SeedSource::Dns {
+                host: "zuk-sink".to_string(),
+                port: 7000
+            })
+        );
+        // Untouched settings keep their default
+        assert_eq!(config.cluster_id, "zuklink-cluster");
+    }
+
+    #[test]
+    fn test_env_rebalance() {
+        let enabled = HashMap::from([("YELLOWPAGE_REBALANCE", "true")]);
+        let tuned = HashMap::from([("YELLOWPAGE_REBALANCE_MIN_BACKLOG", "100")]);
+        let disabled = HashMap::from([
+            ("YELLOWPAGE_REBALANCE", "false"),
+            ("YELLOWPAGE_REBALANCE_MIN_BACKLOG", "100"),
+        ]);
+
+        let config = |vars| {
+            YellowpageConfig::default()
+                .with_env_overrides(lookup(vars))
+                .unwrap()
+                .rebalance
+        };
+
+        assert_eq!(config(&enabled), Some(RebalanceConfig::default()));
+        assert_eq!(config(&tuned).unwrap().min_backlog, 100);
+        assert_eq!(config(&disabled), None);
+    }
+
+    #[test]
+    fn test_env_gossip_keys() {
+        let primary = GossipKey::generate(2);
+        let secondary = GossipKey::generate(1);
+        let keys = format!("{}, {}", primary, secondary);
+        let vars = HashMap::from([("YELLOWPAGE_GOSSIP_KEYS", keys.as_str())]);
+
+        let config = YellowpageConfig::default()
+            .with_env_overrides(lookup(&vars))
+            .unwrap();
+
+        assert_eq!(config.gossip_keys, vec![primary, secondary]);
+    }
+
+    #[test]
+    fn test_validate_rejects_duplicate_gossip_key_ids() {
+        let config = YellowpageConfig::new("node-1", "127.0.0.1:7000".parse().unwrap())
+            .with_gossip_keys(vec![GossipKey::generate(1), GossipKey::generate(1)]);
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_env_invalid_value() {
+        let vars = HashMap::from([("YELLOWPAGE_GOSSIP_INTERVAL", "fast")]);
+
+        let result = YellowpageConfig::default().with_env_overrides(lookup(&vars));
+
+        assert!(matches!(result, Err(GossipError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let config = YellowpageConfig::from_toml(
+            r#"
+            node_id = "receiver-1"
+            seeds = ["receiver-2:7000"]
+            gossip_interval = "250ms"
+            dead_node_grace_period = "1h"
+            generation = { fixed = 7 }
+            seed_provider = "file:/etc/zuk/seeds"
+
+            [rebalance]
+            threshold = 2.0
+            shard_cooldown = "10m"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.node_id, "receiver-1");
+        assert_eq!(config.seeds, vec!["receiver-2:7000"]);
+        assert_eq!(config.gossip_interval, Duration::from_millis(250));
+        assert_eq!(config.dead_node_grace_period, Duration::from_secs(3600));
+        assert_eq!(config.generation, GenerationSource::Fixed(7));
+        assert_eq!(
+            config.seed_provider,
+            Some(SeedSource::File("/etc/zuk/seeds".into()))
+        );
+        assert_eq!(config.phi_threshold, 8.0);
+        assert_eq!(
+            config.rebalance,
+            Some(RebalanceConfig {
+                threshold: 2.0,
+                shard_cooldown: Duration::from_secs(600),
+                ..RebalanceConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_toml_rejects_unknown_settings() {
+        assert!(YellowpageConfig::from_toml("gossip_intreval = \"1s\"").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = YellowpageConfig::new("node-1", "127.0.0.1:7000".parse().unwrap());
+        assert!(config.validate().is_ok());
+
+        assert!(YellowpageConfig::default().validate().is_err());
+        assert!(config
+            .clone()
+            .with_gossip_interval(Duration::ZERO)
+            .validate()
+            .is_err());
+        assert!(config.clone().with_phi_threshold(0.0).validate().is_err());
+        assert!(config
+            .clone()
+            .with_expected_cluster_size(0)
+            .validate()
+            .is_err());
+        assert!(config
+            .with_heartbeat_intervals(Duration::from_secs(10), Duration::from_secs(1))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_generation_source_parse() {
+        assert_eq!(
+            "timestamp".parse::<GenerationSource>(),
+            Ok(GenerationSource::Timestamp)
+        );
+        assert_eq!(
+            "12".parse::<GenerationSource>(),
+            Ok(GenerationSource::Fixed(12))
+        );
+        assert!("yesterday".parse::<GenerationSource>().is_err());
+    }
+}
@@ -0,0 +1,469 @@
+//! Gossip packet encryption
+//!
+//! In plaintext mode anyone who can reach the gossip port and knows the
+//! cluster id can join the cluster or forge heartbeats. With a shared key,
+//! every packet is sealed with ChaCha20-Poly1305 and packets whose tag does
+//! not verify are dropped before being handed to Chitchat.
+//!
+//! ## Packet Format
+//!
+//! ```text
+//! ┌─────────┬────────┬────────────┬──────────────────────────┐
+//! │ version │ key id │ nonce (12) │ ciphertext + tag (16)    │
+//! └─────────┴────────┴────────────┴──────────────────────────┘
+//! ```
+//!
+//! The version, key id and cluster id are authenticated as associated data, so
+//! a packet sealed for another cluster is rejected even with the same key.
+//!
+//! A message too large to be sealed into one datagram is split into
+//! fragments, each sealed on its own with a longer header (version 2):
+//!
+//! ```text
+//! ┌─────────┬────────┬────────────────┬───────┬───────┬────────────┬──────────────────────┐
+//! │ version │ key id │ message id (4) │ index │ count │ nonce (12) │ ciphertext + tag (16)│
+//! └─────────┴────────┴────────────────┴───────┴───────┴────────────┴──────────────────────┘
+//! ```
+//!
+//! The whole header is authenticated, so fragments cannot be moved from one
+//! message to another.
+//!
+//! ## Key Rotation
+//!
+//! A [`Keyring`] holds a primary key, used to seal outgoing packets, and any
+//! number of secondary keys, only used to open incoming packets. Packets carry
+//! the id of the key that sealed them. To rotate without a split cluster:
+//!
+//! 1. Add the new key as secondary on every node
+//! 2. Make it primary on every node
+//! 3. Remove the old key
+//!
+//! ## Replays
+//!
+//! Packets carry no counter or timestamp, so a captured packet is accepted
+//! again if resent. It cannot forge state: Chitchat only applies values newer
+//! than the ones it has, so a replayed packet repeats known state. A replayed
+//! request still gets a response, sent to the address the packet came from.
+//! The same holds for fragments, which only complete the message they were
+//! sealed for.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{GossipError, Result};
+
+/// Version of packets carrying a whole message
+const VERSION: u8 = 1;
+
+/// Version of packets carrying a fragment of a message
+const FRAGMENT_VERSION: u8 = 2;
+
+/// Size of a ChaCha20-Poly1305 key
+pub const KEY_LEN: usize = 32;
+
+/// Size of the nonce carried by each packet
+const NONCE_LEN: usize = 12;
+
+/// Size of the authentication tag ending each packet
+const TAG_LEN: usize = 16;
+
+/// Bytes of the header preceding the nonce
+const HEADER_LEN: usize = 2;
+
+/// Bytes of the header of a fragment (message id, index and count added)
+const FRAGMENT_HEADER_LEN: usize = HEADER_LEN + 6;
+
+/// Bytes added to every packet (header, nonce and tag)
+pub(crate) const OVERHEAD: usize = HEADER_LEN + NONCE_LEN + TAG_LEN;
+
+/// Bytes added to every fragment (header, nonce and tag)
+pub(crate) const FRAGMENT_OVERHEAD: usize = FRAGMENT_HEADER_LEN + NONCE_LEN + TAG_LEN;
+
+/// Shared key used to seal gossip packets
+///
+/// Written as `<id>:<base64 key>`, e.g. `1:q2mN...=`, where the id is a number
+/// between 0 and 255 identifying the key during rotation.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct GossipKey {
+    id: u8,
+    key: [u8; KEY_LEN],
+}
+
+impl GossipKey {
+    /// Create a key from raw bytes
+    pub fn new(id: u8, key: [u8; KEY_LEN]) -> Self {
+        Self { id, key }
+    }
+
+    /// Generate a random key
+    pub fn generate(id: u8) -> Self {
+        Self::new(id, ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Identifier of the key, carried by every packet it seals
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+}
+
+/// Never print key material
+impl fmt::Debug for GossipKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GossipKey")
+            .field("id", &self.id)
+            .field("key", &"<redacted>")
+            .finish()
+    }
+}
+
+impl fmt::Display for GossipKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.id, BASE64.encode(self.key))
+    }
+}
+
+impl FromStr for GossipKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (id, key) = s
+            .split_once(':')
+            .ok_or_else(|| "expected '<id>:<base64 key>'".to_string())?;
+
+        let id = id
+            .trim()
+            .parse()
+            .map_err(|_| format!("key id must be a number between 0 and 255, got '{}'", id))?;
+        let key = BASE64
+            .decode(key.trim())
+            .map_err(|e| format!("key {} is not valid base64: {}", id, e))?;
+        let key = key.try_into().map_err(|key: Vec<u8>| {
+            format!("key {} must be {} bytes, got {}", id, KEY_LEN, key.len())
+        })?;
+
+        Ok(Self { id, key })
+    }
+}
+
+impl TryFrom<String> for GossipKey {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<GossipKey> for String {
+    fn from(key: GossipKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// Content of an opened packet
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Opened {
+    /// A whole message
+    Message(Vec<u8>),
+    /// Fragment `index` of the `count` fragments of a message
+    Fragment {
+        message_id: u32,
+        index: u8,
+        count: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// Keys used to seal and open gossip packets
+pub(crate) struct Keyring {
+    /// Associated data binding packets to the cluster
+    cluster_id: Vec<u8>,
+    /// Key sealing outgoing packets
+    primary: (u8, ChaCha20Poly1305),
+    /// Every accepted key, primary included
+    keys: Vec<(u8, ChaCha20Poly1305)>,
+}
+
+impl Keyring {
+    /// Build a keyring, the first key being the primary one
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` if `keys` is empty or two keys share
+    /// the same id.
+    pub(crate) fn new(cluster_id: &str, keys: &[GossipKey]) -> Result<Self> {
+        let Some(primary) = keys.first() else {
+            return Err(GossipError::config_error(
+                "gossip keyring must not be empty",
+            ));
+        };
+
+        for (index, key) in keys.iter().enumerate() {
+            if keys[..index].iter().any(|other| other.id == key.id) {
+                return Err(GossipError::config_error(format!(
+                    "duplicate gossip key id {}",
+                    key.id
+                )));
+            }
+        }
+
+        let cipher = |key: &GossipKey| ChaCha20Poly1305::new(&Key::from(key.key));
+
+        Ok(Self {
+            cluster_id: cluster_id.as_bytes().to_vec(),
+            primary: (primary.id, cipher(primary)),
+            keys: keys.iter().map(|key| (key.id, cipher(key))).collect(),
+        })
+    }
+
+    /// Encrypt and authenticate a packet with the primary key
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let (key_id, _) = &self.primary;
+        self.seal_with_header(&[VERSION, *key_id], plaintext)
+    }
+
+    /// Encrypt and authenticate fragment `index` of `count` of a message
+    pub(crate) fn seal_fragment(
+        &self,
+        message_id: u32,
+        index: u8,
+        count: u8,
+        plaintext: &[u8],
+    ) -> Vec<u8> {
+        let (key_id, _) = &self.primary;
+        let mut header = [0; FRAGMENT_HEADER_LEN];
+        header[..HEADER_LEN].copy_from_slice(&[FRAGMENT_VERSION, *key_id]);
+        header[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&message_id.to_be_bytes());
+        header[HEADER_LEN + 4] = index;
+        header[HEADER_LEN + 5] = count;
+        self.seal_with_header(&header, plaintext)
+    }
+
+    fn seal_with_header(&self, header: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let (_, cipher) = &self.primary;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &self.aad(header),
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption cannot fail for gossip-sized packets");
+
+        let mut packet = Vec::with_capacity(header.len() + NONCE_LEN + ciphertext.len());
+        packet.extend_from_slice(header);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&ciphertext);
+        packet
+    }
+
+    /// Verify and decrypt a packet
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the rejection if the packet is truncated, uses
+    /// an unknown version or key, or fails authentication.
+    pub(crate) fn open(&self, packet: &[u8]) -> std::result::Result<Opened, String> {
+        let header_len = match packet.first() {
+            Some(&VERSION) => HEADER_LEN,
+            Some(&FRAGMENT_VERSION) => FRAGMENT_HEADER_LEN,
+            Some(version) => return Err(format!("unsupported packet version {}", version)),
+            None => return Err("empty packet".to_string()),
+        };
+        if packet.len() < header_len + NONCE_LEN + TAG_LEN {
+            return Err(format!("packet too short ({} bytes)", packet.len()));
+        }
+
+        let (header, rest) = packet.split_at(header_len);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("nonce is NONCE_LEN bytes");
+
+        let key_id = header[1];
+        let (_, cipher) = self
+            .keys
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .ok_or_else(|| format!("unknown key id {}", key_id))?;
+
+        let plaintext = cipher
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &self.aad(header),
+                },
+            )
+            .map_err(|_| format!("authentication failed with key id {}", key_id))?;
+
+        if header_len == HEADER_LEN {
+            return Ok(Opened::Message(plaintext));
+        }
+        Ok(Opened::Fragment {
+            message_id: u32::from_be_bytes(
+                header[HEADER_LEN..HEADER_LEN + 4]
+                    .try_into()
+                    .expect("message id is 4 bytes"),
+            ),
+            index: header[HEADER_LEN + 4],
+            count: header[HEADER_LEN + 5],
+            data: plaintext,
+        })
+    }
+
+    fn aad(&self, header: &[u8]) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(header.len() + self.cluster_id.len());
+        aad.extend_from_slice(header);
+        aad.extend_from_slice(&self.cluster_id);
+        aad
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(cluster_id: &str, keys: &[GossipKey]) -> Keyring {
+        Keyring::new(cluster_id, keys).unwrap()
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let keyring = keyring("zuklink", &[GossipKey::generate(1)]);
+
+        let packet = keyring.seal(b"heartbeat");
+
+        assert_eq!(packet.len(), b"heartbeat".len() + OVERHEAD);
+        assert_eq!(
+            keyring.open(&packet).unwrap(),
+            Opened::Message(b"heartbeat".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_fragment_roundtrip() {
+        let keyring = keyring("zuklink", &[GossipKey::generate(1)]);
+
+        let packet = keyring.seal_fragment(7, 1, 2, b"delta");
+
+        assert_eq!(packet.len(), b"delta".len() + FRAGMENT_OVERHEAD);
+        assert_eq!(
+            keyring.open(&packet).unwrap(),
+            Opened::Fragment {
+                message_id: 7,
+                index: 1,
+                count: 2,
+                data: b"delta".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_moved_fragment_rejected() {
+        let keyring = keyring("zuklink", &[GossipKey::generate(1)]);
+
+        // Same fragment claimed by another message, or at another index
+        let mut packet = keyring.seal_fragment(7, 1, 2, b"delta");
+        packet[HEADER_LEN + 3] ^= 0x01;
+        assert!(keyring.open(&packet).is_err());
+
+        let mut packet = keyring.seal_fragment(7, 1, 2, b"delta");
+        packet[HEADER_LEN + 4] = 0;
+        assert!(keyring.open(&packet).is_err());
+    }
+
+    #[test]
+    fn test_tampered_packet_rejected() {
+        let keyring = keyring("zuklink", &[GossipKey::generate(1)]);
+
+        let mut packet = keyring.seal(b"heartbeat");
+        let last = packet.len() - 1;
+        packet[last] ^= 0x01;
+
+        assert!(keyring.open(&packet).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_or_cluster_rejected() {
+        let key = GossipKey::generate(1);
+        let packet = keyring("zuklink", std::slice::from_ref(&key)).seal(b"heartbeat");
+
+        // Same id, different key material
+        assert!(keyring("zuklink", &[GossipKey::generate(1)])
+            .open(&packet)
+            .is_err());
+        // Same key, different cluster
+        assert!(keyring("other", &[key]).open(&packet).is_err());
+        // Unknown key id
+        assert!(keyring("zuklink", &[GossipKey::generate(2)])
+            .open(&packet)
+            .is_err());
+    }
+
+    #[test]
+    fn test_truncated_packet_rejected() {
+        let keyring = keyring("zuklink", &[GossipKey::generate(1)]);
+
+        assert!(keyring.open(&[VERSION, 1, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_rotation_accepts_secondary_keys() {
+        let old = GossipKey::generate(1);
+        let new = GossipKey::generate(2);
+
+        // Step 1: node still sealing with the old key
+        let before = keyring("zuklink", &[old.clone(), new.clone()]);
+        // Step 2: node already sealing with the new key
+        let after = keyring("zuklink", &[new, old]);
+
+        assert_eq!(
+            after.open(&before.seal(b"a")).unwrap(),
+            Opened::Message(b"a".to_vec())
+        );
+        assert_eq!(
+            before.open(&after.seal(b"b")).unwrap(),
+            Opened::Message(b"b".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_keyring_validation() {
+        assert!(Keyring::new("zuklink", &[]).is_err());
+        assert!(
+            Keyring::new("zuklink", &[GossipKey::generate(1), GossipKey::generate(1)]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_key_parse_roundtrip() {
+        let key = GossipKey::generate(7);
+
+        assert_eq!(key.to_string().parse::<GossipKey>(), Ok(key));
+        assert!("7".parse::<GossipKey>().is_err());
+        assert!("7:not-base64!".parse::<GossipKey>().is_err());
+        assert!(format!("7:{}", BASE64.encode([0u8; 16]))
+            .parse::<GossipKey>()
+            .is_err());
+        assert!(format!("256:{}", BASE64.encode([0u8; 32]))
+            .parse::<GossipKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_debug_is_redacted() {
+        let key = GossipKey::new(3, [42; KEY_LEN]);
+
+        assert_eq!(
+            format!("{:?}", key),
+            "GossipKey { id: 3, key: \"<redacted>\" }"
+        );
+    }
+}
@@ -27,6 +27,18 @@ pub enum GossipError {
     /// Generic error from underlying Chitchat library
     #[error("Chitchat error: {0}")]
     ChitchatError(String),
+
+    /// Metadata key is invalid or belongs to the reserved namespace
+    #[error("Invalid metadata key '{key}': {reason}")]
+    InvalidKey { key: String, reason: String },
+
+    /// Typed metadata value could not be encoded or decoded
+    #[error("Failed to (de)serialize metadata '{key}': {reason}")]
+    SerializationError { key: String, reason: String },
+
+    /// Seed provider could not be queried
+    #[error("Seed discovery failed ({provider}): {reason}")]
+    SeedDiscoveryError { provider: String, reason: String },
 }
 
 impl GossipError {
@@ -44,4 +56,28 @@ impl GossipError {
     pub fn node_not_found(node_id: impl Into<String>) -> Self {
         Self::NodeNotFound(node_id.into())
     }
+
+    /// Create an invalid metadata key error
+    pub fn invalid_key(key: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::InvalidKey {
+            key: key.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Create a metadata serialization error
+    pub fn serialization_error(key: impl Into<String>, reason: impl ToString) -> Self {
+        Self::SerializationError {
+            key: key.into(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Create a seed discovery error
+    pub fn seed_discovery_error(provider: impl Into<String>, reason: impl ToString) -> Self {
+        Self::SeedDiscoveryError {
+            provider: provider.into(),
+            reason: reason.to_string(),
+        }
+    }
 }
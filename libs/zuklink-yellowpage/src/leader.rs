@@ -0,0 +1,197 @@
+//! Leader election on top of the gossip membership
+//!
+//! Some cluster-wide duties (retention cleanup, compaction scheduling, ...)
+//! must run on exactly one node. Every node runs the same deterministic rule
+//! over its view of the live set, so all nodes sharing a view agree on the
+//! leader without exchanging any extra message.
+//!
+//! ## Election Rule
+//!
+//! The leader is the live node with the lowest `(generation_id, node_id)` pair.
+//! Nodes that announced their departure are not eligible.
+//! Since the generation id is the node start time, the oldest node leads and a
+//! newly joined node never steals leadership from a healthy one.
+//!
+//! ## Settle Delay
+//!
+//! A new candidate only becomes leader once it has been observed continuously
+//! for the settle delay. This absorbs short membership flaps (a node briefly
+//! suspected by the failure detector) and gives a freshly started node time to
+//! discover its peers before claiming leadership.
+
+use chitchat::{Chitchat, ChitchatId};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::node::{active_nodes, NodeId};
+use crate::view::ClusterMetrics;
+
+/// Interval at which the live set is re-evaluated
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pick the leader candidate from a set of live nodes
+///
+/// Returns `None` if the live set is empty.
+pub(crate) fn elect<'a>(live_nodes: impl Iterator<Item = &'a ChitchatId>) -> Option<NodeId> {
+    live_nodes
+        .min_by(|a, b| (a.generation_id, &a.node_id).cmp(&(b.generation_id, &b.node_id)))
+        .map(|chitchat_id| NodeId(chitchat_id.node_id.clone()))
+}
+
+/// Debounces candidate changes so leadership only moves once stable
+#[derive(Debug)]
+pub(crate) struct LeaderTracker {
+    settle_delay: Duration,
+    current: Option<NodeId>,
+    pending: Option<(Option<NodeId>, Instant)>,
+}
+
+impl LeaderTracker {
+    pub(crate) fn new(settle_delay: Duration) -> Self {
+        Self {
+            settle_delay,
+            current: None,
+            pending: None,
+        }
+    }
+
+    /// Feed the latest candidate
+    ///
+    /// Returns the new leader when leadership changes, `None` otherwise.
+    pub(crate) fn observe(
+        &mut self,
+        candidate: Option<NodeId>,
+        now: Instant,
+    ) -> Option<Option<NodeId>> {
+        if candidate == self.current {
+            self.pending = None;
+            return None;
+        }
+
+        match &self.pending {
+            Some((pending, since)) if *pending == candidate => {
+                if now.duration_since(*since) >= self.settle_delay {
+                    self.current = candidate;
+                    self.pending = None;
+                    Some(self.current.clone())
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.pending = Some((candidate, now));
+                None
+            }
+        }
+    }
+}
+
+/// Spawn the background task publishing leadership changes
+pub(crate) fn spawn_leader_task(
+    chitchat: Arc<Mutex<Chitchat>>,
+    settle_delay: Duration,
+    leader_tx: watch::Sender<Option<NodeId>>,
+    metrics: Arc<ClusterMetrics>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tracker = LeaderTracker::new(settle_delay);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let candidate = {
+                let chitchat_guard = chitchat.lock().await;
+                elect(active_nodes(&chitchat_guard))
+            };
+
+            if let Some(leader) = tracker.observe(candidate, Instant::now()) {
+                info!(leader = ?leader, "Cluster leader changed");
+                metrics.record_leader_change();
+                leader_tx.send_replace(leader);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chitchat_id(node_id: &str, generation_id: u64) -> ChitchatId {
+        ChitchatId::new(
+            node_id.to_string(),
+            generation_id,
+            "127.0.0.1:7000".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_elect_lowest_generation() {
+        let nodes = [
+            chitchat_id("node-1", 30),
+            chitchat_id("node-2", 10),
+            chitchat_id("node-3", 20),
+        ];
+
+        assert_eq!(elect(nodes.iter()), Some(NodeId::new("node-2")));
+    }
+
+    #[test]
+    fn test_elect_breaks_ties_by_node_id() {
+        let nodes = [chitchat_id("node-b", 10), chitchat_id("node-a", 10)];
+
+        assert_eq!(elect(nodes.iter()), Some(NodeId::new("node-a")));
+    }
+
+    #[test]
+    fn test_elect_empty_cluster() {
+        assert_eq!(elect(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_tracker_waits_for_settle_delay() {
+        let mut tracker = LeaderTracker::new(Duration::from_secs(5));
+        let start = Instant::now();
+        let candidate = Some(NodeId::new("node-1"));
+
+        assert_eq!(tracker.observe(candidate.clone(), start), None);
+        assert_eq!(
+            tracker.observe(candidate.clone(), start + Duration::from_secs(4)),
+            None
+        );
+        assert_eq!(
+            tracker.observe(candidate.clone(), start + Duration::from_secs(5)),
+            Some(candidate)
+        );
+    }
+
+    #[test]
+    fn test_tracker_ignores_flapping_candidate() {
+        let mut tracker = LeaderTracker::new(Duration::from_secs(5));
+        let start = Instant::now();
+        let leader = Some(NodeId::new("node-1"));
+        let other = Some(NodeId::new("node-2"));
+
+        tracker.observe(leader.clone(), start);
+        tracker.observe(leader.clone(), start + Duration::from_secs(5));
+
+        // node-1 briefly disappears, then comes back before the delay elapses
+        assert_eq!(
+            tracker.observe(other.clone(), start + Duration::from_secs(6)),
+            None
+        );
+        assert_eq!(
+            tracker.observe(leader, start + Duration::from_secs(8)),
+            None
+        );
+        assert_eq!(
+            tracker.observe(other, start + Duration::from_secs(12)),
+            None
+        );
+    }
+}
@@ -1,28 +1,75 @@
+mod config;
+mod encryption;
 mod error;
+mod leader;
+mod metadata;
 mod node;
+mod rebalance;
+mod seeds;
+mod sharding;
+mod transport;
+mod view;
 
+pub use chitchat::transport::{Transport, UdpTransport};
+pub use config::{GenerationSource, YellowpageConfig, CONFIG_FILE_ENV};
+pub use encryption::GossipKey;
 pub use error::{GossipError, Result};
-pub use node::NodeId;
+pub use metadata::RESERVED_PREFIX;
+pub use node::{NodeId, NodeStatus};
+pub use rebalance::{LoadReport, RebalanceConfig, ShardAssignments};
+pub use seeds::{
+    DnsSeeds, EnvSeeds, FileSeeds, Resolver, SeedProvider, SeedSource, SrvSeeds, StaticSeeds,
+    SystemResolver,
+};
+pub use sharding::{shard_of, ShardId, DEFAULT_CAPACITY, SHARD_COUNT};
+pub use transport::ChannelTransport;
+pub use view::{ClusterView, MetricsSnapshot};
 
-use chitchat::transport::UdpTransport;
-use chitchat::{spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId, FailureDetectorConfig};
+use chitchat::{spawn_chitchat, ChitchatConfig, ChitchatHandle, ChitchatId};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use tracing::info;
 
+use crate::metadata::{CAPACITY_KEY, LOAD_KEY, OBSERVER_KEY, STATUS_KEY};
+use crate::node::{active_nodes, live_chitchat_id};
+use crate::view::ClusterMetrics;
+
 /// Main entry point for cluster coordination
 ///
 /// Wraps Chitchat to provide a simplified API for ZukLink's needs:
 /// - Discovery of live nodes
-/// - Consistent ordering for sharding
+/// - Consistent ordering and capacity-weighted sharding
 /// - Metadata storage (role, load, etc.)
+/// - Leader election for cluster-wide duties
 pub struct Yellowpage {
-    /// Handle to the Chitchat instance
-    handle: ChitchatHandle,
+    /// Handle to the Chitchat instance, shared with the seed refresh task
+    handle: Arc<ChitchatHandle>,
     /// This node's unique identifier
     node_id: NodeId,
     /// Cluster identifier
     cluster_id: String,
+    /// Current leader as seen by this node
+    leader_rx: watch::Receiver<Option<NodeId>>,
+    /// Background task tracking leadership changes
+    leader_task: JoinHandle<()>,
+    /// Minimum number of live nodes required, if quorum checks are enabled
+    quorum: Option<usize>,
+    /// Membership and partition counters
+    metrics: Arc<ClusterMetrics>,
+    /// Background task maintaining the membership metrics
+    view_task: JoinHandle<()>,
+    /// Background task moving shards off overloaded nodes, if enabled
+    rebalance_task: Option<JoinHandle<()>>,
+    /// Background task feeding the seed provider's updates to Chitchat, if any
+    seed_task: Option<JoinHandle<()>>,
+    /// Time given to gossip to propagate a departure before stopping
+    departure_grace: Duration,
 }
 
 impl Yellowpage {
@@ -52,52 +99,241 @@ impl Yellowpage {
         cluster_id: String,
         listen_addr: SocketAddr,
         seeds: Vec<String>,
+    ) -> Result<Self> {
+        let config = YellowpageConfig::new(node_id, listen_addr)
+            .with_cluster_id(cluster_id)
+            .with_seeds(seeds);
+
+        Self::with_config(config).await
+    }
+
+    /// Create a new Yellowpage instance from a full configuration
+    ///
+    /// Exposes the gossip timings, failure detector and generation id source.
+    /// See [`YellowpageConfig`] for loading them from a file or environment
+    /// variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The configuration is invalid
+    /// - Cannot bind to the listen address
+    /// - Chitchat initialization fails
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::{Yellowpage, YellowpageConfig};
+    /// # async fn example() -> zuklink_yellowpage::Result<()> {
+    /// let config = YellowpageConfig::load()?;
+    /// let yellowpage = Yellowpage::with_config(config).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_config(config: YellowpageConfig) -> Result<Self> {
+        config.validate()?;
+
+        let seeds = Self::resolve_seeds(&config).await;
+        let transport = transport::from_config(&config)?;
+        let seed_provider = config.seed_provider.clone();
+
+        Self::start(config, seeds, seed_provider, transport.as_ref()).await
+    }
+
+    /// Create a new Yellowpage instance gossiping over a custom transport
+    ///
+    /// Used with [`ChannelTransport`] to run whole clusters in memory, with
+    /// simulated packet loss, latency and partitions, under a paused Tokio
+    /// clock. `config.gossip_keys` is ignored: the transport is responsible
+    /// for securing packets (see [`ChannelTransport::with_gossip_keys`]).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Yellowpage::with_config`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::{ChannelTransport, Yellowpage, YellowpageConfig};
+    /// # async fn example() -> zuklink_yellowpage::Result<()> {
+    /// let network = ChannelTransport::new();
+    ///
+    /// let config = YellowpageConfig::local("node-1", "10.0.0.1:7000".parse().unwrap());
+    /// let node1 = Yellowpage::with_transport(config, &network).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_transport(
+        config: YellowpageConfig,
+        transport: &dyn Transport,
+    ) -> Result<Self> {
+        config.validate()?;
+
+        let seeds = Self::resolve_seeds(&config).await;
+        let seed_provider = config.seed_provider.clone();
+
+        Self::start(config, seeds, seed_provider, transport).await
+    }
+
+    /// Create a new Yellowpage instance, discovering seeds with a custom provider
+    ///
+    /// The provider is queried until it returns a peer or the configured
+    /// `seed_discovery_timeout` elapses; its seeds are added to the static
+    /// ones. It is then queried again every `seed_refresh_interval`. This
+    /// overrides `config.seed_provider`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Yellowpage::with_config`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::{DnsSeeds, Yellowpage, YellowpageConfig};
+    /// # async fn example() -> zuklink_yellowpage::Result<()> {
+    /// let config = YellowpageConfig::new("receiver-1", "0.0.0.0:7000".parse().unwrap());
+    /// let provider = DnsSeeds::new("zuk-sink.default.svc.cluster.local", 7000);
+    ///
+    /// let yellowpage = Yellowpage::with_seed_provider(config, provider).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_seed_provider<P: SeedProvider + 'static>(
+        config: YellowpageConfig,
+        provider: P,
+    ) -> Result<Self> {
+        config.validate()?;
+
+        let seeds = seeds::discover_seeds(
+            &provider,
+            &config.seeds,
+            config.listen_addr,
+            config.seed_discovery_timeout,
+        )
+        .await;
+        let transport = transport::from_config(&config)?;
+
+        Self::start(config, seeds, Some(provider), transport.as_ref()).await
+    }
+
+    /// Static seeds, completed by the configured seed provider if any
+    async fn resolve_seeds(config: &YellowpageConfig) -> Vec<String> {
+        match &config.seed_provider {
+            Some(seed_provider) => {
+                seeds::discover_seeds(
+                    seed_provider,
+                    &config.seeds,
+                    config.listen_addr,
+                    config.seed_discovery_timeout,
+                )
+                .await
+            }
+            None => config.seeds.clone(),
+        }
+    }
+
+    /// Join the cluster with a validated configuration and resolved seeds
+    async fn start<P: SeedProvider + 'static>(
+        config: YellowpageConfig,
+        seeds: Vec<String>,
+        seed_provider: Option<P>,
+        transport: &dyn Transport,
     ) -> Result<Self> {
         info!(
-            node_id = %node_id,
-            cluster_id = %cluster_id,
-            listen_addr = %listen_addr,
+            node_id = %config.node_id,
+            cluster_id = %config.cluster_id,
+            listen_addr = %config.listen_addr,
             seeds = ?seeds,
+            gossip_interval = ?config.gossip_interval,
+            phi_threshold = config.phi_threshold,
             "Initializing Yellowpage"
         );
 
-        // Create ChitchatId with node_id, generation (using current timestamp), and advertise addr
-        let generation_id = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .expect("System time should be after Unix epoch")
-            .as_secs();
-
-        let chitchat_id = ChitchatId::new(node_id.clone(), generation_id, listen_addr);
+        let generation_id = config.generation.generation_id();
+        let chitchat_id =
+            ChitchatId::new(config.node_id.clone(), generation_id, config.listen_addr);
 
-        let config = ChitchatConfig {
+        let chitchat_config = ChitchatConfig {
             chitchat_id,
-            cluster_id: cluster_id.clone(),
-            gossip_interval: Duration::from_millis(500),
-            listen_addr,
-            seed_nodes: seeds.clone(),
-            failure_detector_config: FailureDetectorConfig::default(),
-            marked_for_deletion_grace_period: Duration::from_secs(60),
+            cluster_id: config.cluster_id.clone(),
+            gossip_interval: config.gossip_interval,
+            listen_addr: config.listen_addr,
+            seed_nodes: seeds,
+            failure_detector_config: config.failure_detector_config(),
+            marked_for_deletion_grace_period: config.marked_for_deletion_grace_period,
             catchup_callback: None,
             extra_liveness_predicate: None,
         };
 
-        // Create UDP transport
-        let transport = UdpTransport;
+        // Advertise the node as ready, with its capacity, from the first gossip round
+        let capacity = config.effective_capacity();
+        let mut initial_key_values = vec![
+            (STATUS_KEY.to_string(), NodeStatus::Ready.to_string()),
+            (CAPACITY_KEY.to_string(), capacity.to_string()),
+        ];
+        if config.observer {
+            initial_key_values.push((OBSERVER_KEY.to_string(), "true".to_string()));
+        }
 
         // Spawn Chitchat in background
-        let handle = spawn_chitchat(config, Vec::new(), &transport)
+        let handle = spawn_chitchat(chitchat_config, initial_key_values, transport)
             .await
+            .map(Arc::new)
             .map_err(|e| GossipError::config_error(format!("Failed to spawn chitchat: {}", e)))?;
 
+        // Track leadership and membership changes in background
+        let quorum = config.quorum();
+        let node_id = NodeId(config.node_id);
+        let metrics = Arc::new(ClusterMetrics::default());
+
+        let (leader_tx, leader_rx) = watch::channel(None);
+        let leader_task = leader::spawn_leader_task(
+            handle.chitchat(),
+            config.leader_settle_delay,
+            leader_tx,
+            metrics.clone(),
+        );
+        let view_task =
+            view::spawn_view_task(handle.chitchat(), node_id.clone(), quorum, metrics.clone());
+        let rebalance_task = config.rebalance.map(|rebalance| {
+            rebalance::spawn_rebalance_task(
+                handle.chitchat(),
+                node_id.clone(),
+                quorum,
+                leader_rx.clone(),
+                rebalance,
+            )
+        });
+        let seed_task = seed_provider.map(|provider| {
+            seeds::spawn_refresh_task(
+                handle.clone(),
+                provider,
+                config.listen_addr,
+                config.seed_refresh_interval,
+            )
+        });
+
         info!(
             node_id = %node_id,
+            generation_id = generation_id,
+            capacity = capacity,
+            observer = config.observer,
+            quorum = ?quorum,
             "Yellowpage initialized successfully"
         );
 
         Ok(Self {
             handle,
-            node_id: NodeId(node_id),
-            cluster_id,
+            node_id,
+            cluster_id: config.cluster_id,
+            leader_rx,
+            leader_task,
+            quorum,
+            metrics,
+            view_task,
+            rebalance_task,
+            seed_task,
+            departure_grace: config.departure_grace,
         })
     }
 
@@ -105,6 +341,10 @@ impl Yellowpage {
     ///
     /// This is the core method used by receivers for consistent hashing.
     /// The list is always sorted to ensure all nodes agree on the same ordering.
+    /// Nodes that announced their departure (see [`Yellowpage::leave`]) are
+    /// excluded, so their work is rebalanced without waiting for the failure
+    /// detector. Observers (see [`YellowpageConfig::with_observer`]) are
+    /// excluded too.
     ///
     /// # Returns
     ///
@@ -122,53 +362,289 @@ impl Yellowpage {
     /// # }
     /// ```
     pub async fn get_live_nodes(&self) -> Vec<NodeId> {
+        self.cluster_view().await.nodes().to_vec()
+    }
+
+    /// Get a consistent snapshot of the cluster view
+    ///
+    /// Unlike separate calls to [`Yellowpage::get_live_nodes`] and
+    /// [`Yellowpage::my_index`], the node list, this node's index and the
+    /// quorum status are read under a single lock and cannot disagree.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::Yellowpage;
+    /// # async fn example(yellowpage: &Yellowpage) {
+    /// let view = yellowpage.cluster_view().await;
+    /// if !view.has_quorum() {
+    ///     // Minority side of a partition: stop claiming work
+    ///     return;
+    /// }
+    /// if view.owns("segment-42.zuk") {
+    ///     // Process the segment, assigned in proportion to capacity
+    /// }
+    /// # }
+    /// ```
+    pub async fn cluster_view(&self) -> ClusterView {
         let chitchat = self.handle.chitchat();
         let chitchat_guard = chitchat.lock().await;
 
-        let mut nodes: Vec<NodeId> = chitchat_guard
-            .live_nodes()
-            .map(|chitchat_id| NodeId(chitchat_id.node_id.clone()))
-            .collect();
+        view::current_view(&chitchat_guard, &self.node_id, self.quorum)
+    }
+
+    /// Check whether this node's view holds a majority of the expected cluster
+    ///
+    /// Always `true` when no expected cluster size is configured. A node
+    /// without quorum is likely on the minority side of a network partition
+    /// and must not claim shards.
+    pub async fn has_quorum(&self) -> bool {
+        self.cluster_view().await.has_quorum()
+    }
+
+    /// Get the minimum number of live nodes required, if quorum checks are enabled
+    pub fn quorum(&self) -> Option<usize> {
+        self.quorum
+    }
+
+    /// Get the membership and partition metrics
+    ///
+    /// Counters are maintained by a background task, so the snapshot may lag
+    /// the live view by a fraction of a second.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Change the capacity weight advertised by this node
+    ///
+    /// Peers pick the new weight up on the next gossip round, after which
+    /// [`ClusterView::owner`] gives this node a proportionally larger or
+    /// smaller share of the keys. `0` hands all of its shards to the others.
+    pub async fn set_capacity(&self, capacity: u32) {
+        self.write_metadata(CAPACITY_KEY, &capacity.to_string())
+            .await;
+    }
+
+    /// Advertise this node's backlog
+    ///
+    /// When rebalancing is enabled, the leader compares the reports of all
+    /// nodes and moves hot shards from overloaded nodes to idle ones. Report
+    /// the load regularly (e.g. after every poll) so it reflects the backlog.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::SerializationError` if the report cannot be
+    /// encoded.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::{LoadReport, Yellowpage};
+    /// # async fn example(yellowpage: &Yellowpage) -> zuklink_yellowpage::Result<()> {
+    /// let report = LoadReport {
+    ///     pending_segments: 120,
+    ///     pending_bytes: 64 * 1024 * 1024,
+    ///     processing_rate: 3.5,
+    ///     ..LoadReport::default()
+    /// };
+    /// yellowpage.report_load(&report).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn report_load(&self, report: &LoadReport) -> Result<()> {
+        let raw = metadata::encode(LOAD_KEY, report)?;
 
-        // CRITICAL: Sort to ensure consistent ordering across all nodes
-        nodes.sort();
+        let chitchat = self.handle.chitchat();
+        let mut chitchat_guard = chitchat.lock().await;
+        chitchat_guard
+            .self_node_state()
+            .set(LOAD_KEY.to_string(), raw);
 
-        nodes
+        Ok(())
     }
 
     /// Get the number of live nodes in the cluster
     pub async fn cluster_size(&self) -> usize {
         let chitchat = self.handle.chitchat();
         let chitchat_guard = chitchat.lock().await;
-        chitchat_guard.live_nodes().count()
+        active_nodes(&chitchat_guard).count()
     }
 
     /// Get this node's position in the sorted cluster view
     ///
     /// Returns `None` if this node is not in the live nodes list
-    /// (which shouldn't happen under normal circumstances).
+    /// (which only happens once it has announced its departure).
     pub async fn my_index(&self) -> Option<usize> {
-        let live_nodes = self.get_live_nodes().await;
-        live_nodes.iter().position(|id| id == &self.node_id)
+        self.cluster_view().await.my_index()
     }
 
     /// Set a metadata key-value pair for this node
     ///
     /// Metadata is propagated to all nodes in the cluster via gossip.
     ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::InvalidKey` if the key is empty or belongs to the
+    /// reserved `zuk.*` namespace.
+    ///
     /// # Example
     ///
     /// ```rust,no_run
     /// # use zuklink_yellowpage::Yellowpage;
-    /// # async fn example(yellowpage: &Yellowpage) {
+    /// # async fn example(yellowpage: &Yellowpage) -> zuklink_yellowpage::Result<()> {
     /// // Mark this node's role
-    /// yellowpage.set_metadata("role", "receiver").await;
+    /// yellowpage.set_metadata("role", "receiver").await?;
     ///
     /// // Report current load (for future load balancing)
-    /// yellowpage.set_metadata("cpu_load", "0.75").await;
+    /// yellowpage.set_metadata("cpu_load", "0.75").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_metadata(&self, key: &str, value: &str) -> Result<()> {
+        metadata::validate_user_key(key)?;
+        self.write_metadata(key, value).await;
+        Ok(())
+    }
+
+    /// Set a typed metadata value for this node
+    ///
+    /// The value is JSON-encoded before being gossiped, so any `Serialize`
+    /// type can be advertised without inventing a string encoding.
+    ///
+    /// # Errors
+    ///
+    /// - `GossipError::InvalidKey` if the key is empty or reserved
+    /// - `GossipError::SerializationError` if the value cannot be encoded
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::Yellowpage;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Capacity {
+    ///     cpus: u32,
+    ///     topics: Vec<String>,
+    /// }
+    ///
+    /// # async fn example(yellowpage: &Yellowpage) -> zuklink_yellowpage::Result<()> {
+    /// let capacity = Capacity { cpus: 8, topics: vec!["billing".to_string()] };
+    /// yellowpage.set_typed("capacity", &capacity).await?;
+    /// # Ok(())
     /// # }
     /// ```
-    pub async fn set_metadata(&self, key: &str, value: &str) {
+    pub async fn set_typed<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        metadata::validate_user_key(key)?;
+        let raw = metadata::encode(key, value)?;
+        self.write_metadata(key, &raw).await;
+        Ok(())
+    }
+
+    /// Delete a metadata key from this node
+    ///
+    /// The deletion is propagated to all nodes via gossip.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::InvalidKey` if the key is empty or reserved.
+    pub async fn delete_metadata(&self, key: &str) -> Result<()> {
+        metadata::validate_user_key(key)?;
+
+        let chitchat = self.handle.chitchat();
+        let mut chitchat_guard = chitchat.lock().await;
+        chitchat_guard.self_node_state().delete(key);
+
+        info!(node_id = %self.node_id, key = key, "Metadata deleted");
+        Ok(())
+    }
+
+    /// Get metadata for a specific node
+    ///
+    /// Returns `None` if the node doesn't exist or the key is not set.
+    pub async fn get_metadata(&self, node_id: &NodeId, key: &str) -> Option<String> {
+        let chitchat = self.handle.chitchat();
+        let chitchat_guard = chitchat.lock().await;
+
+        let chitchat_id = live_chitchat_id(&chitchat_guard, node_id)?;
+        chitchat_guard
+            .node_state(&chitchat_id)
+            .and_then(|state| state.get(key).map(|v| v.to_string()))
+    }
+
+    /// Get a typed metadata value for a specific node
+    ///
+    /// Returns `Ok(None)` if the node doesn't exist or the key is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::SerializationError` if the stored value cannot be
+    /// decoded as `T`.
+    pub async fn get_typed<T: DeserializeOwned>(
+        &self,
+        node_id: &NodeId,
+        key: &str,
+    ) -> Result<Option<T>> {
+        self.get_metadata(node_id, key)
+            .await
+            .map(|raw| metadata::decode(key, &raw))
+            .transpose()
+    }
+
+    /// Get a metadata key for all live nodes at once
+    ///
+    /// Nodes that did not set the key are omitted, and so are the nodes
+    /// [`get_live_nodes`](Self::get_live_nodes) excludes (left nodes and
+    /// observers). This takes the cluster lock once, instead of once per node
+    /// as with repeated `get_metadata` calls.
+    pub async fn get_metadata_all(&self, key: &str) -> BTreeMap<NodeId, String> {
+        let chitchat = self.handle.chitchat();
+        let chitchat_guard = chitchat.lock().await;
+
+        active_nodes(&chitchat_guard)
+            .filter_map(|chitchat_id| {
+                let value = chitchat_guard.node_state(chitchat_id)?.get(key)?;
+                Some((NodeId(chitchat_id.node_id.clone()), value.to_string()))
+            })
+            .collect()
+    }
+
+    /// Get a typed metadata value for all live nodes at once
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::SerializationError` if any stored value cannot be
+    /// decoded as `T`.
+    pub async fn get_typed_all<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<BTreeMap<NodeId, T>> {
+        self.get_metadata_all(key)
+            .await
+            .into_iter()
+            .map(|(node_id, raw)| Ok((node_id, metadata::decode(key, &raw)?)))
+            .collect()
+    }
+
+    /// Get every metadata key-value pair of a specific node
+    ///
+    /// Returns `None` if the node is not live.
+    pub async fn get_node_metadata(&self, node_id: &NodeId) -> Option<BTreeMap<String, String>> {
+        let chitchat = self.handle.chitchat();
+        let chitchat_guard = chitchat.lock().await;
+
+        let chitchat_id = live_chitchat_id(&chitchat_guard, node_id)?;
+        chitchat_guard.node_state(&chitchat_id).map(|state| {
+            state
+                .key_values()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+    }
+
+    /// Write a metadata key without namespace validation
+    ///
+    /// Used internally to publish `zuk.*` keys.
+    pub(crate) async fn write_metadata(&self, key: &str, value: &str) {
         let chitchat = self.handle.chitchat();
         let mut chitchat_guard = chitchat.lock().await;
 
@@ -184,26 +660,105 @@ impl Yellowpage {
         );
     }
 
-    /// Get metadata for a specific node
+    /// Get the current cluster leader
     ///
-    /// Returns `None` if the node doesn't exist or the key is not set.
-    pub async fn get_metadata(&self, node_id: &NodeId, key: &str) -> Option<String> {
-        let chitchat = self.handle.chitchat();
-        let chitchat_guard = chitchat.lock().await;
+    /// The leader is the live node with the lowest generation id (the oldest
+    /// node), ties broken by node ID. A new candidate is only accepted once it
+    /// has been stable for the settle delay, so this returns `None` right after
+    /// startup until the view has settled.
+    ///
+    /// Leadership is derived from the local, eventually consistent view: during
+    /// a network partition each side may elect its own leader. Duties guarded by
+    /// [`Yellowpage::is_leader`] must therefore be safe to run twice.
+    pub fn leader(&self) -> Option<NodeId> {
+        self.leader_rx.borrow().clone()
+    }
 
-        // Find the ChitchatId for this node_id
-        let chitchat_id = chitchat_guard
-            .live_nodes()
-            .find(|chitchat_id| chitchat_id.node_id == node_id.0)
-            .cloned();
-
-        if let Some(id) = chitchat_id {
-            chitchat_guard
-                .node_state(&id)
-                .and_then(|state| state.get(key).map(|v| v.to_string()))
-        } else {
-            None
-        }
+    /// Check whether this node is currently the cluster leader
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::Yellowpage;
+    /// # async fn example(yellowpage: &Yellowpage) {
+    /// if yellowpage.is_leader() {
+    ///     // Run retention cleanup, compaction scheduling, ...
+    /// }
+    /// # }
+    /// ```
+    pub fn is_leader(&self) -> bool {
+        self.leader_rx.borrow().as_ref() == Some(&self.node_id)
+    }
+
+    /// Subscribe to leadership changes
+    ///
+    /// The returned receiver yields the new leader every time leadership moves.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::Yellowpage;
+    /// # async fn example(yellowpage: &Yellowpage) {
+    /// let mut leader_rx = yellowpage.subscribe_leader();
+    /// while leader_rx.changed().await.is_ok() {
+    ///     let leader = leader_rx.borrow_and_update().clone();
+    ///     println!("New leader: {:?}", leader);
+    /// }
+    /// # }
+    /// ```
+    pub fn subscribe_leader(&self) -> watch::Receiver<Option<NodeId>> {
+        self.leader_rx.clone()
+    }
+
+    /// Get the lifecycle status advertised by a node
+    ///
+    /// Returns `None` if the node is not live or advertises no valid status.
+    pub async fn node_status(&self, node_id: &NodeId) -> Option<NodeStatus> {
+        self.get_metadata(node_id, STATUS_KEY)
+            .await
+            .and_then(|status| status.parse().ok())
+    }
+
+    /// Start a graceful leave by marking this node as `leaving`
+    ///
+    /// The node stays in the cluster view and keeps its share of the work,
+    /// but the caller is expected to stop claiming new work, finish in-flight
+    /// work and flush its progress before calling [`Yellowpage::leave`].
+    pub async fn begin_leave(&self) {
+        info!(node_id = %self.node_id, "Node is leaving, draining in-flight work");
+        self.write_metadata(STATUS_KEY, NodeStatus::Leaving.as_str())
+            .await;
+    }
+
+    /// Announce departure and shut down
+    ///
+    /// The node is marked as `left`, which removes it from the peers' cluster
+    /// view on the next gossip round so they rebalance immediately instead of
+    /// waiting for the failure detector to time out. Gossip keeps running for
+    /// a short grace period to propagate the announcement.
+    ///
+    /// Takes `&self`, so that a handle shared with HTTP handlers or
+    /// background duties can still leave; every clone stops gossiping.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use zuklink_yellowpage::Yellowpage;
+    /// # async fn drain() {}
+    /// # async fn example(yellowpage: Yellowpage) {
+    /// yellowpage.begin_leave().await;
+    /// drain().await; // finish in-flight work, flush checkpoints
+    /// yellowpage.leave().await;
+    /// # }
+    /// ```
+    pub async fn leave(&self) {
+        info!(node_id = %self.node_id, "Announcing departure to the cluster");
+        self.write_metadata(STATUS_KEY, NodeStatus::Left.as_str())
+            .await;
+
+        tokio::time::sleep(self.departure_grace).await;
+
+        self.shutdown().await;
     }
 
     /// Get this node's ID
@@ -216,9 +771,34 @@ impl Yellowpage {
         &self.cluster_id
     }
 
-    /// Gracefully shutdown the Yellowpage instance
-    pub async fn shutdown(self) {
+    /// Stop gossiping immediately
+    ///
+    /// Peers only notice the node is gone once their failure detector times
+    /// out. Prefer [`Yellowpage::leave`] for planned shutdowns.
+    pub async fn shutdown(&self) {
         info!(node_id = %self.node_id, "Shutting down Yellowpage");
-        let _ = self.handle.shutdown().await;
+        self.abort_tasks();
+        let _ = self.handle.initiate_shutdown();
+        let _ = self.handle.termination_watcher().await;
+    }
+
+    /// Stop the background tasks of this node
+    fn abort_tasks(&self) {
+        self.leader_task.abort();
+        self.view_task.abort();
+        if let Some(rebalance_task) = &self.rebalance_task {
+            rebalance_task.abort();
+        }
+        if let Some(seed_task) = &self.seed_task {
+            seed_task.abort();
+        }
+    }
+}
+
+/// A node dropped without [`Yellowpage::shutdown`] stops its tasks too
+impl Drop for Yellowpage {
+    fn drop(&mut self) {
+        self.abort_tasks();
+        self.handle.abort();
     }
 }
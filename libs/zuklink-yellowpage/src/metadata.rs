@@ -0,0 +1,117 @@
+//! Node metadata keys and typed encoding
+//!
+//! Metadata is stored in the Chitchat key-value store as plain strings.
+//! This module defines the reserved key namespace used by ZukLink internals
+//! and the JSON encoding used for typed values.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{GossipError, Result};
+
+/// Prefix of the key namespace reserved for ZukLink internals
+///
+/// Keys starting with this prefix (e.g. `zuk.status`) can only be written by
+/// Yellowpage itself. They can still be read by anyone.
+pub const RESERVED_PREFIX: &str = "zuk.";
+
+/// Key holding the node lifecycle status (see [`crate::NodeStatus`])
+pub(crate) const STATUS_KEY: &str = "zuk.status";
+
+/// Key holding the node capacity weight used for shard assignment
+pub(crate) const CAPACITY_KEY: &str = "zuk.capacity";
+
+/// Key holding the node load report (see [`crate::LoadReport`])
+pub(crate) const LOAD_KEY: &str = "zuk.load";
+
+/// Key marking observers, which follow the cluster without owning shards
+pub(crate) const OBSERVER_KEY: &str = "zuk.observer";
+
+/// Key holding the shard overrides published by the leader
+pub(crate) const ASSIGNMENTS_KEY: &str = "zuk.assignments";
+
+/// Check whether a key belongs to the reserved namespace
+pub(crate) fn is_reserved(key: &str) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+/// Validate a key written through the public metadata API
+pub(crate) fn validate_user_key(key: &str) -> Result<()> {
+    if key.is_empty() {
+        return Err(GossipError::invalid_key(key, "key cannot be empty"));
+    }
+
+    if is_reserved(key) {
+        return Err(GossipError::invalid_key(
+            key,
+            format!("the '{}' namespace is reserved", RESERVED_PREFIX),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Encode a typed value for storage in the key-value store
+pub(crate) fn encode<T: Serialize + ?Sized>(key: &str, value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| GossipError::serialization_error(key, e))
+}
+
+/// Decode a typed value read from the key-value store
+pub(crate) fn decode<T: DeserializeOwned>(key: &str, raw: &str) -> Result<T> {
+    serde_json::from_str(raw).map_err(|e| GossipError::serialization_error(key, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Capacity {
+        cpus: u32,
+        topics: Vec<String>,
+    }
+
+    #[test]
+    fn test_reserved_namespace() {
+        assert!(is_reserved("zuk.status"));
+        assert!(!is_reserved("zukstatus"));
+        assert!(!is_reserved("role"));
+    }
+
+    #[test]
+    fn test_validate_user_key() {
+        assert!(validate_user_key("role").is_ok());
+        assert!(matches!(
+            validate_user_key("zuk.status"),
+            Err(GossipError::InvalidKey { .. })
+        ));
+        assert!(matches!(
+            validate_user_key(""),
+            Err(GossipError::InvalidKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_typed_roundtrip() {
+        let capacity = Capacity {
+            cpus: 8,
+            topics: vec!["billing".to_string()],
+        };
+
+        let raw = encode("capacity", &capacity).unwrap();
+        let decoded: Capacity = decode("capacity", &raw).unwrap();
+
+        assert_eq!(decoded, capacity);
+    }
+
+    #[test]
+    fn test_decode_invalid_value() {
+        let result: Result<Capacity> = decode("capacity", "not json");
+
+        assert!(matches!(
+            result,
+            Err(GossipError::SerializationError { .. })
+        ));
+    }
+}
@@ -1,7 +1,13 @@
 //! Node identification and state management
 
+use chitchat::{Chitchat, ChitchatId};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+
+use crate::metadata::{OBSERVER_KEY, STATUS_KEY};
 
 /// Unique identifier for a node in the cluster
 ///
@@ -40,13 +46,138 @@ impl From<&str> for NodeId {
     }
 }
 
+/// Lifecycle status advertised by a node under the `zuk.status` key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+    /// Node is up and owns its share of the work
+    Ready,
+    /// Node is draining: it finishes in-flight work but claims nothing new
+    Leaving,
+    /// Node has announced its departure and is excluded from the cluster view
+    Left,
+}
+
+impl NodeStatus {
+    /// Get the string representation stored in metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Leaving => "leaving",
+            Self::Left => "left",
+        }
+    }
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NodeStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ready" => Ok(Self::Ready),
+            "leaving" => Ok(Self::Leaving),
+            "left" => Ok(Self::Left),
+            other => Err(format!("unknown node status '{}'", other)),
+        }
+    }
+}
+
+/// Iterate over live nodes that have not announced their departure
+///
+/// Nodes without a status (e.g. running an older version) are considered active.
+/// Observers are not: they own no shards and are not counted for quorum.
+pub(crate) fn active_nodes(chitchat: &Chitchat) -> impl Iterator<Item = &ChitchatId> {
+    chitchat.live_nodes().filter(|chitchat_id| {
+        let Some(state) = chitchat.node_state(chitchat_id) else {
+            return true;
+        };
+        let left = state
+            .get(STATUS_KEY)
+            .and_then(|status| status.parse::<NodeStatus>().ok())
+            == Some(NodeStatus::Left);
+        !left && state.get(OBSERVER_KEY) != Some("true")
+    })
+}
+
+/// Chitchat ID of a live node
+///
+/// Looked up by key in the live nodes map Chitchat maintains on every
+/// membership change, rather than by scanning every live node. This node is
+/// always live, even before the map is first published.
+pub(crate) fn live_chitchat_id(chitchat: &Chitchat, node_id: &NodeId) -> Option<ChitchatId> {
+    if chitchat.self_chitchat_id().node_id == node_id.0 {
+        return Some(chitchat.self_chitchat_id().clone());
+    }
+
+    let live_nodes = chitchat.live_nodes_watcher();
+    let live_nodes = live_nodes.borrow();
+    find_node(&live_nodes, node_id).cloned()
+}
+
+/// Latest generation of a node in a map keyed by Chitchat ID
+///
+/// Chitchat IDs sort by node ID first, so the generations of a node are
+/// contiguous and found with a range lookup.
+fn find_node<'a, V>(
+    nodes: &'a BTreeMap<ChitchatId, V>,
+    node_id: &NodeId,
+) -> Option<&'a ChitchatId> {
+    let first = ChitchatId::new(
+        node_id.0.clone(),
+        0,
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+    );
+
+    nodes
+        .range(first..)
+        .map(|(chitchat_id, _)| chitchat_id)
+        .take_while(|chitchat_id| chitchat_id.node_id == node_id.0)
+        .last()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_find_node_picks_latest_generation() {
+        let id = |node_id: &str, generation_id: u64| {
+            ChitchatId::new(
+                node_id.to_string(),
+                generation_id,
+                "127.0.0.1:7000".parse().unwrap(),
+            )
+        };
+        let nodes: BTreeMap<ChitchatId, ()> = [
+            (id("node-1", 5), ()),
+            (id("node-2", 1), ()),
+            (id("node-2", 9), ()),
+            (id("node-20", 0), ()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            find_node(&nodes, &NodeId::new("node-1")),
+            Some(&id("node-1", 5))
+        );
+        assert_eq!(
+            find_node(&nodes, &NodeId::new("node-2")),
+            Some(&id("node-2", 9))
+        );
+        assert_eq!(find_node(&nodes, &NodeId::new("node-3")), None);
+        assert_eq!(find_node(&nodes, &NodeId::new("node")), None);
+    }
+
     #[test]
     fn test_node_id_ordering() {
-        let mut ids = vec![
+        let mut ids = [
             NodeId::new("node-3"),
             NodeId::new("node-1"),
             NodeId::new("node-2"),
@@ -74,4 +205,13 @@ mod tests {
         let id = NodeId::new("receiver-1");
         assert_eq!(format!("{}", id), "receiver-1");
     }
+
+    #[test]
+    fn test_node_status_roundtrip() {
+        for status in [NodeStatus::Ready, NodeStatus::Leaving, NodeStatus::Left] {
+            assert_eq!(status.as_str().parse::<NodeStatus>(), Ok(status));
+        }
+
+        assert!("gone".parse::<NodeStatus>().is_err());
+    }
 }
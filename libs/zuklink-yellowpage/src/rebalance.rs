@@ -0,0 +1,422 @@
+//! Load-aware shard rebalancing
+//!
+//! Hash-based assignment ignores how far behind each node is: a node owning a
+//! few hot shards can accumulate a backlog while its peers sit idle. Nodes
+//! gossip a [`LoadReport`] under the reserved `zuk.load` key and, when enabled,
+//! the leader runs a [`Rebalancer`] that moves hot shards from overloaded nodes
+//! to idle ones.
+//!
+//! ## Assignment Overrides
+//!
+//! Moves are published by the leader as [`ShardAssignments`] under the
+//! reserved `zuk.assignments` key: a map of shard overrides stamped with an
+//! epoch. Every node applies the overrides with the highest epoch found in its
+//! view, so nodes sharing a view agree on every owner. Overrides pointing to a
+//! node that left the view are ignored, and the shard falls back to its
+//! natural (hash-based) owner.
+//!
+//! ## Hysteresis
+//!
+//! Loads fluctuate from one poll to the next, so moving shards on every
+//! imbalance would make assignments thrash:
+//!
+//! - The imbalance must last for `sustain_rounds` consecutive rounds
+//! - The busiest node must exceed the average load by `threshold`
+//! - A moved shard cannot move again before `shard_cooldown`
+//! - A shard only moves if the target stays less loaded than the source
+//! - At most one shard moves per round
+
+use chitchat::Chitchat;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use crate::metadata::{self, ASSIGNMENTS_KEY};
+use crate::node::NodeId;
+use crate::sharding::ShardId;
+use crate::view::{self, ClusterView};
+
+/// Backlog advertised by a node
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoadReport {
+    /// Segments owned by the node and not processed yet
+    pub pending_segments: u64,
+    /// Total size of the pending segments
+    pub pending_bytes: u64,
+    /// Segments processed per second, averaged over the last minutes
+    pub processing_rate: f64,
+    /// Pending segments per shard, used to pick the shards to move
+    ///
+    /// Nodes may only report their busiest shards.
+    #[serde(default)]
+    pub shards: BTreeMap<ShardId, u64>,
+}
+
+/// Shard ownership overrides published by the leader
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardAssignments {
+    /// Incremented on every change, the highest epoch wins
+    pub epoch: u64,
+    /// Shards moved away from their natural owner
+    pub overrides: BTreeMap<ShardId, NodeId>,
+}
+
+/// Rebalancer settings
+///
+/// Written as a `[rebalance]` table in the configuration file, or enabled with
+/// `YELLOWPAGE_REBALANCE=true` and tuned with `YELLOWPAGE_REBALANCE_*`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RebalanceConfig {
+    /// Interval between two rebalancing rounds (`YELLOWPAGE_REBALANCE_INTERVAL`)
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Ratio of the average load above which a node is overloaded (`YELLOWPAGE_REBALANCE_THRESHOLD`)
+    pub threshold: f64,
+    /// Backlog below which a node is never considered overloaded (`YELLOWPAGE_REBALANCE_MIN_BACKLOG`)
+    pub min_backlog: u64,
+    /// Consecutive imbalanced rounds required before moving a shard (`YELLOWPAGE_REBALANCE_SUSTAIN_ROUNDS`)
+    pub sustain_rounds: u32,
+    /// Minimum time between two moves of the same shard (`YELLOWPAGE_REBALANCE_SHARD_COOLDOWN`)
+    #[serde(with = "humantime_serde")]
+    pub shard_cooldown: Duration,
+}
+
+impl Default for RebalanceConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            threshold: 1.5,
+            min_backlog: 10,
+            sustain_rounds: 3,
+            shard_cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Load of a node relative to its capacity
+struct NodeLoad<'a> {
+    node_id: &'a NodeId,
+    weight: f64,
+    pending: f64,
+    report: &'a LoadReport,
+}
+
+impl NodeLoad<'_> {
+    fn normalized(&self) -> f64 {
+        self.pending / self.weight
+    }
+}
+
+/// Decides which shards to move, with hysteresis
+#[derive(Debug)]
+pub(crate) struct Rebalancer {
+    config: RebalanceConfig,
+    /// Consecutive rounds the current imbalance has lasted
+    streak: u32,
+    /// Last time each shard was moved
+    moved_at: HashMap<ShardId, Instant>,
+}
+
+impl Rebalancer {
+    pub(crate) fn new(config: RebalanceConfig) -> Self {
+        Self {
+            config,
+            streak: 0,
+            moved_at: HashMap::new(),
+        }
+    }
+
+    /// Run one round over the current view
+    ///
+    /// Returns the assignments to publish when they changed, `None` otherwise.
+    pub(crate) fn plan(&mut self, view: &ClusterView, now: Instant) -> Option<ShardAssignments> {
+        let mut assignments = view.assignments().clone();
+        let mut changed = prune(&mut assignments, view);
+
+        if let Some((shard, target)) = self.pick_move(view, now) {
+            info!(shard = shard, target = %target, "Moving shard to a less loaded node");
+            self.moved_at.insert(shard, now);
+            self.streak = 0;
+
+            if view.natural_owner(shard) == Some(&target) {
+                assignments.overrides.remove(&shard);
+            } else {
+                assignments.overrides.insert(shard, target);
+            }
+            changed = true;
+        }
+
+        changed.then(|| {
+            assignments.epoch += 1;
+            assignments
+        })
+    }
+
+    /// Pick a hot shard of the busiest node and the idlest node to move it to
+    fn pick_move(&mut self, view: &ClusterView, now: Instant) -> Option<(ShardId, NodeId)> {
+        let loads: Vec<NodeLoad<'_>> = view
+            .nodes()
+            .iter()
+            .filter_map(|node_id| {
+                let weight = view.weight(node_id).filter(|weight| *weight > 0)?;
+                let report = view.load(node_id)?;
+                Some(NodeLoad {
+                    node_id,
+                    weight: f64::from(weight),
+                    pending: report.pending_segments as f64,
+                    report,
+                })
+            })
+            .collect();
+
+        let busiest = loads
+            .iter()
+            .max_by(|a, b| a.normalized().total_cmp(&b.normalized()))?;
+        let idlest = loads
+            .iter()
+            .min_by(|a, b| a.normalized().total_cmp(&b.normalized()))?;
+
+        let total_pending: f64 = loads.iter().map(|load| load.pending).sum();
+        let total_weight: f64 = loads.iter().map(|load| load.weight).sum();
+        let average = total_pending / total_weight;
+
+        let imbalanced = busiest.node_id != idlest.node_id
+            && busiest.pending >= self.config.min_backlog as f64
+            && busiest.normalized() > average * self.config.threshold;
+
+        if !imbalanced {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < self.config.sustain_rounds {
+            debug!(
+                node_id = %busiest.node_id,
+                streak = self.streak,
+                "Node overloaded, waiting for the imbalance to persist"
+            );
+            return None;
+        }
+
+        // Hottest shard that still leaves the target less loaded than the source
+        let mut shards: Vec<(ShardId, u64)> = busiest
+            .report
+            .shards
+            .iter()
+            .map(|(shard, pending)| (*shard, *pending))
+            .collect();
+        shards.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        shards
+            .into_iter()
+            .filter(|(shard, _)| view.shard_owner(*shard) == Some(busiest.node_id))
+            .filter(|(shard, _)| {
+                self.moved_at.get(shard).map_or(true, |at| {
+                    now.duration_since(*at) >= self.config.shard_cooldown
+                })
+            })
+            .find(|(_, pending)| {
+                let moved = *pending as f64;
+                moved > 0.0
+                    && (idlest.pending + moved) / idlest.weight
+                        < (busiest.pending - moved) / busiest.weight
+            })
+            .map(|(shard, _)| (shard, idlest.node_id.clone()))
+    }
+}
+
+/// Drop overrides that no longer apply
+///
+/// Returns whether any override was removed.
+fn prune(assignments: &mut ShardAssignments, view: &ClusterView) -> bool {
+    let before = assignments.overrides.len();
+
+    assignments.overrides.retain(|shard, node_id| {
+        view.weight(node_id).is_some_and(|weight| weight > 0)
+            && view.natural_owner(*shard) != Some(node_id)
+    });
+
+    assignments.overrides.len() != before
+}
+
+/// Spawn the background task running the rebalancer on the leader
+pub(crate) fn spawn_rebalance_task(
+    chitchat: Arc<Mutex<Chitchat>>,
+    self_id: NodeId,
+    quorum: Option<usize>,
+    leader_rx: watch::Receiver<Option<NodeId>>,
+    config: RebalanceConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.interval);
+        let mut rebalancer = Rebalancer::new(config);
+
+        loop {
+            interval.tick().await;
+
+            if leader_rx.borrow().as_ref() != Some(&self_id) {
+                continue;
+            }
+
+            let mut chitchat_guard = chitchat.lock().await;
+            let view = view::current_view(&chitchat_guard, &self_id, quorum);
+
+            // The minority side of a partition must not reassign shards
+            if !view.has_quorum() {
+                continue;
+            }
+
+            if let Some(assignments) = rebalancer.plan(&view, Instant::now()) {
+                match metadata::encode(ASSIGNMENTS_KEY, &assignments) {
+                    Ok(raw) => {
+                        info!(
+                            epoch = assignments.epoch,
+                            overrides = assignments.overrides.len(),
+                            "Publishing shard assignments"
+                        );
+                        chitchat_guard
+                            .self_node_state()
+                            .set(ASSIGNMENTS_KEY.to_string(), raw);
+                    }
+                    Err(e) => debug!(error = %e, "Failed to encode shard assignments"),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sharding::SHARD_COUNT;
+
+    fn config() -> RebalanceConfig {
+        RebalanceConfig {
+            sustain_rounds: 2,
+            ..RebalanceConfig::default()
+        }
+    }
+
+    /// Two equal nodes, `node-1` reporting a backlog on its first shards
+    fn view(backlog: u64, assignments: ShardAssignments) -> ClusterView {
+        let node_1 = NodeId::new("node-1");
+        let node_2 = NodeId::new("node-2");
+        let base = ClusterView::new(
+            vec![(node_1.clone(), 1), (node_2.clone(), 1)],
+            &node_1,
+            None,
+        );
+
+        let hot_shards: BTreeMap<ShardId, u64> = (0..SHARD_COUNT)
+            .filter(|shard| base.natural_owner(*shard) == Some(&node_1))
+            .take(4)
+            .map(|shard| (shard, backlog / 4))
+            .collect();
+
+        base.with_state(
+            vec![
+                (
+                    node_1,
+                    LoadReport {
+                        pending_segments: backlog,
+                        shards: hot_shards,
+                        ..LoadReport::default()
+                    },
+                ),
+                (node_2, LoadReport::default()),
+            ],
+            assignments,
+        )
+    }
+
+    #[test]
+    fn test_balanced_cluster_moves_nothing() {
+        let mut rebalancer = Rebalancer::new(config());
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert_eq!(
+                rebalancer.plan(&view(0, ShardAssignments::default()), now),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_small_backlog_is_ignored() {
+        let mut rebalancer = Rebalancer::new(config());
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert_eq!(
+                rebalancer.plan(&view(8, ShardAssignments::default()), now),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_sustained_imbalance_moves_hot_shard() {
+        let mut rebalancer = Rebalancer::new(config());
+        let now = Instant::now();
+        let view = view(400, ShardAssignments::default());
+
+        // First round only starts the streak
+        assert_eq!(rebalancer.plan(&view, now), None);
+
+        let assignments = rebalancer.plan(&view, now).expect("a shard should move");
+        assert_eq!(assignments.epoch, 1);
+        assert_eq!(assignments.overrides.len(), 1);
+
+        let (shard, target) = assignments.overrides.iter().next().unwrap();
+        assert_eq!(target, &NodeId::new("node-2"));
+        assert_eq!(view.shard_owner(*shard), Some(&NodeId::new("node-1")));
+    }
+
+    #[test]
+    fn test_moved_shard_respects_cooldown() {
+        let mut rebalancer = Rebalancer::new(config());
+        let now = Instant::now();
+        // The view has not caught up with the published overrides yet
+        let view = view(400, ShardAssignments::default());
+
+        rebalancer.plan(&view, now);
+        let first = rebalancer.plan(&view, now).unwrap();
+        rebalancer.plan(&view, now);
+        let second = rebalancer.plan(&view, now).unwrap();
+
+        assert_ne!(
+            first.overrides.keys().next(),
+            second.overrides.keys().next(),
+            "a shard must not move twice within the cooldown"
+        );
+
+        // Once the cooldown elapsed, the hottest shard may move again
+        let later = now + config().shard_cooldown;
+        rebalancer.plan(&view, later);
+        assert_eq!(rebalancer.plan(&view, later), Some(first));
+    }
+
+    #[test]
+    fn test_overrides_to_departed_nodes_are_pruned() {
+        let mut rebalancer = Rebalancer::new(config());
+        let assignments = ShardAssignments {
+            epoch: 7,
+            overrides: BTreeMap::from([(3, NodeId::new("node-9"))]),
+        };
+
+        let published = rebalancer
+            .plan(&view(0, assignments), Instant::now())
+            .unwrap();
+
+        assert_eq!(published.epoch, 8);
+        assert!(published.overrides.is_empty());
+    }
+}
@@ -0,0 +1,828 @@
+//! Seed discovery
+//!
+//! Seeds are only needed to join the cluster: once a node has exchanged a
+//! gossip round with any member, the rest of the membership spreads by gossip.
+//! Instead of templating seed addresses into each node's startup script, a
+//! [`SeedProvider`] finds them at startup:
+//!
+//! - [`EnvSeeds`]: comma-separated list in an environment variable
+//! - [`FileSeeds`]: one seed per line in a file (e.g. a mounted ConfigMap)
+//! - [`DnsSeeds`]: A/AAAA lookup of a headless service name
+//! - [`SrvSeeds`]: SRV lookup, for services exposing the gossip port by name
+//!
+//! ## Retries
+//!
+//! Discovery is retried until at least one seed other than the node itself is
+//! found or the discovery timeout elapses. This covers pods starting before
+//! their peers are registered in DNS, or a seed file written by an init
+//! container: the file is re-read and names are re-resolved on every attempt.
+//! If nothing is found in time, the node bootstraps a new cluster alone.
+//!
+//! ## Re-resolution
+//!
+//! Chitchat keeps its seed list for the lifetime of the node but periodically
+//! re-resolves seed host names. [`DnsSeeds`] therefore also hands the service
+//! name to Chitchat (see [`SeedProvider::dns_names`]), so peers started later
+//! are still found through DNS.
+//!
+//! Other sources are refreshed by a background task: every
+//! `seed_refresh_interval`, the provider is queried again (a seed file only
+//! when its modification time changed) and the node gossips with the seeds
+//! that are not live members yet. A node that bootstrapped alone thus joins
+//! the cluster once the file is updated or the SRV record lists a peer.
+//!
+//! ## Testing
+//!
+//! DNS providers are generic over a [`Resolver`], so tests can plug a fake one
+//! instead of depending on the system DNS configuration.
+
+use chitchat::ChitchatHandle;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::error::{GossipError, Result};
+
+/// Interval between two discovery attempts
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Source of seed nodes
+///
+/// Implementations are queried at startup, possibly several times, and must
+/// return the current list of seeds as `host:port` strings.
+pub trait SeedProvider: Send + Sync {
+    /// Discover the current seed nodes
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::SeedDiscoveryError` if the source cannot be read.
+    fn seeds(&self) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Host names Chitchat should keep re-resolving after startup
+    ///
+    /// They are added to the seed list even if discovery timed out, but do not
+    /// count as a discovered peer.
+    fn dns_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Last modification time of the source, if it has one
+    ///
+    /// Once started, the seeds are only queried again when it changed.
+    /// Sources without one are queried on every refresh.
+    fn modified(&self) -> impl Future<Output = Option<SystemTime>> + Send {
+        async { None }
+    }
+}
+
+/// DNS resolver used by the DNS seed providers
+pub trait Resolver: Send + Sync {
+    /// Resolve a host name to socket addresses (A/AAAA records)
+    fn lookup_host(
+        &self,
+        host: &str,
+        port: u16,
+    ) -> impl Future<Output = io::Result<Vec<SocketAddr>>> + Send;
+
+    /// Resolve an SRV record to `(target, port)` pairs
+    fn lookup_srv(&self, name: &str)
+        -> impl Future<Output = io::Result<Vec<(String, u16)>>> + Send;
+}
+
+/// Resolver backed by the system DNS configuration
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    async fn lookup_host(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+
+    async fn lookup_srv(&self, name: &str) -> io::Result<Vec<(String, u16)>> {
+        let resolver = hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(io::Error::other)?;
+        let lookup = resolver.srv_lookup(name).await.map_err(io::Error::other)?;
+
+        Ok(lookup
+            .iter()
+            .map(|srv| {
+                let target = srv.target().to_utf8();
+                (target.trim_end_matches('.').to_string(), srv.port())
+            })
+            .collect())
+    }
+}
+
+/// Fixed list of seeds
+#[derive(Debug, Clone, Default)]
+pub struct StaticSeeds(pub Vec<String>);
+
+impl SeedProvider for StaticSeeds {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Comma-separated seeds read from an environment variable
+#[derive(Debug, Clone)]
+pub struct EnvSeeds {
+    var: String,
+}
+
+impl EnvSeeds {
+    /// Read seeds from the given variable
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl SeedProvider for EnvSeeds {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        Ok(std::env::var(&self.var)
+            .map(|seeds| parse_list(&seeds))
+            .unwrap_or_default())
+    }
+}
+
+/// Seeds read from a file, one `host:port` per line
+///
+/// Blank lines and lines starting with `#` are ignored. The file is re-read on
+/// every discovery attempt, then whenever its modification time changes; a
+/// missing file is treated as empty so that it can be written after the node
+/// starts.
+#[derive(Debug, Clone)]
+pub struct FileSeeds {
+    path: PathBuf,
+}
+
+impl FileSeeds {
+    /// Read seeds from the given file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SeedProvider for FileSeeds {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => Ok(content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(GossipError::seed_discovery_error(
+                format!("file {}", self.path.display()),
+                e,
+            )),
+        }
+    }
+
+    async fn modified(&self) -> Option<SystemTime> {
+        let metadata = tokio::fs::metadata(&self.path).await.ok()?;
+        metadata.modified().ok()
+    }
+}
+
+/// Seeds resolved from the A/AAAA records of a host name
+///
+/// Typically a Kubernetes headless service or a docker compose service name,
+/// which resolve to the address of every replica.
+#[derive(Debug, Clone)]
+pub struct DnsSeeds<R = SystemResolver> {
+    host: String,
+    port: u16,
+    resolver: R,
+}
+
+impl DnsSeeds {
+    /// Resolve `host` with the system resolver, using `port` for every address
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self::with_resolver(host, port, SystemResolver)
+    }
+}
+
+impl<R: Resolver> DnsSeeds<R> {
+    /// Resolve `host` with a custom resolver
+    pub fn with_resolver(host: impl Into<String>, port: u16, resolver: R) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            resolver,
+        }
+    }
+}
+
+impl<R: Resolver> SeedProvider for DnsSeeds<R> {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        let addrs = self
+            .resolver
+            .lookup_host(&self.host, self.port)
+            .await
+            .map_err(|e| GossipError::seed_discovery_error(format!("dns {}", self.host), e))?;
+
+        Ok(addrs.iter().map(SocketAddr::to_string).collect())
+    }
+
+    fn dns_names(&self) -> Vec<String> {
+        vec![format!("{}:{}", self.host, self.port)]
+    }
+}
+
+/// Seeds resolved from an SRV record
+///
+/// Each record target is returned as a host name with the record port.
+#[derive(Debug, Clone)]
+pub struct SrvSeeds<R = SystemResolver> {
+    name: String,
+    resolver: R,
+}
+
+impl SrvSeeds {
+    /// Resolve the SRV record `name` with the system resolver
+    pub fn new(name: impl Into<String>) -> Self {
+        Self::with_resolver(name, SystemResolver)
+    }
+}
+
+impl<R: Resolver> SrvSeeds<R> {
+    /// Resolve the SRV record `name` with a custom resolver
+    pub fn with_resolver(name: impl Into<String>, resolver: R) -> Self {
+        Self {
+            name: name.into(),
+            resolver,
+        }
+    }
+}
+
+impl<R: Resolver> SeedProvider for SrvSeeds<R> {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        let records = self
+            .resolver
+            .lookup_srv(&self.name)
+            .await
+            .map_err(|e| GossipError::seed_discovery_error(format!("srv {}", self.name), e))?;
+
+        Ok(records
+            .into_iter()
+            .map(|(target, port)| format!("{}:{}", target, port))
+            .collect())
+    }
+}
+
+/// Built-in seed provider selected by configuration
+///
+/// Parsed from `env:<VAR>`, `file:<path>`, `dns:<host>:<port>` or `srv:<name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SeedSource {
+    /// Comma-separated seeds in an environment variable
+    Env(String),
+    /// Seeds listed in a file
+    File(PathBuf),
+    /// A/AAAA lookup of a host name
+    Dns { host: String, port: u16 },
+    /// SRV lookup
+    Srv(String),
+}
+
+impl SeedProvider for SeedSource {
+    async fn seeds(&self) -> Result<Vec<String>> {
+        match self {
+            Self::Env(var) => EnvSeeds::new(var.as_str()).seeds().await,
+            Self::File(path) => FileSeeds::new(path.as_path()).seeds().await,
+            Self::Dns { host, port } => DnsSeeds::new(host.as_str(), *port).seeds().await,
+            Self::Srv(name) => SrvSeeds::new(name.as_str()).seeds().await,
+        }
+    }
+
+    fn dns_names(&self) -> Vec<String> {
+        match self {
+            Self::Dns { host, port } => DnsSeeds::new(host.as_str(), *port).dns_names(),
+            _ => Vec::new(),
+        }
+    }
+
+    async fn modified(&self) -> Option<SystemTime> {
+        match self {
+            Self::File(path) => FileSeeds::new(path.as_path()).modified().await,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SeedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Env(var) => write!(f, "env:{}", var),
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Dns { host, port } => write!(f, "dns:{}:{}", host, port),
+            Self::Srv(name) => write!(f, "srv:{}", name),
+        }
+    }
+}
+
+impl FromStr for SeedSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected '<kind>:<value>', got '{}'", s))?;
+
+        if value.is_empty() {
+            return Err(format!("missing value in seed source '{}'", s));
+        }
+
+        match kind {
+            "env" => Ok(Self::Env(value.to_string())),
+            "file" => Ok(Self::File(PathBuf::from(value))),
+            "dns" => {
+                let (host, port) = value
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("expected 'dns:<host>:<port>', got '{}'", s))?;
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port in seed source '{}'", s))?;
+                Ok(Self::Dns {
+                    host: host.to_string(),
+                    port,
+                })
+            }
+            "srv" => Ok(Self::Srv(value.to_string())),
+            other => Err(format!(
+                "unknown seed source '{}' (expected env, file, dns or srv)",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for SeedSource {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SeedSource> for String {
+    fn from(source: SeedSource) -> Self {
+        source.to_string()
+    }
+}
+
+/// Query `provider` until it returns seeds other than this node or `timeout` elapses
+///
+/// Static seeds and the provider's DNS names are always kept. If discovery
+/// found nothing else, the node bootstraps a new cluster.
+pub(crate) async fn discover_seeds<P: SeedProvider>(
+    provider: &P,
+    static_seeds: &[String],
+    listen_addr: SocketAddr,
+    timeout: Duration,
+) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let static_seeds = merge(static_seeds, provider.dns_names());
+
+    loop {
+        match provider.seeds().await {
+            Ok(discovered) => {
+                let discovered: Vec<String> = discovered
+                    .into_iter()
+                    .filter(|seed| seed.parse::<SocketAddr>().ok() != Some(listen_addr))
+                    .collect();
+
+                if !discovered.is_empty() {
+                    info!(seeds = ?discovered, "Discovered seed nodes");
+                    return merge(&static_seeds, discovered);
+                }
+
+                debug!("No seed node discovered yet");
+            }
+            Err(e) => warn!(error = %e, "Seed discovery failed"),
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            warn!(
+                timeout = ?timeout,
+                "No seed node discovered, bootstrapping a new cluster"
+            );
+            return static_seeds;
+        }
+
+        tokio::time::sleep(RETRY_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// Seeds of a provider, tracked after startup
+pub(crate) struct SeedWatcher<P> {
+    provider: P,
+    listen_addr: SocketAddr,
+    /// Modification time of the source when `seeds` was read
+    modified: Option<SystemTime>,
+    seeds: Vec<String>,
+}
+
+impl<P: SeedProvider> SeedWatcher<P> {
+    pub(crate) fn new(provider: P, listen_addr: SocketAddr) -> Self {
+        Self {
+            provider,
+            listen_addr,
+            modified: None,
+            seeds: Vec::new(),
+        }
+    }
+
+    /// Current seeds, other than this node
+    pub(crate) fn seeds(&self) -> &[String] {
+        &self.seeds
+    }
+
+    /// Query the provider again, returning `true` if the seeds changed
+    ///
+    /// A source whose modification time did not change is not read again. On
+    /// error, the previous seeds are kept.
+    pub(crate) async fn poll(&mut self) -> bool {
+        let modified = self.provider.modified().await;
+        if modified.is_some() && modified == self.modified {
+            return false;
+        }
+
+        let seeds: Vec<String> = match self.provider.seeds().await {
+            Ok(seeds) => seeds
+                .into_iter()
+                .filter(|seed| seed.parse::<SocketAddr>().ok() != Some(self.listen_addr))
+                .collect(),
+            Err(e) => {
+                warn!(error = %e, "Seed refresh failed");
+                return false;
+            }
+        };
+
+        self.modified = modified;
+        if seeds == self.seeds {
+            return false;
+        }
+        self.seeds = seeds;
+        true
+    }
+}
+
+/// Spawn the task feeding the provider's seeds to Chitchat
+///
+/// Chitchat's seed list cannot change once started, so every `interval` the
+/// node gossips directly with the seeds that are not live members.
+pub(crate) fn spawn_refresh_task<P: SeedProvider + 'static>(
+    handle: Arc<ChitchatHandle>,
+    provider: P,
+    listen_addr: SocketAddr,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut watcher = SeedWatcher::new(provider, listen_addr);
+        let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval);
+
+        loop {
+            ticker.tick().await;
+
+            if watcher.poll().await {
+                info!(seeds = ?watcher.seeds(), "Seed nodes changed");
+            }
+
+            let live: HashSet<SocketAddr> = {
+                let chitchat = handle.chitchat();
+                let chitchat_guard = chitchat.lock().await;
+                chitchat_guard
+                    .live_nodes()
+                    .map(|chitchat_id| chitchat_id.gossip_advertise_addr)
+                    .collect()
+            };
+
+            for seed in watcher.seeds() {
+                let addrs = match tokio::net::lookup_host(seed.as_str()).await {
+                    Ok(addrs) => addrs,
+                    Err(e) => {
+                        debug!(seed = %seed, error = %e, "Failed to resolve seed");
+                        continue;
+                    }
+                };
+                for addr in addrs.filter(|addr| !live.contains(addr)) {
+                    debug!(seed = %addr, "Gossiping with seed");
+                    let _ = handle.gossip(addr);
+                }
+            }
+        }
+    })
+}
+
+/// Append discovered seeds to the static ones, without duplicates
+fn merge(static_seeds: &[String], discovered: Vec<String>) -> Vec<String> {
+    let mut seeds = static_seeds.to_vec();
+    for seed in discovered {
+        if !seeds.contains(&seed) {
+            seeds.push(seed);
+        }
+    }
+    seeds
+}
+
+/// Split a comma-separated list, dropping blank entries
+pub(crate) fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|seed| !seed.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Resolver answering from fixed tables
+    #[derive(Default)]
+    struct FakeResolver {
+        hosts: HashMap<String, Vec<&'static str>>,
+        srv: HashMap<String, Vec<(&'static str, u16)>>,
+    }
+
+    impl Resolver for FakeResolver {
+        async fn lookup_host(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            let ips = self
+                .hosts
+                .get(host)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown host"))?;
+            Ok(ips
+                .iter()
+                .map(|ip| SocketAddr::new(ip.parse().unwrap(), port))
+                .collect())
+        }
+
+        async fn lookup_srv(&self, name: &str) -> io::Result<Vec<(String, u16)>> {
+            Ok(self
+                .srv
+                .get(name)
+                .map(|records| {
+                    records
+                        .iter()
+                        .map(|(target, port)| (target.to_string(), *port))
+                        .collect()
+                })
+                .unwrap_or_default())
+        }
+    }
+
+    /// Resolver whose SRV answer changes on every lookup, the last one sticking
+    struct ChangingSrvResolver {
+        lookups: AtomicUsize,
+        answers: Vec<Vec<(&'static str, u16)>>,
+    }
+
+    impl Resolver for ChangingSrvResolver {
+        async fn lookup_host(&self, _host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+            Err(io::Error::new(io::ErrorKind::NotFound, "unknown host"))
+        }
+
+        async fn lookup_srv(&self, _name: &str) -> io::Result<Vec<(String, u16)>> {
+            let lookup = self.lookups.fetch_add(1, Ordering::SeqCst);
+            let answer = &self.answers[lookup.min(self.answers.len() - 1)];
+            Ok(answer
+                .iter()
+                .map(|(target, port)| (target.to_string(), *port))
+                .collect())
+        }
+    }
+
+    /// Provider returning nothing for the first calls
+    struct SlowProvider {
+        calls: AtomicUsize,
+        ready_after: usize,
+    }
+
+    impl SeedProvider for SlowProvider {
+        async fn seeds(&self) -> Result<Vec<String>> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.ready_after {
+                Ok(vec![])
+            } else {
+                Ok(vec!["10.0.0.2:7000".to_string()])
+            }
+        }
+    }
+
+    fn listen_addr() -> SocketAddr {
+        "10.0.0.1:7000".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dns_seeds() {
+        let resolver = FakeResolver {
+            hosts: HashMap::from([("zuk-sink".to_string(), vec!["10.0.0.1", "10.0.0.2"])]),
+            ..Default::default()
+        };
+
+        let seeds = DnsSeeds::with_resolver("zuk-sink", 7000, resolver)
+            .seeds()
+            .await
+            .unwrap();
+
+        assert_eq!(seeds, vec!["10.0.0.1:7000", "10.0.0.2:7000"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discovery_keeps_dns_name_when_alone() {
+        // A headless service only resolving to this node
+        let resolver = FakeResolver {
+            hosts: HashMap::from([("zuk-sink".to_string(), vec!["10.0.0.1"])]),
+            ..Default::default()
+        };
+        let provider = DnsSeeds::with_resolver("zuk-sink", 7000, resolver);
+
+        let seeds = discover_seeds(&provider, &[], listen_addr(), Duration::from_secs(5)).await;
+
+        assert_eq!(seeds, vec!["zuk-sink:7000"]);
+    }
+
+    #[tokio::test]
+    async fn test_dns_seeds_lookup_failure() {
+        let result = DnsSeeds::with_resolver("unknown", 7000, FakeResolver::default())
+            .seeds()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(GossipError::SeedDiscoveryError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_srv_seeds() {
+        let resolver = FakeResolver {
+            srv: HashMap::from([(
+                "_gossip._udp.zuk-sink".to_string(),
+                vec![("sink-0.zuk-sink", 7000), ("sink-1.zuk-sink", 7001)],
+            )]),
+            ..Default::default()
+        };
+
+        let seeds = SrvSeeds::with_resolver("_gossip._udp.zuk-sink", resolver)
+            .seeds()
+            .await
+            .unwrap();
+
+        assert_eq!(seeds, vec!["sink-0.zuk-sink:7000", "sink-1.zuk-sink:7001"]);
+    }
+
+    #[tokio::test]
+    async fn test_file_seeds() {
+        let path = std::env::temp_dir().join(format!("yellowpage-seeds-{}", std::process::id()));
+        std::fs::write(&path, "# seeds\nreceiver-1:7000\n\n  receiver-2:7000  \n").unwrap();
+
+        let seeds = FileSeeds::new(&path).seeds().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(seeds, vec!["receiver-1:7000", "receiver-2:7000"]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_empty() {
+        let seeds = FileSeeds::new("/nonexistent/yellowpage-seeds")
+            .seeds()
+            .await
+            .unwrap();
+
+        assert!(seeds.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watcher_rereads_changed_file() {
+        let path =
+            std::env::temp_dir().join(format!("yellowpage-seeds-watch-{}", std::process::id()));
+        std::fs::write(&path, "receiver-2:7000\n").unwrap();
+        let mut watcher = SeedWatcher::new(FileSeeds::new(&path), listen_addr());
+
+        assert!(watcher.poll().await);
+        assert_eq!(watcher.seeds(), ["receiver-2:7000"]);
+
+        // Same modification time: the file is not read again
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "receiver-3:7000\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert!(!watcher.poll().await);
+        assert_eq!(watcher.seeds(), ["receiver-2:7000"]);
+
+        // A newer modification time picks up the new content
+        file.set_modified(modified + Duration::from_secs(1))
+            .unwrap();
+        assert!(watcher.poll().await);
+        assert_eq!(watcher.seeds(), ["receiver-3:7000"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watcher_requeries_srv() {
+        let resolver = ChangingSrvResolver {
+            lookups: AtomicUsize::new(0),
+            answers: vec![
+                vec![("sink-0.zuk-sink", 7000)],
+                vec![("sink-0.zuk-sink", 7000)],
+                vec![("sink-0.zuk-sink", 7000), ("sink-1.zuk-sink", 7000)],
+            ],
+        };
+        let provider = SrvSeeds::with_resolver("_gossip._udp.zuk-sink", resolver);
+        let mut watcher = SeedWatcher::new(provider, listen_addr());
+
+        assert!(watcher.poll().await);
+        assert!(!watcher.poll().await);
+        assert!(watcher.poll().await);
+
+        assert_eq!(
+            watcher.seeds(),
+            ["sink-0.zuk-sink:7000", "sink-1.zuk-sink:7000"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discovery_retries_until_found() {
+        let provider = SlowProvider {
+            calls: AtomicUsize::new(0),
+            ready_after: 3,
+        };
+
+        let seeds = discover_seeds(
+            &provider,
+            &["static:7000".to_string()],
+            listen_addr(),
+            Duration::from_secs(10),
+        )
+        .await;
+
+        assert_eq!(seeds, vec!["static:7000", "10.0.0.2:7000"]);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discovery_ignores_self_and_times_out() {
+        let provider = StaticSeeds(vec!["10.0.0.1:7000".to_string()]);
+        let start = Instant::now();
+
+        let seeds = discover_seeds(&provider, &[], listen_addr(), Duration::from_secs(5)).await;
+
+        assert!(seeds.is_empty());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_seed_source_parse() {
+        assert_eq!(
+            "env:ZUK_SEEDS".parse::<SeedSource>(),
+            Ok(SeedSource::Env("ZUK_SEEDS".to_string()))
+        );
+        assert_eq!(
+            "file:/etc/zuk/seeds".parse::<SeedSource>(),
+            Ok(SeedSource::File(PathBuf::from("/etc/zuk/seeds")))
+        );
+        assert_eq!(
+            "dns:zuk-sink.default.svc.cluster.local:7000".parse::<SeedSource>(),
+            Ok(SeedSource::Dns {
+                host: "zuk-sink.default.svc.cluster.local".to_string(),
+                port: 7000
+            })
+        );
+        assert_eq!(
+            "srv:_gossip._udp.zuk-sink".parse::<SeedSource>(),
+            Ok(SeedSource::Srv("_gossip._udp.zuk-sink".to_string()))
+        );
+
+        assert!("dns:zuk-sink".parse::<SeedSource>().is_err());
+        assert!("consul:zuk-sink".parse::<SeedSource>().is_err());
+        assert!("file:".parse::<SeedSource>().is_err());
+    }
+
+    #[test]
+    fn test_seed_source_display_roundtrip() {
+        let source = SeedSource::Dns {
+            host: "zuk-sink".to_string(),
+            port: 7000,
+        };
+
+        assert_eq!(source.to_string().parse::<SeedSource>(), Ok(source));
+    }
+}
@@ -0,0 +1,221 @@
+//! Weighted shard assignment
+//!
+//! Keys are grouped into [`SHARD_COUNT`] virtual shards, the unit of
+//! ownership that the rebalancer can move between nodes.
+//!
+//! Every node advertises a capacity weight under the reserved `zuk.capacity`
+//! key. A shard is owned by the node with the highest weighted rendezvous score
+//! (Highest Random Weight hashing):
+//!
+//! ```text
+//! score(node, shard) = -weight(node) / ln(hash(node, shard))
+//! ```
+//!
+//! with `hash` mapped to `(0, 1)`. Each node then owns a share of the shards
+//! proportional to its weight, and adding or removing a node only moves the
+//! shards it gains or loses: the other assignments are left untouched.
+//!
+//! The hash is FNV-1a with a SplitMix64 finalizer, stable across Rust versions
+//! and platforms (unlike `DefaultHasher`), so every node sharing a view computes
+//! the same owner.
+
+use crate::node::NodeId;
+
+/// Weight of nodes that do not advertise a capacity
+pub const DEFAULT_CAPACITY: u32 = 1;
+
+/// Number of virtual shards keys are grouped into
+pub const SHARD_COUNT: u16 = 1024;
+
+/// Identifier of a virtual shard, between 0 and `SHARD_COUNT - 1`
+pub type ShardId = u16;
+
+/// Virtual shard a key belongs to
+///
+/// Stable across nodes, versions and platforms.
+pub fn shard_of(key: &str) -> ShardId {
+    (mix(hash(key.as_bytes())) % u64::from(SHARD_COUNT)) as ShardId
+}
+
+/// Default capacity of this node: its number of available CPUs
+pub(crate) fn local_capacity() -> u32 {
+    std::thread::available_parallelism()
+        .map(|cpus| cpus.get() as u32)
+        .unwrap_or(DEFAULT_CAPACITY)
+}
+
+/// Pick the natural owner of a shard among weighted members
+///
+/// Members with a zero weight never own shards. Returns `None` if no member
+/// has a positive weight.
+pub(crate) fn shard_owner<'a>(
+    shard: ShardId,
+    members: impl IntoIterator<Item = (&'a NodeId, u32)>,
+) -> Option<&'a NodeId> {
+    rendezvous(mix(u64::from(shard)), members)
+}
+
+/// Member with the highest weighted score for `key_hash`
+fn rendezvous<'a>(
+    key_hash: u64,
+    members: impl IntoIterator<Item = (&'a NodeId, u32)>,
+) -> Option<&'a NodeId> {
+    members
+        .into_iter()
+        .filter(|(_, weight)| *weight > 0)
+        .map(|(node_id, weight)| (score(key_hash, node_id, weight), node_id))
+        .max_by(|(a, a_id), (b, b_id)| a.total_cmp(b).then_with(|| b_id.cmp(a_id)))
+        .map(|(_, node_id)| node_id)
+}
+
+/// Weighted rendezvous score of a node for a key
+fn score(key_hash: u64, node_id: &NodeId, weight: u32) -> f64 {
+    let combined = mix(key_hash ^ mix(hash(node_id.as_str().as_bytes())));
+
+    // Uniform value in (0, 1): never 0 nor 1, so ln() is finite and negative
+    let uniform = ((combined >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+
+    -f64::from(weight) / uniform.ln()
+}
+
+/// Stable 64-bit hash (FNV-1a)
+fn hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// SplitMix64 finalizer, spreading FNV's weak low bits
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn members(weights: &[(&str, u32)]) -> Vec<(NodeId, u32)> {
+        weights
+            .iter()
+            .map(|(id, weight)| (NodeId::new(*id), *weight))
+            .collect()
+    }
+
+    fn owner<'a>(key: &str, members: &'a [(NodeId, u32)]) -> Option<&'a NodeId> {
+        rendezvous(
+            hash(key.as_bytes()),
+            members.iter().map(|(id, weight)| (id, *weight)),
+        )
+    }
+
+    fn keys() -> impl Iterator<Item = String> {
+        (0..20_000).map(|i| format!("segment-{}.zuk", i))
+    }
+
+    fn distribution(members: &[(NodeId, u32)]) -> HashMap<NodeId, usize> {
+        let mut counts = HashMap::new();
+        for key in keys() {
+            *counts
+                .entry(owner(&key, members).unwrap().clone())
+                .or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        // FNV-1a reference values
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_shard_of_is_stable_and_in_range() {
+        assert_eq!(shard_of("segment-1.zuk"), shard_of("segment-1.zuk"));
+        assert!(keys().all(|key| shard_of(&key) < SHARD_COUNT));
+
+        // Keys spread over most shards
+        let used: std::collections::HashSet<ShardId> = keys().map(|key| shard_of(&key)).collect();
+        assert!(used.len() > 1000);
+    }
+
+    #[test]
+    fn test_shard_owner_is_deterministic() {
+        let members = members(&[("node-1", 1), ("node-2", 2)]);
+        let pairs = || members.iter().map(|(id, weight)| (id, *weight));
+
+        for shard in 0..SHARD_COUNT {
+            assert_eq!(shard_owner(shard, pairs()), shard_owner(shard, pairs()));
+            assert!(shard_owner(shard, pairs()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_owner_ignores_member_order() {
+        let a = members(&[("node-1", 1), ("node-2", 4), ("node-3", 2)]);
+        let b = members(&[("node-3", 2), ("node-1", 1), ("node-2", 4)]);
+
+        for key in keys().take(1000) {
+            assert_eq!(owner(&key, &a), owner(&key, &b));
+        }
+    }
+
+    #[test]
+    fn test_shares_follow_weights() {
+        let members = members(&[("small", 2), ("medium", 4), ("large", 8)]);
+        let counts = distribution(&members);
+        let total = keys().count() as f64;
+
+        for (node_id, weight) in &members {
+            let share = counts[node_id] as f64 / total;
+            let expected = f64::from(*weight) / 14.0;
+            assert!(
+                (share - expected).abs() < 0.02,
+                "{} owns {:.3}, expected {:.3}",
+                node_id,
+                share,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_equal_weights_share_evenly() {
+        let members = members(&[("node-1", 1), ("node-2", 1), ("node-3", 1), ("node-4", 1)]);
+        let counts = distribution(&members);
+
+        for count in counts.values() {
+            assert!((4_500..5_500).contains(count), "unbalanced: {:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_removing_a_node_only_moves_its_keys() {
+        let before = members(&[("node-1", 1), ("node-2", 2), ("node-3", 3)]);
+        let after = members(&[("node-1", 1), ("node-3", 3)]);
+
+        for key in keys().take(2000) {
+            let previous = owner(&key, &before).unwrap();
+            if previous.as_str() != "node-2" {
+                assert_eq!(owner(&key, &after), Some(previous));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_weight_owns_nothing() {
+        let members = members(&[("node-1", 0), ("node-2", 1)]);
+
+        for key in keys().take(1000) {
+            assert_eq!(owner(&key, &members).unwrap().as_str(), "node-2");
+        }
+        assert_eq!(owner("key", &self::members(&[("node-1", 0)])), None);
+        assert_eq!(owner("key", &[]), None);
+    }
+}
@@ -0,0 +1,691 @@
+//! Gossip transports
+//!
+//! Chitchat talks to its peers through a [`Transport`]. Yellowpage uses the
+//! plain [`UdpTransport`] by default, or [`EncryptedUdpTransport`] when gossip
+//! keys are configured.
+//!
+//! ## In-Memory Transport
+//!
+//! [`ChannelTransport`] delivers messages through in-process channels instead
+//! of UDP sockets. Combined with a paused Tokio clock it makes cluster tests
+//! fast and deterministic: no ports to allocate, no real sleeps, and packet
+//! loss, latency and partitions under the test's control. Random loss is drawn
+//! from a seeded generator so a failing run can be replayed.
+//!
+//! Messages cross the in-memory network serialized, like UDP packets, and are
+//! sealed with the gossip keys of a node when opened through
+//! [`ChannelTransport::with_gossip_keys`]. Packets larger than a UDP datagram
+//! are dropped, as a socket would refuse to send them.
+//!
+//! ## Fragments
+//!
+//! Chitchat fills messages up to the size of a UDP datagram, leaving no room
+//! for the header and tag of a sealed packet. Sealed messages that do not fit
+//! are split into fragments, sealed one by one and reassembled by the
+//! receiving socket. Losing a fragment loses the message, like losing a
+//! packet: Chitchat sends the state again in a later round.
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chitchat::transport::{Socket, Transport, UdpTransport};
+use chitchat::{ChitchatMessage, Deserializable, Serializable};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::YellowpageConfig;
+use crate::encryption::{Keyring, Opened, FRAGMENT_OVERHEAD, OVERHEAD};
+use crate::error::Result;
+
+/// Largest payload of a UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// Most fragments a sealed message is split into
+///
+/// Chitchat messages are at most a datagram long, so they take two.
+const MAX_FRAGMENTS: usize = 4;
+
+/// Incomplete messages kept while waiting for their other fragments
+const MAX_PENDING_MESSAGES: usize = 32;
+
+/// Build the transport selected by the configuration
+pub(crate) fn from_config(config: &YellowpageConfig) -> Result<Box<dyn Transport>> {
+    if config.gossip_keys.is_empty() {
+        return Ok(Box::new(UdpTransport));
+    }
+
+    let keyring = Keyring::new(&config.cluster_id, &config.gossip_keys)?;
+    info!(keys = config.gossip_keys.len(), "Gossip encryption enabled");
+    Ok(Box::new(EncryptedUdpTransport::new(keyring)))
+}
+
+/// Turns messages into packets and back, sealing them if a keyring is given
+///
+/// Sealed messages too large for one datagram are split into fragments,
+/// reassembled on the other side before being handed to Chitchat.
+struct Codec {
+    keyring: Option<Arc<Keyring>>,
+    /// ID of the next fragmented message sent
+    next_message_id: u32,
+    /// Fragments received of incomplete messages
+    reassembly: Reassembly,
+}
+
+impl Codec {
+    fn new(keyring: Option<Arc<Keyring>>) -> Self {
+        Self {
+            keyring,
+            next_message_id: OsRng.next_u32(),
+            reassembly: Reassembly::default(),
+        }
+    }
+
+    /// Serialize a message into the packets to send
+    fn encode(&mut self, msg: &ChitchatMessage) -> anyhow::Result<Vec<Vec<u8>>> {
+        let plaintext = msg.serialize_to_vec();
+        let Some(keyring) = &self.keyring else {
+            return Ok(vec![plaintext]);
+        };
+
+        if plaintext.len() + OVERHEAD <= MAX_DATAGRAM_SIZE {
+            return Ok(vec![keyring.seal(&plaintext)]);
+        }
+
+        let chunks: Vec<&[u8]> = plaintext
+            .chunks(MAX_DATAGRAM_SIZE - FRAGMENT_OVERHEAD)
+            .collect();
+        if chunks.len() > MAX_FRAGMENTS {
+            anyhow::bail!(
+                "Gossip message of {} bytes too large to seal, at most {} fragments are sent",
+                plaintext.len(),
+                MAX_FRAGMENTS
+            );
+        }
+
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        let count = chunks.len() as u8;
+        Ok(chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| keyring.seal_fragment(message_id, index as u8, count, chunk))
+            .collect())
+    }
+
+    /// Open and deserialize a packet
+    ///
+    /// Returns `None` if the packet is rejected, or is a fragment of a message
+    /// not complete yet.
+    fn decode(&mut self, from: SocketAddr, packet: &[u8]) -> Option<ChitchatMessage> {
+        let plaintext = match &self.keyring {
+            Some(keyring) => match keyring.open(packet) {
+                Ok(Opened::Message(plaintext)) => Cow::Owned(plaintext),
+                Ok(Opened::Fragment {
+                    message_id,
+                    index,
+                    count,
+                    data,
+                }) => Cow::Owned(
+                    self.reassembly
+                        .insert(from, message_id, index, count, data)?,
+                ),
+                Err(reason) => {
+                    warn!(from = %from, reason = %reason, "Rejected gossip packet");
+                    return None;
+                }
+            },
+            None => Cow::Borrowed(packet),
+        };
+
+        match ChitchatMessage::deserialize(&mut &plaintext[..]) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                debug!(from = %from, error = %e, "Invalid gossip message");
+                None
+            }
+        }
+    }
+}
+
+/// Fragments received of messages not complete yet, oldest first
+///
+/// Fragments lost on the way leave their message incomplete: it is evicted
+/// once [`MAX_PENDING_MESSAGES`] newer ones are pending, and Chitchat sends
+/// the state again in a later round.
+#[derive(Default)]
+struct Reassembly {
+    pending: VecDeque<PendingMessage>,
+}
+
+struct PendingMessage {
+    from: SocketAddr,
+    message_id: u32,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+impl Reassembly {
+    /// Add a fragment, returning its message once every fragment arrived
+    fn insert(
+        &mut self,
+        from: SocketAddr,
+        message_id: u32,
+        index: u8,
+        count: u8,
+        data: Vec<u8>,
+    ) -> Option<Vec<u8>> {
+        let (index, count) = (index as usize, count as usize);
+        if !(2..=MAX_FRAGMENTS).contains(&count) || index >= count {
+            warn!(from = %from, index, count, "Rejected gossip fragment");
+            return None;
+        }
+
+        let position = match self
+            .pending
+            .iter()
+            .position(|pending| pending.from == from && pending.message_id == message_id)
+        {
+            Some(position) if self.pending[position].fragments.len() == count => position,
+            Some(_) => {
+                warn!(from = %from, message_id, count, "Gossip fragment count mismatch");
+                return None;
+            }
+            None => {
+                if self.pending.len() == MAX_PENDING_MESSAGES {
+                    if let Some(evicted) = self.pending.pop_front() {
+                        debug!(from = %evicted.from, message_id = evicted.message_id, "Dropped incomplete gossip message");
+                    }
+                }
+                self.pending.push_back(PendingMessage {
+                    from,
+                    message_id,
+                    fragments: vec![None; count],
+                });
+                self.pending.len() - 1
+            }
+        };
+
+        self.pending[position].fragments[index] = Some(data);
+        if self.pending[position].fragments.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let complete = self.pending.remove(position)?;
+        Some(complete.fragments.into_iter().flatten().flatten().collect())
+    }
+}
+
+/// UDP transport sealing every packet with the cluster keyring
+///
+/// Packets that fail authentication are dropped and logged; they never reach
+/// Chitchat. Chitchat fills messages up to the size of a UDP datagram, so a
+/// message within [`OVERHEAD`] bytes of it is sent as several fragments.
+pub(crate) struct EncryptedUdpTransport {
+    keyring: Arc<Keyring>,
+}
+
+impl EncryptedUdpTransport {
+    pub(crate) fn new(keyring: Keyring) -> Self {
+        Self {
+            keyring: Arc::new(keyring),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for EncryptedUdpTransport {
+    async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
+        let socket = UdpSocket::bind(listen_addr)
+            .await
+            .with_context(|| format!("Failed to bind to {}", listen_addr))?;
+
+        Ok(Box::new(EncryptedUdpSocket {
+            socket,
+            codec: Codec::new(Some(self.keyring.clone())),
+            buf: vec![0; MAX_DATAGRAM_SIZE],
+        }))
+    }
+}
+
+struct EncryptedUdpSocket {
+    socket: UdpSocket,
+    codec: Codec,
+    buf: Vec<u8>,
+}
+
+#[async_trait]
+impl Socket for EncryptedUdpSocket {
+    async fn send(&mut self, to: SocketAddr, msg: ChitchatMessage) -> anyhow::Result<()> {
+        for packet in self.codec.encode(&msg)? {
+            // A single undeliverable packet must not stop gossip
+            if let Err(e) = self.socket.send_to(&packet, to).await {
+                warn!(to = %to, size = packet.len(), error = %e, "Failed to send gossip packet");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
+        loop {
+            let (len, from) = self
+                .socket
+                .recv_from(&mut self.buf)
+                .await
+                .context("Failed to receive gossip packet")?;
+
+            if let Some(msg) = self.codec.decode(from, &self.buf[..len]) {
+                return Ok((from, msg));
+            }
+        }
+    }
+}
+
+/// Packet in flight on the in-memory network
+type Envelope = (SocketAddr, Vec<u8>);
+
+/// In-memory transport simulating an unreliable network
+///
+/// Clones share the same network: open every node of a test cluster on the
+/// same `ChannelTransport`, then drive faults from the test.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use zuklink_yellowpage::ChannelTransport;
+/// # let (a, b) = ("10.0.0.1:7000".parse().unwrap(), "10.0.0.2:7000".parse().unwrap());
+/// let network = ChannelTransport::with_seed(42);
+/// network.set_loss_rate(0.2);
+/// network.set_latency(Duration::from_millis(50));
+/// network.partition(&[a], &[b]);
+/// // ...
+/// network.heal();
+/// ```
+#[derive(Clone, Default)]
+pub struct ChannelTransport {
+    network: Arc<Mutex<Network>>,
+    /// Keys sealing the packets of sockets opened from this handle
+    keyring: Option<Arc<Keyring>>,
+}
+
+#[derive(Default)]
+struct Network {
+    /// Inbox of every open socket
+    inboxes: HashMap<SocketAddr, mpsc::UnboundedSender<Envelope>>,
+    /// Directed links on which every message is dropped
+    cut_links: HashSet<(SocketAddr, SocketAddr)>,
+    /// Probability of dropping a message, between 0 and 1
+    loss_rate: f64,
+    /// Delay before a message reaches its destination
+    latency: Duration,
+    /// Generator deciding which messages are lost
+    rng: SplitMix64,
+    /// Messages dropped so far (loss, partition or unknown destination)
+    dropped: u64,
+}
+
+impl ChannelTransport {
+    /// Create a reliable network
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a network whose random packet loss is drawn from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        let transport = Self::new();
+        transport.network().rng = SplitMix64(seed);
+        transport
+    }
+
+    /// Handle on the same network whose sockets seal packets with the gossip
+    /// keys of `config`, as [`Yellowpage::with_config`] does over UDP
+    ///
+    /// Without gossip keys, packets are sent in plaintext.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GossipError::ConfigError` if the keys are invalid.
+    ///
+    /// [`Yellowpage::with_config`]: crate::Yellowpage::with_config
+    pub fn with_gossip_keys(&self, config: &YellowpageConfig) -> Result<Self> {
+        let keyring = if config.gossip_keys.is_empty() {
+            None
+        } else {
+            Some(Arc::new(Keyring::new(
+                &config.cluster_id,
+                &config.gossip_keys,
+            )?))
+        };
+
+        Ok(Self {
+            network: self.network.clone(),
+            keyring,
+        })
+    }
+
+    /// Drop each message with the given probability (clamped to `0..=1`)
+    pub fn set_loss_rate(&self, loss_rate: f64) {
+        self.network().loss_rate = loss_rate.clamp(0.0, 1.0);
+    }
+
+    /// Delay every message by `latency`
+    pub fn set_latency(&self, latency: Duration) {
+        self.network().latency = latency;
+    }
+
+    /// Cut every link between the two groups, in both directions
+    pub fn partition(&self, side_a: &[SocketAddr], side_b: &[SocketAddr]) {
+        let mut network = self.network();
+        for a in side_a {
+            for b in side_b {
+                network.cut_links.insert((*a, *b));
+                network.cut_links.insert((*b, *a));
+            }
+        }
+    }
+
+    /// Cut every link to and from `addr`
+    pub fn isolate(&self, addr: SocketAddr) {
+        let others: Vec<SocketAddr> = self.network().inboxes.keys().copied().collect();
+        self.partition(&[addr], &others);
+    }
+
+    /// Restore every cut link
+    pub fn heal(&self) {
+        self.network().cut_links.clear();
+    }
+
+    /// Number of messages dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.network().dropped
+    }
+
+    fn network(&self) -> std::sync::MutexGuard<'_, Network> {
+        self.network.lock().expect("network lock poisoned")
+    }
+}
+
+#[async_trait]
+impl Transport for ChannelTransport {
+    async fn open(&self, listen_addr: SocketAddr) -> anyhow::Result<Box<dyn Socket>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let mut network = self.network();
+        if network
+            .inboxes
+            .get(&listen_addr)
+            .is_some_and(|inbox| !inbox.is_closed())
+        {
+            anyhow::bail!("Address {} already in use", listen_addr);
+        }
+        network.inboxes.insert(listen_addr, tx);
+
+        Ok(Box::new(ChannelSocket {
+            listen_addr,
+            network: self.network.clone(),
+            codec: Codec::new(self.keyring.clone()),
+            rx,
+        }))
+    }
+}
+
+struct ChannelSocket {
+    listen_addr: SocketAddr,
+    network: Arc<Mutex<Network>>,
+    codec: Codec,
+    rx: mpsc::UnboundedReceiver<Envelope>,
+}
+
+impl ChannelSocket {
+    /// Deliver one packet, unless the network loses it
+    fn deliver(&self, to: SocketAddr, packet: Vec<u8>) {
+        let (inbox, latency) = {
+            let mut network = self.network.lock().expect("network lock poisoned");
+
+            let lost = network.loss_rate > 0.0 && network.rng.next_f64() < network.loss_rate;
+            let cut = network.cut_links.contains(&(self.listen_addr, to));
+            let oversized = packet.len() > MAX_DATAGRAM_SIZE;
+            if oversized {
+                warn!(to = %to, size = packet.len(), "Gossip packet larger than a datagram");
+            }
+
+            match network.inboxes.get(&to).cloned() {
+                Some(inbox) if !lost && !cut && !oversized => (inbox, network.latency),
+                _ => {
+                    network.dropped += 1;
+                    return;
+                }
+            }
+        };
+
+        let envelope = (self.listen_addr, packet);
+        if latency.is_zero() {
+            let _ = inbox.send(envelope);
+        } else {
+            tokio::spawn(async move {
+                tokio::time::sleep(latency).await;
+                let _ = inbox.send(envelope);
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl Socket for ChannelSocket {
+    async fn send(&mut self, to: SocketAddr, msg: ChitchatMessage) -> anyhow::Result<()> {
+        for packet in self.codec.encode(&msg)? {
+            self.deliver(to, packet);
+        }
+
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> anyhow::Result<(SocketAddr, ChitchatMessage)> {
+        loop {
+            let (from, packet) = self
+                .rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("Channel transport closed"))?;
+
+            if let Some(msg) = self.codec.decode(from, &packet) {
+                return Ok((from, msg));
+            }
+        }
+    }
+}
+
+/// Messages sent to a closed socket are dropped, as with UDP
+impl Drop for ChannelSocket {
+    fn drop(&mut self) {
+        if let Ok(mut network) = self.network.lock() {
+            network.inboxes.remove(&self.listen_addr);
+        }
+    }
+}
+
+/// Small deterministic generator for simulated packet loss
+#[derive(Default)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::GossipKey;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_split_mix_is_deterministic() {
+        let mut a = SplitMix64(7);
+        let mut b = SplitMix64(7);
+
+        for _ in 0..100 {
+            let value = a.next_f64();
+            assert_eq!(value, b.next_f64());
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+
+    /// Syn message of exactly `len` bytes once serialized
+    fn syn_of_len(len: usize) -> ChitchatMessage {
+        let syn = |cluster_id: String| ChitchatMessage::Syn {
+            cluster_id,
+            digest: Default::default(),
+        };
+        let empty_len = syn(String::new()).serialized_len();
+        syn("z".repeat(len - empty_len))
+    }
+
+    #[tokio::test]
+    async fn test_largest_message_is_fragmented() {
+        let config = YellowpageConfig::local("node-1", addr(1))
+            .with_gossip_keys(vec![GossipKey::generate(1)]);
+        let network = ChannelTransport::new().with_gossip_keys(&config).unwrap();
+        let mut sender = network.open(addr(1)).await.unwrap();
+        let mut receiver = network.open(addr(2)).await.unwrap();
+
+        // Chitchat fills messages up to a whole datagram
+        sender
+            .send(addr(2), syn_of_len(MAX_DATAGRAM_SIZE))
+            .await
+            .unwrap();
+        let (from, msg) = receiver.recv().await.unwrap();
+
+        assert_eq!(from, addr(1));
+        assert_eq!(msg, syn_of_len(MAX_DATAGRAM_SIZE));
+        assert_eq!(network.dropped(), 0, "Fragments should fit in a datagram");
+    }
+
+    #[test]
+    fn test_sealed_packets_fit_in_a_datagram() {
+        let keyring = Arc::new(Keyring::new("zuklink", &[GossipKey::generate(1)]).unwrap());
+        let mut codec = Codec::new(Some(keyring));
+
+        let whole = codec
+            .encode(&syn_of_len(MAX_DATAGRAM_SIZE - OVERHEAD))
+            .unwrap();
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].len(), MAX_DATAGRAM_SIZE);
+
+        let fragments = codec
+            .encode(&syn_of_len(MAX_DATAGRAM_SIZE - OVERHEAD + 1))
+            .unwrap();
+        assert_eq!(fragments.len(), 2);
+        assert!(fragments
+            .iter()
+            .all(|packet| packet.len() <= MAX_DATAGRAM_SIZE));
+    }
+
+    #[test]
+    fn test_reassembly_out_of_order() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.insert(addr(1), 7, 1, 2, b"lo".to_vec()), None);
+        // Same message ID from another node is another message
+        assert_eq!(reassembly.insert(addr(2), 7, 0, 2, b"ha".to_vec()), None);
+        assert_eq!(
+            reassembly.insert(addr(1), 7, 0, 2, b"hel".to_vec()),
+            Some(b"hello".to_vec())
+        );
+        assert_eq!(reassembly.pending.len(), 1);
+    }
+
+    #[test]
+    fn test_reassembly_rejects_invalid_fragments() {
+        let mut reassembly = Reassembly::default();
+
+        assert_eq!(reassembly.insert(addr(1), 7, 2, 2, vec![1]), None);
+        assert_eq!(reassembly.insert(addr(1), 7, 0, 1, vec![1]), None);
+        assert_eq!(
+            reassembly.insert(addr(1), 7, 0, MAX_FRAGMENTS as u8 + 1, vec![1]),
+            None
+        );
+        assert!(reassembly.pending.is_empty());
+
+        // A fragment disagreeing on the count of its message is dropped
+        assert_eq!(reassembly.insert(addr(1), 7, 0, 2, vec![1]), None);
+        assert_eq!(reassembly.insert(addr(1), 7, 1, 3, vec![2]), None);
+        assert_eq!(
+            reassembly.insert(addr(1), 7, 1, 2, vec![2]),
+            Some(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn test_reassembly_evicts_oldest_message() {
+        let mut reassembly = Reassembly::default();
+
+        for message_id in 0..=MAX_PENDING_MESSAGES as u32 {
+            assert_eq!(reassembly.insert(addr(1), message_id, 0, 2, vec![1]), None);
+        }
+        assert_eq!(reassembly.pending.len(), MAX_PENDING_MESSAGES);
+
+        // The first message lost its first fragment, the last one completes
+        assert_eq!(reassembly.insert(addr(1), 0, 1, 2, vec![2]), None);
+        assert_eq!(
+            reassembly.insert(addr(1), MAX_PENDING_MESSAGES as u32, 1, 2, vec![2]),
+            Some(vec![1, 2])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_same_address_twice_fails() {
+        let network = ChannelTransport::new();
+
+        let _socket = network.open(addr(1)).await.unwrap();
+
+        assert!(network.open(addr(1)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_closed_socket_frees_address() {
+        let network = ChannelTransport::new();
+
+        drop(network.open(addr(1)).await.unwrap());
+
+        assert!(network.open(addr(1)).await.is_ok());
+    }
+
+    #[test]
+    fn test_partition_cuts_both_directions() {
+        let network = ChannelTransport::new();
+
+        network.partition(&[addr(1)], &[addr(2), addr(3)]);
+
+        {
+            let cut = &network.network().cut_links;
+            assert!(cut.contains(&(addr(1), addr(2))));
+            assert!(cut.contains(&(addr(3), addr(1))));
+            assert!(!cut.contains(&(addr(2), addr(3))));
+        }
+
+        network.heal();
+        assert!(network.network().cut_links.is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+//! Cluster view, quorum and membership metrics
+//!
+//! When the gossip network partitions, each side only sees its own members and
+//! would happily split the whole keyspace among them: every file ends up
+//! processed once per side. With an expected cluster size, a node only
+//! considers its view trustworthy if it holds a strict majority of the expected
+//! members. At most one side of a partition can have a majority, so the other
+//! side pauses instead of double processing.
+//!
+//! ## Ownership
+//!
+//! The view carries the capacity advertised by each node, so that
+//! [`ClusterView::owner`] assigns keys in proportion to capacity (see the
+//! `sharding` module), then applies the shard overrides published by the
+//! rebalancer (see the `rebalance` module). Nodes sharing the same view agree
+//! on every owner.
+//!
+//! ## Metrics
+//!
+//! A background task watches the view and maintains [`ClusterMetrics`]:
+//! membership changes, quorum losses and leader changes are counted so that
+//! partitions show up on dashboards even when they heal quickly.
+
+use chitchat::Chitchat;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::metadata::{self, ASSIGNMENTS_KEY, CAPACITY_KEY, LOAD_KEY};
+use crate::node::{active_nodes, NodeId};
+use crate::rebalance::{LoadReport, ShardAssignments};
+use crate::sharding::{self, ShardId, DEFAULT_CAPACITY};
+
+/// Interval at which the view is re-evaluated
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Minimum number of members forming a majority of `expected_size`
+pub(crate) fn quorum_for(expected_size: usize) -> usize {
+    expected_size / 2 + 1
+}
+
+/// Snapshot of the cluster membership as seen by this node
+///
+/// Taken under a single lock, so the node list, capacities, loads, shard
+/// assignments, this node's index and the quorum status are always consistent
+/// with each other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterView {
+    nodes: Vec<NodeId>,
+    /// Capacity of each node, in the same order as `nodes`
+    weights: Vec<u32>,
+    /// Load reported by each node, in the same order as `nodes`
+    loads: Vec<Option<LoadReport>>,
+    /// Overrides with the highest epoch published in the view
+    assignments: ShardAssignments,
+    my_index: Option<usize>,
+    quorum: Option<usize>,
+}
+
+impl ClusterView {
+    /// Build a view from the active nodes and their capacity
+    pub(crate) fn new(
+        mut members: Vec<(NodeId, u32)>,
+        self_id: &NodeId,
+        quorum: Option<usize>,
+    ) -> Self {
+        // CRITICAL: Sort to ensure consistent ordering across all nodes
+        members.sort();
+        let (nodes, weights): (Vec<_>, Vec<_>) = members.into_iter().unzip();
+        let my_index = nodes.iter().position(|id| id == self_id);
+        let loads = vec![None; nodes.len()];
+
+        Self {
+            nodes,
+            weights,
+            loads,
+            assignments: ShardAssignments::default(),
+            my_index,
+            quorum,
+        }
+    }
+
+    /// Attach the reported loads and the shard assignments to the view
+    pub(crate) fn with_state(
+        mut self,
+        loads: Vec<(NodeId, LoadReport)>,
+        assignments: ShardAssignments,
+    ) -> Self {
+        for (node_id, load) in loads {
+            if let Some(index) = self.index_of(&node_id) {
+                self.loads[index] = Some(load);
+            }
+        }
+        self.assignments = assignments;
+        self
+    }
+
+    fn index_of(&self, node_id: &NodeId) -> Option<usize> {
+        self.nodes.iter().position(|id| id == node_id)
+    }
+
+    /// Sorted list of active nodes
+    pub fn nodes(&self) -> &[NodeId] {
+        &self.nodes
+    }
+
+    /// Number of active nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the view is empty
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Position of this node in the sorted view
+    pub fn my_index(&self) -> Option<usize> {
+        self.my_index
+    }
+
+    /// Capacity advertised by a node, if it is in the view
+    pub fn weight(&self, node_id: &NodeId) -> Option<u32> {
+        self.index_of(node_id).map(|index| self.weights[index])
+    }
+
+    /// Load reported by a node, if any
+    pub fn load(&self, node_id: &NodeId) -> Option<&LoadReport> {
+        self.index_of(node_id)
+            .and_then(|index| self.loads[index].as_ref())
+    }
+
+    /// Shard overrides applied by this view
+    pub fn assignments(&self) -> &ShardAssignments {
+        &self.assignments
+    }
+
+    /// Sum of the capacities of all nodes
+    pub fn total_weight(&self) -> u64 {
+        self.weights.iter().map(|weight| u64::from(*weight)).sum()
+    }
+
+    /// Node owning `key`
+    ///
+    /// Returns `None` if no node has a positive capacity.
+    pub fn owner(&self, key: &str) -> Option<&NodeId> {
+        self.shard_owner(sharding::shard_of(key))
+    }
+
+    /// Node owning a shard: its override target if still in the view,
+    /// otherwise its natural owner
+    pub fn shard_owner(&self, shard: ShardId) -> Option<&NodeId> {
+        self.assignments
+            .overrides
+            .get(&shard)
+            .and_then(|target| {
+                let index = self.index_of(target)?;
+                (self.weights[index] > 0).then_some(&self.nodes[index])
+            })
+            .or_else(|| self.natural_owner(shard))
+    }
+
+    /// Node owning a shard by capacity-weighted hashing, ignoring overrides
+    pub fn natural_owner(&self, shard: ShardId) -> Option<&NodeId> {
+        sharding::shard_owner(shard, self.nodes.iter().zip(self.weights.iter().copied()))
+    }
+
+    /// Whether this node owns `key`
+    pub fn owns(&self, key: &str) -> bool {
+        match self.my_index {
+            Some(my_index) => self.owner(key) == Some(&self.nodes[my_index]),
+            None => false,
+        }
+    }
+
+    /// Minimum number of members required, if an expected size is configured
+    pub fn quorum(&self) -> Option<usize> {
+        self.quorum
+    }
+
+    /// Whether this view holds a majority of the expected members
+    ///
+    /// Always `true` when no expected cluster size is configured.
+    pub fn has_quorum(&self) -> bool {
+        self.quorum
+            .map_or(true, |quorum| self.nodes.len() >= quorum)
+    }
+}
+
+/// Build the current view from the Chitchat state
+pub(crate) fn current_view(
+    chitchat: &Chitchat,
+    self_id: &NodeId,
+    quorum: Option<usize>,
+) -> ClusterView {
+    let mut members = Vec::new();
+    let mut loads = Vec::new();
+    let mut assignments = ShardAssignments::default();
+    let mut publisher: Option<NodeId> = None;
+
+    for chitchat_id in active_nodes(chitchat) {
+        let node_id = NodeId(chitchat_id.node_id.clone());
+        let state = chitchat.node_state(chitchat_id);
+        let get = |key: &str| state.and_then(|state| state.get(key));
+
+        // Nodes running an older version advertise no capacity
+        let weight = get(CAPACITY_KEY)
+            .and_then(|capacity| capacity.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+
+        if let Some(load) = get(LOAD_KEY).and_then(|raw| metadata::decode(LOAD_KEY, raw).ok()) {
+            loads.push((node_id.clone(), load));
+        }
+
+        // A new leader may publish while the previous one is still visible:
+        // highest epoch wins, ties going to the smallest node id
+        if let Some(published) = get(ASSIGNMENTS_KEY)
+            .and_then(|raw| metadata::decode::<ShardAssignments>(ASSIGNMENTS_KEY, raw).ok())
+        {
+            let newer = match &publisher {
+                None => true,
+                Some(current) => {
+                    published.epoch > assignments.epoch
+                        || (published.epoch == assignments.epoch && node_id < *current)
+                }
+            };
+            if newer {
+                assignments = published;
+                publisher = Some(node_id.clone());
+            }
+        }
+
+        members.push((node_id, weight));
+    }
+
+    ClusterView::new(members, self_id, quorum).with_state(loads, assignments)
+}
+
+/// Membership counters maintained in the background
+#[derive(Debug, Default)]
+pub(crate) struct ClusterMetrics {
+    live_nodes: AtomicU64,
+    has_quorum: AtomicBool,
+    membership_changes: AtomicU64,
+    quorum_losses: AtomicU64,
+    leader_changes: AtomicU64,
+}
+
+impl ClusterMetrics {
+    /// Count a leadership change
+    pub(crate) fn record_leader_change(&self) {
+        self.leader_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take a consistent-enough copy of the counters
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            live_nodes: self.live_nodes.load(Ordering::Relaxed),
+            has_quorum: self.has_quorum.load(Ordering::Relaxed),
+            membership_changes_total: self.membership_changes.load(Ordering::Relaxed),
+            quorum_lost_total: self.quorum_losses.load(Ordering::Relaxed),
+            leader_changes_total: self.leader_changes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Point-in-time copy of the membership metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    /// Active nodes in this node's view
+    pub live_nodes: u64,
+    /// Whether the view currently holds a quorum
+    pub has_quorum: bool,
+    /// Number of times the set of active nodes changed
+    pub membership_changes_total: u64,
+    /// Number of times the view lost its quorum (likely partitions)
+    pub quorum_lost_total: u64,
+    /// Number of leadership changes
+    pub leader_changes_total: u64,
+}
+
+/// Tracks transitions between successive views
+#[derive(Debug, Default)]
+struct ViewTracker {
+    last: Option<ClusterView>,
+}
+
+impl ViewTracker {
+    /// Record a new view, updating metrics and logging partition events
+    fn observe(&mut self, view: ClusterView, metrics: &ClusterMetrics) {
+        metrics
+            .live_nodes
+            .store(view.len() as u64, Ordering::Relaxed);
+        metrics
+            .has_quorum
+            .store(view.has_quorum(), Ordering::Relaxed);
+
+        if let Some(last) = &self.last {
+            if last.nodes != view.nodes {
+                metrics.membership_changes.fetch_add(1, Ordering::Relaxed);
+                info!(
+                    nodes = ?view.nodes,
+                    previous = last.len(),
+                    current = view.len(),
+                    "Cluster membership changed"
+                );
+            }
+
+            match (last.has_quorum(), view.has_quorum()) {
+                (true, false) => {
+                    metrics.quorum_losses.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        live_nodes = view.len(),
+                        quorum = ?view.quorum,
+                        "Quorum lost, possible network partition: pausing work"
+                    );
+                }
+                (false, true) => info!(live_nodes = view.len(), "Quorum regained"),
+                _ => {}
+            }
+        } else if !view.has_quorum() {
+            warn!(
+                live_nodes = view.len(),
+                quorum = ?view.quorum,
+                "Waiting for quorum"
+            );
+        }
+
+        self.last = Some(view);
+    }
+}
+
+/// Spawn the background task maintaining membership metrics
+pub(crate) fn spawn_view_task(
+    chitchat: Arc<Mutex<Chitchat>>,
+    self_id: NodeId,
+    quorum: Option<usize>,
+    metrics: Arc<ClusterMetrics>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tracker = ViewTracker::default();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let view = {
+                let chitchat_guard = chitchat.lock().await;
+                current_view(&chitchat_guard, &self_id, quorum)
+            };
+
+            tracker.observe(view, &metrics);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(nodes: &[&str], quorum: Option<usize>) -> ClusterView {
+        ClusterView::new(
+            nodes.iter().map(|id| (NodeId::new(*id), 1)).collect(),
+            &NodeId::new("node-2"),
+            quorum,
+        )
+    }
+
+    #[test]
+    fn test_quorum_is_strict_majority() {
+        assert_eq!(quorum_for(1), 1);
+        assert_eq!(quorum_for(2), 2);
+        assert_eq!(quorum_for(3), 2);
+        assert_eq!(quorum_for(4), 3);
+        assert_eq!(quorum_for(5), 3);
+    }
+
+    #[test]
+    fn test_view_is_sorted() {
+        let view = view(&["node-3", "node-1", "node-2"], None);
+
+        assert_eq!(
+            view.nodes(),
+            &[
+                NodeId::new("node-1"),
+                NodeId::new("node-2"),
+                NodeId::new("node-3")
+            ]
+        );
+        assert_eq!(view.my_index(), Some(1));
+    }
+
+    #[test]
+    fn test_weights_follow_sorted_nodes() {
+        let view = ClusterView::new(
+            vec![(NodeId::new("node-3"), 8), (NodeId::new("node-1"), 2)],
+            &NodeId::new("node-1"),
+            None,
+        );
+
+        assert_eq!(view.weight(&NodeId::new("node-1")), Some(2));
+        assert_eq!(view.weight(&NodeId::new("node-3")), Some(8));
+        assert_eq!(view.weight(&NodeId::new("node-2")), None);
+        assert_eq!(view.total_weight(), 10);
+    }
+
+    #[test]
+    fn test_every_key_has_exactly_one_owner() {
+        let nodes = ["node-1", "node-2", "node-3"];
+        let views: Vec<ClusterView> = nodes
+            .iter()
+            .map(|self_id| {
+                ClusterView::new(
+                    vec![
+                        (NodeId::new("node-1"), 1),
+                        (NodeId::new("node-2"), 2),
+                        (NodeId::new("node-3"), 4),
+                    ],
+                    &NodeId::new(*self_id),
+                    None,
+                )
+            })
+            .collect();
+
+        for i in 0..500 {
+            let key = format!("file-{}.zuk", i);
+            let owners = views.iter().filter(|view| view.owns(&key)).count();
+            assert_eq!(owners, 1, "{} owned {} times", key, owners);
+        }
+    }
+
+    #[test]
+    fn test_has_quorum() {
+        assert!(view(&["node-1"], None).has_quorum());
+        assert!(view(&["node-1", "node-2"], Some(2)).has_quorum());
+        assert!(!view(&["node-2"], Some(2)).has_quorum());
+    }
+
+    #[test]
+    fn test_both_sides_of_a_partition_cannot_have_quorum() {
+        let quorum = Some(quorum_for(4));
+
+        assert!(!view(&["node-1", "node-2"], quorum).has_quorum());
+        assert!(!view(&["node-3", "node-4"], quorum).has_quorum());
+    }
+
+    #[test]
+    fn test_tracker_counts_partition_events() {
+        let metrics = ClusterMetrics::default();
+        let mut tracker = ViewTracker::default();
+        let quorum = Some(quorum_for(3));
+
+        tracker.observe(view(&["node-1", "node-2", "node-3"], quorum), &metrics);
+        tracker.observe(view(&["node-2"], quorum), &metrics);
+        tracker.observe(view(&["node-2"], quorum), &metrics);
+        tracker.observe(view(&["node-1", "node-2", "node-3"], quorum), &metrics);
+
+        assert_eq!(
+            metrics.snapshot(),
+            MetricsSnapshot {
+                live_nodes: 3,
+                has_quorum: true,
+                membership_changes_total: 2,
+                quorum_lost_total: 1,
+                leader_changes_total: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Cluster fixture shared by the integration tests
+//!
+//! Nodes gossip over a `ChannelTransport`, to be used under a paused Tokio
+//! clock. Node `i` of a cluster named `name` is `<name>-<i>`, listens on
+//! `10.0.0.<i>:7000`, joins through node 1 and has generation `i`, so node 1
+//! leads once the cluster has settled.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use zuklink_yellowpage::{
+    ChannelTransport, GenerationSource, NodeId, Yellowpage, YellowpageConfig,
+};
+
+/// Gossip address of node `index`
+pub fn addr(index: u8) -> SocketAddr {
+    SocketAddr::from(([10, 0, 0, index], 7000))
+}
+
+/// ID of node `index` of a cluster
+pub fn node_id(name: &str, index: u8) -> NodeId {
+    NodeId::new(format!("{}-{}", name, index))
+}
+
+/// IDs of the given nodes of a cluster
+pub fn node_ids(name: &str, indexes: &[u8]) -> Vec<NodeId> {
+    indexes.iter().map(|index| node_id(name, *index)).collect()
+}
+
+/// Configuration of node `index` of a cluster, with the `local` preset
+pub fn config(name: &str, index: u8) -> YellowpageConfig {
+    let seeds = if index == 1 {
+        vec![]
+    } else {
+        vec![addr(1).to_string()]
+    };
+
+    YellowpageConfig::local(node_id(name, index).0, addr(index))
+        .with_seeds(seeds)
+        .with_generation(GenerationSource::Fixed(index as u64))
+}
+
+/// Start a node on the network
+pub async fn start(network: &ChannelTransport, config: YellowpageConfig) -> Yellowpage {
+    Yellowpage::with_transport(config, network)
+        .await
+        .expect("Failed to create node")
+}
+
+/// Start `size` nodes, node 1 first
+pub async fn spawn_cluster(network: &ChannelTransport, name: &str, size: u8) -> Vec<Yellowpage> {
+    spawn_cluster_with(network, name, size, |config| config).await
+}
+
+/// Start `size` nodes, node 1 first, adjusting the configuration of each
+pub async fn spawn_cluster_with(
+    network: &ChannelTransport,
+    name: &str,
+    size: u8,
+    configure: impl Fn(YellowpageConfig) -> YellowpageConfig,
+) -> Vec<Yellowpage> {
+    let mut nodes = Vec::new();
+    for index in 1..=size {
+        nodes.push(start(network, configure(config(name, index))).await);
+    }
+    nodes
+}
+
+/// Stop every node
+pub async fn shutdown_all(nodes: Vec<Yellowpage>) {
+    for node in nodes {
+        node.shutdown().await;
+    }
+}
@@ -0,0 +1,56 @@
+//! Integration tests for Yellowpage configuration
+//!
+//! These tests verify that:
+//! 1. A cluster built with the `local` preset converges in well under a second
+//! 2. A fixed generation id source drives the leader election
+//! 3. Invalid configurations are rejected before binding
+
+mod common;
+
+use common::{config, start};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{
+    ChannelTransport, GenerationSource, GossipError, NodeId, Yellowpage, YellowpageConfig,
+};
+
+/// Test that the local preset converges fast and honors fixed generations
+#[tokio::test(start_paused = true)]
+async fn test_local_preset_converges_fast() {
+    let network = ChannelTransport::new();
+    let node1 = start(
+        &network,
+        config("config", 1).with_generation(GenerationSource::Fixed(2)),
+    )
+    .await;
+    let node2 = start(
+        &network,
+        config("config", 2).with_generation(GenerationSource::Fixed(1)),
+    )
+    .await;
+
+    // A few 50ms gossip rounds are enough
+    sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(node1.cluster_size().await, 2);
+    assert_eq!(node2.cluster_size().await, 2);
+
+    // The lowest generation leads, even though it joined last
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(node1.leader(), Some(NodeId::new("config-2")));
+    assert!(node2.is_leader());
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+}
+
+/// Test that an invalid configuration is rejected
+#[tokio::test]
+async fn test_invalid_config_rejected() {
+    let config = YellowpageConfig::new("config-3", "127.0.0.1:17233".parse().unwrap())
+        .with_gossip_interval(Duration::ZERO);
+
+    let result = Yellowpage::with_config(config).await;
+
+    assert!(matches!(result, Err(GossipError::ConfigError(_))));
+}
@@ -0,0 +1,128 @@
+//! Integration tests for encrypted gossip
+//!
+//! These tests verify that:
+//! 1. Nodes sharing a gossip key form a cluster
+//! 2. A node with another key cannot join
+//! 3. Nodes in the middle of a key rotation still talk to each other
+//! 4. State larger than a datagram still syncs once sealed
+
+mod common;
+
+use common::{config, start};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, GossipKey, Yellowpage};
+
+/// Start node `index`, sealing its packets with `keys`
+async fn start_sealed(
+    network: &ChannelTransport,
+    name: &str,
+    index: u8,
+    keys: Vec<GossipKey>,
+) -> Yellowpage {
+    let config = config(name, index).with_gossip_keys(keys);
+    let transport = network
+        .with_gossip_keys(&config)
+        .expect("Invalid gossip keys");
+
+    start(&transport, config).await
+}
+
+/// Test that only nodes holding the key join the cluster
+#[tokio::test(start_paused = true)]
+async fn test_shared_key_required_to_join() {
+    let network = ChannelTransport::new();
+    let key = GossipKey::generate(1);
+
+    let node1 = start_sealed(&network, "crypt", 1, vec![key.clone()]).await;
+    let node2 = start_sealed(&network, "crypt", 2, vec![key]).await;
+    let intruder = start_sealed(&network, "crypt", 3, vec![GossipKey::generate(1)]).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(node1.get_live_nodes().await.len(), 2);
+    assert_eq!(node2.get_live_nodes().await.len(), 2);
+    assert_eq!(
+        intruder.cluster_size().await,
+        1,
+        "Intruder should stay alone"
+    );
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+    intruder.shutdown().await;
+}
+
+/// Test that nodes sealing with different keys of the same keyring converge
+#[tokio::test(start_paused = true)]
+async fn test_key_rotation() {
+    let network = ChannelTransport::new();
+    let old = GossipKey::generate(1);
+    let new = GossipKey::generate(2);
+
+    // node1 still seals with the old key, node2 already switched to the new one
+    let node1 = start_sealed(&network, "rotate", 1, vec![old.clone(), new.clone()]).await;
+    let node2 = start_sealed(&network, "rotate", 2, vec![new, old]).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(node1.cluster_size().await, 2);
+    assert_eq!(node2.cluster_size().await, 2);
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+}
+
+/// Test that a node joining a cluster with a large state receives all of it
+///
+/// The state takes several deltas filled up to the size of a datagram, each
+/// sealed without any packet exceeding a datagram.
+#[tokio::test(start_paused = true)]
+async fn test_large_state_syncs() {
+    let network = ChannelTransport::new();
+    let key = GossipKey::generate(1);
+
+    // Values that do not compress, like the deltas of a real cluster state
+    let value = |index: u64| {
+        let mut z = index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        format!("{:016x}", z ^ (z >> 31))
+    };
+
+    let node1 = start_sealed(&network, "large", 1, vec![key.clone()]).await;
+    for index in 0..5_000 {
+        node1
+            .set_metadata(&format!("shard.{}", index), &value(index))
+            .await
+            .unwrap();
+    }
+
+    let node2 = start_sealed(&network, "large", 2, vec![key]).await;
+    sleep(Duration::from_secs(10)).await;
+
+    let node1_id = node1.node_id().clone();
+    for index in [0, 2_500, 4_999] {
+        assert_eq!(
+            node2
+                .get_metadata(&node1_id, &format!("shard.{}", index))
+                .await
+                .as_deref(),
+            Some(value(index).as_str()),
+            "shard.{} should have synced",
+            index
+        );
+    }
+    let synced = node2
+        .get_node_metadata(&node1_id)
+        .await
+        .expect("node1 should be known")
+        .keys()
+        .filter(|key| key.starts_with("shard."))
+        .count();
+    assert_eq!(synced, 5_000);
+    assert_eq!(network.dropped(), 0, "No packet should exceed a datagram");
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+}
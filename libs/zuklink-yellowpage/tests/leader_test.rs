@@ -0,0 +1,66 @@
+//! Integration tests for leader election
+//!
+//! These tests verify that:
+//! 1. A single node eventually elects itself
+//! 2. All nodes sharing a view agree on the same leader
+//! 3. Exactly one node reports itself as leader
+
+mod common;
+
+use common::{config, shutdown_all, spawn_cluster_with, start};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::ChannelTransport;
+
+/// Test that a lone node becomes leader once the settle delay has elapsed
+#[tokio::test(start_paused = true)]
+async fn test_single_node_elects_itself() {
+    let network = ChannelTransport::new();
+    let node = start(
+        &network,
+        config("leader", 1).with_leader_settle_delay(Duration::from_secs(5)),
+    )
+    .await;
+
+    // No leader before the view has settled
+    sleep(Duration::from_secs(1)).await;
+    assert!(!node.is_leader(), "Leadership should wait for settle delay");
+
+    sleep(Duration::from_secs(5)).await;
+
+    assert_eq!(node.leader().as_ref(), Some(node.node_id()));
+    assert!(node.is_leader(), "Single node should be leader");
+
+    node.shutdown().await;
+}
+
+/// Test that two nodes agree on a single leader
+#[tokio::test(start_paused = true)]
+async fn test_two_nodes_agree_on_leader() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_cluster_with(&network, "leader", 2, |config| {
+        config.with_leader_settle_delay(Duration::from_secs(5))
+    })
+    .await;
+
+    let mut leader_rx = nodes[1].subscribe_leader();
+
+    // Wait for gossip to converge and the leader to settle
+    tokio::time::timeout(Duration::from_secs(10), leader_rx.changed())
+        .await
+        .expect("Leader should be elected")
+        .expect("Leader channel should stay open");
+    sleep(Duration::from_secs(1)).await;
+
+    let leader1 = nodes[0].leader();
+    let leader2 = nodes[1].leader();
+
+    assert!(leader1.is_some(), "Node1 should know the leader");
+    assert_eq!(leader1, leader2, "Both nodes should agree on the leader");
+    assert!(
+        nodes[0].is_leader() ^ nodes[1].is_leader(),
+        "Exactly one node should be leader"
+    );
+
+    shutdown_all(nodes).await;
+}
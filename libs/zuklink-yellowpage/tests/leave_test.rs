@@ -0,0 +1,96 @@
+//! Integration tests for the graceful leave protocol
+//!
+//! These tests verify that:
+//! 1. A leaving node stays in the cluster view while it drains
+//! 2. Peers see the `leaving` status via gossip
+//! 3. Peers drop the node from their view as soon as it announces departure
+//! 4. A node shared with other tasks still leaves
+//! 5. A dropped node stops gossiping
+
+mod common;
+
+use common::{addr, config, spawn_cluster, start};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, NodeStatus, Transport};
+
+/// Test that a departing node is removed from the peer's view immediately
+#[tokio::test(start_paused = true)]
+async fn test_graceful_leave_rebalances_immediately() {
+    let network = ChannelTransport::new();
+    let mut nodes = spawn_cluster(&network, "leave", 2).await;
+    let node2 = nodes.pop().unwrap();
+    let node1 = nodes.pop().unwrap();
+
+    // Wait for gossip to converge
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(node1.cluster_size().await, 2);
+    assert_eq!(
+        node1.node_status(node2.node_id()).await,
+        Some(NodeStatus::Ready)
+    );
+
+    // Draining nodes keep their place in the view
+    node2.begin_leave().await;
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(
+        node1.node_status(node2.node_id()).await,
+        Some(NodeStatus::Leaving)
+    );
+    assert_eq!(
+        node1.cluster_size().await,
+        2,
+        "Leaving node is still a member"
+    );
+
+    // Departure is visible long before the failure detector would notice
+    node2.leave().await;
+
+    let live_nodes = node1.get_live_nodes().await;
+    assert_eq!(live_nodes, vec![node1.node_id().clone()]);
+    assert_eq!(node1.my_index().await, Some(0));
+
+    node1.shutdown().await;
+}
+
+/// Test that a node still held by other tasks leaves the cluster
+#[tokio::test(start_paused = true)]
+async fn test_shared_node_leaves() {
+    let network = ChannelTransport::new();
+    let mut nodes = spawn_cluster(&network, "shared", 2).await;
+    let node2 = Arc::new(nodes.pop().unwrap());
+    let node1 = nodes.pop().unwrap();
+    sleep(Duration::from_secs(1)).await;
+
+    // e.g. held by HTTP handlers while the node shuts down
+    let handler = node2.clone();
+    node2.leave().await;
+
+    assert_eq!(node1.get_live_nodes().await, vec![node1.node_id().clone()]);
+    assert!(
+        !handler.get_live_nodes().await.is_empty(),
+        "Handle still usable"
+    );
+
+    node1.shutdown().await;
+}
+
+/// Test that dropping a node without shutting it down stops its gossip
+#[tokio::test(start_paused = true)]
+async fn test_dropped_node_stops() {
+    let network = ChannelTransport::new();
+    let node = start(&network, config("dropped", 1)).await;
+
+    assert!(network.open(addr(1)).await.is_err(), "Address in use");
+
+    drop(node);
+    sleep(Duration::from_millis(10)).await;
+
+    assert!(
+        network.open(addr(1)).await.is_ok(),
+        "Gossip socket should be closed"
+    );
+}
@@ -0,0 +1,107 @@
+//! Integration tests for node metadata
+//!
+//! These tests verify that:
+//! 1. Typed metadata set on one node is readable from its peers
+//! 2. Bulk reads return the value of every live node
+//! 3. Deleted keys disappear from the cluster view
+//! 4. The reserved `zuk.*` namespace is rejected
+//! 5. Bulk reads skip nodes that left the cluster
+
+mod common;
+
+use common::{config, shutdown_all, spawn_cluster, start};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, GossipError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Capacity {
+    cpus: u32,
+    topics: Vec<String>,
+}
+
+/// Test that typed metadata propagates between two nodes
+#[tokio::test(start_paused = true)]
+async fn test_typed_metadata_propagates() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_cluster(&network, "meta", 2).await;
+    let (node1, node2) = (&nodes[0], &nodes[1]);
+
+    let capacity1 = Capacity {
+        cpus: 8,
+        topics: vec!["billing".to_string()],
+    };
+    let capacity2 = Capacity {
+        cpus: 2,
+        topics: vec![],
+    };
+
+    node1.set_typed("capacity", &capacity1).await.unwrap();
+    node2.set_typed("capacity", &capacity2).await.unwrap();
+    node1.set_metadata("role", "receiver").await.unwrap();
+
+    // Wait for gossip to converge
+    sleep(Duration::from_secs(1)).await;
+
+    let seen: Option<Capacity> = node2.get_typed(node1.node_id(), "capacity").await.unwrap();
+    assert_eq!(seen, Some(capacity1.clone()));
+
+    let all: BTreeMap<_, Capacity> = node2.get_typed_all("capacity").await.unwrap();
+    assert_eq!(all.len(), 2, "Both nodes should advertise a capacity");
+    assert_eq!(all[node1.node_id()], capacity1);
+    assert_eq!(all[node2.node_id()], capacity2);
+
+    // Delete a key and check it disappears on the peer
+    node1.delete_metadata("role").await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(node2.get_metadata(node1.node_id(), "role").await, None);
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that bulk reads see the same membership as the live nodes
+#[tokio::test(start_paused = true)]
+async fn test_metadata_all_skips_left_nodes() {
+    let network = ChannelTransport::new();
+    let mut nodes = spawn_cluster(&network, "meta-left", 2).await;
+    let node2 = nodes.pop().unwrap();
+    let node1 = nodes.pop().unwrap();
+
+    let capacity = Capacity {
+        cpus: 4,
+        topics: vec![],
+    };
+    node1.set_typed("capacity", &capacity).await.unwrap();
+    node2.set_typed("capacity", &capacity).await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(node1.get_metadata_all("capacity").await.len(), 2);
+
+    node2.leave().await;
+
+    let expected = vec![node1.node_id().clone()];
+    let all = node1.get_metadata_all("capacity").await;
+    assert_eq!(all.into_keys().collect::<Vec<_>>(), expected);
+    let typed: BTreeMap<_, Capacity> = node1.get_typed_all("capacity").await.unwrap();
+    assert_eq!(typed.into_keys().collect::<Vec<_>>(), expected);
+    assert_eq!(node1.get_live_nodes().await, expected);
+
+    node1.shutdown().await;
+}
+
+/// Test that the reserved namespace cannot be written
+#[tokio::test(start_paused = true)]
+async fn test_reserved_namespace_rejected() {
+    let network = ChannelTransport::new();
+    let node = start(&network, config("meta", 1)).await;
+
+    let result = node.set_metadata("zuk.status", "ready").await;
+    assert!(matches!(result, Err(GossipError::InvalidKey { .. })));
+
+    let result = node.delete_metadata("zuk.status").await;
+    assert!(matches!(result, Err(GossipError::InvalidKey { .. })));
+
+    node.shutdown().await;
+}
@@ -0,0 +1,45 @@
+//! Integration tests for observers
+//!
+//! These tests verify that:
+//! 1. An observer sees the processing nodes and their metadata
+//! 2. An observer owns no keys, in its own view and in the view of peers
+//! 3. Processing nodes do not count observers as members
+
+mod common;
+
+use common::{config, start};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::ChannelTransport;
+
+/// Test that an observer resolves owners without taking part in sharding
+#[tokio::test(start_paused = true)]
+async fn test_observer_owns_nothing() {
+    let network = ChannelTransport::new();
+    let node = start(&network, config("observer", 1)).await;
+    node.set_metadata("events_url", "http://127.0.0.1:8080/events")
+        .await
+        .unwrap();
+
+    let observer = start(&network, config("observer", 2).with_observer(true)).await;
+
+    sleep(Duration::from_millis(500)).await;
+
+    // Processing nodes ignore the observer
+    assert_eq!(node.cluster_size().await, 1);
+    assert_eq!(node.my_index().await, Some(0));
+
+    // The observer sees the processing node, and owns nothing
+    let view = observer.cluster_view().await;
+    assert_eq!(view.nodes(), &[node.node_id().clone()]);
+    assert_eq!(view.my_index(), None);
+    assert!(!view.owns("segment-1.zuk"));
+    assert_eq!(view.owner("segment-1.zuk"), Some(node.node_id()));
+    assert_eq!(
+        observer.get_metadata(node.node_id(), "events_url").await,
+        Some("http://127.0.0.1:8080/events".to_string())
+    );
+
+    observer.shutdown().await;
+    node.shutdown().await;
+}
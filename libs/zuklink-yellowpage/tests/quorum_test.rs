@@ -0,0 +1,113 @@
+//! Split-brain detection tests on the in-memory transport
+//!
+//! These tests verify that:
+//! 1. Only the majority side of a partition keeps its quorum
+//! 2. Quorum losses and membership changes are counted in the metrics
+//! 3. Quorum checks are disabled without an expected cluster size
+
+mod common;
+
+use common::{addr, shutdown_all, spawn_cluster, spawn_cluster_with};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, Yellowpage};
+
+/// Start `size` nodes expecting a cluster of `expected_cluster_size`
+async fn spawn_expecting(
+    network: &ChannelTransport,
+    size: u8,
+    expected_cluster_size: usize,
+) -> Vec<Yellowpage> {
+    spawn_cluster_with(network, "quorum", size, |config| {
+        config.with_expected_cluster_size(expected_cluster_size)
+    })
+    .await
+}
+
+/// Test that the minority side of a partition loses its quorum
+#[tokio::test(start_paused = true)]
+async fn test_minority_side_loses_quorum() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_expecting(&network, 5, 5).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    for node in &nodes {
+        assert_eq!(node.quorum(), Some(3));
+        assert!(node.has_quorum().await);
+    }
+
+    network.partition(&[addr(1), addr(2)], &[addr(3), addr(4), addr(5)]);
+
+    // Leave time for the failure detector on both sides
+    sleep(Duration::from_secs(30)).await;
+
+    for node in &nodes[..2] {
+        let view = node.cluster_view().await;
+        assert_eq!(view.len(), 2);
+        assert!(!view.has_quorum(), "{} should lose quorum", node.node_id());
+
+        let metrics = node.metrics();
+        assert!(!metrics.has_quorum);
+        assert_eq!(metrics.quorum_lost_total, 1);
+        assert_eq!(metrics.live_nodes, 2);
+    }
+    for node in &nodes[2..] {
+        assert_eq!(node.cluster_size().await, 3);
+        assert!(
+            node.has_quorum().await,
+            "{} should keep quorum",
+            node.node_id()
+        );
+        assert_eq!(node.metrics().quorum_lost_total, 0);
+    }
+
+    network.heal();
+    sleep(Duration::from_secs(5)).await;
+
+    for node in &nodes {
+        assert!(node.has_quorum().await);
+
+        let metrics = node.metrics();
+        assert!(metrics.has_quorum);
+        assert_eq!(metrics.live_nodes, 5);
+        assert!(metrics.membership_changes_total >= 2);
+    }
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that an even split leaves no side with a quorum
+#[tokio::test(start_paused = true)]
+async fn test_even_split_has_no_quorum() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_expecting(&network, 4, 4).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    network.partition(&[addr(1), addr(2)], &[addr(3), addr(4)]);
+    sleep(Duration::from_secs(30)).await;
+
+    for node in &nodes {
+        assert!(!node.has_quorum().await);
+    }
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that quorum checks are disabled without an expected cluster size
+#[tokio::test(start_paused = true)]
+async fn test_no_expected_size_always_has_quorum() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_cluster(&network, "quorum", 3).await;
+
+    sleep(Duration::from_secs(1)).await;
+    network.isolate(addr(1));
+    sleep(Duration::from_secs(30)).await;
+
+    assert_eq!(nodes[0].quorum(), None);
+    assert_eq!(nodes[0].cluster_size().await, 1);
+    assert!(nodes[0].has_quorum().await);
+
+    shutdown_all(nodes).await;
+}
@@ -0,0 +1,90 @@
+//! Integration tests for seed discovery
+//!
+//! These tests verify that:
+//! 1. A node joins the cluster through a seed file instead of explicit seeds
+//! 2. The seed file is re-read until it lists a peer
+//! 3. A node that bootstrapped alone joins once the seed file is updated
+
+mod common;
+
+use common::{addr, config, start};
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, SeedSource};
+
+/// Test that a node waits for the seed file to be written, then joins
+#[tokio::test(start_paused = true)]
+async fn test_join_through_seed_file() {
+    let network = ChannelTransport::new();
+    let node1 = start(&network, config("seeds", 1)).await;
+
+    let path = std::env::temp_dir().join(format!("yellowpage-seeds-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // The file only appears after the node started, as with an init container
+    let writer_path = path.clone();
+    let writer = tokio::spawn(async move {
+        sleep(Duration::from_millis(1500)).await;
+        std::fs::write(&writer_path, format!("# written late\n{}\n", addr(1))).unwrap();
+    });
+
+    let node2 = start(
+        &network,
+        config("seeds", 2)
+            .with_seeds(vec![])
+            .with_seed_provider(SeedSource::File(path.clone()))
+            .with_seed_discovery_timeout(Duration::from_secs(10)),
+    )
+    .await;
+
+    writer.await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    sleep(Duration::from_millis(500)).await;
+
+    assert_eq!(node1.cluster_size().await, 2);
+    assert_eq!(node2.cluster_size().await, 2);
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+}
+
+/// Test that the seed file is still watched after discovery timed out
+#[tokio::test(start_paused = true)]
+async fn test_join_after_seed_file_update() {
+    let network = ChannelTransport::new();
+    let node1 = start(&network, config("refresh", 1)).await;
+
+    let path = std::env::temp_dir().join(format!(
+        "yellowpage-seeds-refresh-test-{}",
+        std::process::id()
+    ));
+    std::fs::write(&path, "# no peer yet\n").unwrap();
+
+    let node2 = start(
+        &network,
+        config("refresh", 2)
+            .with_seeds(vec![])
+            .with_seed_provider(SeedSource::File(path.clone()))
+            .with_seed_discovery_timeout(Duration::from_secs(1))
+            .with_seed_refresh_interval(Duration::from_secs(5)),
+    )
+    .await;
+
+    sleep(Duration::from_secs(2)).await;
+    assert_eq!(node2.cluster_size().await, 1);
+
+    std::fs::write(&path, format!("{}\n", addr(1))).unwrap();
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(std::time::SystemTime::now() + Duration::from_secs(1))
+        .unwrap();
+
+    sleep(Duration::from_secs(5)).await;
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(node1.cluster_size().await, 2);
+    assert_eq!(node2.cluster_size().await, 2);
+
+    node1.shutdown().await;
+    node2.shutdown().await;
+}
@@ -0,0 +1,166 @@
+//! Deterministic cluster tests on the in-memory transport
+//!
+//! Every test runs a whole cluster on a `ChannelTransport` under a paused Tokio
+//! clock: no UDP ports are bound and sleeps advance virtual time instantly, so
+//! these tests are fast and can run in parallel.
+//!
+//! These tests verify that:
+//! 1. The cluster converges, even with packet loss and latency
+//! 2. A partition splits the cluster view and heals back
+//! 3. Files are rebalanced without duplicates when a node fails
+//! 4. Files are split in proportion to the advertised capacities
+
+mod common;
+
+use common::{addr, config, node_ids, shutdown_all, spawn_cluster, start};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::sleep;
+use zuklink_yellowpage::{ChannelTransport, NodeId};
+
+/// Test that a reliable network converges within a few gossip rounds
+#[tokio::test(start_paused = true)]
+async fn test_cluster_converges() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_cluster(&network, "sim", 5).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    for node in &nodes {
+        assert_eq!(
+            node.get_live_nodes().await,
+            node_ids("sim", &[1, 2, 3, 4, 5])
+        );
+        assert_eq!(node.leader(), Some(NodeId::new("sim-1")));
+    }
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that gossip converges despite packet loss and latency
+#[tokio::test(start_paused = true)]
+async fn test_converges_despite_loss_and_latency() {
+    let network = ChannelTransport::with_seed(7);
+    network.set_loss_rate(0.3);
+    network.set_latency(Duration::from_millis(20));
+
+    let nodes = spawn_cluster(&network, "sim", 5).await;
+
+    sleep(Duration::from_secs(5)).await;
+
+    for node in &nodes {
+        assert_eq!(
+            node.get_live_nodes().await,
+            node_ids("sim", &[1, 2, 3, 4, 5])
+        );
+    }
+    assert!(network.dropped() > 0, "Some messages should have been lost");
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that each side of a partition forms its own view, then heals
+#[tokio::test(start_paused = true)]
+async fn test_partition_and_heal() {
+    let network = ChannelTransport::new();
+    let nodes = spawn_cluster(&network, "sim", 4).await;
+
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(nodes[0].cluster_size().await, 4);
+
+    network.partition(&[addr(1), addr(2)], &[addr(3), addr(4)]);
+
+    // Leave time for the failure detector on both sides
+    sleep(Duration::from_secs(30)).await;
+
+    assert_eq!(nodes[0].get_live_nodes().await, node_ids("sim", &[1, 2]));
+    assert_eq!(nodes[2].get_live_nodes().await, node_ids("sim", &[3, 4]));
+
+    // Each side elects its own leader
+    assert_eq!(nodes[1].leader(), Some(NodeId::new("sim-1")));
+    assert_eq!(nodes[3].leader(), Some(NodeId::new("sim-3")));
+
+    network.heal();
+    sleep(Duration::from_secs(5)).await;
+
+    for node in &nodes {
+        assert_eq!(node.get_live_nodes().await, node_ids("sim", &[1, 2, 3, 4]));
+        assert_eq!(node.leader(), Some(NodeId::new("sim-1")));
+    }
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that files are rebalanced without duplicates when a node crashes
+#[tokio::test(start_paused = true)]
+async fn test_rebalancing_after_node_failure() {
+    let network = ChannelTransport::new();
+    let mut nodes = spawn_cluster(&network, "sim", 3).await;
+
+    sleep(Duration::from_secs(1)).await;
+
+    // Crash without announcing departure
+    nodes.pop().unwrap().shutdown().await;
+    sleep(Duration::from_secs(30)).await;
+
+    let test_files: Vec<String> = (0..100).map(|i| format!("file-{}.zuk", i)).collect();
+    let mut assigned = HashSet::new();
+
+    for node in &nodes {
+        let view = node.cluster_view().await;
+        assert_eq!(view.nodes(), node_ids("sim", &[1, 2]).as_slice());
+
+        for file in test_files.iter().filter(|file| view.owns(file)) {
+            assert!(assigned.insert(file.clone()), "{} assigned twice", file);
+        }
+    }
+
+    assert_eq!(
+        assigned.len(),
+        test_files.len(),
+        "Every file must be assigned"
+    );
+
+    shutdown_all(nodes).await;
+}
+
+/// Test that advertised capacities split files proportionally, exactly once
+#[tokio::test(start_paused = true)]
+async fn test_weighted_assignment_follows_capacity() {
+    let network = ChannelTransport::new();
+    let mut nodes = Vec::new();
+
+    for (index, capacity) in [(1u8, 1), (2, 2), (3, 4)] {
+        nodes.push(start(&network, config("sim", index).with_capacity(capacity)).await);
+    }
+
+    sleep(Duration::from_secs(1)).await;
+
+    let test_files: Vec<String> = (0..7000).map(|i| format!("file-{}.zuk", i)).collect();
+    let mut assigned = HashSet::new();
+    let mut shares = Vec::new();
+
+    for node in &nodes {
+        let view = node.cluster_view().await;
+        assert_eq!(view.total_weight(), 7);
+
+        let owned: Vec<&String> = test_files.iter().filter(|f| view.owns(f)).collect();
+        for file in &owned {
+            assert!(assigned.insert((*file).clone()), "{} assigned twice", file);
+        }
+        shares.push(owned.len());
+    }
+
+    assert_eq!(assigned.len(), test_files.len());
+    // Expected 1000 / 2000 / 4000
+    for (share, expected) in shares.iter().zip([1000, 2000, 4000]) {
+        assert!(
+            share.abs_diff(expected) < 200,
+            "share {} too far from {}",
+            share,
+            expected
+        );
+    }
+
+    shutdown_all(nodes).await;
+}